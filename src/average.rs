@@ -1,11 +1,13 @@
 use std::{
     collections::VecDeque,
-    sync::mpsc::{Receiver, Sender},
+    sync::mpsc::Receiver,
     thread::{self, JoinHandle},
     time::SystemTime,
 };
 
+use crate::bus::Publisher;
 use crate::data::{Data, Telemetry};
+use crate::envelope::Envelope;
 use crate::log_config::{GENERAL_LOG, MOVING_AVERAGE_LOG};
 use crate::logger::log;
 
@@ -14,20 +16,22 @@ pub struct Average;
 
 impl Average {
     pub fn run(
-        mut tx: Vec<Sender<Telemetry>>,
-        rx: Receiver<Telemetry>,
+        tx: Publisher<Telemetry>,
+        rx: Receiver<Envelope<Telemetry>>,
         buffer_length: usize,
     ) -> JoinHandle<()> {
         thread::spawn(move || {
             let mut buffer = VecDeque::with_capacity(buffer_length);
 
-            while let Ok(Telemetry::Position(new_data)) = rx.recv() {
+            while let Ok(Envelope {
+                message: Telemetry::Position(new_data),
+                ..
+            }) = rx.recv()
+            {
                 Self::handle_data_buffer(&mut buffer, new_data, buffer_length);
                 let avg_data = Self::calculate_average(&buffer);
                 log(MOVING_AVERAGE_LOG, avg_data);
-                tx.retain(|tx| tx.send(Telemetry::Position(avg_data)).is_ok());
-
-                if tx.is_empty() {
+                if tx.publish(Telemetry::Position(avg_data)) == 0 {
                     break;
                 }
             }
@@ -44,6 +48,7 @@ impl Average {
         }
     }
 
+    // The average is stamped with the newest measurement time, so its lag can be measured
     fn calculate_average(buffer: &VecDeque<Data>) -> Data {
         let count = buffer.len();
         let mut sum_x: f64 = 0.0;
@@ -60,7 +65,9 @@ impl Average {
             x: sum_x / count as f64,
             y: sum_y / count as f64,
             z: sum_z / count as f64,
-            timestamp: SystemTime::now(),
+            timestamp: buffer
+                .back()
+                .map_or_else(SystemTime::now, |newest| newest.timestamp),
         }
     }
 }
@@ -104,6 +111,7 @@ mod test {
         approx::assert_abs_diff_eq!(calculate_average_output.x, local_avg_x);
         approx::assert_abs_diff_eq!(calculate_average_output.y, local_avg_y);
         approx::assert_abs_diff_eq!(calculate_average_output.z, local_avg_z);
+        assert_eq!(calculate_average_output.timestamp, buffer[2].timestamp);
     }
 
     #[test]
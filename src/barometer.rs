@@ -0,0 +1,287 @@
+use crate::{
+    bus::Publisher,
+    config::{LOCAL_FRAME, LOCAL_ORIGIN},
+    coordinates::EnuPosition,
+    data::{Data, KinematicState, Telemetry},
+    log_config::{BAROMETER_LOG, GENERAL_LOG},
+    logger::log,
+    periodic_runner,
+    utils::get_cycle_duration,
+};
+use std::{
+    error::Error,
+    num::NonZeroU32,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::SystemTime,
+};
+
+use rand::rng;
+use rand_distr::{Distribution, Normal, StandardNormal};
+
+// International Standard Atmosphere, valid in the troposphere
+const SEA_LEVEL_PRESSURE: f64 = 101_325.0; // Pa
+const SEA_LEVEL_TEMPERATURE: f64 = 288.15; // K
+const TEMPERATURE_LAPSE_RATE: f64 = 0.0065; // K/m
+const PRESSURE_EXPONENT: f64 = 5.25588; // g M / (R L)
+const KELVIN_OFFSET: f64 = 273.15;
+// Temperature at which the sensor is calibrated, its temperature error vanishes there
+const CALIBRATION_TEMPERATURE: f64 = 15.0; // °C
+
+// Errors of the sensor, in the pressure it measures
+#[derive(Debug, Clone, Copy)]
+pub struct BarometerConfig {
+    pub noise_sigma: f64,             // Pa
+    pub bias_drift_sigma: f64,        // Pa/sqrt(s), random walk of the pressure bias
+    pub temperature_coefficient: f64, // Pa/°C away from the calibration temperature
+    pub temperature_offset: f64,      // °C, air temperature above the standard atmosphere
+}
+
+pub fn isa_pressure(altitude: f64) -> f64 {
+    SEA_LEVEL_PRESSURE
+        * (1.0 - TEMPERATURE_LAPSE_RATE * altitude / SEA_LEVEL_TEMPERATURE).powf(PRESSURE_EXPONENT)
+}
+
+pub fn isa_temperature(altitude: f64) -> f64 {
+    SEA_LEVEL_TEMPERATURE - TEMPERATURE_LAPSE_RATE * altitude - KELVIN_OFFSET
+}
+
+// Altitude above mean sea level the standard atmosphere has the pressure at
+pub fn pressure_altitude(pressure: f64) -> f64 {
+    SEA_LEVEL_TEMPERATURE / TEMPERATURE_LAPSE_RATE
+        * (1.0 - (pressure / SEA_LEVEL_PRESSURE).powf(1.0 / PRESSURE_EXPONENT))
+}
+
+pub struct Barometer {
+    tx: Publisher<Telemetry>,
+    kinematic_state: Arc<Mutex<KinematicState>>,
+    prev_timestamp: SystemTime,
+    config: BarometerConfig,
+    bias: f64,
+    noise_generator: Normal<f64>,
+}
+
+impl Barometer {
+    pub fn run(
+        kinematic_state: Arc<Mutex<KinematicState>>,
+        tx: Publisher<Telemetry>,
+        shutdown: Arc<AtomicBool>,
+        frequency: NonZeroU32,
+        config: BarometerConfig,
+    ) -> JoinHandle<()> {
+        let mut barometer = Barometer::new(kinematic_state, tx, config);
+        std::thread::spawn(move || {
+            if let Err(e) = periodic_runner::run_periodicaly(
+                || barometer.step(),
+                || shutdown.load(Ordering::SeqCst),
+                get_cycle_duration(frequency),
+            ) {
+                eprintln!("Barometer internal error: {e}. Aborting.")
+            }
+
+            log(GENERAL_LOG, "Barometer removed".to_string());
+        })
+    }
+
+    fn new(
+        kinematic_state: Arc<Mutex<KinematicState>>,
+        tx: Publisher<Telemetry>,
+        config: BarometerConfig,
+    ) -> Barometer {
+        let prev_timestamp = kinematic_state.lock().unwrap().timestamp;
+        Barometer {
+            tx,
+            kinematic_state,
+            prev_timestamp,
+            config,
+            bias: 0.0,
+            noise_generator: Normal::new(0.0, config.noise_sigma).unwrap(),
+        }
+    }
+
+    // Measures the pressure at the simulated altitude above the local origin
+    // and reports the altitude the standard atmosphere gives for it
+    fn step(&mut self) -> Result<(), Box<dyn Error>> {
+        let current_state = *self.kinematic_state.lock().unwrap();
+
+        let elapsed = current_state
+            .timestamp
+            .duration_since(self.prev_timestamp)?;
+        self.prev_timestamp = current_state.timestamp;
+        let drift: f64 = StandardNormal.sample(&mut rng());
+        self.bias += self.config.bias_drift_sigma * elapsed.as_secs_f64().sqrt() * drift;
+
+        let altitude = LOCAL_ORIGIN.altitude + current_state.position.z;
+        let temperature = isa_temperature(altitude) + self.config.temperature_offset;
+        let pressure = isa_pressure(altitude)
+            + self.bias
+            + self.config.temperature_coefficient * (temperature - CALIBRATION_TEMPERATURE)
+            + self.noise_generator.sample(&mut rng());
+        let up = pressure_altitude(pressure) - LOCAL_ORIGIN.altitude;
+
+        let data_to_send = Data {
+            x: pressure,
+            y: temperature,
+            z: LOCAL_FRAME
+                .enu_to_local(EnuPosition {
+                    east: 0.0,
+                    north: 0.0,
+                    up,
+                })
+                .z,
+            timestamp: current_state.timestamp,
+        };
+
+        log(BAROMETER_LOG, data_to_send);
+        if self.tx.publish(Telemetry::Altitude(data_to_send)) == 0 {
+            return Err("No subscribers for barometer output.".into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntest_timeout::timeout;
+    use std::{sync::mpsc, time::Duration};
+
+    const IDEAL: BarometerConfig = BarometerConfig {
+        noise_sigma: 0.0,
+        bias_drift_sigma: 0.0,
+        temperature_coefficient: 0.0,
+        temperature_offset: 0.0,
+    };
+
+    fn barometer(
+        kinematic_state: &Arc<Mutex<KinematicState>>,
+        tx: Publisher<Telemetry>,
+        config: BarometerConfig,
+    ) -> Barometer {
+        Barometer::new(Arc::clone(kinematic_state), tx, config)
+    }
+
+    fn measured_altitude(rx: &mpsc::Receiver<crate::envelope::Envelope<Telemetry>>) -> Data {
+        let Telemetry::Altitude(data) = rx.recv().unwrap().message else {
+            panic!("Barometer should provide altitude.");
+        };
+        data
+    }
+
+    #[test]
+    fn standard_atmosphere_matches_reference_values() {
+        approx::assert_abs_diff_eq!(isa_pressure(0.0), 101_325.0);
+        approx::assert_abs_diff_eq!(isa_pressure(1000.0), 89_874.6, epsilon = 1.0);
+        approx::assert_abs_diff_eq!(isa_temperature(1000.0), 8.5, epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(
+            pressure_altitude(isa_pressure(2500.0)),
+            2500.0,
+            epsilon = 1e-6
+        );
+    }
+
+    #[test]
+    fn given_ideal_sensor_expect_altitude_above_origin() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, rx) = mpsc::channel();
+        let mut barometer = barometer(&kinematic_state, vec![tx].into(), IDEAL);
+        kinematic_state.lock().unwrap().position.z = 42.0;
+
+        assert!(barometer.step().is_ok());
+
+        let data = measured_altitude(&rx);
+        approx::assert_abs_diff_eq!(data.x, isa_pressure(LOCAL_ORIGIN.altitude + 42.0));
+        approx::assert_abs_diff_eq!(data.y, isa_temperature(LOCAL_ORIGIN.altitude + 42.0));
+        approx::assert_abs_diff_eq!(data.z, 42.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn given_warmer_air_expect_temperature_error() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, rx) = mpsc::channel();
+        let warm = BarometerConfig {
+            temperature_coefficient: 1.5,
+            temperature_offset: 10.0,
+            ..IDEAL
+        };
+        let mut barometer = barometer(&kinematic_state, vec![tx].into(), warm);
+
+        assert!(barometer.step().is_ok());
+
+        let data = measured_altitude(&rx);
+        let temperature = isa_temperature(LOCAL_ORIGIN.altitude) + 10.0;
+        approx::assert_abs_diff_eq!(data.y, temperature);
+        approx::assert_abs_diff_eq!(
+            data.x,
+            isa_pressure(LOCAL_ORIGIN.altitude) + 1.5 * (temperature - CALIBRATION_TEMPERATURE),
+            epsilon = 1e-9
+        );
+        // a higher pressure reads as a lower altitude
+        assert!(data.z < 0.0);
+    }
+
+    #[test]
+    fn given_bias_drift_expect_bias_to_wander_over_time() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, rx) = mpsc::channel();
+        let drifting = BarometerConfig {
+            bias_drift_sigma: 5.0,
+            ..IDEAL
+        };
+        let mut barometer = barometer(&kinematic_state, vec![tx].into(), drifting);
+
+        assert!(barometer.step().is_ok());
+        approx::assert_abs_diff_eq!(measured_altitude(&rx).z, 0.0, epsilon = 1e-6);
+
+        kinematic_state.lock().unwrap().timestamp += Duration::from_secs(100);
+        assert!(barometer.step().is_ok());
+        approx::assert_abs_diff_ne!(measured_altitude(&rx).z, 0.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn given_noise_enabled_expect_output_with_noise() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, rx) = mpsc::channel();
+        let noisy = BarometerConfig {
+            noise_sigma: 10.0,
+            ..IDEAL
+        };
+        let mut barometer = barometer(&kinematic_state, vec![tx].into(), noisy);
+
+        assert!(barometer.step().is_ok());
+        approx::assert_abs_diff_ne!(
+            measured_altitude(&rx).x,
+            isa_pressure(LOCAL_ORIGIN.altitude)
+        );
+    }
+
+    #[test]
+    fn given_next_timestamp_is_behind_previous_expect_step_to_fail() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, _rx) = mpsc::channel();
+        let mut barometer = barometer(&kinematic_state, vec![tx].into(), IDEAL);
+        kinematic_state.lock().unwrap().timestamp -= Duration::from_secs(1);
+
+        assert!(barometer.step().is_err());
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn given_rx_goes_out_of_scope_barometer_shuts_down() {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, rx) = mpsc::channel();
+        let handle = Barometer::run(
+            kinematic_state,
+            vec![tx].into(),
+            shutdown,
+            NonZeroU32::new(10).unwrap(),
+            IDEAL,
+        );
+        drop(rx);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,419 @@
+//! Compact binary container for telemetry logs, for long recordings of high-rate data.
+//!
+//! Layout, all integers and floats little endian:
+//! - magic `RSDFBLOG`, format version (u16)
+//! - header length (u32) and header: UTF-8 `key=value` lines, `source` and `schema` first,
+//!   followed by the configuration of the run that wrote the log
+//! - records, each a payload length (u32) followed by the payload
+//!
+//! The telemetry payload is `seq` (u64), `measurement_time` and `receive_time` (u64 nanoseconds
+//! since the Unix epoch, UTC), `type` (u8, 0 position, 1 acceleration, 2 altitude,
+//! 3 magnetic_field, 4 velocity, 5 range) and `x,y,z` (f64).
+//! Readers skip payload bytes they do not know, so fields may be appended in later versions.
+//! A record cut short, e.g. by a crash while writing, ends the log.
+use std::{
+    error::Error,
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use csv::WriterBuilder;
+
+use crate::{
+    config::{GENERATOR_FREQ, GPS_FREQ, GPS_OUTPUT_NOISE_SIGMA, IMU_FREQ, IMU_OUTPUT_NOISE_SIGMA},
+    log_schema::{LogRecord, TelemetryKind, TelemetryRecord},
+};
+
+pub const BINARY_LOG_EXTENSION: &str = "bin";
+const MAGIC: &[u8; 8] = b"RSDFBLOG";
+const FORMAT_VERSION: u16 = 1;
+pub const TELEMETRY_SCHEMA: &str = "seq:u64,measurement_time:u64ns,receive_time:u64ns,\
+    type:u8(0 position|1 acceleration|2 altitude|3 magnetic_field|4 velocity|5 range),\
+    x:f64,y:f64,z:f64";
+const TELEMETRY_PAYLOAD_SIZE: usize = 8 + 8 + 8 + 1 + 8 * 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Csv,
+    #[allow(dead_code)]
+    Binary,
+}
+
+#[derive(Debug)]
+pub struct InvalidBinaryLog(String);
+
+impl fmt::Display for InvalidBinaryLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid binary log: {}", self.0)
+    }
+}
+
+impl Error for InvalidBinaryLog {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryLogHeader {
+    pub source: String,
+    pub schema: String,
+    pub config: Vec<(String, String)>,
+}
+
+impl BinaryLogHeader {
+    // Header of a telemetry log, recording the rates and noise levels of the current build
+    pub fn telemetry(source: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            schema: TELEMETRY_SCHEMA.to_string(),
+            config: [
+                ("GENERATOR_FREQ", GENERATOR_FREQ.to_string()),
+                ("IMU_FREQ", IMU_FREQ.to_string()),
+                ("GPS_FREQ", GPS_FREQ.to_string()),
+                ("GPS_OUTPUT_NOISE_SIGMA", GPS_OUTPUT_NOISE_SIGMA.to_string()),
+                ("IMU_OUTPUT_NOISE_SIGMA", IMU_OUTPUT_NOISE_SIGMA.to_string()),
+            ]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+        }
+    }
+
+    // Magic, version and header, everything a log file starts with
+    pub fn encode(&self) -> Vec<u8> {
+        let mut text = format!("source={}\nschema={}\n", self.source, self.schema);
+        for (key, value) in &self.config {
+            text.push_str(&format!("{key}={value}\n"));
+        }
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(text.as_bytes());
+        bytes
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, InvalidBinaryLog> {
+        if take(input, MAGIC.len()) != Some(MAGIC.as_slice()) {
+            return Err(InvalidBinaryLog("not a binary log".to_string()));
+        }
+        let version =
+            take_u16(input).ok_or_else(|| InvalidBinaryLog("missing version".to_string()))?;
+        if version != FORMAT_VERSION {
+            return Err(InvalidBinaryLog(format!("unsupported version {version}")));
+        }
+        let text = take_u32(input)
+            .and_then(|length| take(input, length as usize))
+            .ok_or_else(|| InvalidBinaryLog("header cut short".to_string()))?;
+        let text = std::str::from_utf8(text).map_err(|e| InvalidBinaryLog(e.to_string()))?;
+
+        let mut config: Vec<(String, String)> = text
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let mut required = |key: &str| match config.iter().position(|(name, _)| name == key) {
+            Some(index) => Ok(config.remove(index).1),
+            None => Err(InvalidBinaryLog(format!("header without {key}"))),
+        };
+        Ok(Self {
+            source: required("source")?,
+            schema: required("schema")?,
+            config,
+        })
+    }
+}
+
+pub fn nanoseconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+}
+
+// The source is stored once in the header, not in every record
+pub fn encode_telemetry_record(record: &TelemetryRecord) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(4 + TELEMETRY_PAYLOAD_SIZE);
+    bytes.extend_from_slice(&(TELEMETRY_PAYLOAD_SIZE as u32).to_le_bytes());
+    bytes.extend_from_slice(&record.seq.to_le_bytes());
+    bytes.extend_from_slice(&nanoseconds(record.measurement_time).to_le_bytes());
+    bytes.extend_from_slice(&nanoseconds(record.receive_time).to_le_bytes());
+    bytes.push(match record.kind {
+        TelemetryKind::Position => 0,
+        TelemetryKind::Acceleration => 1,
+        TelemetryKind::Altitude => 2,
+        TelemetryKind::MagneticField => 3,
+        TelemetryKind::Velocity => 4,
+        TelemetryKind::Range => 5,
+    });
+    for value in [record.x, record.y, record.z] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes
+}
+
+pub fn take<'a>(input: &mut &'a [u8], length: usize) -> Option<&'a [u8]> {
+    if input.len() < length {
+        return None;
+    }
+    let (taken, rest) = input.split_at(length);
+    *input = rest;
+    Some(taken)
+}
+
+pub fn take_u16(input: &mut &[u8]) -> Option<u16> {
+    take(input, 2).map(|bytes| u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn take_u32(input: &mut &[u8]) -> Option<u32> {
+    take(input, 4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+pub fn take_u64(input: &mut &[u8]) -> Option<u64> {
+    take(input, 8).map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+pub fn take_f64(input: &mut &[u8]) -> Option<f64> {
+    take_u64(input).map(f64::from_bits)
+}
+
+fn field<T>(value: Option<T>) -> Result<T, InvalidBinaryLog> {
+    value.ok_or_else(|| InvalidBinaryLog("record too short".to_string()))
+}
+
+fn decode_telemetry_record(
+    source: &str,
+    mut payload: &[u8],
+) -> Result<TelemetryRecord, InvalidBinaryLog> {
+    let input = &mut payload;
+    let seq = field(take_u64(input))?;
+    let measurement_time = UNIX_EPOCH + Duration::from_nanos(field(take_u64(input))?);
+    let receive_time = UNIX_EPOCH + Duration::from_nanos(field(take_u64(input))?);
+    let kind = match field(take(input, 1))?[0] {
+        0 => TelemetryKind::Position,
+        1 => TelemetryKind::Acceleration,
+        2 => TelemetryKind::Altitude,
+        3 => TelemetryKind::MagneticField,
+        4 => TelemetryKind::Velocity,
+        5 => TelemetryKind::Range,
+        kind => return Err(InvalidBinaryLog(format!("unknown type {kind}"))),
+    };
+    Ok(TelemetryRecord {
+        seq,
+        source: source.to_string(),
+        measurement_time,
+        receive_time,
+        kind,
+        x: field(take_f64(input))?,
+        y: field(take_f64(input))?,
+        z: field(take_f64(input))?,
+    })
+}
+
+pub fn read_binary_telemetry_log(
+    path: &Path,
+) -> Result<(BinaryLogHeader, Vec<TelemetryRecord>), Box<dyn Error>> {
+    let content = fs::read(path)?;
+    let mut input = content.as_slice();
+    let header = BinaryLogHeader::decode(&mut input)?;
+    if header.schema != TELEMETRY_SCHEMA {
+        return Err(InvalidBinaryLog(format!("unknown schema {}", header.schema)).into());
+    }
+
+    let mut records = Vec::new();
+    while let Some(payload) =
+        take_u32(&mut input).and_then(|length| take(&mut input, length as usize))
+    {
+        records.push(decode_telemetry_record(&header.source, payload)?);
+    }
+    Ok((header, records))
+}
+
+pub fn binary_log_path_in(directory: &Path, component_name: &str) -> PathBuf {
+    let file_name = component_name.to_lowercase();
+    directory.join(format!("{file_name}.{BINARY_LOG_EXTENSION}"))
+}
+
+// Writes the records as a CSV telemetry log, returns how many were converted
+pub fn convert_to_csv(input: &Path, output: &Path) -> Result<usize, Box<dyn Error>> {
+    let (_, records) = read_binary_telemetry_log(input)?;
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut writer = WriterBuilder::new().has_headers(false).from_path(output)?;
+    writer.write_record(TelemetryRecord::HEADER)?;
+    for record in &records {
+        writer.serialize(record)?;
+    }
+    writer.flush()?;
+    Ok(records.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv_handler::read_log;
+
+    fn record(seq: u64, kind: TelemetryKind) -> TelemetryRecord {
+        TelemetryRecord {
+            seq,
+            source: "IMU_LOG".to_string(),
+            measurement_time: UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789),
+            receive_time: UNIX_EPOCH + Duration::new(1_700_000_000, 123_999_001),
+            kind,
+            x: 0.1,
+            y: -2.5,
+            z: f64::MAX,
+        }
+    }
+
+    fn write_log(path: &Path, records: &[TelemetryRecord]) {
+        let mut bytes = BinaryLogHeader::telemetry("IMU_LOG").encode();
+        for record in records {
+            bytes.extend(encode_telemetry_record(record));
+        }
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, bytes).unwrap();
+    }
+
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("binary_log_{name}_{}", std::process::id()))
+            .join("imu_log.bin")
+    }
+
+    #[test]
+    fn header_roundtrip_keeps_schema_and_config() {
+        let header = BinaryLogHeader::telemetry("GPS_LOG");
+        let bytes = header.encode();
+
+        let decoded = BinaryLogHeader::decode(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(decoded, header);
+        assert!(decoded
+            .config
+            .contains(&("IMU_FREQ".to_string(), IMU_FREQ.to_string())));
+    }
+
+    #[test]
+    fn records_roundtrip_with_nanosecond_timestamps() {
+        let path = test_path("roundtrip");
+        let records = [
+            record(0, TelemetryKind::Acceleration),
+            record(1, TelemetryKind::Position),
+        ];
+        write_log(&path, &records);
+
+        let (header, read_records) = read_binary_telemetry_log(&path).unwrap();
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+
+        assert_eq!(header.source, "IMU_LOG");
+        assert_eq!(read_records, records);
+    }
+
+    #[test]
+    fn record_is_much_smaller_than_its_csv_row() {
+        let record = record(12345, TelemetryKind::Acceleration);
+        let csv_row = crate::log_stream::csv_row(&record).unwrap();
+
+        assert_eq!(
+            encode_telemetry_record(&record).len(),
+            4 + TELEMETRY_PAYLOAD_SIZE
+        );
+        assert!(encode_telemetry_record(&record).len() < csv_row.len());
+    }
+
+    #[test]
+    fn truncated_last_record_ends_the_log() {
+        let path = test_path("truncated");
+        write_log(
+            &path,
+            &[
+                record(0, TelemetryKind::Position),
+                record(1, TelemetryKind::Position),
+            ],
+        );
+        let content = fs::read(&path).unwrap();
+        fs::write(&path, &content[..content.len() - 10]).unwrap();
+
+        let (_, records) = read_binary_telemetry_log(&path).unwrap();
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+
+        assert_eq!(records.len(), 1);
+    }
+
+    #[test]
+    fn unknown_trailing_payload_bytes_are_skipped() {
+        let path = test_path("extended");
+        let mut bytes = BinaryLogHeader::telemetry("IMU_LOG").encode();
+        let mut extended = encode_telemetry_record(&record(0, TelemetryKind::Position));
+        extended.splice(0..4, ((TELEMETRY_PAYLOAD_SIZE + 2) as u32).to_le_bytes());
+        extended.extend_from_slice(&[7, 7]);
+        bytes.extend(extended);
+        bytes.extend(encode_telemetry_record(&record(1, TelemetryKind::Position)));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, bytes).unwrap();
+
+        let (_, records) = read_binary_telemetry_log(&path).unwrap();
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+
+        assert_eq!(
+            records.iter().map(|record| record.seq).collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn every_telemetry_type_roundtrips() {
+        let path = test_path("types");
+        let records: Vec<TelemetryRecord> = [
+            TelemetryKind::Position,
+            TelemetryKind::Acceleration,
+            TelemetryKind::Altitude,
+            TelemetryKind::MagneticField,
+            TelemetryKind::Velocity,
+            TelemetryKind::Range,
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(seq, kind)| record(seq as u64, kind))
+        .collect();
+        write_log(&path, &records);
+
+        let (_, read_records) = read_binary_telemetry_log(&path).unwrap();
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+
+        assert_eq!(read_records, records);
+    }
+
+    #[test]
+    fn given_other_file_expect_error() {
+        let path = test_path("not_binary");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "seq,source\n").unwrap();
+
+        let result = read_binary_telemetry_log(&path);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn converted_log_reads_as_csv_telemetry_log() {
+        let path = test_path("convert");
+        let output = path.with_extension("csv");
+        write_log(
+            &path,
+            &[
+                record(0, TelemetryKind::Acceleration),
+                record(1, TelemetryKind::Acceleration),
+            ],
+        );
+
+        let converted = convert_to_csv(&path, &output).unwrap();
+        let records = read_log::<TelemetryRecord>(&output).unwrap();
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+
+        assert_eq!(converted, 2);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].source, "IMU_LOG");
+        assert_eq!(records[1].kind, TelemetryKind::Acceleration);
+        approx::assert_abs_diff_eq!(records[1].y, -2.5);
+    }
+}
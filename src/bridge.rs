@@ -0,0 +1,482 @@
+//! UDP bridge carrying telemetry topics between processes, e.g. the estimators in one
+//! process and the plots in another, on the same host or across a LAN.
+//!
+//! Every datagram holds one message, all integers and floats little endian:
+//! - magic `RSDFUDP`, wire format version (u16)
+//! - topic name length (u16) and the UTF-8 topic name, e.g. `/estimators/kalman`
+//! - `sequence` (u64) of the message on the sending bus
+//! - `measurement_time` and `publish_time` (u64 nanoseconds since the Unix epoch, UTC)
+//! - `type` (u8, 0 position, 1 acceleration, 2 altitude, 3 magnetic_field, 4 velocity, 5 range)
+//!   and `x,y,z` (f64)
+//!
+//! Bytes after the known fields are ignored, so fields may be appended in later versions
+//! while the version stays; a different version is a format the receiver cannot read.
+//! The receiving side publishes the messages on its own bus, where they get a local source
+//! and sequence. UDP may lose or reorder datagrams, the receiver counts both from the
+//! sequence of the sending bus.
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt, io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    binary_log::{nanoseconds, take, take_f64, take_u16, take_u64},
+    bus::{Bus, DataSource, Publisher},
+    data::{Data, Telemetry},
+    envelope::{Envelope, SequenceStatus, SequenceTracker, SourceId},
+    log_config::GENERAL_LOG,
+    logger::log,
+};
+
+const MAGIC: &[u8; 7] = b"RSDFUDP";
+const WIRE_VERSION: u16 = 1;
+const MAX_DATAGRAM_SIZE: usize = 1024;
+const RECEIVE_POLL_INTERVAL: Duration = Duration::from_millis(100); // how often shutdown is checked
+
+#[derive(Debug, PartialEq)]
+pub struct InvalidDatagram(String);
+
+impl fmt::Display for InvalidDatagram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid bridge datagram: {}", self.0)
+    }
+}
+
+impl Error for InvalidDatagram {}
+
+// A message as it travels between processes
+#[derive(Debug, Clone)]
+pub struct BridgeMessage {
+    pub topic: String,
+    pub envelope: Envelope<Telemetry>,
+}
+
+pub fn encode_datagram(topic: &str, envelope: &Envelope<Telemetry>) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&WIRE_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(topic.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(topic.as_bytes());
+    bytes.extend_from_slice(&envelope.sequence.to_le_bytes());
+    bytes.extend_from_slice(&nanoseconds(envelope.measurement_time).to_le_bytes());
+    bytes.extend_from_slice(&nanoseconds(envelope.publish_time).to_le_bytes());
+    let (kind, data) = match envelope.message {
+        Telemetry::Position(data) => (0, data),
+        Telemetry::Acceleration(data) => (1, data),
+        Telemetry::Altitude(data) => (2, data),
+        Telemetry::MagneticField(data) => (3, data),
+        Telemetry::Velocity(data) => (4, data),
+        Telemetry::Range(data) => (5, data),
+    };
+    bytes.push(kind);
+    for value in [data.x, data.y, data.z] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes
+}
+
+fn field<T>(value: Option<T>) -> Result<T, InvalidDatagram> {
+    value.ok_or_else(|| InvalidDatagram("datagram too short".to_string()))
+}
+
+fn time(input: &mut &[u8]) -> Result<SystemTime, InvalidDatagram> {
+    Ok(UNIX_EPOCH + Duration::from_nanos(field(take_u64(input))?))
+}
+
+// The source of the decoded envelope is the given one, ids are only unique within a process
+pub fn decode_datagram(
+    mut datagram: &[u8],
+    source: SourceId,
+) -> Result<BridgeMessage, InvalidDatagram> {
+    let input = &mut datagram;
+    if take(input, MAGIC.len()) != Some(MAGIC.as_slice()) {
+        return Err(InvalidDatagram("not a bridge datagram".to_string()));
+    }
+    let version = field(take_u16(input))?;
+    if version != WIRE_VERSION {
+        return Err(InvalidDatagram(format!("unsupported version {version}")));
+    }
+    let topic_length = field(take_u16(input))?;
+    let topic = std::str::from_utf8(field(take(input, topic_length as usize))?)
+        .map_err(|e| InvalidDatagram(e.to_string()))?
+        .to_string();
+    let sequence = field(take_u64(input))?;
+    let measurement_time = time(input)?;
+    let publish_time = time(input)?;
+    let kind = field(take(input, 1))?[0];
+    let data = Data {
+        x: field(take_f64(input))?,
+        y: field(take_f64(input))?,
+        z: field(take_f64(input))?,
+        timestamp: measurement_time,
+    };
+    let message = match kind {
+        0 => Telemetry::Position(data),
+        1 => Telemetry::Acceleration(data),
+        2 => Telemetry::Altitude(data),
+        3 => Telemetry::MagneticField(data),
+        4 => Telemetry::Velocity(data),
+        5 => Telemetry::Range(data),
+        kind => return Err(InvalidDatagram(format!("unknown type {kind}"))),
+    };
+    Ok(BridgeMessage {
+        topic,
+        envelope: Envelope {
+            source,
+            sequence,
+            measurement_time,
+            publish_time,
+            message,
+        },
+    })
+}
+
+// Forwards the topics of the sources to the target until their publishers stop,
+// the handle returns the number of datagrams sent
+pub fn start_sender(
+    bus: &Bus,
+    sources: &[DataSource],
+    target: impl ToSocketAddrs,
+) -> io::Result<JoinHandle<usize>> {
+    let target = target
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no bridge target address"))?;
+    let bind_address: SocketAddr = if target.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    }
+    .parse()
+    .unwrap();
+    let socket = Arc::new(UdpSocket::bind(bind_address)?);
+
+    let forwarders: Vec<JoinHandle<usize>> = sources
+        .iter()
+        .map(|source| {
+            let (topic, rx) = (source.topic_name(), bus.subscribe(&source.topic()));
+            let socket = Arc::clone(&socket);
+            thread::spawn(move || {
+                let mut sent = 0;
+                for envelope in rx {
+                    // nobody listening is not an error for UDP, the receiver may start later
+                    if socket
+                        .send_to(&encode_datagram(&topic, &envelope), target)
+                        .is_ok()
+                    {
+                        sent += 1;
+                    }
+                }
+                sent
+            })
+        })
+        .collect();
+
+    Ok(thread::spawn(move || {
+        let sent = forwarders
+            .into_iter()
+            .map(|forwarder| forwarder.join().unwrap_or(0))
+            .sum();
+        log(
+            GENERAL_LOG,
+            format!("UDP bridge to {target} removed, {sent} messages sent"),
+        );
+        sent
+    }))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BridgeStatistics {
+    pub received: u64,
+    pub lost: u64,
+    pub out_of_order: u64,
+    pub invalid: u64,
+    pub unknown_topic: u64,
+}
+
+impl fmt::Display for BridgeStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} received, {} lost, {} out of order, {} invalid, {} of unknown topics",
+            self.received, self.lost, self.out_of_order, self.invalid, self.unknown_topic
+        )
+    }
+}
+
+// Receiving side, bound before it runs so the address can be handed to the sender
+pub struct BridgeReceiver {
+    socket: UdpSocket,
+}
+
+impl BridgeReceiver {
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_read_timeout(Some(RECEIVE_POLL_INTERVAL))?;
+        Ok(Self { socket })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    // Publishes the received messages of the sources on the bus until shutdown.
+    // Out-of-order datagrams are dropped, so local consumers see every topic in order.
+    pub fn run(
+        self,
+        bus: &Bus,
+        sources: &[DataSource],
+        shutdown: Arc<AtomicBool>,
+    ) -> JoinHandle<BridgeStatistics> {
+        let publishers: HashMap<String, (SourceId, Publisher<Telemetry>)> = sources
+            .iter()
+            .map(|source| {
+                (
+                    source.topic_name(),
+                    (SourceId::next(), bus.publisher(&source.topic())),
+                )
+            })
+            .collect();
+
+        thread::spawn(move || {
+            let mut statistics = BridgeStatistics::default();
+            let mut sequence_tracker = SequenceTracker::default();
+            let mut buffer = [0u8; MAX_DATAGRAM_SIZE];
+            let remote = SourceId::next(); // replaced by the source of the topic once it is known
+            while !shutdown.load(Ordering::SeqCst) {
+                let length = match self.socket.recv_from(&mut buffer) {
+                    Ok((length, _)) => length,
+                    Err(e)
+                        if matches!(
+                            e.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                        ) =>
+                    {
+                        continue
+                    }
+                    Err(e) => {
+                        eprintln!("UDP bridge: {e}");
+                        continue;
+                    }
+                };
+                let message = match decode_datagram(&buffer[..length], remote) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        eprintln!("UDP bridge: {e}. Datagram skipped.");
+                        statistics.invalid += 1;
+                        continue;
+                    }
+                };
+                let Some((source, publisher)) = publishers.get(&message.topic) else {
+                    statistics.unknown_topic += 1;
+                    continue;
+                };
+                statistics.received += 1;
+                let envelope = Envelope {
+                    source: *source,
+                    ..message.envelope
+                };
+                match sequence_tracker.check(&envelope) {
+                    SequenceStatus::OutOfOrder => continue,
+                    SequenceStatus::Gap { missing } => statistics.lost += missing,
+                    SequenceStatus::InOrder => {}
+                }
+                publisher.publish(envelope.message);
+            }
+            statistics.out_of_order = sequence_tracker.out_of_order();
+            log(
+                GENERAL_LOG,
+                format!("UDP bridge receiver removed, {statistics}"),
+            );
+            statistics
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntest_timeout::timeout;
+    use std::sync::mpsc;
+
+    fn envelope(sequence: u64, x: f64) -> Envelope<Telemetry> {
+        Envelope::new(
+            SourceId::next(),
+            sequence,
+            Telemetry::Position(Data { x, ..Data::new() }),
+        )
+    }
+
+    #[test]
+    fn test_datagram_round_trip() {
+        let sent = Envelope::new(
+            SourceId::next(),
+            7,
+            Telemetry::Acceleration(Data {
+                x: 1.0,
+                y: -2.5,
+                z: 3.0,
+                ..Data::new()
+            }),
+        );
+        let source = SourceId::next();
+        let received = decode_datagram(&encode_datagram("/sensors/imu", &sent), source).unwrap();
+        assert_eq!(received.topic, "/sensors/imu");
+        let envelope = received.envelope;
+        assert_eq!((envelope.source, envelope.sequence), (source, 7));
+        assert_eq!(
+            (envelope.measurement_time, envelope.publish_time),
+            (sent.measurement_time, sent.publish_time)
+        );
+        let Telemetry::Acceleration(data) = envelope.message else {
+            panic!("Acceleration expected");
+        };
+        assert_eq!((data.x, data.y, data.z), (1.0, -2.5, 3.0));
+    }
+
+    #[test]
+    fn test_every_type_keeps_its_code() {
+        let data = Data {
+            x: 4.0,
+            ..Data::new()
+        };
+        let telemetry = [
+            Telemetry::Position(data),
+            Telemetry::Acceleration(data),
+            Telemetry::Altitude(data),
+            Telemetry::MagneticField(data),
+            Telemetry::Velocity(data),
+            Telemetry::Range(data),
+        ];
+        for (code, sent) in telemetry.into_iter().enumerate() {
+            let datagram =
+                encode_datagram("/sensors/any", &Envelope::new(SourceId::next(), 0, sent));
+            assert_eq!(datagram[datagram.len() - 25], code as u8);
+            let received = decode_datagram(&datagram, SourceId::next())
+                .unwrap()
+                .envelope
+                .message;
+            assert_eq!(
+                std::mem::discriminant(&received),
+                std::mem::discriminant(&sent)
+            );
+            approx::assert_abs_diff_eq!(received.data().x, 4.0);
+        }
+    }
+
+    #[test]
+    fn test_datagram_of_other_version_or_cut_short_is_invalid() {
+        let mut datagram = encode_datagram("/sensors/gps", &envelope(0, 1.0));
+        assert!(decode_datagram(&datagram[..datagram.len() - 1], SourceId::next()).is_err());
+
+        datagram[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(WIRE_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            decode_datagram(&datagram, SourceId::next()).unwrap_err(),
+            InvalidDatagram(format!("unsupported version {}", WIRE_VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn test_appended_fields_are_ignored() {
+        let mut datagram = encode_datagram("/sensors/gps", &envelope(3, 1.0));
+        datagram.extend_from_slice(&[0xAB; 16]);
+        assert_eq!(
+            decode_datagram(&datagram, SourceId::next())
+                .unwrap()
+                .envelope
+                .sequence,
+            3
+        );
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn test_topics_are_bridged_over_loopback() {
+        let (remote_bus, local_bus) = (Bus::new(), Bus::new());
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let receiver = BridgeReceiver::bind("127.0.0.1:0").unwrap();
+        let address = receiver.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+        local_bus.subscribe_with(&DataSource::Kalman.topic(), tx);
+        let receiver = receiver.run(&local_bus, &[DataSource::Kalman], Arc::clone(&shutdown));
+        drop(local_bus);
+
+        let sender =
+            start_sender(&remote_bus, &[DataSource::Kalman, DataSource::Gps], address).unwrap();
+        let kalman = remote_bus.publisher(&DataSource::Kalman.topic());
+        let gps = remote_bus.publisher(&DataSource::Gps.topic());
+        drop(remote_bus);
+        for x in [1.0, 2.0, 3.0] {
+            kalman.publish(Telemetry::Position(Data { x, ..Data::new() }));
+        }
+        gps.publish(Telemetry::Position(Data::new()));
+        let received: Vec<f64> = rx
+            .iter()
+            .take(3)
+            .map(|envelope| envelope.message.data().x)
+            .collect();
+        assert_eq!(received, vec![1.0, 2.0, 3.0]);
+
+        drop((kalman, gps));
+        assert_eq!(sender.join().unwrap(), 4);
+        shutdown.store(true, Ordering::SeqCst);
+        let statistics = receiver.join().unwrap();
+        assert_eq!(statistics.received, 3);
+        // the bridge stopped publishing, so the local stream ends
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn test_receiver_counts_lost_reordered_and_invalid_datagrams() {
+        let bus = Bus::new();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let receiver = BridgeReceiver::bind("127.0.0.1:0").unwrap();
+        let address = receiver.local_addr().unwrap();
+        let rx = bus.subscribe(&DataSource::Gps.topic());
+        let receiver = receiver.run(&bus, &[DataSource::Gps], Arc::clone(&shutdown));
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for sequence in [0, 1, 4, 3] {
+            socket
+                .send_to(
+                    &encode_datagram("/sensors/gps", &envelope(sequence, sequence as f64)),
+                    address,
+                )
+                .unwrap();
+        }
+        socket.send_to(b"not telemetry", address).unwrap();
+        socket
+            .send_to(&encode_datagram("/sensors/imu", &envelope(0, 0.0)), address)
+            .unwrap();
+        // once the last datagram arrived all the others were handled
+        socket
+            .send_to(&encode_datagram("/sensors/gps", &envelope(5, 5.0)), address)
+            .unwrap();
+        let received: Vec<f64> = rx
+            .iter()
+            .take(4)
+            .map(|envelope| envelope.message.data().x)
+            .collect();
+        assert_eq!(received, vec![0.0, 1.0, 4.0, 5.0]);
+
+        shutdown.store(true, Ordering::SeqCst);
+        assert_eq!(
+            receiver.join().unwrap(),
+            BridgeStatistics {
+                received: 5,
+                lost: 2,
+                out_of_order: 1,
+                invalid: 1,
+                unknown_topic: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,617 @@
+//! Publish/subscribe bus connecting the components through named, typed topics.
+//!
+//! Subscribing and publishing may start in any order and at any time, a subscriber receives
+//! everything published after it subscribed. Dropping the receiver unsubscribes, the publishers
+//! notice it on their next publish. Once the last publisher of a topic is gone, its subscribers
+//! are disconnected so they see the end of the stream, exactly like the end of an mpsc channel.
+//! Every message is delivered in an `Envelope` stamped with the topic's source id and sequence.
+//! Subscriptions are unbounded mpsc channels unless they ask for a bounded queue, whose
+//! dropped messages are tracked by the bus for the shutdown report.
+use std::{
+    any::{type_name, Any, TypeId},
+    borrow::Cow,
+    collections::HashMap,
+    marker::PhantomData,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+};
+
+use crate::{
+    data::Telemetry,
+    envelope::{Envelope, SourceId, Timestamped},
+    queue::{self, QueueConfig, QueueReceiver, QueueSender, QueueStatistics},
+};
+
+#[allow(unused)]
+#[derive(Copy, Clone, Debug, PartialEq, Hash, Eq)]
+pub enum DataSource {
+    Imu,
+    Gps,
+    GpsReceiver(u8),
+    Barometer,
+    Magnetometer,
+    Odometry,
+    Uwb,
+    Kalman,
+    Average,
+    InertialNavigator,
+    Imm,
+    InformationFilter,
+    Complementary,
+    GpsSmoothing,
+    Visualization,
+    Groundtruth,
+}
+
+impl DataSource {
+    pub fn topic_name(self) -> String {
+        match self {
+            DataSource::Imu => "/sensors/imu".to_string(),
+            DataSource::Gps => "/sensors/gps".to_string(),
+            DataSource::GpsReceiver(index) => format!("/sensors/gps_receiver_{}", index + 1),
+            DataSource::Barometer => "/sensors/barometer".to_string(),
+            DataSource::Magnetometer => "/sensors/magnetometer".to_string(),
+            DataSource::Odometry => "/sensors/odometry".to_string(),
+            DataSource::Uwb => "/sensors/uwb".to_string(),
+            DataSource::Groundtruth => "/groundtruth".to_string(),
+            DataSource::Kalman => "/estimators/kalman".to_string(),
+            DataSource::Average => "/estimators/moving_average".to_string(),
+            DataSource::InertialNavigator => "/estimators/inertial_navigator".to_string(),
+            DataSource::Imm => "/estimators/imm".to_string(),
+            DataSource::InformationFilter => "/estimators/information_filter".to_string(),
+            DataSource::Complementary => "/estimators/complementary".to_string(),
+            DataSource::GpsSmoothing => "/estimators/gps_smoothing".to_string(),
+            DataSource::Visualization => "/visualization".to_string(),
+        }
+    }
+
+    // The telemetry stream every built-in component publishes on
+    pub fn topic(self) -> Topic<Telemetry> {
+        Topic::named(self.topic_name())
+    }
+}
+
+// Name of a stream of `T` messages, a name is bound to one message type on a bus
+pub struct Topic<T> {
+    name: Cow<'static, str>,
+    message: PhantomData<fn() -> T>,
+}
+
+impl<T> Topic<T> {
+    #[allow(dead_code)]
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name: Cow::Borrowed(name),
+            message: PhantomData,
+        }
+    }
+
+    pub fn named(name: String) -> Self {
+        Self {
+            name: Cow::Owned(name),
+            message: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<T> Clone for Topic<T> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            message: PhantomData,
+        }
+    }
+}
+
+enum Subscriber<T> {
+    Channel(Sender<Envelope<T>>),
+    Queue(QueueSender<Envelope<T>>),
+}
+
+impl<T> Subscriber<T> {
+    // false once the receiving end is gone
+    fn send(&self, message: Envelope<T>) -> bool {
+        match self {
+            Subscriber::Channel(tx) => tx.send(message).is_ok(),
+            Subscriber::Queue(tx) => tx.send(message).is_ok(),
+        }
+    }
+}
+
+struct TopicState<T> {
+    source: SourceId,
+    next_sequence: u64,
+    subscribers: Vec<Arc<Subscriber<T>>>,
+    publishers: usize,
+}
+
+impl<T> TopicState<T> {
+    fn new(subscribers: Vec<Arc<Subscriber<T>>>, publishers: usize) -> Self {
+        Self {
+            source: SourceId::next(),
+            next_sequence: 0,
+            subscribers,
+            publishers,
+        }
+    }
+}
+
+type SharedState<T> = Arc<Mutex<TopicState<T>>>;
+
+struct TopicEntry {
+    message_type: TypeId,
+    message_type_name: &'static str,
+    state: Box<dyn Any + Send>,
+    counts: Box<dyn Fn() -> (usize, usize) + Send>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicInfo {
+    pub name: String,
+    pub message_type: &'static str,
+    pub publishers: usize,
+    // includes receivers dropped since the last publish
+    pub subscribers: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkReport {
+    pub topic: String,
+    pub subscriber: &'static str,
+    pub config: QueueConfig,
+    pub statistics: QueueStatistics,
+}
+
+impl std::fmt::Display for LinkReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} -> {} ({}, capacity {}): {} queued, {} dropped",
+            self.topic,
+            self.subscriber,
+            self.config.policy,
+            self.config.capacity,
+            self.statistics.queued,
+            self.statistics.dropped
+        )
+    }
+}
+
+struct MonitoredLink {
+    topic: String,
+    subscriber: &'static str,
+    config: QueueConfig,
+    statistics: Box<dyn Fn() -> QueueStatistics + Send>,
+}
+
+// Statistics of the bounded subscriptions, outlives the bus so they can be reported at shutdown
+#[derive(Clone, Default)]
+pub struct LinkMonitor {
+    links: Arc<Mutex<Vec<MonitoredLink>>>,
+}
+
+impl LinkMonitor {
+    pub fn reports(&self) -> Vec<LinkReport> {
+        self.links
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|link| LinkReport {
+                topic: link.topic.clone(),
+                subscriber: link.subscriber,
+                config: link.config,
+                statistics: (link.statistics)(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Bus {
+    topics: Arc<Mutex<HashMap<String, TopicEntry>>>,
+    links: LinkMonitor,
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state<T: Send + 'static>(&self, topic: &Topic<T>) -> SharedState<T> {
+        let mut topics = self.topics.lock().unwrap();
+        let entry = topics.entry(topic.name().to_string()).or_insert_with(|| {
+            let state: SharedState<T> = Arc::new(Mutex::new(TopicState::new(Vec::new(), 0)));
+            let counted = Arc::clone(&state);
+            TopicEntry {
+                message_type: TypeId::of::<T>(),
+                message_type_name: type_name::<T>(),
+                state: Box::new(state),
+                counts: Box::new(move || {
+                    let state = counted.lock().unwrap();
+                    (state.publishers, state.subscribers.len())
+                }),
+            }
+        });
+        assert!(
+            entry.message_type == TypeId::of::<T>(),
+            "Topic {} carries {}, not {}",
+            topic.name(),
+            entry.message_type_name,
+            type_name::<T>()
+        );
+        Arc::clone(entry.state.downcast_ref::<SharedState<T>>().unwrap())
+    }
+
+    pub fn publisher<T: Send + 'static>(&self, topic: &Topic<T>) -> Publisher<T> {
+        let state = self.state(topic);
+        state.lock().unwrap().publishers += 1;
+        Publisher { state }
+    }
+
+    pub fn subscribe<T: Send + 'static>(&self, topic: &Topic<T>) -> Receiver<Envelope<T>> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribe_with(topic, tx);
+        rx
+    }
+
+    // Lets several topics feed the same receiver
+    pub fn subscribe_with<T: Send + 'static>(
+        &self,
+        topic: &Topic<T>,
+        subscriber: Sender<Envelope<T>>,
+    ) {
+        self.state(topic)
+            .lock()
+            .unwrap()
+            .subscribers
+            .push(Arc::new(Subscriber::Channel(subscriber)));
+    }
+
+    // Subscribes through a bounded queue, `subscriber` names the consumer in the link reports
+    pub fn subscribe_queued<T: Send + 'static>(
+        &self,
+        topic: &Topic<T>,
+        subscriber: &'static str,
+        config: QueueConfig,
+    ) -> QueueReceiver<Envelope<T>> {
+        let (tx, rx) = queue::bounded(config);
+        let monitor = tx.monitor();
+        self.links.links.lock().unwrap().push(MonitoredLink {
+            topic: topic.name().to_string(),
+            subscriber,
+            config,
+            statistics: Box::new(move || monitor.statistics()),
+        });
+        self.state(topic)
+            .lock()
+            .unwrap()
+            .subscribers
+            .push(Arc::new(Subscriber::Queue(tx)));
+        rx
+    }
+
+    pub fn link_monitor(&self) -> LinkMonitor {
+        self.links.clone()
+    }
+
+    // Every topic used so far, sorted by name
+    pub fn topics(&self) -> Vec<TopicInfo> {
+        let topics = self.topics.lock().unwrap();
+        let mut infos: Vec<TopicInfo> = topics
+            .iter()
+            .map(|(name, entry)| {
+                let (publishers, subscribers) = (entry.counts)();
+                TopicInfo {
+                    name: name.clone(),
+                    message_type: entry.message_type_name,
+                    publishers,
+                    subscribers,
+                }
+            })
+            .collect();
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        infos
+    }
+}
+
+pub struct Publisher<T> {
+    state: SharedState<T>,
+}
+
+impl<T: Clone + Timestamped> Publisher<T> {
+    // Returns the number of subscribers the message reached, unsubscribed ones are dropped.
+    // The sequence advances even without subscribers, so late ones see no false gap.
+    // Sends without holding the topic lock, a blocking queue must not stall the whole topic.
+    pub fn publish(&self, message: T) -> usize {
+        let (envelope, subscribers) = {
+            let mut state = self.state.lock().unwrap();
+            let envelope = Envelope::new(state.source, state.next_sequence, message);
+            state.next_sequence += 1;
+            (envelope, state.subscribers.clone())
+        };
+        let gone: Vec<_> = subscribers
+            .into_iter()
+            .filter(|subscriber| !subscriber.send(envelope.clone()))
+            .collect();
+        let mut state = self.state.lock().unwrap();
+        state
+            .subscribers
+            .retain(|subscriber| !gone.iter().any(|dead| Arc::ptr_eq(dead, subscriber)));
+        state.subscribers.len()
+    }
+}
+
+impl<T> Publisher<T> {
+    pub fn subscriber_count(&self) -> usize {
+        self.state.lock().unwrap().subscribers.len()
+    }
+}
+
+// A publisher feeding fixed receivers, without a bus
+impl<T> From<Vec<Sender<Envelope<T>>>> for Publisher<T> {
+    fn from(subscribers: Vec<Sender<Envelope<T>>>) -> Self {
+        let subscribers = subscribers
+            .into_iter()
+            .map(|tx| Arc::new(Subscriber::Channel(tx)))
+            .collect();
+        Self {
+            state: Arc::new(Mutex::new(TopicState::new(subscribers, 1))),
+        }
+    }
+}
+
+impl<T> Clone for Publisher<T> {
+    fn clone(&self) -> Self {
+        self.state.lock().unwrap().publishers += 1;
+        Self {
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl<T> Drop for Publisher<T> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        state.publishers -= 1;
+        if state.publishers == 0 {
+            state.subscribers.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntest_timeout::timeout;
+    use std::{sync::mpsc::TryRecvError, thread, time::Duration};
+
+    const NUMBERS: Topic<u32> = Topic::new("/numbers");
+
+    impl Timestamped for u32 {
+        fn measurement_time(&self) -> std::time::SystemTime {
+            std::time::UNIX_EPOCH
+        }
+    }
+
+    #[test]
+    fn subscriber_receives_messages_published_after_subscribing() {
+        let bus = Bus::new();
+        let publisher = bus.publisher(&NUMBERS);
+        assert_eq!(publisher.publish(1), 0);
+
+        let rx = bus.subscribe(&NUMBERS);
+        assert_eq!(publisher.publish(2), 1);
+
+        assert_eq!(
+            rx.try_iter()
+                .map(|envelope| envelope.message)
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn subscribing_before_the_publisher_exists_works() {
+        let bus = Bus::new();
+        let rx = bus.subscribe(&NUMBERS);
+        let publisher = bus.publisher(&NUMBERS);
+
+        publisher.publish(7);
+
+        assert_eq!(rx.recv().unwrap().message, 7);
+    }
+
+    #[test]
+    fn dropped_receiver_is_unsubscribed() {
+        let bus = Bus::new();
+        let publisher = bus.publisher(&NUMBERS);
+        let rx_kept = bus.subscribe(&NUMBERS);
+        let rx_dropped = bus.subscribe(&NUMBERS);
+        assert_eq!(publisher.subscriber_count(), 2);
+
+        drop(rx_dropped);
+
+        assert_eq!(publisher.publish(1), 1);
+        assert_eq!(publisher.subscriber_count(), 1);
+        assert_eq!(rx_kept.recv().unwrap().message, 1);
+    }
+
+    #[test]
+    fn stream_ends_when_the_last_publisher_is_dropped() {
+        let bus = Bus::new();
+        let rx = bus.subscribe(&NUMBERS);
+        let publisher = bus.publisher(&NUMBERS);
+        let second_publisher = publisher.clone();
+
+        drop(publisher);
+        second_publisher.publish(3);
+        assert_eq!(rx.recv().unwrap().message, 3);
+
+        drop(second_publisher);
+        assert!(matches!(rx.try_recv(), Err(TryRecvError::Disconnected)));
+    }
+
+    #[test]
+    fn several_topics_feed_one_receiver() {
+        let bus = Bus::new();
+        let (tx, rx) = mpsc::channel();
+        bus.subscribe_with(&DataSource::Imu.topic(), tx.clone());
+        bus.subscribe_with(&DataSource::Gps.topic(), tx);
+
+        bus.publisher(&DataSource::Imu.topic())
+            .publish(Telemetry::Acceleration(crate::data::Data::new()));
+        bus.publisher(&DataSource::Gps.topic())
+            .publish(Telemetry::Position(crate::data::Data::new()));
+
+        let received: Vec<Telemetry> = rx.iter().map(|envelope| envelope.message).collect();
+        assert!(matches!(
+            received[..],
+            [Telemetry::Acceleration(_), Telemetry::Position(_)]
+        ));
+    }
+
+    #[test]
+    fn messages_are_numbered_per_topic() {
+        let bus = Bus::new();
+        let rx = bus.subscribe(&NUMBERS);
+        let publisher = bus.publisher(&NUMBERS);
+        let second_publisher = publisher.clone();
+
+        publisher.publish(1);
+        second_publisher.publish(2);
+        publisher.publish(3);
+
+        let envelopes: Vec<Envelope<u32>> = rx.try_iter().collect();
+        let sequences: Vec<u64> = envelopes.iter().map(|envelope| envelope.sequence).collect();
+        assert_eq!(sequences, vec![0, 1, 2]);
+        assert!(envelopes
+            .iter()
+            .all(|envelope| envelope.source == envelopes[0].source));
+        assert!(envelopes
+            .iter()
+            .all(|envelope| envelope.measurement_time == std::time::UNIX_EPOCH));
+        assert!(envelopes
+            .iter()
+            .all(|envelope| envelope.publish_time > std::time::UNIX_EPOCH));
+    }
+
+    #[test]
+    fn topics_are_introspectable() {
+        let bus = Bus::new();
+        let _publisher = bus.publisher(&NUMBERS);
+        let _rx_1 = bus.subscribe(&DataSource::Kalman.topic());
+        let _rx_2 = bus.subscribe(&DataSource::Kalman.topic());
+
+        let topics = bus.topics();
+
+        assert_eq!(topics.len(), 2);
+        assert_eq!(topics[0].name, "/estimators/kalman");
+        assert_eq!((topics[0].publishers, topics[0].subscribers), (0, 2));
+        assert!(topics[0].message_type.ends_with("Telemetry"));
+        assert_eq!(topics[1].name, "/numbers");
+        assert_eq!((topics[1].publishers, topics[1].subscribers), (1, 0));
+    }
+
+    #[test]
+    fn queued_subscription_reports_dropped_messages() {
+        let bus = Bus::new();
+        let config = QueueConfig {
+            capacity: 2,
+            policy: queue::OverflowPolicy::DropOldest,
+        };
+        let rx = bus.subscribe_queued(&NUMBERS, "slow consumer", config);
+        let link_monitor = bus.link_monitor();
+        let publisher = bus.publisher(&NUMBERS);
+        drop(bus);
+
+        for number in 0..5 {
+            assert_eq!(publisher.publish(number), 1);
+        }
+        drop(publisher);
+
+        assert_eq!(
+            rx.iter()
+                .map(|envelope| envelope.message)
+                .collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+        let reports = link_monitor.reports();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].topic, "/numbers");
+        assert_eq!(
+            reports[0].statistics,
+            QueueStatistics {
+                queued: 5,
+                dropped: 3
+            }
+        );
+        assert_eq!(
+            reports[0].to_string(),
+            "/numbers -> slow consumer (drop oldest, capacity 2): 5 queued, 3 dropped"
+        );
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn blocked_subscriber_does_not_lock_the_topic() {
+        let bus = Bus::new();
+        let config = QueueConfig {
+            capacity: 1,
+            policy: queue::OverflowPolicy::Block,
+        };
+        let rx = bus.subscribe_queued(&NUMBERS, "blocking consumer", config);
+        let publisher = bus.publisher(&NUMBERS);
+        let sender = thread::spawn(move || {
+            publisher.publish(1);
+            publisher.publish(2);
+        });
+        thread::sleep(Duration::from_millis(50));
+
+        let _late_rx = bus.subscribe(&NUMBERS);
+        assert_eq!(bus.topics()[0].subscribers, 2);
+
+        assert_eq!(rx.recv().unwrap().message, 1);
+        assert_eq!(rx.recv().unwrap().message, 2);
+        sender.join().unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "carries")]
+    fn given_topic_used_with_other_type_expect_panic() {
+        let bus = Bus::new();
+        let _rx = bus.subscribe(&NUMBERS);
+        let _publisher = bus.publisher(&Topic::<String>::new("/numbers"));
+    }
+
+    #[test]
+    fn every_source_has_its_own_topic() {
+        let mut sources = vec![
+            DataSource::Imu,
+            DataSource::Gps,
+            DataSource::Barometer,
+            DataSource::Magnetometer,
+            DataSource::Odometry,
+            DataSource::Uwb,
+            DataSource::Kalman,
+            DataSource::Average,
+            DataSource::InertialNavigator,
+            DataSource::Imm,
+            DataSource::InformationFilter,
+            DataSource::Complementary,
+            DataSource::GpsSmoothing,
+            DataSource::Visualization,
+            DataSource::Groundtruth,
+        ];
+        sources.extend((0..4).map(DataSource::GpsReceiver));
+        let mut names: Vec<String> = sources.iter().map(|source| source.topic_name()).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), sources.len());
+    }
+}
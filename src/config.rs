@@ -1,36 +1,204 @@
-use std::num::NonZeroU32;
+use std::{num::NonZeroU32, time::Duration};
 use plotters::style::RGBColor;
+
+use crate::{
+    barometer::BarometerConfig,
+    binary_log::LogFormat,
+    bus::DataSource,
+    coordinates::{GeodeticPosition, LocalFrame},
+    gps_smoothing::SmoothingFilterType,
+    gps::GpsReceiverConfig,
+    kalman::{GpsOutage, KalmanTimeReference, KalmanUpdateForm},
+    log_config::{GPS_RECEIVER_1_LOG, GPS_RECEIVER_2_LOG},
+    log_stream::RotationPolicy,
+    magnetometer::{EarthFieldConfig, MagnetometerConfig},
+    nmea::NmeaOutput,
+    odometry::OdometryConfig,
+    queue::{OverflowPolicy, QueueConfig},
+    trajectory_generator::vehicle::VehicleLimits,
+    uwb::UwbConfig,
+};
 // Here are stored configuration values for the project
 
 // Refresh rate for tasks in Hz
 pub const GENERATOR_FREQ: NonZeroU32 = NonZeroU32::new(100).unwrap();
 pub const IMU_FREQ: NonZeroU32 = NonZeroU32::new(20).unwrap();
 pub const GPS_FREQ: NonZeroU32 = NonZeroU32::new(5).unwrap();
+pub const BAROMETER_FREQ: NonZeroU32 = NonZeroU32::new(10).unwrap();
+pub const MAGNETOMETER_FREQ: NonZeroU32 = NonZeroU32::new(10).unwrap();
+pub const ODOMETRY_FREQ: NonZeroU32 = NonZeroU32::new(10).unwrap();
+pub const UWB_FREQ: NonZeroU32 = NonZeroU32::new(10).unwrap();
 
 // Trajectory generator config
 pub const HELIX_FREQUENCY: f64 = 0.5;
+pub const CAR_LIMITS: VehicleLimits = VehicleLimits {
+    max_speed: 15.0,       // m/s
+    max_acceleration: 3.0, // m/s^2
+};
+pub const QUADROTOR_LIMITS: VehicleLimits = VehicleLimits {
+    max_speed: 10.0,
+    max_acceleration: 4.0,
+};
+pub const PEDESTRIAN_LIMITS: VehicleLimits = VehicleLimits {
+    max_speed: 2.0,
+    max_acceleration: 1.5,
+};
 
 // Sensor output noise parameters
 pub const GPS_OUTPUT_NOISE_SIGMA: f64 = 10.0;
 pub const IMU_OUTPUT_NOISE_SIGMA: f64 = 1.0;
+pub const BAROMETER_CONFIG: BarometerConfig = BarometerConfig {
+    noise_sigma: 6.0,              // Pa, about 0.5 m
+    bias_drift_sigma: 0.5,         // Pa/sqrt(s)
+    temperature_coefficient: 1.5,  // Pa/°C
+    temperature_offset: 5.0,       // °C
+};
+pub const MAGNETOMETER_CONFIG: MagnetometerConfig = MagnetometerConfig {
+    hard_iron: [8.0, -5.0, 2.5], // µT
+    soft_iron: [
+        [1.05, 0.03, -0.01],
+        [0.03, 0.97, 0.02],
+        [-0.01, 0.02, 1.01],
+    ],
+    noise_sigma: 0.3, // µT
+};
+pub const ODOMETRY_CONFIG: OdometryConfig = OdometryConfig {
+    scale_error: 0.02,
+    noise_sigma: 0.2, // m/s
+    slip_rate: 0.05,  // events/s
+    slip_duration: Duration::from_millis(1500),
+    slip_ratio: 0.3,
+};
+pub const UWB_CONFIG: UwbConfig = UwbConfig {
+    noise_sigma: 0.1, // m
+    nlos_rate: 0.02,  // events/s per anchor
+    nlos_duration: Duration::from_secs(2),
+    nlos_bias: 1.0,   // m
+};
+
+// NMEA output of the main GPS, e.g. Some(NmeaOutput::File("output/gps.nmea")),
+// Some(NmeaOutput::Device("/dev/pts/3")) or Some(NmeaOutput::Tcp("127.0.0.1:10110"))
+pub const GPS_NMEA_OUTPUT: Option<NmeaOutput> = None;
+
+// Coordinate frames: sensors publish, and estimators estimate, in the local frame around the origin
+pub const LOCAL_ORIGIN: GeodeticPosition = GeodeticPosition {
+    latitude: 52.2297,
+    longitude: 21.0122,
+    altitude: 100.0,
+};
+pub const LOCAL_FRAME: LocalFrame = LocalFrame::Enu;
+// Earth magnetic field around the local origin
+pub const EARTH_MAGNETIC_FIELD: EarthFieldConfig = EarthFieldConfig {
+    intensity: 50.0,   // µT
+    declination: 6.5,  // degrees
+    inclination: 68.5, // degrees
+};
+
+// UWB anchors at fixed positions in the local frame, in m, at different heights to observe the vertical
+pub const UWB_ANCHORS: [[f64; 3]; 4] = [
+    [-30.0, -30.0, 0.0],
+    [30.0, -30.0, 5.0],
+    [30.0, 30.0, 0.0],
+    [-30.0, 30.0, 5.0],
+];
+
+// Additional GPS receivers fused by the information filter together with the main GPS
+pub const GPS_RECEIVERS: [GpsReceiverConfig; 2] = [
+    GpsReceiverConfig {
+        frequency: NonZeroU32::new(2).unwrap(),
+        noise_sigma: 5.0,
+        log_name: GPS_RECEIVER_1_LOG,
+    },
+    GpsReceiverConfig {
+        frequency: NonZeroU32::new(10).unwrap(),
+        noise_sigma: 20.0,
+        log_name: GPS_RECEIVER_2_LOG,
+    },
+];
 
 // Kalman tuning parameters
 pub const KALMAN_GPS_SIGMA: f64 = 10.0;
 pub const KALMAN_ACC_SIGMA: f64 = 1.0;
+pub const KALMAN_BAROMETER_SIGMA: f64 = 1.0;
+pub const KALMAN_ODOMETRY_SIGMA: f64 = 0.1;
+pub const KALMAN_UWB_SIGMA: f64 = 0.01;
+// GPS-denied dead reckoning, e.g. Some(GpsOutage { start: Duration::from_secs(10), duration: Duration::from_secs(30) })
+pub const KALMAN_GPS_OUTAGE: Option<GpsOutage> = None;
 pub const KALMAN_TIMING_TOLERANCE: f64 = 0.02; // 0.01 = 1% of timing tolerance
+pub const KALMAN_UPDATE_FORM: KalmanUpdateForm = KalmanUpdateForm::Standard;
+pub const KALMAN_TIME_REFERENCE: KalmanTimeReference = KalmanTimeReference::Arrival;
+
+// Kalman auto-tuning search space
+pub const TUNING_GPS_SIGMA_RANGE: (f64, f64) = (0.1, 100.0);
+pub const TUNING_ACC_SIGMA_RANGE: (f64, f64) = (0.01, 10.0);
+pub const TUNING_GRID_STEPS: usize = 8;
+pub const TUNING_NELDER_MEAD_ITERATIONS: usize = 60;
+
+// IMM tuning parameters
+pub const IMM_MODEL_STAY_PROBABILITY: f64 = 0.95;
+pub const IMM_CV_ACC_SIGMA: f64 = 0.5;
+pub const IMM_CA_JERK_SIGMA: f64 = 5.0;
+pub const IMM_CT_TURN_RATE: f64 = 2.0 * std::f64::consts::PI * HELIX_FREQUENCY; // rad/s
+pub const IMM_GPS_SIGMA: f64 = GPS_OUTPUT_NOISE_SIGMA;
+pub const IMM_ACC_SIGMA: f64 = IMU_OUTPUT_NOISE_SIGMA;
+
+// Information filter tuning parameters
+pub const INFORMATION_FILTER_ACC_SIGMA: f64 = IMU_OUTPUT_NOISE_SIGMA;
+
+// Complementary filter tuning parameters
+pub const COMPLEMENTARY_CROSSOVER_FREQUENCY: f64 = 0.1; // Hz, below it GPS is trusted, above it IMU
+pub const COMPLEMENTARY_DAMPING_RATIO: f64 = std::f64::consts::FRAC_1_SQRT_2;
 
 // Visualiziation parameters
 pub const FPS: u32 = 5;
 pub const PLOT_RANGE_WINDOW: u128 = 15;
 pub const PLOT_RANGE_Y_AXIS_MIN: f64 = -20.0;
 pub const PLOT_RANGE_Y_AXIS_MAX: f64 = 120.0;
+// Queues between the estimators and the plots, a stalled window only loses the oldest samples
+pub const REAL_TIME_PLOT_QUEUE: QueueConfig = QueueConfig {
+    capacity: 1024,
+    policy: OverflowPolicy::DropOldest,
+};
+pub const STATIC_PLOT_QUEUE: QueueConfig = QueueConfig::UNBOUNDED; // the final plot needs every sample
+
+// Log storage: streamed to disk during the run, or kept in memory and saved at shutdown
+pub const LOG_STREAMING: bool = true;
+pub const LOG_TELEMETRY_FORMAT: LogFormat = LogFormat::Csv; // of streamed telemetry logs, replay and tuning read CSV
+pub const LOG_STREAM_BUFFER_SIZE: usize = 4096; // rows in flight before logging components block
+pub const LOG_ROTATION: RotationPolicy = RotationPolicy {
+    max_file_size: 64 * 1024 * 1024,
+    max_file_age: Duration::from_secs(3600),
+};
+pub const MCAP_RECORDING: bool = false; // records every channel to output/recording.mcap
+
+// UDP bridge between a simulating process (--bridge) and a plotting one (--remote-plot)
+pub const BRIDGE_ADDRESS: &str = "127.0.0.1:47800"; // where the plotting process listens
+pub const BRIDGE_SOURCES: [DataSource; 9] = [
+    DataSource::Gps,
+    DataSource::Average,
+    DataSource::Kalman,
+    DataSource::InertialNavigator,
+    DataSource::Imm,
+    DataSource::InformationFilter,
+    DataSource::Complementary,
+    DataSource::GpsSmoothing,
+    DataSource::Groundtruth,
+];
 
 // Average filter tuning parameters
 pub const BUFFER_LENGTH: usize = 3;
 
+// GPS smoothing filter selection and tuning parameters
+pub const GPS_SMOOTHING_FILTER: SmoothingFilterType =
+    SmoothingFilterType::AlphaBeta { alpha: 0.5, beta: 0.1 };
+
 // Plot chart colors
 pub const GROUNDTRUTH_PLOT_COLOR: RGBColor = RGBColor(0, 0, 0);     // black
 pub const GPS_PLOT_COLOR: RGBColor = RGBColor(150, 150, 150);       // light grey
 pub const AVERAGE_PLOT_COLOR: RGBColor = RGBColor(0, 0, 255);       // blue
 pub const INERTIAL_PLOT_COLOR: RGBColor = RGBColor(0, 225, 0);      // dark green
 pub const KALMAN_PLOT_COLOR: RGBColor = RGBColor(255, 0, 0);        // red
+pub const IMM_PLOT_COLOR: RGBColor = RGBColor(255, 140, 0);         // orange
+pub const INFORMATION_PLOT_COLOR: RGBColor = RGBColor(160, 0, 200);  // purple
+pub const COMPLEMENTARY_PLOT_COLOR: RGBColor = RGBColor(0, 170, 170); // teal
+pub const GPS_SMOOTHING_PLOT_COLOR: RGBColor = RGBColor(140, 70, 20); // brown
@@ -0,0 +1,359 @@
+//! Coordinate frames: WGS84 geodetic, Earth-centred Earth-fixed (ECEF) and the local
+//! east-north-up (ENU) and north-east-down (NED) frames around a geodetic origin.
+//!
+//! Conversions are exact: geodetic to ECEF in closed form, ECEF to geodetic with the
+//! closed-form solution of Heikkinen (1982), local frames by rotating ECEF differences.
+//! The trajectory generator simulates in ENU, `LocalFrame` maps its x, y, z to the frame
+//! sensors publish in, so estimators and plots work in the configured `LOCAL_FRAME`.
+use nalgebra::{Matrix3, Vector3};
+
+use crate::data::Data;
+
+const WGS84_SEMI_MAJOR_AXIS: f64 = 6_378_137.0; // m
+const WGS84_FLATTENING: f64 = 1.0 / 298.257_223_563;
+const WGS84_SEMI_MINOR_AXIS: f64 = WGS84_SEMI_MAJOR_AXIS * (1.0 - WGS84_FLATTENING);
+const WGS84_ECCENTRICITY_SQUARED: f64 = WGS84_FLATTENING * (2.0 - WGS84_FLATTENING);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeodeticPosition {
+    pub latitude: f64,  // degrees, north positive
+    pub longitude: f64, // degrees, east positive
+    pub altitude: f64,  // m above the ellipsoid
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EcefPosition {
+    pub x: f64, // m, towards latitude 0, longitude 0
+    pub y: f64, // m, towards latitude 0, longitude 90 east
+    pub z: f64, // m, towards the north pole
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnuPosition {
+    pub east: f64,
+    pub north: f64,
+    pub up: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NedPosition {
+    pub north: f64,
+    pub east: f64,
+    pub down: f64,
+}
+
+impl GeodeticPosition {
+    pub fn to_ecef(self) -> EcefPosition {
+        let (sin_latitude, cos_latitude) = self.latitude.to_radians().sin_cos();
+        let (sin_longitude, cos_longitude) = self.longitude.to_radians().sin_cos();
+        let prime_vertical = WGS84_SEMI_MAJOR_AXIS
+            / (1.0 - WGS84_ECCENTRICITY_SQUARED * sin_latitude * sin_latitude).sqrt();
+        EcefPosition {
+            x: (prime_vertical + self.altitude) * cos_latitude * cos_longitude,
+            y: (prime_vertical + self.altitude) * cos_latitude * sin_longitude,
+            z: (prime_vertical * (1.0 - WGS84_ECCENTRICITY_SQUARED) + self.altitude) * sin_latitude,
+        }
+    }
+
+    // Rotates ECEF differences into east, north, up at this position
+    #[rustfmt::skip]
+    fn enu_rotation(self) -> Matrix3<f64> {
+        let (sin_latitude, cos_latitude) = self.latitude.to_radians().sin_cos();
+        let (sin_longitude, cos_longitude) = self.longitude.to_radians().sin_cos();
+        Matrix3::new(
+            -sin_longitude, cos_longitude, 0.0,
+            -sin_latitude * cos_longitude, -sin_latitude * sin_longitude, cos_latitude,
+            cos_latitude * cos_longitude, cos_latitude * sin_longitude, sin_latitude,
+        )
+    }
+}
+
+impl EcefPosition {
+    fn vector(self) -> Vector3<f64> {
+        Vector3::new(self.x, self.y, self.z)
+    }
+
+    // Valid everywhere but within a few kilometres of the centre of the Earth
+    pub fn to_geodetic(self) -> GeodeticPosition {
+        let (a, b, e2) = (
+            WGS84_SEMI_MAJOR_AXIS,
+            WGS84_SEMI_MINOR_AXIS,
+            WGS84_ECCENTRICITY_SQUARED,
+        );
+        let second_eccentricity_squared = (a * a - b * b) / (b * b);
+        let p = self.x.hypot(self.y);
+        let f = 54.0 * b * b * self.z * self.z;
+        let g = p * p + (1.0 - e2) * self.z * self.z - e2 * (a * a - b * b);
+        let c = e2 * e2 * f * p * p / (g * g * g);
+        let s = (1.0 + c + (c * c + 2.0 * c).sqrt()).cbrt();
+        let k = s + 1.0 + 1.0 / s;
+        let big_p = f / (3.0 * k * k * g * g);
+        let q = (1.0 + 2.0 * e2 * e2 * big_p).sqrt();
+        let r0 = -(big_p * e2 * p) / (1.0 + q)
+            + (a * a / 2.0 * (1.0 + 1.0 / q)
+                - big_p * (1.0 - e2) * self.z * self.z / (q * (1.0 + q))
+                - big_p * p * p / 2.0)
+                .max(0.0)
+                .sqrt();
+        let u = ((p - e2 * r0).powi(2) + self.z * self.z).sqrt();
+        let v = ((p - e2 * r0).powi(2) + (1.0 - e2) * self.z * self.z).sqrt();
+        let z0 = b * b * self.z / (a * v);
+        GeodeticPosition {
+            latitude: (self.z + second_eccentricity_squared * z0)
+                .atan2(p)
+                .to_degrees(),
+            longitude: self.y.atan2(self.x).to_degrees(),
+            altitude: u * (1.0 - b * b / (a * v)),
+        }
+    }
+
+    pub fn to_enu(self, origin: &GeodeticPosition) -> EnuPosition {
+        let enu = origin.enu_rotation() * (self.vector() - origin.to_ecef().vector());
+        EnuPosition {
+            east: enu.x,
+            north: enu.y,
+            up: enu.z,
+        }
+    }
+}
+
+impl EnuPosition {
+    pub fn to_ecef(self, origin: &GeodeticPosition) -> EcefPosition {
+        let ecef = origin.to_ecef().vector()
+            + origin.enu_rotation().transpose() * Vector3::new(self.east, self.north, self.up);
+        EcefPosition {
+            x: ecef.x,
+            y: ecef.y,
+            z: ecef.z,
+        }
+    }
+
+    pub fn to_ned(self) -> NedPosition {
+        NedPosition {
+            north: self.north,
+            east: self.east,
+            down: -self.up,
+        }
+    }
+}
+
+impl NedPosition {
+    pub fn to_enu(self) -> EnuPosition {
+        EnuPosition {
+            east: self.east,
+            north: self.north,
+            up: -self.down,
+        }
+    }
+}
+
+// The local frame x, y, z of positions, velocities and accelerations refer to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocalFrame {
+    Enu, // x east, y north, z up, as simulated
+    #[allow(dead_code)]
+    Ned, // x north, y east, z down
+}
+
+impl LocalFrame {
+    pub fn local_to_enu(self, local: Vector3<f64>) -> EnuPosition {
+        match self {
+            LocalFrame::Enu => EnuPosition {
+                east: local.x,
+                north: local.y,
+                up: local.z,
+            },
+            LocalFrame::Ned => NedPosition {
+                north: local.x,
+                east: local.y,
+                down: local.z,
+            }
+            .to_enu(),
+        }
+    }
+
+    pub fn enu_to_local(self, enu: EnuPosition) -> Vector3<f64> {
+        match self {
+            LocalFrame::Enu => Vector3::new(enu.east, enu.north, enu.up),
+            LocalFrame::Ned => {
+                let ned = enu.to_ned();
+                Vector3::new(ned.north, ned.east, ned.down)
+            }
+        }
+    }
+
+    // Data of the simulation, always east, north, up, as published in this frame
+    pub fn simulated_to_local(self, data: Data) -> Data {
+        let local = self.enu_to_local(EnuPosition {
+            east: data.x,
+            north: data.y,
+            up: data.z,
+        });
+        Data {
+            x: local.x,
+            y: local.y,
+            z: local.z,
+            ..data
+        }
+    }
+
+    pub fn local_to_geodetic(
+        self,
+        origin: &GeodeticPosition,
+        local: Vector3<f64>,
+    ) -> GeodeticPosition {
+        self.local_to_enu(local).to_ecef(origin).to_geodetic()
+    }
+
+    pub fn geodetic_to_local(
+        self,
+        origin: &GeodeticPosition,
+        position: GeodeticPosition,
+    ) -> Vector3<f64> {
+        self.enu_to_local(position.to_ecef().to_enu(origin))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGIN: GeodeticPosition = GeodeticPosition {
+        latitude: 52.2297,
+        longitude: 21.0122,
+        altitude: 100.0,
+    };
+
+    fn assert_geodetic_eq(left: GeodeticPosition, right: GeodeticPosition) {
+        approx::assert_abs_diff_eq!(left.latitude, right.latitude, epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(left.longitude, right.longitude, epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(left.altitude, right.altitude, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_reference_points_in_ecef() {
+        let equator = GeodeticPosition {
+            latitude: 0.0,
+            longitude: 0.0,
+            altitude: 0.0,
+        }
+        .to_ecef();
+        approx::assert_abs_diff_eq!(equator.x, WGS84_SEMI_MAJOR_AXIS, epsilon = 1e-6);
+        let north_pole = GeodeticPosition {
+            latitude: 90.0,
+            longitude: 0.0,
+            altitude: 10.0,
+        }
+        .to_ecef();
+        approx::assert_abs_diff_eq!(north_pole.z, WGS84_SEMI_MINOR_AXIS + 10.0, epsilon = 1e-6);
+        approx::assert_abs_diff_eq!(north_pole.x.hypot(north_pole.y), 0.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_geodetic_and_ecef_round_trip() {
+        for position in [
+            ORIGIN,
+            GeodeticPosition {
+                latitude: -33.8688,
+                longitude: 151.2093,
+                altitude: -20.0,
+            },
+            GeodeticPosition {
+                latitude: 89.9,
+                longitude: -179.5,
+                altitude: 8848.0,
+            },
+            GeodeticPosition {
+                latitude: 0.0,
+                longitude: 0.0,
+                altitude: 400_000.0,
+            },
+            GeodeticPosition {
+                latitude: 90.0,
+                longitude: 0.0,
+                altitude: 0.0,
+            },
+        ] {
+            assert_geodetic_eq(position.to_ecef().to_geodetic(), position);
+        }
+    }
+
+    #[test]
+    fn test_enu_axes_point_east_north_and_up() {
+        let north = GeodeticPosition {
+            latitude: ORIGIN.latitude + 0.001,
+            ..ORIGIN
+        }
+        .to_ecef()
+        .to_enu(&ORIGIN);
+        assert!(north.north > 110.0 && north.east.abs() < 1e-6);
+        let east = GeodeticPosition {
+            longitude: ORIGIN.longitude + 0.001,
+            ..ORIGIN
+        }
+        .to_ecef()
+        .to_enu(&ORIGIN);
+        assert!(east.east > 60.0 && east.north.abs() < 0.01);
+        let up = GeodeticPosition {
+            altitude: ORIGIN.altitude + 5.0,
+            ..ORIGIN
+        }
+        .to_ecef()
+        .to_enu(&ORIGIN);
+        approx::assert_abs_diff_eq!(up.up, 5.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_enu_and_geodetic_round_trip_far_from_origin() {
+        let enu = EnuPosition {
+            east: 120_000.0,
+            north: -45_000.0,
+            up: 300.0,
+        };
+        let back = enu.to_ecef(&ORIGIN).to_geodetic().to_ecef().to_enu(&ORIGIN);
+        approx::assert_abs_diff_eq!(back.east, enu.east, epsilon = 1e-6);
+        approx::assert_abs_diff_eq!(back.north, enu.north, epsilon = 1e-6);
+        approx::assert_abs_diff_eq!(back.up, enu.up, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_local_frames() {
+        let local = Vector3::new(1.0, 2.0, 3.0);
+        assert_eq!(
+            LocalFrame::Ned.local_to_enu(local),
+            EnuPosition {
+                east: 2.0,
+                north: 1.0,
+                up: -3.0
+            }
+        );
+        assert_eq!(
+            LocalFrame::Ned.enu_to_local(LocalFrame::Ned.local_to_enu(local)),
+            local
+        );
+        assert_eq!(
+            LocalFrame::Enu.enu_to_local(LocalFrame::Enu.local_to_enu(local)),
+            local
+        );
+
+        let simulated = Data {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+            ..Data::new()
+        };
+        let published = LocalFrame::Ned.simulated_to_local(simulated);
+        assert_eq!((published.x, published.y, published.z), (2.0, 1.0, -3.0));
+        assert_eq!(published.timestamp, simulated.timestamp);
+
+        // the same point, whichever frame it is given in
+        let enu = LocalFrame::Enu.local_to_geodetic(&ORIGIN, Vector3::new(10.0, 20.0, 30.0));
+        let ned = LocalFrame::Ned.local_to_geodetic(&ORIGIN, Vector3::new(20.0, 10.0, -30.0));
+        assert_geodetic_eq(enu, ned);
+        approx::assert_abs_diff_eq!(
+            LocalFrame::Ned.geodetic_to_local(&ORIGIN, ned),
+            Vector3::new(20.0, 10.0, -30.0),
+            epsilon = 1e-6
+        );
+    }
+}
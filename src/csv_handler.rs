@@ -1,105 +1,401 @@
-use crate::data::{Data, Telemetry};
+use crate::binary_log::{binary_log_path_in, encode_telemetry_record, BinaryLogHeader, LogFormat};
+use crate::config::GPS_RECEIVERS;
+use crate::data::Data;
 use crate::log_config::*;
-use crate::logger::get_data;
-use csv::Writer;
+use crate::log_schema::{
+    GeneralRecord, ImmProbabilitiesRecord, LogRecord, TelemetryKind, TelemetryRecord,
+};
+use crate::log_stream::{csv_header, csv_row, log_parts, remove_rotated_parts, LogWriter};
+use crate::logger::{get_data, stream, LogEntry};
+use crate::trajectory_generator::recorded::RecordedSample;
+use csv::{Reader, ReaderBuilder, Trim, WriterBuilder};
 use std::fmt::Debug;
 use std::fs::create_dir_all;
 use std::path::{Path, PathBuf};
 use std::{error::Error, fs::OpenOptions};
 
-const OUTPUT_PATH: &str = "output";
-const CSV_EXTENSION: &str = "csv";
+pub const OUTPUT_PATH: &str = "output";
+pub const CSV_EXTENSION: &str = "csv";
 
-fn save_log_to_file<T: serde::Serialize + Send + Clone + Debug + Sync + 'static>(
+fn save_log_to_file<T: Send + Clone + Debug + Sync + 'static, R: LogRecord>(
     path: &str,
     component_name: &str,
+    to_record: impl Fn(u64, &str, &LogEntry<T>) -> R,
 ) -> Result<(), Box<dyn Error>> {
     let parent = Path::new(path).parent().ok_or("Cannot create directory.")?;
 
     create_dir_all(parent)?;
+    remove_rotated_parts(Path::new(path))?;
 
     let file = OpenOptions::new()
         .write(true)
         .create(true)
         .truncate(true)
         .open(path)?;
-    let mut writter = Writer::from_writer(file);
+    // the header is written by hand, so that logs without entries have one as well
+    let mut writter = WriterBuilder::new().has_headers(false).from_writer(file);
+    writter.write_record(R::HEADER)?;
     let logged_component = get_data::<T>(component_name);
 
     if let Some(data) = logged_component {
-        for entry in data {
-            writter.serialize(&entry.data)?;
+        for (seq, entry) in data.iter().enumerate() {
+            writter.serialize(to_record(seq as u64, component_name, entry))?;
         }
-        writter.flush()?;
     } else {
         eprintln!("Logged {component_name} data not found")
     }
+    writter.flush()?;
 
     Ok(())
 }
 
-fn save_log_handle<T: serde::Serialize + Send + Clone + Debug + Sync + 'static>(
+// Reads the rotated parts of a streamed log before the log itself
+pub fn read_log<R: LogRecord>(path: &Path) -> Result<Vec<R>, Box<dyn Error>> {
+    let mut records = Vec::new();
+    for part in log_parts(path) {
+        let mut reader = Reader::from_path(part)?;
+        for record in reader.deserialize() {
+            records.push(record?);
+        }
+    }
+    Ok(records)
+}
+
+pub fn read_data_log(path: &Path) -> Result<Vec<Data>, Box<dyn Error>> {
+    Ok(read_log::<TelemetryRecord>(path)?
+        .iter()
+        .map(TelemetryRecord::data)
+        .collect())
+}
+
+pub fn read_recorded_trajectory(path: &Path) -> Result<Vec<RecordedSample>, Box<dyn Error>> {
+    let mut reader = ReaderBuilder::new().trim(Trim::All).from_path(path)?;
+    let mut samples = Vec::new();
+    for record in reader.deserialize() {
+        samples.push(record?);
+    }
+    Ok(samples)
+}
+
+fn save_log_handle<T: Send + Clone + Debug + Sync + 'static, R: LogRecord>(
     path: &str,
     component_name: &str,
+    to_record: impl Fn(u64, &str, &LogEntry<T>) -> R,
 ) {
-    match save_log_to_file::<T>(path, component_name) {
+    match save_log_to_file(path, component_name, to_record) {
         Ok(_) => println!("{component_name} data saved to {path}"),
         Err(e) => eprintln!("Error {e}"),
     }
 }
 
+fn save_data_log_handle(component_name: &str, kind: TelemetryKind) {
+    save_log_handle(
+        concat_path(component_name).as_str(),
+        component_name,
+        |seq, source, entry: &LogEntry<Data>| TelemetryRecord::from_data(seq, source, kind, entry),
+    );
+}
+
+fn save_telemetry_log_handle(component_name: &str) {
+    save_log_handle(
+        concat_path(component_name).as_str(),
+        component_name,
+        TelemetryRecord::from_telemetry,
+    );
+}
+
+pub fn log_path_in(directory: &Path, component_name: &str) -> PathBuf {
+    let file_name = component_name.to_lowercase();
+    directory.join(format!("{file_name}.{CSV_EXTENSION}"))
+}
+
 #[inline]
 fn concat_path(component_name: &str) -> String {
-    let file_name = component_name.to_lowercase();
-    let mut path = PathBuf::from(OUTPUT_PATH);
-    path.push(format!("{file_name}.{CSV_EXTENSION}"));
-    path.to_string_lossy().into_owned()
+    log_path_in(Path::new(OUTPUT_PATH), component_name)
+        .to_string_lossy()
+        .into_owned()
 }
 
 fn save_imu_log_to_file() {
-    save_log_handle::<Data>(concat_path(IMU_LOG).as_str(), IMU_LOG);
+    save_data_log_handle(IMU_LOG, TelemetryKind::Acceleration);
+}
+
+fn save_barometer_log_to_file() {
+    save_data_log_handle(BAROMETER_LOG, TelemetryKind::Altitude);
+}
+
+fn save_magnetometer_log_to_file() {
+    save_data_log_handle(MAGNETOMETER_LOG, TelemetryKind::MagneticField);
+}
+
+fn save_odometry_log_to_file() {
+    save_data_log_handle(ODOMETRY_LOG, TelemetryKind::Velocity);
+}
+
+fn save_uwb_log_to_file() {
+    save_data_log_handle(UWB_LOG, TelemetryKind::Range);
 }
 
 fn save_gps_log_to_file() {
-    save_log_handle::<Data>(concat_path(GPS_LOG).as_str(), GPS_LOG);
+    save_data_log_handle(GPS_LOG, TelemetryKind::Position);
 }
 
 fn save_inertial_nav_to_file() {
-    save_log_handle::<Telemetry>(
-        concat_path(INTERTIAL_NAVIGATOR_LOG).as_str(),
-        INTERTIAL_NAVIGATOR_LOG,
-    );
+    save_telemetry_log_handle(INTERTIAL_NAVIGATOR_LOG);
 }
 
 fn save_kalman_log_to_file() {
-    save_log_handle::<Telemetry>(concat_path(KALMAN_LOG).as_str(), KALMAN_LOG);
+    save_telemetry_log_handle(KALMAN_LOG);
+}
+
+fn save_imm_log_to_file() {
+    save_telemetry_log_handle(IMM_LOG);
+}
+
+fn save_imm_model_probabilities_log_to_file() {
+    save_log_handle(
+        concat_path(IMM_MODEL_PROBABILITIES_LOG).as_str(),
+        IMM_MODEL_PROBABILITIES_LOG,
+        ImmProbabilitiesRecord::from_probabilities,
+    );
+}
+
+fn save_gps_receivers_log_to_file() {
+    for gps_receiver in GPS_RECEIVERS {
+        save_data_log_handle(gps_receiver.log_name, TelemetryKind::Position);
+    }
+}
+
+fn save_information_filter_log_to_file() {
+    save_telemetry_log_handle(INFORMATION_FILTER_LOG);
+}
+
+fn save_complementary_log_to_file() {
+    save_telemetry_log_handle(COMPLEMENTARY_LOG);
+}
+
+fn save_gps_smoothing_log_to_file() {
+    save_data_log_handle(GPS_SMOOTHING_LOG, TelemetryKind::Position);
 }
 
 fn save_general_log_to_file() {
-    save_log_handle::<String>(concat_path(GENERAL_LOG).as_str(), GENERAL_LOG);
+    save_log_handle(
+        concat_path(GENERAL_LOG).as_str(),
+        GENERAL_LOG,
+        GeneralRecord::from_message,
+    );
 }
 
 fn save_groundtruth_log_to_file() {
-    save_log_handle::<Data>(concat_path(GROUNDTRUTH_LOG).as_str(), GROUNDTRUTH_LOG);
+    save_data_log_handle(GROUNDTRUTH_LOG, TelemetryKind::Position);
 }
 
 fn save_moving_average_log_to_file() {
-    save_log_handle::<Data>(concat_path(MOVING_AVERAGE_LOG).as_str(), MOVING_AVERAGE_LOG);
+    save_data_log_handle(MOVING_AVERAGE_LOG, TelemetryKind::Position);
 }
 
 pub fn save_logs_to_file() {
     save_gps_log_to_file();
     save_imu_log_to_file();
+    save_barometer_log_to_file();
+    save_magnetometer_log_to_file();
+    save_odometry_log_to_file();
+    save_uwb_log_to_file();
     save_inertial_nav_to_file();
     save_kalman_log_to_file();
+    save_imm_log_to_file();
+    save_imm_model_probabilities_log_to_file();
+    save_gps_receivers_log_to_file();
+    save_information_filter_log_to_file();
+    save_complementary_log_to_file();
+    save_gps_smoothing_log_to_file();
     save_general_log_to_file();
     save_groundtruth_log_to_file();
     save_moving_average_log_to_file();
 }
 
+type RowEncoder<R> = fn(&R) -> Result<Vec<u8>, Box<dyn Error>>;
+
+fn stream_log<T: Send + Clone + Debug + Sync + 'static, R: 'static>(
+    writer: &mut LogWriter,
+    (path, header): (PathBuf, Vec<u8>),
+    component_name: &'static str,
+    to_record: impl Fn(u64, &str, &LogEntry<T>) -> R + Send + 'static,
+    encode: RowEncoder<R>,
+    retain: bool,
+) {
+    let file = writer.open(&path, header);
+    let mut seq = 0;
+    stream::<T>(
+        component_name,
+        Box::new(move |entry| {
+            match encode(&to_record(seq, component_name, entry)) {
+                Ok(row) => file.write(row),
+                Err(e) => eprintln!("Cannot serialize {component_name} entry: {e}"),
+            }
+            seq += 1;
+        }),
+        retain,
+    );
+}
+
+fn stream_csv_log<T: Send + Clone + Debug + Sync + 'static, R: LogRecord + 'static>(
+    writer: &mut LogWriter,
+    directory: &Path,
+    component_name: &'static str,
+    to_record: impl Fn(u64, &str, &LogEntry<T>) -> R + Send + 'static,
+    retain: bool,
+) {
+    let file = (
+        log_path_in(directory, component_name),
+        csv_header(R::HEADER),
+    );
+    stream_log(writer, file, component_name, to_record, csv_row, retain);
+}
+
+fn stream_telemetry_records<T: Send + Clone + Debug + Sync + 'static>(
+    writer: &mut LogWriter,
+    directory: &Path,
+    component_name: &'static str,
+    to_record: impl Fn(u64, &str, &LogEntry<T>) -> TelemetryRecord + Send + 'static,
+    format: LogFormat,
+) {
+    match format {
+        LogFormat::Csv => stream_csv_log(writer, directory, component_name, to_record, false),
+        LogFormat::Binary => {
+            let file = (
+                binary_log_path_in(directory, component_name),
+                BinaryLogHeader::telemetry(component_name).encode(),
+            );
+            let encode = |record: &TelemetryRecord| Ok(encode_telemetry_record(record));
+            stream_log(writer, file, component_name, to_record, encode, false);
+        }
+    }
+}
+
+fn stream_data_log(
+    writer: &mut LogWriter,
+    directory: &Path,
+    component_name: &'static str,
+    kind: TelemetryKind,
+    format: LogFormat,
+) {
+    stream_telemetry_records(
+        writer,
+        directory,
+        component_name,
+        move |seq, source, entry: &LogEntry<Data>| {
+            TelemetryRecord::from_data(seq, source, kind, entry)
+        },
+        format,
+    );
+}
+
+fn stream_telemetry_log(
+    writer: &mut LogWriter,
+    directory: &Path,
+    component_name: &'static str,
+    format: LogFormat,
+) {
+    stream_telemetry_records(
+        writer,
+        directory,
+        component_name,
+        TelemetryRecord::from_telemetry,
+        format,
+    );
+}
+
+// Streams every log `save_logs_to_file` would save into the same files, as the entries come.
+// Telemetry logs are written in `telemetry_format`, the others are always CSV.
+// The IMM model probabilities stay in memory as well, the static plot reads them at the end.
+pub fn stream_logs_to_files(writer: &mut LogWriter, directory: &Path, telemetry_format: LogFormat) {
+    let format = telemetry_format;
+    stream_data_log(writer, directory, GPS_LOG, TelemetryKind::Position, format);
+    stream_data_log(
+        writer,
+        directory,
+        IMU_LOG,
+        TelemetryKind::Acceleration,
+        format,
+    );
+    stream_data_log(
+        writer,
+        directory,
+        BAROMETER_LOG,
+        TelemetryKind::Altitude,
+        format,
+    );
+    stream_data_log(
+        writer,
+        directory,
+        MAGNETOMETER_LOG,
+        TelemetryKind::MagneticField,
+        format,
+    );
+    stream_data_log(
+        writer,
+        directory,
+        ODOMETRY_LOG,
+        TelemetryKind::Velocity,
+        format,
+    );
+    stream_data_log(writer, directory, UWB_LOG, TelemetryKind::Range, format);
+    stream_telemetry_log(writer, directory, INTERTIAL_NAVIGATOR_LOG, format);
+    stream_telemetry_log(writer, directory, KALMAN_LOG, format);
+    stream_telemetry_log(writer, directory, IMM_LOG, format);
+    stream_csv_log(
+        writer,
+        directory,
+        IMM_MODEL_PROBABILITIES_LOG,
+        ImmProbabilitiesRecord::from_probabilities,
+        true,
+    );
+    for gps_receiver in GPS_RECEIVERS {
+        stream_data_log(
+            writer,
+            directory,
+            gps_receiver.log_name,
+            TelemetryKind::Position,
+            format,
+        );
+    }
+    stream_telemetry_log(writer, directory, INFORMATION_FILTER_LOG, format);
+    stream_telemetry_log(writer, directory, COMPLEMENTARY_LOG, format);
+    stream_data_log(
+        writer,
+        directory,
+        GPS_SMOOTHING_LOG,
+        TelemetryKind::Position,
+        format,
+    );
+    stream_csv_log(
+        writer,
+        directory,
+        GENERAL_LOG,
+        GeneralRecord::from_message,
+        false,
+    );
+    stream_data_log(
+        writer,
+        directory,
+        GROUNDTRUTH_LOG,
+        TelemetryKind::Position,
+        format,
+    );
+    stream_data_log(
+        writer,
+        directory,
+        MOVING_AVERAGE_LOG,
+        TelemetryKind::Position,
+        format,
+    );
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::data::Telemetry;
     use crate::logger::log;
     use std::fs;
     use std::io::Read;
@@ -119,6 +415,10 @@ mod tests {
         }
     }
 
+    fn position_record(seq: u64, source: &str, entry: &LogEntry<Data>) -> TelemetryRecord {
+        TelemetryRecord::from_data(seq, source, TelemetryKind::Position, entry)
+    }
+
     fn cleanup_test_directory() {
         let _ = fs::remove_dir_all("test_output");
     }
@@ -152,13 +452,16 @@ mod tests {
 
     #[test]
     fn test_save_log_to_file_no_data() {
-        let result = save_log_to_file::<Data>(TEST_NO_DATA_PATH, EMPTY_TEST_COMPONENT);
+        let result = save_log_to_file(TEST_NO_DATA_PATH, EMPTY_TEST_COMPONENT, position_record);
 
         assert!(result.is_ok());
 
         assert!(Path::new(TEST_NO_DATA_PATH).exists());
         let content = read_file_content(TEST_NO_DATA_PATH);
-        assert!(content.is_empty());
+        assert_eq!(
+            content,
+            "seq,source,measurement_time,receive_time,type,x,y,z\n"
+        );
 
         let _ = fs::remove_dir_all("test_output_no_data");
     }
@@ -168,7 +471,7 @@ mod tests {
         let test_data = create_test_data();
         log(TEST_COMPONENT, test_data);
 
-        let result = save_log_to_file::<Data>(TEST_PATH, TEST_COMPONENT);
+        let result = save_log_to_file(TEST_PATH, TEST_COMPONENT, position_record);
 
         assert!(result.is_ok());
         assert!(Path::new(TEST_PATH).exists());
@@ -179,16 +482,262 @@ mod tests {
         cleanup_test_directory();
     }
 
+    #[test]
+    fn test_read_data_log_returns_saved_data() {
+        const ROUNDTRIP_PATH: &str = "test_output_roundtrip/test_log.csv";
+        const ROUNDTRIP_COMPONENT: &str = "ROUNDTRIP_TEST_COMPONENT";
+        let test_data = create_test_data();
+        log(ROUNDTRIP_COMPONENT, test_data);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+
+        save_log_to_file(ROUNDTRIP_PATH, ROUNDTRIP_COMPONENT, position_record).unwrap();
+        let read_data = read_data_log(Path::new(ROUNDTRIP_PATH)).unwrap();
+
+        assert_eq!(read_data.len(), 1);
+        approx::assert_abs_diff_eq!(read_data[0].x, test_data.x);
+        approx::assert_abs_diff_eq!(read_data[0].y, test_data.y);
+        approx::assert_abs_diff_eq!(read_data[0].z, test_data.z);
+        let time_difference = match read_data[0].timestamp.duration_since(test_data.timestamp) {
+            Ok(difference) => difference,
+            Err(e) => e.duration(),
+        };
+        assert!(time_difference.as_millis() < 1);
+
+        let _ = fs::remove_dir_all("test_output_roundtrip");
+    }
+
+    #[test]
+    fn test_telemetry_log_roundtrip_keeps_schema_fields() {
+        const TELEMETRY_PATH: &str = "test_output_telemetry_roundtrip/test_log.csv";
+        const TELEMETRY_COMPONENT: &str = "TELEMETRY_ROUNDTRIP_TEST_COMPONENT";
+        let position = create_test_data();
+        let acceleration = Data {
+            x: -1.0,
+            ..create_test_data()
+        };
+        log(TELEMETRY_COMPONENT, Telemetry::Position(position));
+        log(TELEMETRY_COMPONENT, Telemetry::Acceleration(acceleration));
+        std::thread::sleep(std::time::Duration::from_millis(10));
+
+        save_log_to_file(
+            TELEMETRY_PATH,
+            TELEMETRY_COMPONENT,
+            TelemetryRecord::from_telemetry,
+        )
+        .unwrap();
+        let content = read_file_content(TELEMETRY_PATH);
+        let records = read_log::<TelemetryRecord>(Path::new(TELEMETRY_PATH)).unwrap();
+        let _ = fs::remove_dir_all("test_output_telemetry_roundtrip");
+
+        assert!(content.starts_with("seq,source,measurement_time,receive_time,type,x,y,z\n0,TELEMETRY_ROUNDTRIP_TEST_COMPONENT,"));
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records.iter().map(|record| record.seq).collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+        assert!(records
+            .iter()
+            .all(|record| record.source == TELEMETRY_COMPONENT));
+        assert_eq!(records[0].kind, TelemetryKind::Position);
+        assert_eq!(records[1].kind, TelemetryKind::Acceleration);
+        approx::assert_abs_diff_eq!(records[1].x, -1.0);
+        assert!(
+            records[0].receive_time
+                >= records[0].measurement_time - std::time::Duration::from_millis(1)
+        );
+    }
+
+    #[test]
+    fn test_general_log_roundtrip() {
+        const GENERAL_PATH: &str = "test_output_general_roundtrip/test_log.csv";
+        const GENERAL_COMPONENT: &str = "GENERAL_ROUNDTRIP_TEST_COMPONENT";
+        log(
+            GENERAL_COMPONENT,
+            "Estimator removed, with a comma".to_string(),
+        );
+        std::thread::sleep(std::time::Duration::from_millis(10));
+
+        save_log_to_file(GENERAL_PATH, GENERAL_COMPONENT, GeneralRecord::from_message).unwrap();
+        let records = read_log::<GeneralRecord>(Path::new(GENERAL_PATH)).unwrap();
+        let _ = fs::remove_dir_all("test_output_general_roundtrip");
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].seq, 0);
+        assert_eq!(records[0].message, "Estimator removed, with a comma");
+    }
+
+    #[test]
+    fn test_streamed_log_is_written_with_schema() {
+        const STREAM_DIRECTORY: &str = "test_output_stream";
+        const STREAM_COMPONENT: &str = "STREAM_TEST_COMPONENT";
+        let mut writer = LogWriter::start(
+            2,
+            crate::log_stream::RotationPolicy {
+                max_file_size: u64::MAX,
+                max_file_age: std::time::Duration::MAX,
+            },
+        );
+        stream_data_log(
+            &mut writer,
+            Path::new(STREAM_DIRECTORY),
+            STREAM_COMPONENT,
+            TelemetryKind::Acceleration,
+            LogFormat::Csv,
+        );
+        for x in 0..5 {
+            log(
+                STREAM_COMPONENT,
+                Data {
+                    x: x as f64,
+                    ..create_test_data()
+                },
+            );
+        }
+        writer.finish();
+
+        let path = log_path_in(Path::new(STREAM_DIRECTORY), STREAM_COMPONENT);
+        let records = read_log::<TelemetryRecord>(&path).unwrap();
+        let _ = fs::remove_dir_all(STREAM_DIRECTORY);
+
+        assert_eq!(
+            records.iter().map(|record| record.seq).collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4]
+        );
+        assert!(records
+            .iter()
+            .all(|record| record.kind == TelemetryKind::Acceleration));
+        approx::assert_abs_diff_eq!(records[4].x, 4.0);
+        assert!(get_data::<Data>(STREAM_COMPONENT).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rotated_log_is_read_whole() {
+        const STREAM_DIRECTORY: &str = "test_output_rotated_stream";
+        const STREAM_COMPONENT: &str = "ROTATED_STREAM_TEST_COMPONENT";
+        // every row starts a new file
+        let mut writer = LogWriter::start(
+            2,
+            crate::log_stream::RotationPolicy {
+                max_file_size: 1,
+                max_file_age: std::time::Duration::MAX,
+            },
+        );
+        stream_data_log(
+            &mut writer,
+            Path::new(STREAM_DIRECTORY),
+            STREAM_COMPONENT,
+            TelemetryKind::Acceleration,
+            LogFormat::Csv,
+        );
+        for x in 0..5 {
+            log(
+                STREAM_COMPONENT,
+                Data {
+                    x: x as f64,
+                    ..create_test_data()
+                },
+            );
+        }
+        writer.finish();
+
+        let path = log_path_in(Path::new(STREAM_DIRECTORY), STREAM_COMPONENT);
+        let parts = log_parts(&path).len();
+        let records = read_data_log(&path).unwrap();
+        let _ = fs::remove_dir_all(STREAM_DIRECTORY);
+
+        assert_eq!(parts, 5);
+        assert_eq!(
+            records.iter().map(|data| data.x).collect::<Vec<_>>(),
+            vec![0.0, 1.0, 2.0, 3.0, 4.0]
+        );
+    }
+
+    #[test]
+    fn test_streamed_binary_log_keeps_nanoseconds() {
+        const STREAM_DIRECTORY: &str = "test_output_binary_stream";
+        const STREAM_COMPONENT: &str = "BINARY_STREAM_TEST_COMPONENT";
+        let mut writer = LogWriter::start(
+            2,
+            crate::log_stream::RotationPolicy {
+                max_file_size: u64::MAX,
+                max_file_age: std::time::Duration::MAX,
+            },
+        );
+        stream_telemetry_log(
+            &mut writer,
+            Path::new(STREAM_DIRECTORY),
+            STREAM_COMPONENT,
+            LogFormat::Binary,
+        );
+        let test_data = create_test_data();
+        log(STREAM_COMPONENT, Telemetry::Position(test_data));
+        writer.finish();
+
+        let path = binary_log_path_in(Path::new(STREAM_DIRECTORY), STREAM_COMPONENT);
+        let (header, records) = crate::binary_log::read_binary_telemetry_log(&path).unwrap();
+        let _ = fs::remove_dir_all(STREAM_DIRECTORY);
+
+        assert_eq!(header.source, STREAM_COMPONENT);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].measurement_time, test_data.timestamp);
+        assert_eq!(records[0].kind, TelemetryKind::Position);
+    }
+
+    #[test]
+    fn test_read_data_log_missing_file() {
+        assert!(read_data_log(Path::new("test_output_missing/missing.csv")).is_err());
+    }
+
+    #[test]
+    fn test_read_recorded_trajectory_with_optional_columns() {
+        const RECORDING_DIRECTORY: &str = "test_output_recording";
+        let path = Path::new(RECORDING_DIRECTORY).join("recording.csv");
+        create_dir_all(RECORDING_DIRECTORY).unwrap();
+        fs::write(
+            &path,
+            "time, x, y, z, vx, vy, vz, roll, pitch, yaw\n\
+             0.0, 1.0, 2.0, 3.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.1\n\
+             0.5, 1.2, 2.0, 3.0, , , , 0.0, 0.0, 0.1\n",
+        )
+        .unwrap();
+
+        let samples = read_recorded_trajectory(&path).unwrap();
+
+        assert_eq!(samples.len(), 2);
+        approx::assert_abs_diff_eq!(samples[1].time, 0.5);
+        approx::assert_abs_diff_eq!(samples[0].z, 3.0);
+        assert_eq!(samples[0].vx, Some(0.5));
+        assert_eq!(samples[1].vx, None);
+        assert_eq!(samples[1].yaw, Some(0.1));
+
+        fs::write(&path, "time,x,y,z\n0.0,1.0,2.0,3.0\n").unwrap();
+        let samples = read_recorded_trajectory(&path).unwrap();
+        assert_eq!(samples[0].vy, None);
+        assert_eq!(samples[0].roll, None);
+
+        let _ = fs::remove_dir_all(RECORDING_DIRECTORY);
+    }
+
     #[test]
     fn test_invalid_path() {
         let invalid_path = format!("test_output/{}invalid|path.csv", '\0');
         let test_data = create_test_data();
         log(TEST_COMPONENT, test_data);
 
-        let result = save_log_to_file::<Data>(invalid_path.as_str(), TEST_COMPONENT);
+        let result = save_log_to_file(invalid_path.as_str(), TEST_COMPONENT, position_record);
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_save_gps_receivers_log_to_file() {
+        save_gps_receivers_log_to_file();
+
+        for gps_receiver in GPS_RECEIVERS {
+            assert!(Path::new(&concat_path(gps_receiver.log_name)).exists());
+            cleanup_test_file(gps_receiver.log_name);
+        }
+    }
+
     macro_rules! test_x_log_to_file {
         ($($name:ident : ($function:expr,$component_name:expr)),+) => {
         $(
@@ -210,6 +759,11 @@ mod tests {
         test_save_imu_log_to_file: (save_imu_log_to_file, IMU_LOG),
         test_save_inertial_nav_to_file: (save_inertial_nav_to_file, INTERTIAL_NAVIGATOR_LOG),
         test_save_kalman_log_to_file: (save_kalman_log_to_file, KALMAN_LOG),
+        test_save_imm_log_to_file: (save_imm_log_to_file, IMM_LOG),
+        test_save_imm_model_probabilities_log_to_file: (save_imm_model_probabilities_log_to_file, IMM_MODEL_PROBABILITIES_LOG),
+        test_save_information_filter_log_to_file: (save_information_filter_log_to_file, INFORMATION_FILTER_LOG),
+        test_save_complementary_log_to_file: (save_complementary_log_to_file, COMPLEMENTARY_LOG),
+        test_save_gps_smoothing_log_to_file: (save_gps_smoothing_log_to_file, GPS_SMOOTHING_LOG),
         test_save_moving_average_log_to_file: (save_moving_average_log_to_file, MOVING_AVERAGE_LOG)
     }
 }
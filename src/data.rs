@@ -1,21 +1,37 @@
+use nalgebra::{Rotation3, Vector3};
 use std::time::SystemTime;
 
-mod string_timestamp {
-    use chrono::{DateTime, Local};
-    use serde::Serializer;
+pub(crate) mod string_timestamp {
+    use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
     use std::time::SystemTime;
 
+    const FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
     pub fn serialize<S>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
         let datetime: DateTime<Local> = (*time).into();
-        let formatted = datetime.format("%Y-%m-%d %H:%M:%S%.3f").to_string();
+        let formatted = datetime.format(FORMAT).to_string();
         serializer.serialize_str(&formatted)
     }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<SystemTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let formatted = String::deserialize(deserializer)?;
+        let naive = NaiveDateTime::parse_from_str(&formatted, FORMAT).map_err(D::Error::custom)?;
+        let datetime = Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .ok_or_else(|| D::Error::custom(format!("Nonexistent local time {formatted}")))?;
+        Ok(datetime.into())
+    }
 }
 
-#[derive(Debug, Copy, Clone, serde::Serialize)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Data {
     pub x: f64,
     pub y: f64,
@@ -45,12 +61,86 @@ impl Default for Data {
 pub enum Telemetry {
     Acceleration(Data),
     Position(Data),
+    // barometer output: x is the pressure in Pa, y the temperature in degrees Celsius and
+    // z the pressure altitude as the vertical coordinate of the local frame
+    Altitude(Data),
+    // magnetometer output in µT, in the body frame (x forward, y left, z up)
+    MagneticField(Data),
+    // wheel odometry output in m/s, in the local frame
+    Velocity(Data),
+    // UWB range in m, x the range, y the index of the anchor, z unused
+    Range(Data),
 }
 
 impl Telemetry {
     pub fn data(&self) -> &Data {
         match self {
-            Telemetry::Acceleration(d) | Telemetry::Position(d) => d,
+            Telemetry::Acceleration(d)
+            | Telemetry::Position(d)
+            | Telemetry::Altitude(d)
+            | Telemetry::MagneticField(d)
+            | Telemetry::Velocity(d)
+            | Telemetry::Range(d) => d,
+        }
+    }
+}
+
+// Ground truth published by the trajectory generator, derivatives are exact for every mode
+#[derive(Debug, Copy, Clone)]
+pub struct KinematicState {
+    pub position: Vector3<f64>,
+    pub velocity: Vector3<f64>,
+    pub acceleration: Vector3<f64>,
+    pub attitude: Vector3<f64>, // roll, pitch, yaw in rad, ZYX Euler angles
+    pub timestamp: SystemTime,
+}
+
+impl KinematicState {
+    pub fn new() -> Self {
+        KinematicState {
+            position: Vector3::zeros(),
+            velocity: Vector3::zeros(),
+            acceleration: Vector3::zeros(),
+            attitude: Vector3::zeros(),
+            timestamp: SystemTime::now(),
+        }
+    }
+
+    pub fn position_data(&self) -> Data {
+        Data {
+            x: self.position.x,
+            y: self.position.y,
+            z: self.position.z,
+            timestamp: self.timestamp,
         }
     }
+
+    // Rotation from the body frame (x forward, y left, z up) to the simulated east, north, up
+    pub fn body_rotation(&self) -> Rotation3<f64> {
+        Rotation3::from_euler_angles(self.attitude.x, self.attitude.y, self.attitude.z)
+    }
+
+    pub fn velocity_data(&self) -> Data {
+        Data {
+            x: self.velocity.x,
+            y: self.velocity.y,
+            z: self.velocity.z,
+            timestamp: self.timestamp,
+        }
+    }
+
+    pub fn acceleration_data(&self) -> Data {
+        Data {
+            x: self.acceleration.x,
+            y: self.acceleration.y,
+            z: self.acceleration.z,
+            timestamp: self.timestamp,
+        }
+    }
+}
+
+impl Default for KinematicState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
@@ -0,0 +1,154 @@
+//! Metadata every message published on the bus travels with.
+//!
+//! Each topic is a source with its own id and a sequence number increasing by one per
+//! published message, so consumers can tell lost samples (a jump in the sequence) from
+//! reordered or repeated ones (a sequence not above the last one). The measurement time is
+//! when the carried value was valid, the publish time when it entered the bus; their
+//! difference is the latency of the producer.
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+    time::SystemTime,
+};
+
+use crate::data::Telemetry;
+
+static NEXT_SOURCE_ID: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SourceId(u32);
+
+impl SourceId {
+    // unique within the process, also for publishers outside a bus
+    pub fn next() -> Self {
+        SourceId(NEXT_SOURCE_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl fmt::Display for SourceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+pub trait Timestamped {
+    fn measurement_time(&self) -> SystemTime;
+}
+
+impl Timestamped for Telemetry {
+    fn measurement_time(&self) -> SystemTime {
+        self.data().timestamp
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Envelope<T> {
+    pub source: SourceId,
+    pub sequence: u64,
+    pub measurement_time: SystemTime,
+    pub publish_time: SystemTime,
+    pub message: T,
+}
+
+impl<T: Timestamped> Envelope<T> {
+    pub fn new(source: SourceId, sequence: u64, message: T) -> Self {
+        Self {
+            source,
+            sequence,
+            measurement_time: message.measurement_time(),
+            publish_time: SystemTime::now(),
+            message,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceStatus {
+    InOrder,
+    Gap { missing: u64 },
+    OutOfOrder,
+}
+
+// Follows the sequence numbers of every source a consumer receives
+#[derive(Debug, Default)]
+pub struct SequenceTracker {
+    last_sequences: HashMap<SourceId, u64>,
+    missing: u64,
+    out_of_order: u64,
+}
+
+impl SequenceTracker {
+    // The first message of a source starts its sequence, a consumer may subscribe late
+    pub fn check<T>(&mut self, envelope: &Envelope<T>) -> SequenceStatus {
+        let Some(last_sequence) = self.last_sequences.get_mut(&envelope.source) else {
+            self.last_sequences
+                .insert(envelope.source, envelope.sequence);
+            return SequenceStatus::InOrder;
+        };
+        if envelope.sequence <= *last_sequence {
+            self.out_of_order += 1;
+            return SequenceStatus::OutOfOrder;
+        }
+        let missing = envelope.sequence - *last_sequence - 1;
+        *last_sequence = envelope.sequence;
+        if missing == 0 {
+            SequenceStatus::InOrder
+        } else {
+            self.missing += missing;
+            SequenceStatus::Gap { missing }
+        }
+    }
+
+    pub fn missing(&self) -> u64 {
+        self.missing
+    }
+
+    pub fn out_of_order(&self) -> u64 {
+        self.out_of_order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Data;
+
+    fn envelope(source: SourceId, sequence: u64) -> Envelope<Telemetry> {
+        Envelope::new(source, sequence, Telemetry::Position(Data::new()))
+    }
+
+    #[test]
+    fn test_source_ids_are_unique() {
+        assert_ne!(SourceId::next(), SourceId::next());
+    }
+
+    #[test]
+    fn test_envelope_takes_measurement_time_from_telemetry() {
+        let data = Data::new();
+        let envelope = Envelope::new(SourceId::next(), 3, Telemetry::Acceleration(data));
+        assert_eq!(envelope.measurement_time, data.timestamp);
+        assert!(envelope.publish_time >= data.timestamp);
+        assert_eq!(envelope.sequence, 3);
+    }
+
+    #[test]
+    fn test_tracker_detects_gaps_and_reordering() {
+        let (imu, gps) = (SourceId::next(), SourceId::next());
+        let mut tracker = SequenceTracker::default();
+
+        assert_eq!(tracker.check(&envelope(imu, 5)), SequenceStatus::InOrder);
+        assert_eq!(tracker.check(&envelope(gps, 0)), SequenceStatus::InOrder);
+        assert_eq!(tracker.check(&envelope(imu, 6)), SequenceStatus::InOrder);
+        assert_eq!(
+            tracker.check(&envelope(imu, 9)),
+            SequenceStatus::Gap { missing: 2 }
+        );
+        assert_eq!(tracker.check(&envelope(imu, 8)), SequenceStatus::OutOfOrder);
+        assert_eq!(tracker.check(&envelope(imu, 9)), SequenceStatus::OutOfOrder);
+        assert_eq!(tracker.check(&envelope(gps, 1)), SequenceStatus::InOrder);
+
+        assert_eq!(tracker.missing(), 2);
+        assert_eq!(tracker.out_of_order(), 2);
+    }
+}
@@ -1,13 +1,19 @@
 use std::{
-    sync::mpsc::Sender,
     thread::JoinHandle,
     sync::mpsc::{Receiver},
 };
 use crate::{
     average::Average,
+    bus::Publisher,
+    complementary::ComplementaryFilter,
+    gps_smoothing::{GpsSmoothing, InvalidSmoothingFilter, SmoothingFilterType},
     data::Telemetry,
-    kalman::KalmanFilter,
+    envelope::Envelope,
+    config::{INFORMATION_FILTER_ACC_SIGMA, KALMAN_TIME_REFERENCE, KALMAN_UPDATE_FORM},
+    kalman::{KalmanFilter, KalmanTimeReference, KalmanUpdateForm},
     inertial_navigator::InertialNavigator,
+    imm::{Imm, ImmConfig},
+    information_filter::{GpsInput, InformationFilter},
 };
 
 #[derive(PartialEq, Eq, Debug)]
@@ -15,22 +21,38 @@ enum EstimatorType {
     Average,
     Kalman,
     InertialNavigator,
+    Imm,
+    InformationFilter,
+    Complementary,
+    GpsSmoothing,
 }
 
 pub struct EstimatorBuilder {
     estimator_type: EstimatorType,
-    subscribers: Vec<Sender<Telemetry>>,
-    input_rx_option: Option<Receiver<Telemetry>>,
+    publisher: Publisher<Telemetry>,
+    input_rx_option: Option<Receiver<Envelope<Telemetry>>>,
     buffer_length_option: Option<usize>,
+    crossover_frequency_option: Option<f64>,
+    smoothing_filter_option: Option<SmoothingFilterType>,
+    imm_config_option: Option<ImmConfig>,
+    kalman_update_form: KalmanUpdateForm,
+    kalman_time_reference: KalmanTimeReference,
+    gps_inputs: Vec<GpsInput>,
 }
 
 impl EstimatorBuilder {
     fn default() -> Self {        
         Self {
             estimator_type: EstimatorType::Average,
-            subscribers: Vec::new(),
+            publisher: Vec::new().into(),
             input_rx_option: None,   
             buffer_length_option: None, 
+            crossover_frequency_option: None,
+            smoothing_filter_option: None,
+            imm_config_option: None,
+            kalman_update_form: KALMAN_UPDATE_FORM,
+            kalman_time_reference: KALMAN_TIME_REFERENCE,
+            gps_inputs: Vec::new(),
         }
     }
 
@@ -56,14 +78,71 @@ impl EstimatorBuilder {
         }
     }
 
-    pub fn with_subscribers(self, subscribers: Vec<Sender<Telemetry>>) -> Self {
+    pub fn new_imm(imm_config: ImmConfig) -> Self {
         Self {
-            subscribers,
+            estimator_type: EstimatorType::Imm,
+            imm_config_option: Some(imm_config),
+            ..Self::default()
+        }
+    }
+
+    pub fn new_complementary(crossover_frequency: f64) -> Self {
+        Self {
+            estimator_type: EstimatorType::Complementary,
+            crossover_frequency_option: Some(crossover_frequency),
+            ..Self::default()
+        }
+    }
+
+    pub fn new_gps_smoothing(
+        smoothing_filter: SmoothingFilterType,
+    ) -> Result<Self, InvalidSmoothingFilter> {
+        smoothing_filter.validate()?;
+        Ok(Self {
+            estimator_type: EstimatorType::GpsSmoothing,
+            smoothing_filter_option: Some(smoothing_filter),
+            ..Self::default()
+        })
+    }
+
+    // The input rx of the information filter carries IMU data, GPS receivers come through with_gps_inputs
+    pub fn new_information_filter() -> Self {
+        Self {
+            estimator_type: EstimatorType::InformationFilter,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_gps_inputs(self, gps_inputs: Vec<GpsInput>) -> Self {
+        Self {
+            gps_inputs,
             ..self
         }
     }
 
-    pub fn with_input_rx(self, input_rx: Receiver<Telemetry>) -> Self {
+    #[allow(dead_code)]
+    pub fn with_kalman_update_form(self, kalman_update_form: KalmanUpdateForm) -> Self {
+        Self {
+            kalman_update_form,
+            ..self
+        }
+    }
+
+    pub fn with_kalman_time_reference(self, kalman_time_reference: KalmanTimeReference) -> Self {
+        Self {
+            kalman_time_reference,
+            ..self
+        }
+    }
+
+    pub fn with_publisher(self, publisher: Publisher<Telemetry>) -> Self {
+        Self {
+            publisher,
+            ..self
+        }
+    }
+
+    pub fn with_input_rx(self, input_rx: Receiver<Envelope<Telemetry>>) -> Self {
         let input_rx_option = Some(input_rx);
         Self {
             input_rx_option,
@@ -76,17 +155,41 @@ impl EstimatorBuilder {
             Some(input_rx) => {
                 match self.estimator_type {
                     EstimatorType::Average => Average::run(
-                        self.subscribers,
+                        self.publisher,
                         input_rx,
                         self.buffer_length_option.expect("Buffer length must be defined!"),
                     ),
                     EstimatorType::Kalman => KalmanFilter::run(
-                        self.subscribers,
+                        self.publisher,
                         input_rx,
+                        self.kalman_update_form,
+                        self.kalman_time_reference,
                     ),
                     EstimatorType::InertialNavigator => InertialNavigator::run(
-                        self.subscribers,
+                        self.publisher,
+                        input_rx,
+                    ),
+                    EstimatorType::Imm => Imm::run(
+                        self.publisher,
+                        input_rx,
+                        self.imm_config_option.expect("IMM configuration must be defined!"),
+                    ),
+                    EstimatorType::InformationFilter => InformationFilter::run(
+                        self.publisher,
+                        input_rx,
+                        self.gps_inputs,
+                        INFORMATION_FILTER_ACC_SIGMA,
+                    ),
+                    EstimatorType::Complementary => ComplementaryFilter::run(
+                        self.publisher,
                         input_rx,
+                        self.crossover_frequency_option
+                            .expect("Crossover frequency must be defined!"),
+                    ),
+                    EstimatorType::GpsSmoothing => GpsSmoothing::run(
+                        self.publisher,
+                        input_rx,
+                        self.smoothing_filter_option.expect("Smoothing filter must be defined!"),
                     ),
                 }
             },
@@ -101,35 +204,55 @@ mod tests {
     use super::*;
     use ntest_timeout::timeout;
 
-    use crate::data::Data;
+    use crate::{data::Data, envelope::SourceId};
 
     #[test]
     fn expect_default_provides_estimator_type_average_with_no_subscribers() {
         let average_config = EstimatorBuilder::default();
         assert_eq!(average_config.estimator_type, EstimatorType::Average);
-        assert!(average_config.subscribers.is_empty());
+        assert_eq!(average_config.publisher.subscriber_count(), 0);
     }
 
     #[test]
     fn given_new_average_expect_builder_with_estimator_type_average() {
         let average_config = EstimatorBuilder::new_average(3_usize);
         assert_eq!(average_config.estimator_type, EstimatorType::Average);
-        assert!(average_config.subscribers.is_empty());
+        assert_eq!(average_config.publisher.subscriber_count(), 0);
     }
 
     #[test]
     fn given_new_kalman_expect_builder_with_estimator_type_kalman() {
         let average_config = EstimatorBuilder::new_kalman();
         assert_eq!(average_config.estimator_type, EstimatorType::Kalman);
-        assert!(average_config.subscribers.is_empty());
+        assert_eq!(average_config.publisher.subscriber_count(), 0);
+    }
+
+    #[test]
+    fn given_kalman_update_form_expect_builder_with_set_update_form() {
+        let kalman_config =
+            EstimatorBuilder::new_kalman().with_kalman_update_form(KalmanUpdateForm::SquareRoot);
+        assert_eq!(
+            kalman_config.kalman_update_form,
+            KalmanUpdateForm::SquareRoot
+        );
+    }
+
+    #[test]
+    fn given_kalman_time_reference_expect_builder_with_set_time_reference() {
+        let kalman_config = EstimatorBuilder::new_kalman()
+            .with_kalman_time_reference(KalmanTimeReference::Measurement);
+        assert_eq!(
+            kalman_config.kalman_time_reference,
+            KalmanTimeReference::Measurement
+        );
     }
 
     #[test]
-    fn given_subscribers_expect_builder_with_provided_transmitters() {
+    fn given_publisher_expect_builder_with_its_subscribers() {
         let (tx_1, _) = std::sync::mpsc::channel();
         let (tx_2, _) = std::sync::mpsc::channel();
-        let builder_cfg = EstimatorBuilder::default().with_subscribers(vec![tx_1, tx_2]);
-        assert_eq!(builder_cfg.subscribers.len(), 2);
+        let builder_cfg = EstimatorBuilder::default().with_publisher(vec![tx_1, tx_2].into());
+        assert_eq!(builder_cfg.publisher.subscriber_count(), 2);
     }
 
     #[test]
@@ -146,7 +269,12 @@ mod tests {
     fn given_input_rx_expect_builder_with_set_input_rx() {
         let (tx, input_rx) = std::sync::mpsc::channel();
         let builder_cfg = EstimatorBuilder::default().with_input_rx(input_rx);
-        tx.send(Telemetry::Acceleration(Data::new())).unwrap();
+        tx.send(Envelope::new(
+            SourceId::next(),
+            0,
+            Telemetry::Acceleration(Data::new()),
+        ))
+        .unwrap();
         assert!(builder_cfg.input_rx_option.unwrap().recv().is_ok());
     }
 
@@ -189,4 +317,103 @@ mod tests {
             .spawn();
         assert!(handle.join().is_ok());
     }
+
+    #[test]
+    fn given_new_imm_expect_builder_with_estimator_type_imm() {
+        let imm_config = EstimatorBuilder::new_imm(ImmConfig::default());
+        assert_eq!(imm_config.estimator_type, EstimatorType::Imm);
+        assert!(imm_config.imm_config_option.is_some());
+        assert_eq!(imm_config.publisher.subscriber_count(), 0);
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn given_imm_builder_expect_spawn_to_spawn_imm_thread() {
+        let (_, input_rx) = std::sync::mpsc::channel();
+        let handle = EstimatorBuilder::new_imm(ImmConfig::default())
+            .with_input_rx(input_rx)
+            .spawn();
+        assert!(handle.join().is_ok());
+    }
+
+    #[test]
+    fn given_gps_inputs_expect_information_filter_builder_with_provided_inputs() {
+        let (_, rx_1) = std::sync::mpsc::channel();
+        let (_, rx_2) = std::sync::mpsc::channel();
+        let builder_cfg = EstimatorBuilder::new_information_filter()
+            .with_gps_inputs(vec![GpsInput::new(rx_1, 1.0), GpsInput::new(rx_2, 2.0)]);
+        assert_eq!(builder_cfg.estimator_type, EstimatorType::InformationFilter);
+        assert_eq!(builder_cfg.gps_inputs.len(), 2);
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn given_information_filter_builder_expect_spawn_to_spawn_information_filter_thread() {
+        let (_, input_rx) = std::sync::mpsc::channel();
+        let handle = EstimatorBuilder::new_information_filter()
+            .with_input_rx(input_rx)
+            .spawn();
+        assert!(handle.join().is_ok());
+    }
+
+    #[test]
+    fn given_new_complementary_expect_builder_with_estimator_type_complementary() {
+        let complementary_config = EstimatorBuilder::new_complementary(0.5);
+        assert_eq!(
+            complementary_config.estimator_type,
+            EstimatorType::Complementary
+        );
+        assert_eq!(complementary_config.crossover_frequency_option, Some(0.5));
+        assert_eq!(complementary_config.publisher.subscriber_count(), 0);
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn given_complementary_builder_expect_spawn_to_spawn_complementary_thread() {
+        let (_, input_rx) = std::sync::mpsc::channel();
+        let handle = EstimatorBuilder::new_complementary(0.5)
+            .with_input_rx(input_rx)
+            .spawn();
+        assert!(handle.join().is_ok());
+    }
+
+    #[test]
+    #[should_panic]
+    fn given_no_crossover_frequency_expect_panic_on_complementary_filter_creation() {
+        let (_, input_rx) = std::sync::mpsc::channel();
+        let mut builder = EstimatorBuilder::new_complementary(0.5)
+            .with_input_rx(input_rx);
+        builder.crossover_frequency_option = None;
+        let _ = builder.spawn();
+    }
+
+    #[test]
+    fn given_new_gps_smoothing_expect_builder_with_selected_filter() {
+        let filter = SmoothingFilterType::Median { window_length: 5 };
+        let smoothing_config = EstimatorBuilder::new_gps_smoothing(filter).unwrap();
+        assert_eq!(smoothing_config.estimator_type, EstimatorType::GpsSmoothing);
+        assert_eq!(smoothing_config.smoothing_filter_option, Some(filter));
+    }
+
+    #[test]
+    fn given_gps_smoothing_with_empty_window_expect_builder_to_reject_it() {
+        assert!(
+            EstimatorBuilder::new_gps_smoothing(SmoothingFilterType::Median { window_length: 0 })
+                .is_err()
+        );
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn given_gps_smoothing_builder_expect_spawn_to_spawn_gps_smoothing_thread() {
+        let (_, input_rx) = std::sync::mpsc::channel();
+        let handle =
+            EstimatorBuilder::new_gps_smoothing(SmoothingFilterType::ExponentialMovingAverage {
+                alpha: 0.3,
+            })
+            .unwrap()
+            .with_input_rx(input_rx)
+            .spawn();
+        assert!(handle.join().is_ok());
+    }
 }
@@ -0,0 +1,220 @@
+#![allow(non_snake_case)]
+use super::initialize_state_using_gps_data;
+use nalgebra::{Matrix3x1, Matrix6, Matrix6x1, Matrix6x3};
+use std::{f64::consts::PI, sync::mpsc::Receiver, thread::JoinHandle, time::SystemTime};
+
+use crate::{
+    bus::Publisher,
+    config::{COMPLEMENTARY_DAMPING_RATIO, GPS_FREQ, IMU_FREQ},
+    data::{Data, Telemetry},
+    envelope::Envelope,
+    kalman::{create_matrix_A, create_matrix_B},
+    log_config::{COMPLEMENTARY_LOG, GENERAL_LOG},
+    logger::log,
+    utils::*,
+};
+
+// Second order complementary filter: the double integrated acceleration passes through
+// a high-pass and the GPS position through a low-pass, both with the same crossover frequency
+pub struct ComplementaryFilter {
+    tx: Publisher<Telemetry>,
+    A: Matrix6<f64>,
+    B: Matrix6x3<f64>,
+    position_gain: f64,
+    velocity_gain: f64,
+    state: Matrix6x1<f64>,
+}
+
+impl ComplementaryFilter {
+    pub fn new(tx: Publisher<Telemetry>, crossover_frequency: f64) -> ComplementaryFilter {
+        let natural_frequency = 2.0 * PI * crossover_frequency;
+        let gps_dt = get_cycle_duration_f64(GPS_FREQ);
+        ComplementaryFilter {
+            tx,
+            A: create_matrix_A(get_cycle_duration_f64(IMU_FREQ)),
+            B: create_matrix_B(get_cycle_duration_f64(IMU_FREQ)),
+            position_gain: 2.0 * COMPLEMENTARY_DAMPING_RATIO * natural_frequency * gps_dt,
+            velocity_gain: natural_frequency.powi(2) * gps_dt,
+            state: Matrix6x1::zeros(),
+        }
+    }
+
+    pub fn predict(&mut self, acceleration: &Data) {
+        let u = Matrix3x1::new(acceleration.x, acceleration.y, acceleration.z);
+        self.state = self.A * self.state + self.B * u;
+    }
+
+    pub fn correct(&mut self, position: &Data) {
+        let error =
+            Matrix3x1::new(position.x, position.y, position.z) - self.state.fixed_rows::<3>(0);
+        let mut position_estimate = self.state.fixed_rows_mut::<3>(0);
+        position_estimate += error * self.position_gain;
+        let mut velocity_estimate = self.state.fixed_rows_mut::<3>(3);
+        velocity_estimate += error * self.velocity_gain;
+    }
+
+    pub fn run(
+        tx: Publisher<Telemetry>,
+        rx: Receiver<Envelope<Telemetry>>,
+        crossover_frequency: f64,
+    ) -> JoinHandle<()> {
+        let mut complementary_filter = ComplementaryFilter::new(tx, crossover_frequency);
+        let mut gps_samples_received: u32 = 0;
+        let mut prev_gps_data: Data = Data::new();
+
+        std::thread::spawn(move || {
+            for Envelope {
+                message: telemetry, ..
+            } in &rx
+            {
+                initialize_state_using_gps_data(
+                    telemetry,
+                    &mut gps_samples_received,
+                    &mut complementary_filter.state,
+                    &mut prev_gps_data,
+                );
+                if gps_samples_received == 2 {
+                    break;
+                }
+            }
+            for Envelope {
+                message: telemetry, ..
+            } in rx
+            {
+                match telemetry {
+                    Telemetry::Acceleration(data) => complementary_filter.predict(&data),
+                    Telemetry::Position(data) => complementary_filter.correct(&data),
+                    Telemetry::Altitude(_)
+                    | Telemetry::MagneticField(_)
+                    | Telemetry::Velocity(_)
+                    | Telemetry::Range(_) => continue,
+                }
+
+                let complementary_position_estimate = Telemetry::Position(Data {
+                    x: complementary_filter.state[0],
+                    y: complementary_filter.state[1],
+                    z: complementary_filter.state[2],
+                    timestamp: SystemTime::now(),
+                });
+
+                if complementary_filter
+                    .tx
+                    .publish(complementary_position_estimate)
+                    == 0
+                {
+                    break;
+                }
+                log(COMPLEMENTARY_LOG, complementary_position_estimate);
+            }
+            log(GENERAL_LOG, "Complementary filter removed".to_string());
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use ntest_timeout::timeout;
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::bus::Publisher;
+
+    fn data(x: f64, y: f64, z: f64) -> Data {
+        Data {
+            x,
+            y,
+            z,
+            timestamp: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn test_gains_follow_crossover_frequency() {
+        let complementary_filter = ComplementaryFilter::new(vec![].into(), 1.0 / (2.0 * PI));
+        let gps_dt = get_cycle_duration_f64(GPS_FREQ);
+        approx::assert_relative_eq!(
+            complementary_filter.position_gain,
+            2.0 * COMPLEMENTARY_DAMPING_RATIO * gps_dt
+        );
+        approx::assert_relative_eq!(complementary_filter.velocity_gain, gps_dt);
+    }
+
+    #[test]
+    fn test_zero_crossover_frequency_integrates_imu_only() {
+        let mut complementary_filter = ComplementaryFilter::new(vec![].into(), 0.0);
+        let steps = IMU_FREQ.get();
+        for _ in 0..steps {
+            complementary_filter.predict(&data(2.0, 0.0, 0.0));
+            complementary_filter.correct(&data(100.0, 100.0, 100.0));
+        }
+
+        // one second of constant acceleration: p = a t^2 / 2, v = a t
+        approx::assert_relative_eq!(complementary_filter.state[0], 1.0, epsilon = 1e-9);
+        approx::assert_relative_eq!(complementary_filter.state[3], 2.0, epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(complementary_filter.state[1], 0.0);
+    }
+
+    #[test]
+    fn test_converges_to_gps_position_without_acceleration() {
+        let mut complementary_filter = ComplementaryFilter::new(vec![].into(), 0.2);
+        for _ in 0..500 {
+            for _ in 0..IMU_FREQ.get() / GPS_FREQ.get() {
+                complementary_filter.predict(&data(0.0, 0.0, 0.0));
+            }
+            complementary_filter.correct(&data(10.0, -5.0, 3.0));
+        }
+
+        approx::assert_relative_eq!(complementary_filter.state[0], 10.0, epsilon = 1e-6);
+        approx::assert_relative_eq!(complementary_filter.state[1], -5.0, epsilon = 1e-6);
+        approx::assert_relative_eq!(complementary_filter.state[2], 3.0, epsilon = 1e-6);
+        approx::assert_abs_diff_eq!(complementary_filter.state[3], 0.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn test_complementary_filter_run() {
+        let (tx_input, input_rx) = mpsc::channel();
+        let tx_imu = Publisher::from(vec![tx_input.clone()]);
+        let tx_gps = Publisher::from(vec![tx_input]);
+        let (tx_complementary, rx_complementary) = mpsc::channel();
+
+        let complementary_handle =
+            ComplementaryFilter::run(vec![tx_complementary].into(), input_rx, 0.1);
+
+        // the state is initialized from the first two GPS samples, nothing is sent until then
+        let _ = tx_gps.publish(Telemetry::Position(data(1.0, 1.0, 1.0)));
+        let _ = tx_gps.publish(Telemetry::Position(data(1.0, 1.0, 1.0)));
+        assert!(matches!(
+            rx_complementary.try_recv(),
+            Err(mpsc::TryRecvError::Empty)
+        ));
+
+        let _ = tx_imu.publish(Telemetry::Acceleration(data(0.0, 0.0, 0.0)));
+        assert!(rx_complementary.recv().is_ok());
+        let _ = tx_gps.publish(Telemetry::Position(data(1.0, 1.0, 1.0)));
+        assert!(rx_complementary.recv().is_ok());
+
+        drop(tx_imu);
+        drop(tx_gps);
+
+        assert!(complementary_handle.join().is_ok());
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn test_complementary_filter_stops_without_subscribers() {
+        let (tx_input, input_rx) = mpsc::channel();
+        let tx_imu = Publisher::from(vec![tx_input]);
+        let (tx_complementary, rx_complementary) = mpsc::channel();
+        drop(rx_complementary);
+
+        let complementary_handle =
+            ComplementaryFilter::run(vec![tx_complementary].into(), input_rx, 0.1);
+        let _ = tx_imu.publish(Telemetry::Position(data(1.0, 1.0, 1.0)));
+        let _ = tx_imu.publish(Telemetry::Position(data(1.0, 1.0, 1.0)));
+        let _ = tx_imu.publish(Telemetry::Acceleration(data(0.0, 0.0, 0.0)));
+
+        assert!(complementary_handle.join().is_ok());
+    }
+}
@@ -0,0 +1,539 @@
+#![allow(non_snake_case)]
+use nalgebra::{DMatrix, Vector3};
+use std::{
+    collections::VecDeque,
+    sync::mpsc::Receiver,
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    bus::Publisher,
+    config::GPS_FREQ,
+    data::{Data, Telemetry},
+    envelope::Envelope,
+    log_config::{GENERAL_LOG, GPS_SMOOTHING_LOG},
+    logger::log,
+    utils::get_cycle_duration_f64,
+};
+
+// Selected with GPS_SMOOTHING_FILTER in config
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmoothingFilterType {
+    ExponentialMovingAverage {
+        alpha: f64,
+    },
+    TriangularWindow {
+        window_length: usize,
+    },
+    Median {
+        window_length: usize,
+    },
+    // causal variant, the fitted polynomial is evaluated at the newest sample
+    SavitzkyGolay {
+        window_length: usize,
+        polynomial_order: usize,
+    },
+    AlphaBeta {
+        alpha: f64,
+        beta: f64,
+    },
+    AlphaBetaGamma {
+        alpha: f64,
+        beta: f64,
+        gamma: f64,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub struct InvalidSmoothingFilter(&'static str);
+
+impl std::fmt::Display for InvalidSmoothingFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid GPS smoothing filter: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidSmoothingFilter {}
+
+impl SmoothingFilterType {
+    pub fn validate(&self) -> Result<(), InvalidSmoothingFilter> {
+        match *self {
+            SmoothingFilterType::TriangularWindow { window_length: 0 }
+            | SmoothingFilterType::Median { window_length: 0 }
+            | SmoothingFilterType::SavitzkyGolay {
+                window_length: 0, ..
+            } => Err(InvalidSmoothingFilter("window length must be at least 1")),
+            SmoothingFilterType::SavitzkyGolay {
+                window_length,
+                polynomial_order,
+            } if polynomial_order >= window_length => Err(InvalidSmoothingFilter(
+                "polynomial order must be below the window length",
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Every smoother returns its estimate stamped with the time of the measurement it consumed,
+// so the lag against ground truth can be measured from the logs
+trait Smoother: Send {
+    fn smooth(&mut self, measurement: &Data) -> Data;
+}
+
+fn to_vector(data: &Data) -> Vector3<f64> {
+    Vector3::new(data.x, data.y, data.z)
+}
+
+fn to_data(vector: Vector3<f64>, measurement: &Data) -> Data {
+    Data {
+        x: vector.x,
+        y: vector.y,
+        z: vector.z,
+        timestamp: measurement.timestamp,
+    }
+}
+
+fn push_to_window(window: &mut VecDeque<Data>, measurement: Data, window_length: usize) {
+    if window.len() == window_length {
+        window.pop_front();
+    }
+    window.push_back(measurement);
+}
+
+struct ExponentialMovingAverage {
+    alpha: f64,
+    estimate: Option<Vector3<f64>>,
+}
+
+impl Smoother for ExponentialMovingAverage {
+    fn smooth(&mut self, measurement: &Data) -> Data {
+        let z = to_vector(measurement);
+        let estimate = match self.estimate {
+            Some(previous) => previous + (z - previous) * self.alpha,
+            None => z,
+        };
+        self.estimate = Some(estimate);
+        to_data(estimate, measurement)
+    }
+}
+
+struct TriangularWindow {
+    window_length: usize,
+    window: VecDeque<Data>,
+}
+
+impl Smoother for TriangularWindow {
+    fn smooth(&mut self, measurement: &Data) -> Data {
+        push_to_window(&mut self.window, *measurement, self.window_length);
+        let n = self.window.len();
+        let mut weighted_sum = Vector3::zeros();
+        let mut weight_sum = 0.0;
+        for (i, data) in self.window.iter().enumerate() {
+            let weight = (i + 1).min(n - i) as f64;
+            weighted_sum += to_vector(data) * weight;
+            weight_sum += weight;
+        }
+        to_data(weighted_sum / weight_sum, measurement)
+    }
+}
+
+struct Median {
+    window_length: usize,
+    window: VecDeque<Data>,
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    }
+}
+
+impl Smoother for Median {
+    fn smooth(&mut self, measurement: &Data) -> Data {
+        push_to_window(&mut self.window, *measurement, self.window_length);
+        let axis =
+            |coordinate: fn(&Data) -> f64| median(self.window.iter().map(coordinate).collect());
+        Data {
+            x: axis(|data| data.x),
+            y: axis(|data| data.y),
+            z: axis(|data| data.z),
+            timestamp: measurement.timestamp,
+        }
+    }
+}
+
+struct SavitzkyGolay {
+    window_length: usize,
+    polynomial_order: usize,
+    window: VecDeque<Data>,
+}
+
+// Least squares weights that evaluate a polynomial fitted to `samples` equally spaced points
+// at the last of them: the first row of (J'J)^-1 J' with J[i][k] = t_i^k and t = 0 at the newest sample
+fn savitzky_golay_coefficients(samples: usize, polynomial_order: usize) -> Option<Vec<f64>> {
+    let order = polynomial_order.min(samples - 1);
+    let J = DMatrix::from_fn(samples, order + 1, |i, k| {
+        (i as f64 - (samples - 1) as f64).powi(k as i32)
+    });
+    let pseudo_inverse = (J.transpose() * &J).try_inverse()? * J.transpose();
+    Some(pseudo_inverse.row(0).iter().copied().collect())
+}
+
+impl Smoother for SavitzkyGolay {
+    fn smooth(&mut self, measurement: &Data) -> Data {
+        push_to_window(&mut self.window, *measurement, self.window_length);
+        let Some(coefficients) =
+            savitzky_golay_coefficients(self.window.len(), self.polynomial_order)
+        else {
+            return *measurement;
+        };
+        let estimate = self
+            .window
+            .iter()
+            .zip(coefficients)
+            .fold(Vector3::zeros(), |sum, (data, coefficient)| {
+                sum + to_vector(data) * coefficient
+            });
+        to_data(estimate, measurement)
+    }
+}
+
+// Alpha-beta-gamma tracker, gamma = 0 gives the alpha-beta tracker
+struct AlphaBetaGamma {
+    alpha: f64,
+    beta: f64,
+    gamma: f64,
+    position: Vector3<f64>,
+    velocity: Vector3<f64>,
+    acceleration: Vector3<f64>,
+    last_measurement: Option<Data>,
+}
+
+impl AlphaBetaGamma {
+    fn new(alpha: f64, beta: f64, gamma: f64) -> Self {
+        Self {
+            alpha,
+            beta,
+            gamma,
+            position: Vector3::zeros(),
+            velocity: Vector3::zeros(),
+            acceleration: Vector3::zeros(),
+            last_measurement: None,
+        }
+    }
+}
+
+impl Smoother for AlphaBetaGamma {
+    fn smooth(&mut self, measurement: &Data) -> Data {
+        let z = to_vector(measurement);
+        let Some(last_measurement) = self.last_measurement.replace(*measurement) else {
+            self.position = z;
+            return *measurement;
+        };
+        let dt = match measurement
+            .timestamp
+            .duration_since(last_measurement.timestamp)
+        {
+            Ok(elapsed) if !elapsed.is_zero() => elapsed.as_secs_f64(),
+            _ => get_cycle_duration_f64(GPS_FREQ),
+        };
+
+        let predicted_position =
+            self.position + self.velocity * dt + self.acceleration * (dt * dt / 2.0);
+        let predicted_velocity = self.velocity + self.acceleration * dt;
+        let residual = z - predicted_position;
+
+        self.position = predicted_position + residual * self.alpha;
+        self.velocity = predicted_velocity + residual * (self.beta / dt);
+        self.acceleration += residual * (2.0 * self.gamma / (dt * dt));
+        to_data(self.position, measurement)
+    }
+}
+
+fn create_smoother(
+    filter_type: SmoothingFilterType,
+) -> Result<Box<dyn Smoother>, InvalidSmoothingFilter> {
+    filter_type.validate()?;
+    Ok(match filter_type {
+        SmoothingFilterType::ExponentialMovingAverage { alpha } => {
+            Box::new(ExponentialMovingAverage {
+                alpha,
+                estimate: None,
+            })
+        }
+        SmoothingFilterType::TriangularWindow { window_length } => Box::new(TriangularWindow {
+            window_length,
+            window: VecDeque::with_capacity(window_length),
+        }),
+        SmoothingFilterType::Median { window_length } => Box::new(Median {
+            window_length,
+            window: VecDeque::with_capacity(window_length),
+        }),
+        SmoothingFilterType::SavitzkyGolay {
+            window_length,
+            polynomial_order,
+        } => Box::new(SavitzkyGolay {
+            window_length,
+            polynomial_order,
+            window: VecDeque::with_capacity(window_length),
+        }),
+        SmoothingFilterType::AlphaBeta { alpha, beta } => {
+            Box::new(AlphaBetaGamma::new(alpha, beta, 0.0))
+        }
+        SmoothingFilterType::AlphaBetaGamma { alpha, beta, gamma } => {
+            Box::new(AlphaBetaGamma::new(alpha, beta, gamma))
+        }
+    })
+}
+
+pub struct GpsSmoothing;
+
+impl GpsSmoothing {
+    pub fn run(
+        tx: Publisher<Telemetry>,
+        rx: Receiver<Envelope<Telemetry>>,
+        filter_type: SmoothingFilterType,
+    ) -> JoinHandle<()> {
+        let mut smoother = create_smoother(filter_type).expect("Smoothing filter must be valid!");
+        thread::spawn(move || {
+            while let Ok(Envelope {
+                message: Telemetry::Position(measurement),
+                ..
+            }) = rx.recv()
+            {
+                let smoothed_data = smoother.smooth(&measurement);
+                log(GPS_SMOOTHING_LOG, smoothed_data);
+                if tx.publish(Telemetry::Position(smoothed_data)) == 0 {
+                    break;
+                }
+            }
+            log(GENERAL_LOG, "GPS smoothing filter removed".to_string());
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::Publisher;
+
+    use ntest_timeout::timeout;
+    use std::{
+        sync::mpsc,
+        time::{Duration, SystemTime},
+    };
+
+    fn measurements(values: &[f64]) -> Vec<Data> {
+        let start = SystemTime::now();
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| Data {
+                x: value,
+                y: 2.0 * value,
+                z: -value,
+                timestamp: start
+                    + Duration::from_secs_f64(i as f64 * get_cycle_duration_f64(GPS_FREQ)),
+            })
+            .collect()
+    }
+
+    fn smooth_all(filter_type: SmoothingFilterType, values: &[f64]) -> Vec<Data> {
+        let mut smoother = create_smoother(filter_type).unwrap();
+        measurements(values)
+            .iter()
+            .map(|data| smoother.smooth(data))
+            .collect()
+    }
+
+    #[test]
+    fn test_output_is_stamped_with_measurement_time() {
+        let filter_types = [
+            SmoothingFilterType::ExponentialMovingAverage { alpha: 0.5 },
+            SmoothingFilterType::TriangularWindow { window_length: 3 },
+            SmoothingFilterType::Median { window_length: 3 },
+            SmoothingFilterType::SavitzkyGolay {
+                window_length: 5,
+                polynomial_order: 2,
+            },
+            SmoothingFilterType::AlphaBeta {
+                alpha: 0.5,
+                beta: 0.1,
+            },
+            SmoothingFilterType::AlphaBetaGamma {
+                alpha: 0.5,
+                beta: 0.1,
+                gamma: 0.01,
+            },
+        ];
+        let input = measurements(&[1.0, 5.0, 2.0, 8.0]);
+        for filter_type in filter_types {
+            let mut smoother = create_smoother(filter_type).unwrap();
+            for data in &input {
+                assert_eq!(smoother.smooth(data).timestamp, data.timestamp);
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_window_or_too_high_polynomial_order_is_rejected() {
+        let invalid = [
+            SmoothingFilterType::TriangularWindow { window_length: 0 },
+            SmoothingFilterType::Median { window_length: 0 },
+            SmoothingFilterType::SavitzkyGolay {
+                window_length: 0,
+                polynomial_order: 0,
+            },
+            SmoothingFilterType::SavitzkyGolay {
+                window_length: 3,
+                polynomial_order: 3,
+            },
+        ];
+        for filter_type in invalid {
+            assert!(filter_type.validate().is_err());
+            assert!(create_smoother(filter_type).is_err());
+        }
+        assert!(SmoothingFilterType::SavitzkyGolay {
+            window_length: 3,
+            polynomial_order: 2
+        }
+        .validate()
+        .is_ok());
+        assert!(SmoothingFilterType::Median { window_length: 1 }
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_window_keeps_its_length() {
+        let mut smoother = Median {
+            window_length: 2,
+            window: VecDeque::new(),
+        };
+        for data in measurements(&[1.0, 2.0, 3.0, 4.0]) {
+            smoother.smooth(&data);
+        }
+        assert_eq!(smoother.window.len(), 2);
+    }
+
+    #[test]
+    fn test_exponential_moving_average() {
+        let output = smooth_all(
+            SmoothingFilterType::ExponentialMovingAverage { alpha: 0.25 },
+            &[4.0, 8.0],
+        );
+        approx::assert_abs_diff_eq!(output[0].x, 4.0);
+        approx::assert_abs_diff_eq!(output[1].x, 5.0);
+        approx::assert_abs_diff_eq!(output[1].y, 10.0);
+    }
+
+    #[test]
+    fn test_triangular_window_weights_middle_samples_most() {
+        let output = smooth_all(
+            SmoothingFilterType::TriangularWindow { window_length: 3 },
+            &[0.0, 4.0, 8.0, 0.0],
+        );
+        // weights 1, 2, 1
+        approx::assert_abs_diff_eq!(output[2].x, 4.0);
+        approx::assert_abs_diff_eq!(output[3].x, 5.0);
+    }
+
+    #[test]
+    fn test_median_rejects_outliers() {
+        let output = smooth_all(
+            SmoothingFilterType::Median { window_length: 3 },
+            &[1.0, 100.0, 2.0, 3.0],
+        );
+        approx::assert_abs_diff_eq!(output[1].x, 50.5);
+        approx::assert_abs_diff_eq!(output[2].x, 2.0);
+        approx::assert_abs_diff_eq!(output[3].x, 3.0);
+        approx::assert_abs_diff_eq!(output[3].z, -3.0);
+    }
+
+    #[test]
+    fn test_savitzky_golay_reproduces_polynomial_of_its_order() {
+        let values: Vec<f64> = (0..8)
+            .map(|i| 0.5 * (i * i) as f64 - i as f64 + 3.0)
+            .collect();
+        let output = smooth_all(
+            SmoothingFilterType::SavitzkyGolay {
+                window_length: 5,
+                polynomial_order: 2,
+            },
+            &values,
+        );
+        for (smoothed, value) in output.iter().zip(values) {
+            approx::assert_relative_eq!(smoothed.x, value, epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_savitzky_golay_coefficients_of_zero_order_are_average() {
+        let coefficients = savitzky_golay_coefficients(4, 0).unwrap();
+        for coefficient in coefficients {
+            approx::assert_relative_eq!(coefficient, 0.25, epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_alpha_beta_tracks_constant_velocity_without_lag() {
+        let values: Vec<f64> = (0..200).map(|i| 3.0 * i as f64).collect();
+        let output = smooth_all(
+            SmoothingFilterType::AlphaBeta {
+                alpha: 0.5,
+                beta: 0.2,
+            },
+            &values,
+        );
+        approx::assert_relative_eq!(
+            output.last().unwrap().x,
+            *values.last().unwrap(),
+            epsilon = 1e-6
+        );
+    }
+
+    #[test]
+    fn test_alpha_beta_gamma_tracks_constant_acceleration_without_lag() {
+        let values: Vec<f64> = (0..300).map(|i| 0.1 * (i * i) as f64).collect();
+        let output = smooth_all(
+            SmoothingFilterType::AlphaBetaGamma {
+                alpha: 0.5,
+                beta: 0.4,
+                gamma: 0.1,
+            },
+            &values,
+        );
+        approx::assert_relative_eq!(
+            output.last().unwrap().x,
+            *values.last().unwrap(),
+            epsilon = 1e-6
+        );
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn test_gps_smoothing_run() {
+        let (tx_gps, input_rx) = mpsc::channel();
+        let tx_gps = Publisher::from(vec![tx_gps]);
+        let (tx_smoothing, rx_smoothing) = mpsc::channel();
+        let handle = GpsSmoothing::run(
+            vec![tx_smoothing].into(),
+            input_rx,
+            SmoothingFilterType::Median { window_length: 3 },
+        );
+
+        let measurement = measurements(&[7.0])[0];
+        assert_eq!(tx_gps.publish(Telemetry::Position(measurement)), 1);
+        let output = rx_smoothing.recv().unwrap().message;
+        assert_eq!(output.data().timestamp, measurement.timestamp);
+        approx::assert_abs_diff_eq!(output.data().x, 7.0);
+
+        drop(tx_gps);
+        assert!(handle.join().is_ok());
+    }
+}
@@ -0,0 +1,723 @@
+#![allow(non_snake_case)]
+
+use super::initialize_state_using_gps_data;
+use crate::{
+    bus::Publisher,
+    config::{
+        GPS_FREQ, IMM_ACC_SIGMA, IMM_CA_JERK_SIGMA, IMM_CT_TURN_RATE, IMM_CV_ACC_SIGMA,
+        IMM_GPS_SIGMA, IMM_MODEL_STAY_PROBABILITY,
+    },
+    data::{string_timestamp, Data, Telemetry},
+    envelope::Envelope,
+    log_config::{GENERAL_LOG, IMM_LOG, IMM_MODEL_PROBABILITIES_LOG},
+    logger::log,
+};
+use nalgebra::{DMatrix, DVector, Matrix3, Matrix6x1, SMatrix, SVector, Vector3};
+use std::{f64::consts::PI, sync::mpsc::Receiver, thread::JoinHandle, time::SystemTime};
+
+// State layout: [x, y, z, vx, vy, vz, ax, ay, az]
+type Vector9 = SVector<f64, 9>;
+type Matrix9 = SMatrix<f64, 9, 9>;
+type Matrix3x9 = SMatrix<f64, 3, 9>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MotionModel {
+    ConstantVelocity { acc_sigma: f64 },
+    ConstantAcceleration { jerk_sigma: f64 },
+    // turn_rate in rad/s around z axis, z keeps constant velocity
+    CoordinatedTurn { turn_rate: f64, acc_sigma: f64 },
+}
+
+impl MotionModel {
+    fn create_matrix_A(&self, dt: f64) -> Matrix9 {
+        let mut A = Matrix9::identity();
+        match *self {
+            MotionModel::ConstantVelocity { .. } => {
+                for axis in 0..3 {
+                    A[(axis, axis + 3)] = dt;
+                    A[(axis + 6, axis + 6)] = 0.0;
+                }
+            }
+            MotionModel::ConstantAcceleration { .. } => {
+                for axis in 0..3 {
+                    A[(axis, axis + 3)] = dt;
+                    A[(axis, axis + 6)] = 0.5 * dt * dt;
+                    A[(axis + 3, axis + 6)] = dt;
+                }
+            }
+            MotionModel::CoordinatedTurn { turn_rate: w, .. } => {
+                let (s, c) = (w * dt).sin_cos();
+                let (arc_along, arc_across) = if w.abs() < f64::EPSILON {
+                    (dt, 0.0)
+                } else {
+                    (s / w, (1.0 - c) / w)
+                };
+                A[(0, 3)] = arc_along;
+                A[(0, 4)] = -arc_across;
+                A[(1, 3)] = arc_across;
+                A[(1, 4)] = arc_along;
+                A[(2, 5)] = dt;
+                A[(3, 3)] = c;
+                A[(3, 4)] = -s;
+                A[(4, 3)] = s;
+                A[(4, 4)] = c;
+                // centripetal acceleration of the rotated velocity: a = w x v
+                A[(6, 3)] = -w * s;
+                A[(6, 4)] = -w * c;
+                A[(7, 3)] = w * c;
+                A[(7, 4)] = -w * s;
+                A[(6, 6)] = 0.0;
+                A[(7, 7)] = 0.0;
+                A[(8, 8)] = 0.0;
+            }
+        }
+        A
+    }
+
+    fn create_matrix_Q(&self, dt: f64) -> Matrix9 {
+        let (gain, sigma) = match *self {
+            MotionModel::ConstantVelocity { acc_sigma }
+            | MotionModel::CoordinatedTurn { acc_sigma, .. } => {
+                ([dt * dt / 2.0, dt, 0.0], acc_sigma)
+            }
+            MotionModel::ConstantAcceleration { jerk_sigma } => {
+                ([dt.powi(3) / 6.0, dt * dt / 2.0, dt], jerk_sigma)
+            }
+        };
+
+        let mut Q = Matrix9::zeros();
+        for axis in 0..3 {
+            for (i, gain_i) in gain.iter().enumerate() {
+                for (j, gain_j) in gain.iter().enumerate() {
+                    Q[(axis + 3 * i, axis + 3 * j)] = gain_i * gain_j * sigma * sigma;
+                }
+            }
+        }
+        Q
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidImmConfig(&'static str);
+
+impl std::fmt::Display for InvalidImmConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid IMM configuration: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidImmConfig {}
+
+#[derive(Debug, Clone)]
+pub struct ImmConfig {
+    models: Vec<MotionModel>,
+    transition_matrix: DMatrix<f64>,
+    gps_sigma: f64,
+    acc_sigma: f64,
+}
+
+impl ImmConfig {
+    /// `transition_matrix[(i, j)]` is the probability of switching from model `i` to model `j`.
+    pub fn new(
+        models: Vec<MotionModel>,
+        transition_matrix: DMatrix<f64>,
+    ) -> Result<Self, InvalidImmConfig> {
+        if models.is_empty() {
+            return Err(InvalidImmConfig("at least one motion model is required"));
+        }
+        if transition_matrix.shape() != (models.len(), models.len()) {
+            return Err(InvalidImmConfig(
+                "transition matrix must be square with one row per model",
+            ));
+        }
+        let rows_are_distributions = transition_matrix
+            .row_iter()
+            .all(|row| row.iter().all(|p| *p >= 0.0) && (row.sum() - 1.0).abs() < 1e-9);
+        if !rows_are_distributions {
+            return Err(InvalidImmConfig(
+                "every transition matrix row must be a probability distribution",
+            ));
+        }
+
+        Ok(Self {
+            models,
+            transition_matrix,
+            gps_sigma: IMM_GPS_SIGMA,
+            acc_sigma: IMM_ACC_SIGMA,
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn with_measurement_noise(self, gps_sigma: f64, acc_sigma: f64) -> Self {
+        Self {
+            gps_sigma,
+            acc_sigma,
+            ..self
+        }
+    }
+}
+
+impl Default for ImmConfig {
+    fn default() -> Self {
+        let models = vec![
+            MotionModel::ConstantVelocity {
+                acc_sigma: IMM_CV_ACC_SIGMA,
+            },
+            MotionModel::ConstantAcceleration {
+                jerk_sigma: IMM_CA_JERK_SIGMA,
+            },
+            MotionModel::CoordinatedTurn {
+                turn_rate: IMM_CT_TURN_RATE,
+                acc_sigma: IMM_CV_ACC_SIGMA,
+            },
+            MotionModel::CoordinatedTurn {
+                turn_rate: -IMM_CT_TURN_RATE,
+                acc_sigma: IMM_CV_ACC_SIGMA,
+            },
+        ];
+        let transition_matrix = create_switching_matrix(models.len(), IMM_MODEL_STAY_PROBABILITY);
+        Self::new(models, transition_matrix).expect("Default IMM configuration must be valid")
+    }
+}
+
+/// Transition matrix keeping the current model with `stay_probability`
+/// and switching uniformly to any other model otherwise.
+pub fn create_switching_matrix(model_count: usize, stay_probability: f64) -> DMatrix<f64> {
+    if model_count == 1 {
+        return DMatrix::identity(1, 1);
+    }
+    let switch_probability = (1.0 - stay_probability) / (model_count - 1) as f64;
+    DMatrix::from_fn(model_count, model_count, |i, j| {
+        if i == j {
+            stay_probability
+        } else {
+            switch_probability
+        }
+    })
+}
+
+// Model probabilities summed by model kind, so that several turn rates share one column
+#[derive(Debug, Copy, Clone, serde::Serialize)]
+pub struct ImmModelProbabilities {
+    pub constant_velocity: f64,
+    pub constant_acceleration: f64,
+    pub coordinated_turn: f64,
+    #[serde(with = "string_timestamp")]
+    pub timestamp: SystemTime,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ModelFilter {
+    model: MotionModel,
+    x: Vector9,
+    P: Matrix9,
+}
+
+impl ModelFilter {
+    fn predict(&mut self, dt: f64) {
+        let A = self.model.create_matrix_A(dt);
+        self.x = A * self.x;
+        self.P = A * self.P * A.transpose() + self.model.create_matrix_Q(dt);
+    }
+
+    // Returns log-likelihood of the measurement, None when innovation covariance is singular
+    fn correct(&mut self, z: &Vector3<f64>, H: &Matrix3x9, R: &Matrix3<f64>) -> Option<f64> {
+        let innovation = z - H * self.x;
+        let S = H * self.P * H.transpose() + R;
+        let S_inv = S.try_inverse()?;
+        let K = self.P * H.transpose() * S_inv;
+        self.x += K * innovation;
+        self.P = (Matrix9::identity() - K * H) * self.P;
+
+        let mahalanobis = (innovation.transpose() * S_inv * innovation)[(0, 0)];
+        Some(-0.5 * (mahalanobis + S.determinant().ln() + 3.0 * (2.0 * PI).ln()))
+    }
+}
+
+pub struct Imm {
+    tx: Publisher<Telemetry>,
+    filters: Vec<ModelFilter>,
+    transition_matrix: DMatrix<f64>,
+    probabilities: DVector<f64>,
+    H_gps: Matrix3x9,
+    H_acc: Matrix3x9,
+    R_gps: Matrix3<f64>,
+    R_acc: Matrix3<f64>,
+    last_measurement_timestamp: Option<SystemTime>,
+}
+
+impl Imm {
+    fn new(tx: Publisher<Telemetry>, config: ImmConfig) -> Imm {
+        let model_count = config.models.len();
+        let gps_variance = config.gps_sigma * config.gps_sigma;
+        let acc_variance = config.acc_sigma * config.acc_sigma;
+        let velocity_variance = 2.0 * gps_variance * (GPS_FREQ.get() as f64).powi(2);
+        #[rustfmt::skip]
+        let P = Matrix9::from_diagonal(&Vector9::from_column_slice(&[
+            gps_variance, gps_variance, gps_variance,
+            velocity_variance, velocity_variance, velocity_variance,
+            acc_variance, acc_variance, acc_variance,
+        ]));
+
+        Imm {
+            tx,
+            filters: config
+                .models
+                .iter()
+                .map(|model| ModelFilter {
+                    model: *model,
+                    x: Vector9::zeros(),
+                    P,
+                })
+                .collect(),
+            transition_matrix: config.transition_matrix,
+            probabilities: DVector::from_element(model_count, 1.0 / model_count as f64),
+            H_gps: Matrix3x9::from_fn(|row, col| if col == row { 1.0 } else { 0.0 }),
+            H_acc: Matrix3x9::from_fn(|row, col| if col == row + 6 { 1.0 } else { 0.0 }),
+            R_gps: Matrix3::identity() * gps_variance,
+            R_acc: Matrix3::identity() * acc_variance,
+            last_measurement_timestamp: None,
+        }
+    }
+
+    fn initialize(&mut self, state: &Matrix6x1<f64>, timestamp: SystemTime) {
+        for filter in &mut self.filters {
+            filter.x.fixed_rows_mut::<6>(0).copy_from(state);
+        }
+        self.last_measurement_timestamp = Some(timestamp);
+    }
+
+    fn step(&mut self, telemetry: Telemetry) {
+        let (H, R, data) = match telemetry {
+            Telemetry::Acceleration(data) => (self.H_acc, self.R_acc, data),
+            Telemetry::Position(data) => (self.H_gps, self.R_gps, data),
+            // altitudes, velocities and ranges are fused by the Kalman filter, the state has no heading
+            Telemetry::Altitude(_)
+            | Telemetry::MagneticField(_)
+            | Telemetry::Velocity(_)
+            | Telemetry::Range(_) => return,
+        };
+
+        // measurements older than the last one are fused without prediction
+        let dt = match self.last_measurement_timestamp {
+            Some(last) => match data.timestamp.duration_since(last) {
+                Ok(elapsed) => {
+                    self.last_measurement_timestamp = Some(data.timestamp);
+                    elapsed.as_secs_f64()
+                }
+                Err(_) => 0.0,
+            },
+            None => {
+                self.last_measurement_timestamp = Some(data.timestamp);
+                0.0
+            }
+        };
+
+        let predicted_probabilities = self.mix();
+        let z = Vector3::new(data.x, data.y, data.z);
+        let log_likelihoods: Vec<Option<f64>> = self
+            .filters
+            .iter_mut()
+            .map(|filter| {
+                filter.predict(dt);
+                filter.correct(&z, &H, &R)
+            })
+            .collect();
+        self.update_probabilities(predicted_probabilities, &log_likelihoods);
+    }
+
+    // Interaction step, returns predicted model probabilities
+    fn mix(&mut self) -> DVector<f64> {
+        let predicted = self.transition_matrix.transpose() * &self.probabilities;
+        let mixed: Vec<Option<(Vector9, Matrix9)>> = (0..self.filters.len())
+            .map(|j| {
+                if predicted[j] <= 0.0 {
+                    return None;
+                }
+                let weights: Vec<f64> = (0..self.filters.len())
+                    .map(|i| self.transition_matrix[(i, j)] * self.probabilities[i] / predicted[j])
+                    .collect();
+                let x = self
+                    .filters
+                    .iter()
+                    .zip(&weights)
+                    .fold(Vector9::zeros(), |x, (filter, w)| x + filter.x * *w);
+                let P =
+                    self.filters
+                        .iter()
+                        .zip(&weights)
+                        .fold(Matrix9::zeros(), |P, (filter, w)| {
+                            let spread = filter.x - x;
+                            P + (filter.P + spread * spread.transpose()) * *w
+                        });
+                Some((x, P))
+            })
+            .collect();
+
+        for (filter, mixed_state) in self.filters.iter_mut().zip(mixed) {
+            if let Some((x, P)) = mixed_state {
+                filter.x = x;
+                filter.P = P;
+            }
+        }
+        predicted
+    }
+
+    fn update_probabilities(&mut self, predicted: DVector<f64>, log_likelihoods: &[Option<f64>]) {
+        let max_log_likelihood = log_likelihoods
+            .iter()
+            .flatten()
+            .fold(f64::NEG_INFINITY, |max, l| max.max(*l));
+        if !max_log_likelihood.is_finite() {
+            self.probabilities = predicted;
+            return;
+        }
+
+        let updated = DVector::from_iterator(
+            predicted.len(),
+            predicted
+                .iter()
+                .zip(log_likelihoods)
+                .map(|(c, l)| l.map_or(0.0, |l| c * (l - max_log_likelihood).exp())),
+        );
+        let total = updated.sum();
+        self.probabilities = if total > 0.0 {
+            updated / total
+        } else {
+            predicted
+        };
+    }
+
+    fn estimate(&self) -> Vector9 {
+        self.filters
+            .iter()
+            .zip(self.probabilities.iter())
+            .fold(Vector9::zeros(), |x, (filter, mu)| x + filter.x * *mu)
+    }
+
+    fn model_probabilities(&self, timestamp: SystemTime) -> ImmModelProbabilities {
+        let mut summary = ImmModelProbabilities {
+            constant_velocity: 0.0,
+            constant_acceleration: 0.0,
+            coordinated_turn: 0.0,
+            timestamp,
+        };
+        for (filter, mu) in self.filters.iter().zip(self.probabilities.iter()) {
+            match filter.model {
+                MotionModel::ConstantVelocity { .. } => summary.constant_velocity += mu,
+                MotionModel::ConstantAcceleration { .. } => summary.constant_acceleration += mu,
+                MotionModel::CoordinatedTurn { .. } => summary.coordinated_turn += mu,
+            }
+        }
+        summary
+    }
+
+    pub fn run(
+        tx: Publisher<Telemetry>,
+        rx: Receiver<Envelope<Telemetry>>,
+        config: ImmConfig,
+    ) -> JoinHandle<()> {
+        let mut imm = Imm::new(tx, config);
+        let mut gps_samples_received: u32 = 0;
+        let mut prev_gps_data: Data = Data::new();
+        let mut initial_state = Matrix6x1::zeros();
+
+        std::thread::spawn(move || {
+            for Envelope {
+                message: telemetry, ..
+            } in &rx
+            {
+                initialize_state_using_gps_data(
+                    telemetry,
+                    &mut gps_samples_received,
+                    &mut initial_state,
+                    &mut prev_gps_data,
+                );
+                if gps_samples_received == 2 {
+                    imm.initialize(&initial_state, telemetry.data().timestamp);
+                    break;
+                }
+            }
+            for Envelope {
+                message: telemetry, ..
+            } in rx
+            {
+                imm.step(telemetry);
+
+                let state = imm.estimate();
+                let timestamp = SystemTime::now();
+                let imm_position_estimate = Telemetry::Position(Data {
+                    x: state[0],
+                    y: state[1],
+                    z: state[2],
+                    timestamp,
+                });
+
+                if imm.tx.publish(imm_position_estimate) == 0 {
+                    break;
+                }
+                log(IMM_LOG, imm_position_estimate);
+                log(
+                    IMM_MODEL_PROBABILITIES_LOG,
+                    imm.model_probabilities(timestamp),
+                );
+            }
+            log(GENERAL_LOG, "IMM estimator removed".to_string());
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ntest_timeout::timeout;
+    use std::{
+        sync::mpsc,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use super::*;
+    use crate::bus::Publisher;
+
+    fn state(position: [f64; 3], velocity: [f64; 3], acceleration: [f64; 3]) -> Vector9 {
+        Vector9::from_iterator(position.into_iter().chain(velocity).chain(acceleration))
+    }
+
+    fn feed_trajectory(imm: &mut Imm, kinematics: impl Fn(f64) -> (Vector3<f64>, Vector3<f64>)) {
+        let start = SystemTime::now();
+        let dt = 0.05;
+        for step in 1..200 {
+            let t = step as f64 * dt;
+            let timestamp = start + Duration::from_secs_f64(t);
+            let (position, acceleration) = kinematics(t);
+            imm.step(Telemetry::Acceleration(Data {
+                x: acceleration.x,
+                y: acceleration.y,
+                z: acceleration.z,
+                timestamp,
+            }));
+            if step % 4 == 0 {
+                imm.step(Telemetry::Position(Data {
+                    x: position.x,
+                    y: position.y,
+                    z: position.z,
+                    timestamp,
+                }));
+            }
+        }
+    }
+
+    #[test]
+    fn test_constant_velocity_model_propagates_position() {
+        let model = MotionModel::ConstantVelocity { acc_sigma: 1.0 };
+        let x =
+            model.create_matrix_A(0.5) * state([1.0, 2.0, 3.0], [2.0, -2.0, 4.0], [1.0, 1.0, 1.0]);
+        approx::assert_abs_diff_eq!(x, state([2.0, 1.0, 5.0], [2.0, -2.0, 4.0], [0.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn test_constant_acceleration_model_propagates_velocity() {
+        let model = MotionModel::ConstantAcceleration { jerk_sigma: 1.0 };
+        let x =
+            model.create_matrix_A(1.0) * state([0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, -2.0]);
+        approx::assert_abs_diff_eq!(
+            x,
+            state([2.0, 0.0, -1.0], [3.0, 0.0, -2.0], [2.0, 0.0, -2.0])
+        );
+    }
+
+    #[test]
+    fn test_coordinated_turn_model_rotates_velocity() {
+        let turn_rate = PI / 2.0;
+        let model = MotionModel::CoordinatedTurn {
+            turn_rate,
+            acc_sigma: 1.0,
+        };
+        let x =
+            model.create_matrix_A(1.0) * state([0.0, 0.0, 0.0], [1.0, 0.0, 1.0], [0.0, 0.0, 0.0]);
+
+        let radius = 1.0 / turn_rate;
+        approx::assert_abs_diff_eq!(
+            x,
+            state(
+                [radius, radius, 1.0],
+                [0.0, 1.0, 1.0],
+                [-turn_rate, 0.0, 0.0]
+            ),
+            epsilon = 1e-12
+        );
+    }
+
+    #[test]
+    fn test_coordinated_turn_with_zero_rate_moves_straight() {
+        let turn = MotionModel::CoordinatedTurn {
+            turn_rate: 0.0,
+            acc_sigma: 1.0,
+        };
+        let straight = MotionModel::ConstantVelocity { acc_sigma: 1.0 };
+        approx::assert_abs_diff_eq!(turn.create_matrix_A(0.1), straight.create_matrix_A(0.1));
+    }
+
+    #[test]
+    fn test_process_noise_is_symmetric() {
+        for model in ImmConfig::default().models {
+            let Q = model.create_matrix_Q(0.05);
+            approx::assert_abs_diff_eq!(Q, Q.transpose());
+        }
+    }
+
+    #[test]
+    fn test_create_switching_matrix_rows_sum_to_one() {
+        let matrix = create_switching_matrix(4, 0.9);
+        approx::assert_abs_diff_eq!(matrix[(0, 0)], 0.9);
+        for row in matrix.row_iter() {
+            approx::assert_abs_diff_eq!(row.sum(), 1.0, epsilon = 1e-12);
+        }
+        approx::assert_abs_diff_eq!(create_switching_matrix(1, 0.9)[(0, 0)], 1.0);
+    }
+
+    #[test]
+    fn test_config_rejects_invalid_transition_matrix() {
+        let models = vec![
+            MotionModel::ConstantVelocity { acc_sigma: 1.0 },
+            MotionModel::ConstantAcceleration { jerk_sigma: 1.0 },
+        ];
+        assert!(ImmConfig::new(vec![], DMatrix::identity(0, 0)).is_err());
+        assert!(ImmConfig::new(models.clone(), DMatrix::identity(3, 3)).is_err());
+        assert!(ImmConfig::new(models.clone(), DMatrix::from_element(2, 2, 0.4)).is_err());
+        assert!(ImmConfig::new(models, create_switching_matrix(2, 0.95)).is_ok());
+    }
+
+    #[test]
+    fn test_straight_motion_favours_constant_velocity_model() {
+        let mut imm = Imm::new(
+            vec![].into(),
+            ImmConfig::default().with_measurement_noise(0.5, 0.1),
+        );
+        imm.initialize(
+            &Matrix6x1::new(0.0, 0.0, 0.0, 2.0, 1.0, 0.0),
+            SystemTime::now(),
+        );
+
+        feed_trajectory(&mut imm, |t| {
+            (Vector3::new(2.0 * t, t, 0.0), Vector3::zeros())
+        });
+
+        let probabilities = imm.model_probabilities(SystemTime::now());
+        assert!(probabilities.constant_velocity > probabilities.constant_acceleration);
+        assert!(probabilities.constant_velocity > probabilities.coordinated_turn);
+    }
+
+    #[test]
+    fn test_accelerating_motion_favours_constant_acceleration_model() {
+        let mut imm = Imm::new(
+            vec![].into(),
+            ImmConfig::default().with_measurement_noise(0.5, 0.1),
+        );
+        imm.initialize(&Matrix6x1::zeros(), SystemTime::now());
+
+        feed_trajectory(&mut imm, |t| {
+            (
+                Vector3::new(t * t, 0.0, 0.5 * t * t),
+                Vector3::new(2.0, 0.0, 1.0),
+            )
+        });
+
+        let probabilities = imm.model_probabilities(SystemTime::now());
+        assert!(probabilities.constant_acceleration > probabilities.constant_velocity);
+        assert!(probabilities.constant_acceleration > probabilities.coordinated_turn);
+        approx::assert_abs_diff_eq!(
+            probabilities.constant_velocity
+                + probabilities.constant_acceleration
+                + probabilities.coordinated_turn,
+            1.0,
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn test_circular_motion_favours_coordinated_turn_model() {
+        let mut imm = Imm::new(
+            vec![].into(),
+            ImmConfig::default().with_measurement_noise(0.5, 0.1),
+        );
+        let w = IMM_CT_TURN_RATE;
+        imm.initialize(
+            &Matrix6x1::new(1.0, 0.0, 0.0, 0.0, w, 0.0),
+            SystemTime::now(),
+        );
+
+        feed_trajectory(&mut imm, |t| {
+            let (s, c) = (w * t).sin_cos();
+            (
+                Vector3::new(c, s, 0.0),
+                Vector3::new(-w * w * c, -w * w * s, 0.0),
+            )
+        });
+
+        let probabilities = imm.model_probabilities(SystemTime::now());
+        assert!(probabilities.coordinated_turn > probabilities.constant_velocity);
+        assert!(probabilities.coordinated_turn > probabilities.constant_acceleration);
+    }
+
+    // First estimate published by a running IMM fed `early_accelerations`, then two GPS fixes
+    // a second apart and an acceleration 100 ms after the second fix
+    fn first_estimate(start: SystemTime, early_accelerations: &[Data]) -> Data {
+        let (tx_input, input_rx) = mpsc::channel();
+        let tx_imu = Publisher::from(vec![tx_input.clone()]);
+        let tx_gps = Publisher::from(vec![tx_input]);
+        let (tx_imm, rx_imm) = mpsc::channel();
+
+        let imm_handle = Imm::run(vec![tx_imm].into(), input_rx, ImmConfig::default());
+
+        for acceleration in early_accelerations {
+            let _ = tx_imu.publish(Telemetry::Acceleration(*acceleration));
+        }
+        let _ = tx_gps.publish(Telemetry::Position(Data {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            timestamp: start,
+        }));
+        let _ = tx_gps.publish(Telemetry::Position(Data {
+            x: 1.0,
+            y: 1.0,
+            z: 1.0,
+            timestamp: start + Duration::from_secs(1),
+        }));
+        let _ = tx_imu.publish(Telemetry::Acceleration(Data {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            timestamp: start + Duration::from_millis(1100),
+        }));
+        let estimate = *rx_imm.recv().unwrap().message.data();
+
+        drop(tx_imu);
+        drop(tx_gps);
+        assert!(imm_handle.join().is_ok());
+        estimate
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn test_imm_run() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_000);
+        let undisturbed = first_estimate(start, &[]);
+        approx::assert_abs_diff_eq!(undisturbed.x, 1.1, epsilon = 1e-2);
+
+        // IMU data before initialization is ignored
+        let early_accelerations: Vec<Data> = (1..=10)
+            .rev()
+            .map(|step| Data {
+                x: 50.0,
+                y: -30.0,
+                z: 20.0,
+                timestamp: start - Duration::from_millis(100 * step),
+            })
+            .collect();
+        let disturbed = first_estimate(start, &early_accelerations);
+        approx::assert_abs_diff_eq!(disturbed.x, undisturbed.x, epsilon = 1e-12);
+        approx::assert_abs_diff_eq!(disturbed.y, undisturbed.y, epsilon = 1e-12);
+        approx::assert_abs_diff_eq!(disturbed.z, undisturbed.z, epsilon = 1e-12);
+    }
+}
@@ -1,6 +1,6 @@
 #![allow(non_snake_case)]
 use std::{
-    sync::mpsc::{Receiver, Sender},
+    sync::mpsc::Receiver,
     thread::JoinHandle,
     time::SystemTime,
 };
@@ -8,8 +8,10 @@ use nalgebra::{Matrix3x1, Matrix6, Matrix6x1, Matrix6x3};
 use super::initialize_state_using_gps_data;
 
 use crate::{
+    bus::Publisher,
     config::IMU_FREQ,
     data::{Data, Telemetry},
+    envelope::Envelope,
     log_config::{INTERTIAL_NAVIGATOR_LOG, GENERAL_LOG},
     logger::log,
     utils::*,
@@ -17,7 +19,7 @@ use crate::{
 };
 
 pub struct InertialNavigator {
-    tx: Vec<Sender<Telemetry>>,
+    tx: Publisher<Telemetry>,
     A: Matrix6<f64>,
     B: Matrix6x3<f64>,
     state: Matrix6x1<f64>,
@@ -25,7 +27,7 @@ pub struct InertialNavigator {
 
 
 impl InertialNavigator {
-    fn new(tx: Vec<Sender<Telemetry>>) -> InertialNavigator {
+    fn new(tx: Publisher<Telemetry>) -> InertialNavigator {
         InertialNavigator {
             tx,
             A: create_matrix_A(get_cycle_duration_f64(IMU_FREQ)),
@@ -41,15 +43,15 @@ impl InertialNavigator {
     }
 
     pub fn run(
-        tx: Vec<Sender<Telemetry>>,
-        rx: Receiver<Telemetry>,
+        tx: Publisher<Telemetry>,
+        rx: Receiver<Envelope<Telemetry>>,
     ) -> JoinHandle<()> {
         let mut inertial_navigator = InertialNavigator::new(tx);
         let mut gps_samples_received : u32 = 0;
         let mut prev_gps_data : Data = Data::new();
 
         std::thread::spawn( move || {
-            for telemetry in &rx {
+            for Envelope { message: telemetry, .. } in &rx {
                 initialize_state_using_gps_data(
                         telemetry,
                         &mut gps_samples_received,
@@ -60,13 +62,17 @@ impl InertialNavigator {
                     break;
                 }
             }
-            for telemetry in rx {
+            for Envelope { message: telemetry, .. } in rx {
                 match telemetry {                          
                     Telemetry::Acceleration(data) => {
                         let u = Matrix3x1::new(data.x, data.y, data.z);
                         inertial_navigator.state = inertial_navigator.A * inertial_navigator.state + inertial_navigator.B * u;
                     }
-                    Telemetry::Position(_data) => {},
+                    Telemetry::Position(_data)
+                    | Telemetry::Altitude(_data)
+                    | Telemetry::MagneticField(_data)
+                    | Telemetry::Velocity(_data)
+                    | Telemetry::Range(_data) => {}
                 }
                 
                 let inertial_navigator_position_estimate = Telemetry::Position(Data {
@@ -76,8 +82,11 @@ impl InertialNavigator {
                     timestamp: SystemTime::now()
                 });
 
-                inertial_navigator.tx.retain(|tx| tx.send(inertial_navigator_position_estimate).is_ok());
-                if inertial_navigator.tx.is_empty() {
+                if inertial_navigator
+                    .tx
+                    .publish(inertial_navigator_position_estimate)
+                    == 0
+                {
                     break;
                 }
                 log(INTERTIAL_NAVIGATOR_LOG, inertial_navigator_position_estimate);
@@ -93,30 +102,31 @@ mod test {
 
     use ntest_timeout::timeout;
     use std::{
-        sync::mpsc::Sender,
         sync::mpsc,
         time::SystemTime,
     };
 
+    use crate::bus::Publisher;
     use super::*;
 
     #[test]
     #[timeout(10000)]
     fn test_new_inertial_navigator() {
         let (tx, _rx) = mpsc::channel();
-        let inertial_navigator = InertialNavigator::new(vec![tx]);
+        let inertial_navigator = InertialNavigator::new(vec![tx].into());
         approx::assert_abs_diff_eq!(inertial_navigator.A[(0,3)], get_cycle_duration_f64(IMU_FREQ));
-        assert!(inertial_navigator.tx[0].send(Telemetry::Acceleration(Data::new())).is_ok());
+        assert_eq!(inertial_navigator.tx.publish(Telemetry::Acceleration(Data::new())), 1);
     }
 
     #[test]
     #[timeout(10000)]
     fn test_inertial_navigator_run(){
-        let (tx_imu, input_rx) = mpsc::channel();
-        let tx_gps = tx_imu.clone();
+        let (tx_input, input_rx) = mpsc::channel();
+        let tx_imu = Publisher::from(vec![tx_input.clone()]);
+        let tx_gps = Publisher::from(vec![tx_input]);
         let (tx_inertial_nav, rx_inertial_nav) = mpsc::channel();
 
-        let transmitters : Vec<Sender<Telemetry>> = vec![tx_inertial_nav]; 
+        let transmitters: Publisher<Telemetry> = vec![tx_inertial_nav].into();
 
         let inertial_nav_handle = InertialNavigator::run(
             transmitters,
@@ -124,19 +134,24 @@ mod test {
         );
 
         // send IMU data and expect nothing in Intertial Navigator's output channel since state is not initialized yet
-        let _ = tx_imu.send(Telemetry::Position(Data { x: 1.0, y: 1.0, z: 1.0, timestamp: SystemTime::now() }));
+        let _ = tx_imu.publish(Telemetry::Position(Data { x: 1.0, y: 1.0, z: 1.0, timestamp: SystemTime::now() }));
         assert!(matches!(rx_inertial_nav.try_recv(), Err(std::sync::mpsc::TryRecvError::Empty)));
 
         // send GPS data and expect nothing in Intertial Navigator's output channel
-        let _ = tx_gps.send(Telemetry::Position(Data { x: 1.0, y: 1.0, z: 1.0, timestamp: SystemTime::now() }));
+        let _ = tx_gps.publish(Telemetry::Position(Data { x: 1.0, y: 1.0, z: 1.0, timestamp: SystemTime::now() }));
         assert!(matches!(rx_inertial_nav.try_recv(), Err(std::sync::mpsc::TryRecvError::Empty)));
 
         // send GPS data and expect nothing in Intertial Navigator's output channel
-        let _ = tx_gps.send(Telemetry::Position(Data { x: 1.0, y: 1.0, z: 1.0, timestamp: SystemTime::now() }));
+        let _ = tx_gps.publish(Telemetry::Position(Data { x: 1.0, y: 1.0, z: 1.0, timestamp: SystemTime::now() }));
         assert!(matches!(rx_inertial_nav.try_recv(), Err(std::sync::mpsc::TryRecvError::Empty)));
 
         // send IMU data and expect something in Intertial Navigator's output channel
-        let _ = tx_imu.send(Telemetry::Acceleration(Data { x: 1.0, y: 1.0, z: 1.0, timestamp: SystemTime::now() }));
+        let _ = tx_imu.publish(Telemetry::Acceleration(Data {
+            x: 1.0,
+            y: 1.0,
+            z: 1.0,
+            timestamp: SystemTime::now(),
+        }));
         assert!(rx_inertial_nav.recv().is_ok());
 
         drop(tx_imu);
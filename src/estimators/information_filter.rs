@@ -0,0 +1,330 @@
+#![allow(non_snake_case)]
+use crate::{
+    bus::Publisher,
+    config::IMU_FREQ,
+    data::{Data, Telemetry},
+    envelope::Envelope,
+    kalman::{create_matrix_A, create_matrix_B},
+    log_config::{GENERAL_LOG, INFORMATION_FILTER_LOG},
+    logger::log,
+    utils::get_cycle_duration_f64,
+};
+use nalgebra::{Matrix3, Matrix3x1, Matrix3x6, Matrix6, Matrix6x1, Matrix6x3};
+use std::{
+    sync::mpsc::{Receiver, TryRecvError},
+    thread::JoinHandle,
+    time::SystemTime,
+};
+
+// Smallest eigenvalue of the information matrix for the state to count as observable
+const MIN_INFORMATION: f64 = 1e-9;
+
+// Position channel of one GPS receiver together with its known noise level
+pub struct GpsInput {
+    rx: Receiver<Envelope<Telemetry>>,
+    noise_sigma: f64,
+}
+
+impl GpsInput {
+    pub fn new(rx: Receiver<Envelope<Telemetry>>, noise_sigma: f64) -> Self {
+        Self { rx, noise_sigma }
+    }
+}
+
+// Information brought by a single position measurement: i = H'R^-1 z, I = H'R^-1 H
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InformationContribution {
+    information_vector: Matrix6x1<f64>,
+    information_matrix: Matrix6<f64>,
+}
+
+impl InformationContribution {
+    pub fn from_position(position: &Data, noise_sigma: f64) -> Self {
+        let H = create_matrix_H();
+        let R_inverse = Matrix3::identity() / noise_sigma.powi(2);
+        let z = Matrix3x1::new(position.x, position.y, position.z);
+        Self {
+            information_vector: H.transpose() * R_inverse * z,
+            information_matrix: H.transpose() * R_inverse * H,
+        }
+    }
+}
+
+pub struct InformationFilter {
+    tx: Publisher<Telemetry>,
+    A_inverse: Matrix6<f64>,
+    B: Matrix6x3<f64>,
+    Q_inverse: Matrix3<f64>,
+    y: Matrix6x1<f64>, // information vector, Y * x
+    Y: Matrix6<f64>,   // information matrix, inverse of the state covariance
+}
+
+impl InformationFilter {
+    // Starts with no information at all, so there is no need to initialize the state from GPS
+    pub fn new(tx: Publisher<Telemetry>, acc_sigma: f64) -> Self {
+        let dt = get_cycle_duration_f64(IMU_FREQ);
+        Self {
+            tx,
+            A_inverse: create_matrix_A(-dt),
+            B: create_matrix_B(dt),
+            Q_inverse: Matrix3::identity() / acc_sigma.powi(2),
+            y: Matrix6x1::zeros(),
+            Y: Matrix6::zeros(),
+        }
+    }
+
+    // Prediction in information form, valid for a singular Y:
+    // M = A^-T Y A^-1, C = M B (B'M B + Q^-1)^-1, Y = (I - C B') M, y = (I - C B') A^-T y + Y B u
+    pub fn predict(&mut self, acceleration: &Data) {
+        let u = Matrix3x1::new(acceleration.x, acceleration.y, acceleration.z);
+        let M = self.A_inverse.transpose() * self.Y * self.A_inverse;
+        let Some(gain_inverse) = (self.B.transpose() * M * self.B + self.Q_inverse).try_inverse()
+        else {
+            eprintln!("Information filter: process noise is not invertible. Prediction skipped.");
+            return;
+        };
+        let C = M * self.B * gain_inverse;
+        let I_CBt = Matrix6::identity() - C * self.B.transpose();
+
+        let Y = I_CBt * M;
+        self.Y = (Y + Y.transpose()) * 0.5;
+        self.y = I_CBt * self.A_inverse.transpose() * self.y + self.Y * self.B * u;
+    }
+
+    // Independent receivers contribute additively, so any subset of them may be fused in one step
+    pub fn fuse(&mut self, contributions: impl IntoIterator<Item = InformationContribution>) {
+        for contribution in contributions {
+            self.y += contribution.information_vector;
+            self.Y += contribution.information_matrix;
+        }
+    }
+
+    pub fn estimate(&self) -> Option<Matrix6x1<f64>> {
+        if self.Y.symmetric_eigenvalues().min() < MIN_INFORMATION {
+            return None;
+        }
+        self.Y.cholesky().map(|cholesky| cholesky.solve(&self.y))
+    }
+
+    // Predicts on every IMU sample, then fuses whatever the receivers delivered in the meantime.
+    // A receiver that goes silent simply contributes nothing, a disconnected one is dropped.
+    pub fn run(
+        tx: Publisher<Telemetry>,
+        rx_imu: Receiver<Envelope<Telemetry>>,
+        mut gps_inputs: Vec<GpsInput>,
+        acc_sigma: f64,
+    ) -> JoinHandle<()> {
+        let mut information_filter = InformationFilter::new(tx, acc_sigma);
+
+        std::thread::spawn(move || {
+            for Envelope {
+                message: telemetry, ..
+            } in rx_imu
+            {
+                let Telemetry::Acceleration(acceleration) = telemetry else {
+                    continue;
+                };
+                information_filter.predict(&acceleration);
+
+                let mut contributions = Vec::new();
+                gps_inputs.retain(|gps_input| loop {
+                    match gps_input.rx.try_recv().map(|envelope| envelope.message) {
+                        Ok(Telemetry::Position(position)) => {
+                            contributions.push(InformationContribution::from_position(
+                                &position,
+                                gps_input.noise_sigma,
+                            ))
+                        }
+                        Ok(
+                            Telemetry::Acceleration(_)
+                            | Telemetry::Altitude(_)
+                            | Telemetry::MagneticField(_)
+                            | Telemetry::Velocity(_)
+                            | Telemetry::Range(_),
+                        ) => {}
+                        Err(TryRecvError::Empty) => break true,
+                        Err(TryRecvError::Disconnected) => break false,
+                    }
+                });
+                information_filter.fuse(contributions);
+
+                let Some(state) = information_filter.estimate() else {
+                    continue;
+                };
+                let information_position_estimate = Telemetry::Position(Data {
+                    x: state[0],
+                    y: state[1],
+                    z: state[2],
+                    timestamp: SystemTime::now(),
+                });
+
+                if information_filter.tx.publish(information_position_estimate) == 0 {
+                    break;
+                }
+                log(INFORMATION_FILTER_LOG, information_position_estimate);
+            }
+            log(GENERAL_LOG, "Information filter removed".to_string());
+        })
+    }
+}
+
+#[rustfmt::skip]
+fn create_matrix_H() -> Matrix3x6<f64> {
+    Matrix3x6::new(
+        1.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+        0.0, 1.0, 0.0, 0.0, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0, 0.0, 0.0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Publisher;
+    use ntest_timeout::timeout;
+    use std::{sync::mpsc, time::Duration};
+
+    fn position(x: f64, y: f64, z: f64) -> Data {
+        Data {
+            x,
+            y,
+            z,
+            timestamp: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn contributions_of_independent_receivers_add_up() {
+        let mut information_filter = InformationFilter::new(vec![].into(), 1.0);
+        information_filter.fuse([
+            InformationContribution::from_position(&position(1.0, 2.0, 3.0), 1.0),
+            InformationContribution::from_position(&position(4.0, 5.0, 6.0), 2.0),
+        ]);
+
+        approx::assert_relative_eq!(information_filter.Y[(0, 0)], 1.0 + 0.25);
+        approx::assert_relative_eq!(information_filter.Y[(3, 3)], 0.0);
+        approx::assert_relative_eq!(information_filter.y[0], 1.0 + 4.0 * 0.25);
+        approx::assert_relative_eq!(information_filter.y[2], 3.0 + 6.0 * 0.25);
+    }
+
+    #[test]
+    fn position_only_information_is_not_enough_for_an_estimate() {
+        let mut information_filter = InformationFilter::new(vec![].into(), 1.0);
+        assert!(information_filter.estimate().is_none());
+
+        information_filter.fuse([InformationContribution::from_position(
+            &position(1.0, 1.0, 1.0),
+            1.0,
+        )]);
+        assert!(information_filter.estimate().is_none());
+    }
+
+    #[test]
+    fn fused_estimate_weights_receivers_by_their_noise() {
+        let mut information_filter = InformationFilter::new(vec![].into(), 1.0);
+        let acceleration = position(0.0, 0.0, 0.0);
+        for _ in 0..200 {
+            information_filter.predict(&acceleration);
+            information_filter.fuse([
+                InformationContribution::from_position(&position(10.0, 10.0, 10.0), 1.0),
+                InformationContribution::from_position(&position(20.0, 20.0, 20.0), 3.0),
+            ]);
+        }
+
+        // weights 1/1 and 1/9 give (10 * 9 + 20) / 10
+        let state = information_filter.estimate().unwrap();
+        approx::assert_relative_eq!(state[0], 11.0, epsilon = 1e-6);
+        approx::assert_relative_eq!(state[3], 0.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn estimate_follows_constant_velocity_target() {
+        let mut information_filter = InformationFilter::new(vec![].into(), 0.1);
+        let dt = get_cycle_duration_f64(IMU_FREQ);
+        let acceleration = position(0.0, 0.0, 0.0);
+        for step in 1..=100 {
+            information_filter.predict(&acceleration);
+            let t = step as f64 * dt;
+            information_filter.fuse([InformationContribution::from_position(
+                &position(2.0 * t, -t, 0.5),
+                0.5,
+            )]);
+        }
+
+        let state = information_filter.estimate().unwrap();
+        approx::assert_relative_eq!(state[0], 2.0 * 100.0 * dt, epsilon = 1e-3);
+        approx::assert_relative_eq!(state[3], 2.0, epsilon = 1e-3);
+        approx::assert_relative_eq!(state[4], -1.0, epsilon = 1e-3);
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn given_silent_receiver_expect_estimates_from_remaining_ones() {
+        let (tx_output, rx_output) = mpsc::channel();
+        let (tx_imu, rx_imu) = mpsc::channel();
+        let (tx_gps_active, rx_gps_active) = mpsc::channel();
+        let (_tx_gps_silent, rx_gps_silent) = mpsc::channel();
+        let (tx_gps_disconnected, rx_gps_disconnected) = mpsc::channel::<Envelope<Telemetry>>();
+        let (tx_imu, tx_gps_active) = (
+            Publisher::from(vec![tx_imu]),
+            Publisher::from(vec![tx_gps_active]),
+        );
+        drop(tx_gps_disconnected);
+
+        let handle = InformationFilter::run(
+            vec![tx_output].into(),
+            rx_imu,
+            vec![
+                GpsInput::new(rx_gps_active, 1.0),
+                GpsInput::new(rx_gps_silent, 1.0),
+                GpsInput::new(rx_gps_disconnected, 1.0),
+            ],
+            1.0,
+        );
+
+        for _ in 0..10 {
+            assert_eq!(
+                tx_gps_active.publish(Telemetry::Position(position(5.0, 5.0, 5.0))),
+                1
+            );
+            assert_eq!(
+                tx_imu.publish(Telemetry::Acceleration(position(0.0, 0.0, 0.0))),
+                1
+            );
+            // velocity becomes observable only from positions fused in different cycles
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        drop(tx_imu);
+        handle.join().unwrap();
+
+        let estimates: Vec<Telemetry> = rx_output
+            .try_iter()
+            .map(|envelope| envelope.message)
+            .collect();
+        assert!(!estimates.is_empty());
+        approx::assert_relative_eq!(estimates.last().unwrap().data().x, 5.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn given_no_subscribers_expect_filter_to_stop() {
+        let (tx_output, rx_output) = mpsc::channel();
+        let (tx_imu, rx_imu) = mpsc::channel();
+        let (tx_gps, rx_gps) = mpsc::channel();
+        let (tx_imu, tx_gps) = (Publisher::from(vec![tx_imu]), Publisher::from(vec![tx_gps]));
+        drop(rx_output);
+
+        let handle = InformationFilter::run(
+            vec![tx_output].into(),
+            rx_imu,
+            vec![GpsInput::new(rx_gps, 1.0)],
+            1.0,
+        );
+        for _ in 0..3 {
+            tx_gps.publish(Telemetry::Position(position(1.0, 1.0, 1.0)));
+            tx_imu.publish(Telemetry::Acceleration(position(0.0, 0.0, 0.0)));
+        }
+        drop(tx_imu);
+        handle.join().unwrap();
+    }
+}
@@ -1,61 +1,330 @@
 #![allow(non_snake_case)]
 
 use std::{
-    sync::mpsc::{Receiver, Sender},
+    sync::mpsc::Receiver,
     thread::JoinHandle,
-    time::{Duration, SystemTime},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use nalgebra::{
+    Const, DMatrix, Matrix1, Matrix1x6, Matrix3, Matrix3x6, Matrix3x1, Matrix6, Matrix6x1,
+    Matrix6x3, SMatrix, SVector, Vector3,
 };
-use nalgebra::{Const, Matrix3, Matrix3x6, Matrix3x1, Matrix6, Matrix6x1, Matrix6x3};
 use crate::{
-    config::{IMU_FREQ, KALMAN_ACC_SIGMA, KALMAN_GPS_SIGMA, KALMAN_TIMING_TOLERANCE},
+    bus::Publisher,
+    config::{
+        IMU_FREQ, KALMAN_ACC_SIGMA, KALMAN_BAROMETER_SIGMA, KALMAN_GPS_OUTAGE, KALMAN_GPS_SIGMA,
+        KALMAN_ODOMETRY_SIGMA, KALMAN_TIMING_TOLERANCE, KALMAN_UPDATE_FORM, KALMAN_UWB_SIGMA,
+        UWB_ANCHORS,
+    },
     data::{Data, Telemetry},
+    envelope::{Envelope, SequenceStatus, SequenceTracker},
     logger::log,
     log_config::{GENERAL_LOG, KALMAN_LOG},
     utils::*,
 };
 use super::initialize_state_using_gps_data;
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KalmanUpdateForm {
+    // P = (I - KH)P, cheapest, but rounding errors can break symmetry over long runs
+    Standard,
+    // P = (I - KH)P(I - KH)' + KRK', keeps P symmetric positive semi-definite
+    Joseph,
+    // propagates a square-root factor S of P = SS' using QR decompositions
+    #[allow(dead_code)]
+    SquareRoot,
+}
+
+// Clock used to check the IMU sample interval
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KalmanTimeReference {
+    // time of arrival at the filter, for live sensors
+    Arrival,
+    // timestamp of the measurement itself, for replayed data arriving faster than real time
+    Measurement,
+}
+
+// GPS fixes ignored by the filter, to study dead reckoning on the other sensors
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GpsOutage {
+    pub start: Duration, // after the first GPS fix, or the UWB ranges the filter started with
+    pub duration: Duration,
+}
+
+impl GpsOutage {
+    fn covers(&self, first_fix: SystemTime, timestamp: SystemTime) -> bool {
+        timestamp
+            .duration_since(first_fix)
+            .is_ok_and(|elapsed| elapsed >= self.start && elapsed < self.start + self.duration)
+    }
+}
+
+#[derive(Debug)]
+pub struct SingularInnovationCovariance;
+
+impl std::fmt::Display for SingularInnovationCovariance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Innovation covariance is singular")
+    }
+}
+
+impl std::error::Error for SingularInnovationCovariance {}
+
+#[derive(Debug)]
+pub enum RangeCorrectionError {
+    UnknownAnchor(usize),
+    // the range has no direction to linearize around
+    AtAnchor,
+    Singular(SingularInnovationCovariance),
+}
+
+impl std::fmt::Display for RangeCorrectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RangeCorrectionError::UnknownAnchor(index) => {
+                write!(f, "Range to unknown anchor {index}")
+            }
+            RangeCorrectionError::AtAnchor => {
+                write!(f, "Estimated position coincides with the anchor")
+            }
+            RangeCorrectionError::Singular(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for RangeCorrectionError {}
+
 #[derive(Debug, Copy, Clone)]
 pub struct KalmanData {
     x: Matrix6x1<f64>,
     P: Matrix6<f64>,
+    S: Matrix6<f64>, // square-root factor of P, kept up to date only by the square-root form
 }
 
 impl KalmanData {
-    pub fn new() -> Self{
+    pub fn new(acc_sigma: f64) -> Self {
+        // a big number to start with arbitrarily uncertain state estimation
+        let initial_uncertainty = 10000.0;
+        let mut S = Matrix6::zeros();
+        S.fixed_view_mut::<6, 3>(0, 0).copy_from(
+            &(create_matrix_B(get_cycle_duration_f64(IMU_FREQ))
+                * (acc_sigma * initial_uncertainty).sqrt()),
+        );
         Self {
             x: Matrix6x1::zeros_generic(Const::<6>, Const::<1>),
             P: create_matrix_Q(
                 get_cycle_duration_f64(IMU_FREQ),
-                KALMAN_ACC_SIGMA
-            ) * 10000.0, // a big number to start with arbitrarily uncertain state estimation        
+                acc_sigma
+            ) * initial_uncertainty,
+            S,
         }
     }
 }
 
 pub struct KalmanFilter {
-    tx: Vec<Sender<Telemetry>>,
+    tx: Publisher<Telemetry>,
     A: Matrix6<f64>,
     B: Matrix6x3<f64>,
     H: Matrix3x6<f64>,
     Q: Matrix6<f64>,
     R: Matrix3<f64>,
+    Q_factor: Matrix6x3<f64>,
+    R_factor: Matrix3<f64>,
+    H_altitude: Matrix1x6<f64>,
+    R_altitude: Matrix1<f64>,
+    R_altitude_factor: Matrix1<f64>,
+    H_velocity: Matrix3x6<f64>,
+    R_velocity: Matrix3<f64>,
+    R_velocity_factor: Matrix3<f64>,
+    anchors: Vec<Vector3<f64>>,
+    R_range: Matrix1<f64>,
+    R_range_factor: Matrix1<f64>,
+    gps_outage: Option<GpsOutage>,
+    update_form: KalmanUpdateForm,
     state: KalmanData,
 }
 
 impl KalmanFilter {
 
-    fn new(tx: Vec<Sender<Telemetry>>) -> KalmanFilter {
-        
+    fn new(tx: Publisher<Telemetry>) -> KalmanFilter {
+        KalmanFilter::with_parameters(tx, KALMAN_GPS_SIGMA, KALMAN_ACC_SIGMA)
+    }
+
+    pub fn with_parameters(
+        tx: Publisher<Telemetry>,
+        gps_sigma: f64,
+        acc_sigma: f64,
+    ) -> KalmanFilter {
         KalmanFilter {
             tx,
             A: create_matrix_A(get_cycle_duration_f64(IMU_FREQ)),
             B: create_matrix_B(get_cycle_duration_f64(IMU_FREQ)),
             H: create_matrix_H(),
-            Q: create_matrix_Q(get_cycle_duration_f64(IMU_FREQ), KALMAN_ACC_SIGMA),
-            R: create_matrix_R(KALMAN_GPS_SIGMA),
-            state: KalmanData::new(),
+            Q: create_matrix_Q(get_cycle_duration_f64(IMU_FREQ), acc_sigma),
+            R: create_matrix_R(gps_sigma),
+            // Q = sigma_acc * BB' and R = sigma_gps * I, see create_matrix_Q and create_matrix_R
+            Q_factor: create_matrix_B(get_cycle_duration_f64(IMU_FREQ)) * acc_sigma.sqrt(),
+            R_factor: create_matrix_R(gps_sigma.sqrt()),
+            H_altitude: create_matrix_H_altitude(),
+            R_altitude: Matrix1::new(KALMAN_BAROMETER_SIGMA),
+            R_altitude_factor: Matrix1::new(KALMAN_BAROMETER_SIGMA.sqrt()),
+            H_velocity: create_matrix_H_velocity(),
+            R_velocity: create_matrix_R(KALMAN_ODOMETRY_SIGMA),
+            R_velocity_factor: create_matrix_R(KALMAN_ODOMETRY_SIGMA.sqrt()),
+            anchors: UWB_ANCHORS
+                .iter()
+                .map(|anchor| Vector3::from(*anchor))
+                .collect(),
+            R_range: Matrix1::new(KALMAN_UWB_SIGMA),
+            R_range_factor: Matrix1::new(KALMAN_UWB_SIGMA.sqrt()),
+            gps_outage: None,
+            update_form: KALMAN_UPDATE_FORM,
+            state: KalmanData::new(acc_sigma),
+        }
+    }
+
+    pub fn with_update_form(self, update_form: KalmanUpdateForm) -> KalmanFilter {
+        KalmanFilter {
+            update_form,
+            ..self
+        }
+    }
+
+    pub fn with_gps_outage(self, gps_outage: Option<GpsOutage>) -> KalmanFilter {
+        KalmanFilter {
+            gps_outage,
+            ..self
+        }
+    }
+
+    pub fn initialize(&mut self, x: Matrix6x1<f64>) {
+        self.state.x = x;
+    }
+
+    pub fn predict(&mut self, acceleration: &Data) {
+        let u = Matrix3x1::new(acceleration.x, acceleration.y, acceleration.z);
+        self.state.x = self.A * self.state.x + self.B * u;
+        match self.update_form {
+            KalmanUpdateForm::Standard | KalmanUpdateForm::Joseph => {
+                self.state.P = self.A * self.state.P * self.A.transpose() + self.Q;
+            }
+            KalmanUpdateForm::SquareRoot => {
+                // triangularize [AS | sqrt(Q)], its triangular factor is the new S
+                let mut pre_array = SMatrix::<f64, 6, 9>::zeros();
+                pre_array
+                    .fixed_view_mut::<6, 6>(0, 0)
+                    .copy_from(&(self.A * self.state.S));
+                pre_array
+                    .fixed_view_mut::<6, 3>(0, 6)
+                    .copy_from(&self.Q_factor);
+                self.state.S = pre_array.transpose().qr().r().transpose();
+                self.state.P = self.state.S * self.state.S.transpose();
+            }
+        }
+    }
+
+    pub fn correct(&mut self, position: &Data) -> Result<(), SingularInnovationCovariance> {
+        let z = Matrix3x1::new(position.x, position.y, position.z);
+        self.update(z, self.H, self.R, self.R_factor)
+    }
+
+    // Altitude of a barometer observes the vertical position only
+    pub fn correct_altitude(
+        &mut self,
+        altitude: &Data,
+    ) -> Result<(), SingularInnovationCovariance> {
+        let z = Matrix1::new(altitude.z);
+        self.update(z, self.H_altitude, self.R_altitude, self.R_altitude_factor)
+    }
+
+    // Velocity of the wheel odometry observes the velocity states only
+    pub fn correct_velocity(
+        &mut self,
+        velocity: &Data,
+    ) -> Result<(), SingularInnovationCovariance> {
+        let z = Matrix3x1::new(velocity.x, velocity.y, velocity.z);
+        self.update(z, self.H_velocity, self.R_velocity, self.R_velocity_factor)
+    }
+
+    // Range to an anchor, nonlinear in the position: linearized around the estimate, as in an EKF,
+    // with H the unit vector from the anchor to the estimated position
+    pub fn correct_range(&mut self, range: &Data) -> Result<(), RangeCorrectionError> {
+        let index = range.y as usize;
+        let anchor = *self
+            .anchors
+            .get(index)
+            .ok_or(RangeCorrectionError::UnknownAnchor(index))?;
+        let offset = self.position_estimate() - anchor;
+        let predicted_range = offset.norm();
+        if predicted_range < f64::EPSILON {
+            return Err(RangeCorrectionError::AtAnchor);
         }
+        let mut H = Matrix1x6::zeros();
+        H.fixed_view_mut::<1, 3>(0, 0)
+            .copy_from(&(offset / predicted_range).transpose());
+        // shifted so that the innovation z - Hx is the range residual
+        let z = Matrix1::new(range.x - predicted_range) + H * self.state.x;
+        self.update(z, H, self.R_range, self.R_range_factor)
+            .map_err(RangeCorrectionError::Singular)
+    }
+
+    // Measurement update for a measurement z = Hx + v of M values, with v ~ N(0, R) and R = R_factor R_factor'
+    fn update<const M: usize>(
+        &mut self,
+        z: SVector<f64, M>,
+        H: SMatrix<f64, M, 6>,
+        R: SMatrix<f64, M, M>,
+        R_factor: SMatrix<f64, M, M>,
+    ) -> Result<(), SingularInnovationCovariance> {
+        let innovation = z - H * self.state.x;
+        let K = match self.update_form {
+            KalmanUpdateForm::Standard | KalmanUpdateForm::Joseph => {
+                let K = self.state.P
+                    * H.transpose()
+                    * (H * self.state.P * H.transpose() + R)
+                        .try_inverse()
+                        .ok_or(SingularInnovationCovariance)?;
+                let I_KH = Matrix6::identity_generic(Const::<6>, Const::<6>) - K * H;
+                self.state.P = if self.update_form == KalmanUpdateForm::Joseph {
+                    I_KH * self.state.P * I_KH.transpose() + K * R * K.transpose()
+                } else {
+                    I_KH * self.state.P
+                };
+                K
+            }
+            KalmanUpdateForm::SquareRoot => {
+                // triangularize [sqrt(R) HS; 0 S] into [sqrt(HPH' + R) 0; K*sqrt(HPH' + R) S+],
+                // sized at run time, the size M + 6 cannot be spelled out in a type
+                let mut pre_array = DMatrix::<f64>::zeros(M + 6, M + 6);
+                pre_array.fixed_view_mut::<M, M>(0, 0).copy_from(&R_factor);
+                pre_array
+                    .fixed_view_mut::<M, 6>(0, M)
+                    .copy_from(&(H * self.state.S));
+                pre_array
+                    .fixed_view_mut::<6, 6>(M, M)
+                    .copy_from(&self.state.S);
+                let post_array = pre_array.transpose().qr().r().transpose();
+
+                let innovation_factor_inverse = post_array
+                    .fixed_view::<M, M>(0, 0)
+                    .into_owned()
+                    .try_inverse()
+                    .ok_or(SingularInnovationCovariance)?;
+                self.state.S = post_array.fixed_view::<6, 6>(M, M).into_owned();
+                self.state.P = self.state.S * self.state.S.transpose();
+                post_array.fixed_view::<6, M>(M, 0) * innovation_factor_inverse
+            }
+        };
+        self.state.x += K * innovation;
+        Ok(())
+    }
+
+    pub fn position_estimate(&self) -> Matrix3x1<f64> {
+        self.state.x.fixed_rows::<3>(0).into_owned()
+    }
+
+    pub fn position_covariance(&self) -> Matrix3<f64> {
+        self.state.P.fixed_view::<3, 3>(0, 0).into_owned()
     }
 
     #[allow(dead_code)]
@@ -68,53 +337,119 @@ impl KalmanFilter {
     }
 
     pub fn run(
-        tx: Vec<Sender<Telemetry>>,
-        rx: Receiver<Telemetry>,
+        tx: Publisher<Telemetry>,
+        rx: Receiver<Envelope<Telemetry>>,
+        update_form: KalmanUpdateForm,
+        time_reference: KalmanTimeReference,
     ) -> JoinHandle<()> {
-        let mut kalman = KalmanFilter::new(tx);
-        let mut last_imu_data_timestamp = SystemTime::now();
+        let mut kalman = KalmanFilter::new(tx)
+            .with_update_form(update_form)
+            .with_gps_outage(KALMAN_GPS_OUTAGE);
+        let mut last_imu_data_timestamp = match time_reference {
+            KalmanTimeReference::Arrival => SystemTime::now(),
+            KalmanTimeReference::Measurement => UNIX_EPOCH,
+        };
         let mut gps_samples_received : u32 = 0;
         let mut prev_gps_data : Data = Data::new();
+        // latest range to every anchor, to start without GPS
+        let mut initial_ranges: Vec<Option<f64>> = vec![None; kalman.anchors.len()];
+
+        let mut sequence_tracker = SequenceTracker::default();
 
         std::thread::spawn( move || {
-            for telemetry in &rx {
+            for envelope in &rx {
+                if sequence_tracker.check(&envelope) == SequenceStatus::OutOfOrder {
+                    continue;
+                }
+                let telemetry = envelope.message;
                 initialize_state_using_gps_data(
                         telemetry,
                         &mut gps_samples_received,
                         &mut kalman.state.x,
                         &mut prev_gps_data,
                 );
+                if let (KalmanTimeReference::Measurement, Telemetry::Acceleration(data)) =
+                    (time_reference, telemetry)
+                {
+                    last_imu_data_timestamp = data.timestamp;
+                }
                 if gps_samples_received == 2 {
                     break;
                 }
+                if let (0, Telemetry::Range(data)) = (gps_samples_received, telemetry) {
+                    if let Some(range) = initial_ranges.get_mut(data.y as usize) {
+                        *range = Some(data.x);
+                    }
+                    let ranges: Option<Vec<f64>> = initial_ranges.iter().copied().collect();
+                    if let Some(position) =
+                        ranges.and_then(|ranges| trilaterate(&kalman.anchors, &ranges))
+                    {
+                        kalman.initialize(Matrix6x1::new(
+                            position.x, position.y, position.z, 0.0, 0.0, 0.0,
+                        ));
+                        // no GPS fix yet, the outage window starts with the ranges
+                        prev_gps_data.timestamp = data.timestamp;
+                        log(
+                            GENERAL_LOG,
+                            "Kalman filter initialized from UWB ranges".to_string(),
+                        );
+                        break;
+                    }
+                }
             }
-            for telemetry in rx {
+            // the initialization keeps the first fix, or the time of the ranges it started with
+            let first_gps_timestamp = prev_gps_data.timestamp;
+            for envelope in rx {
+                match sequence_tracker.check(&envelope) {
+                    SequenceStatus::OutOfOrder => {
+                        eprintln!(
+                            "Kalman: sample {} of source {} is out of order. Sample skipped.",
+                            envelope.sequence, envelope.source
+                        );
+                        continue;
+                    }
+                    SequenceStatus::Gap { missing } => {
+                        eprintln!(
+                            "Kalman: {missing} samples of source {} lost before sample {}.",
+                            envelope.source, envelope.sequence
+                        );
+                    }
+                    SequenceStatus::InOrder => {}
+                }
+                let telemetry = envelope.message;
                 if telemetry_check(
                     telemetry,
                     &mut last_imu_data_timestamp,
+                    time_reference,
                 ) {
                     match telemetry {                    
-                        Telemetry::Acceleration(data) => {
-                            // prediction
-                            // println!(
-                            //     "Kalman received IMU data: {}, {}, {}",
-                            //     data.x, data.y, data.z
-                            // );
-                            let u = Matrix3x1::new(data.x, data.y, data.z);
-                            kalman.state.x = kalman.A * kalman.state.x + kalman.B * u;
-                            kalman.state.P = kalman.A * kalman.state.P * kalman.A.transpose() + kalman.Q;
-                        }
+                        Telemetry::Acceleration(data) => kalman.predict(&data),
                         Telemetry::Position(data) => {
-                            // correction
-                            // println!(
-                            //     "Kalman received GPS data: {}, {}, {}",
-                            //     data.x, data.y, data.z
-                            // );
-                            let z = Matrix3x1::new(data.x, data.y, data.z);
-                            let K = kalman.state.P * kalman.H.transpose() * (kalman.H * kalman.state.P * kalman.H.transpose() + kalman.R).try_inverse().unwrap();
-                            kalman.state.x = kalman.state.x + K * (z - kalman.H * kalman.state.x);
-                            kalman.state.P = (Matrix6::identity_generic(Const::<6>,Const::<6>) - K * kalman.H) * kalman.state.P;
-                        },
+                            if kalman.gps_outage.is_some_and(|outage| {
+                                outage.covers(first_gps_timestamp, data.timestamp)
+                            }) {
+                                // dead reckoning, the estimate is still published
+                            } else if let Err(e) = kalman.correct(&data) {
+                                eprintln!("Kalman: {e}. Correction skipped.");
+                            }
+                        }
+                        Telemetry::Altitude(data) => {
+                            if let Err(e) = kalman.correct_altitude(&data) {
+                                eprintln!("Kalman: {e}. Altitude correction skipped.");
+                            }
+                        }
+                        Telemetry::Velocity(data) => {
+                            if let Err(e) = kalman.correct_velocity(&data) {
+                                eprintln!("Kalman: {e}. Velocity correction skipped.");
+                            }
+                        }
+                        Telemetry::Range(data) => {
+                            if let Err(e) = kalman.correct_range(&data) {
+                                eprintln!("Kalman: {e}. Range correction skipped.");
+                            }
+                        }
+                        // the state has no heading to correct
+                        Telemetry::MagneticField(_) => continue,
                     }
                     
                     let kalman_position_estimate = Telemetry::Position(Data {
@@ -124,18 +459,40 @@ impl KalmanFilter {
                         timestamp: SystemTime::now()
                     });
 
-                    kalman.tx.retain(|tx| tx.send(kalman_position_estimate).is_ok());
-                    if kalman.tx.is_empty() {
+                    if kalman.tx.publish(kalman_position_estimate) == 0 {
                         break;
                     }
                     log(KALMAN_LOG, kalman_position_estimate);
                 }
             }
-            log(GENERAL_LOG, "Kalman filter removed".to_string());
+            log(
+                GENERAL_LOG,
+                format!(
+                    "Kalman filter removed, {} input samples lost, {} out of order",
+                    sequence_tracker.missing(),
+                    sequence_tracker.out_of_order()
+                ),
+            );
         })
     }   
 }
 
+// Least-squares position from the ranges to every anchor: subtracting the range equation of the first anchor
+// from the others leaves a linear system in the position, solvable for four anchors or more, not all in one plane
+fn trilaterate(anchors: &[Vector3<f64>], ranges: &[f64]) -> Option<Vector3<f64>> {
+    let (first_anchor, first_range) = (anchors.first()?, ranges.first()?);
+    let mut normal = Matrix3::zeros();
+    let mut projection = Vector3::zeros();
+    for (anchor, range) in anchors.iter().zip(ranges).skip(1) {
+        let row = 2.0 * (anchor - first_anchor);
+        let b = first_range.powi(2) - range.powi(2) + anchor.norm_squared()
+            - first_anchor.norm_squared();
+        normal += row * row.transpose();
+        projection += row * b;
+    }
+    normal.try_inverse().map(|inverse| inverse * projection)
+}
+
 fn max_expected_imu_interval() -> Duration {
     Duration::from_secs_f64(get_cycle_duration_f64(IMU_FREQ) * (1.0 + KALMAN_TIMING_TOLERANCE))
 }
@@ -147,11 +504,18 @@ fn min_expected_imu_interval() -> Duration {
 fn telemetry_check(
     telemetry: Telemetry,
     last_imu_data_timestamp: &mut SystemTime,
+    time_reference: KalmanTimeReference,
 ) -> bool {
     match telemetry {                    
-        Telemetry::Acceleration(_) => {
-            let current_imu_data_timestamp = SystemTime::now();
-            let imu_elapsed: Duration = current_imu_data_timestamp.duration_since(*last_imu_data_timestamp).unwrap(); 
+        Telemetry::Acceleration(data) => {
+            let current_imu_data_timestamp = match time_reference {
+                KalmanTimeReference::Arrival => SystemTime::now(),
+                KalmanTimeReference::Measurement => data.timestamp,
+            };
+            // a timestamp behind the previous one counts as zero elapsed time, i.e. time inversion
+            let imu_elapsed: Duration = current_imu_data_timestamp
+                .duration_since(*last_imu_data_timestamp)
+                .unwrap_or_default();
             *last_imu_data_timestamp = current_imu_data_timestamp;
 
             if imu_elapsed > max_expected_imu_interval() {
@@ -173,7 +537,11 @@ fn telemetry_check(
                 false
             }
         }
-        Telemetry::Position(_data) => {true},
+        Telemetry::Position(_data)
+        | Telemetry::Altitude(_data)
+        | Telemetry::MagneticField(_data)
+        | Telemetry::Velocity(_data)
+        | Telemetry::Range(_data) => true,
     }
 }
 
@@ -207,6 +575,18 @@ fn create_matrix_H() -> Matrix3x6<f64> {
     )
 }
 
+fn create_matrix_H_altitude() -> Matrix1x6<f64> {
+    Matrix1x6::new(0.0, 0.0, 1.0, 0.0, 0.0, 0.0)
+}
+
+fn create_matrix_H_velocity() -> Matrix3x6<f64> {
+    Matrix3x6::new(
+        0.0, 0.0, 0.0, 1.0, 0.0, 0.0,
+        0.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+        0.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+    )
+}
+
 fn create_matrix_Q(dt: f64, sigma_acc: f64) -> Matrix6<f64> {
     let Q = Matrix6::new(
         dt.powi(4)/4.0, 0.0,            0.0,            dt.powi(3)/2.0, 0.0,            0.0, 
@@ -231,6 +611,7 @@ mod test {
     use std::{sync::mpsc, time::SystemTime};
     use std::time::Duration;
 
+    use crate::{bus::Publisher, envelope::SourceId};
     use super::*;
 
     #[test]
@@ -275,11 +656,425 @@ mod test {
 
     #[test]
     fn test_KalmanData_init() {
-        let kd : KalmanData = KalmanData::new();
+        let kd : KalmanData = KalmanData::new(KALMAN_ACC_SIGMA);
         approx::assert_abs_diff_eq!(kd.P[(5,1)], 0.0);
         approx::assert_abs_diff_eq!(kd.x[5], 0.0);
     }
 
+    #[test]
+    fn test_initial_covariance_follows_acc_sigma() {
+        let kalman =
+            KalmanFilter::with_parameters(vec![].into(), KALMAN_GPS_SIGMA, 2.0 * KALMAN_ACC_SIGMA);
+        let default = KalmanData::new(KALMAN_ACC_SIGMA);
+        approx::assert_relative_eq!(kalman.state.P, default.P * 2.0, max_relative = 1e-12);
+    }
+
+    fn run_long_simulation(update_form: KalmanUpdateForm) -> KalmanFilter {
+        let mut kalman =
+            KalmanFilter::with_parameters(vec![].into(), KALMAN_GPS_SIGMA, KALMAN_ACC_SIGMA)
+                .with_update_form(update_form);
+        let dt = get_cycle_duration_f64(IMU_FREQ);
+        let timestamp = SystemTime::now();
+        for step in 0..50_000 {
+            let t = step as f64 * dt;
+            kalman.predict(&Data {
+                x: t.sin(),
+                y: t.cos(),
+                z: 0.1,
+                timestamp,
+            });
+            if step % 4 == 0 {
+                kalman
+                    .correct(&Data {
+                        x: -t.sin(),
+                        y: -t.cos(),
+                        z: 0.05 * t * t,
+                        timestamp,
+                    })
+                    .unwrap();
+            }
+        }
+        kalman
+    }
+
+    fn assert_symmetric_positive_definite(P: &Matrix6<f64>) {
+        let asymmetry = (P - P.transpose()).abs().max();
+        assert!(
+            asymmetry <= 1e-9 * P.abs().max(),
+            "P lost symmetry: {asymmetry}"
+        );
+        assert!(P.cholesky().is_some(), "P is not positive definite: {P}");
+    }
+
+    #[test]
+    fn test_joseph_form_keeps_covariance_symmetric_positive_definite() {
+        let kalman = run_long_simulation(KalmanUpdateForm::Joseph);
+        assert_symmetric_positive_definite(&kalman.state.P);
+    }
+
+    #[test]
+    fn test_square_root_form_keeps_covariance_symmetric_positive_definite() {
+        let kalman = run_long_simulation(KalmanUpdateForm::SquareRoot);
+        assert_symmetric_positive_definite(&kalman.state.P);
+        assert_symmetric_positive_definite(&(kalman.state.S * kalman.state.S.transpose()));
+    }
+
+    #[test]
+    fn test_update_forms_agree() {
+        let standard = run_long_simulation(KalmanUpdateForm::Standard);
+        for update_form in [KalmanUpdateForm::Joseph, KalmanUpdateForm::SquareRoot] {
+            let robust = run_long_simulation(update_form);
+            approx::assert_relative_eq!(
+                robust.state.x,
+                standard.state.x,
+                epsilon = 1e-6,
+                max_relative = 1e-6
+            );
+            approx::assert_relative_eq!(
+                robust.state.P,
+                standard.state.P,
+                epsilon = 1e-6,
+                max_relative = 1e-6
+            );
+        }
+    }
+
+    #[test]
+    fn test_singular_innovation_covariance_skips_correction() {
+        for update_form in [
+            KalmanUpdateForm::Standard,
+            KalmanUpdateForm::Joseph,
+            KalmanUpdateForm::SquareRoot,
+        ] {
+            let mut kalman = KalmanFilter::with_parameters(vec![].into(), 0.0, KALMAN_ACC_SIGMA)
+                .with_update_form(update_form);
+            kalman.state.P = Matrix6::zeros();
+            kalman.state.S = Matrix6::zeros();
+
+            let result = kalman.correct(&Data {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+                timestamp: SystemTime::now(),
+            });
+
+            assert!(result.is_err());
+            approx::assert_abs_diff_eq!(kalman.state.x, Matrix6x1::zeros());
+        }
+    }
+
+    #[test]
+    fn test_altitude_correction_updates_vertical_channel_only() {
+        let mut kalman =
+            KalmanFilter::with_parameters(vec![].into(), KALMAN_GPS_SIGMA, KALMAN_ACC_SIGMA);
+        let prior = kalman.state;
+
+        kalman
+            .correct_altitude(&Data {
+                x: 101_000.0,
+                y: 15.0,
+                z: 5.0,
+                timestamp: SystemTime::now(),
+            })
+            .unwrap();
+
+        approx::assert_abs_diff_eq!(kalman.state.x[0], 0.0);
+        approx::assert_abs_diff_eq!(kalman.state.x[1], 0.0);
+        assert!(kalman.state.x[2] > 0.0 && kalman.state.x[2] <= 5.0);
+        assert!(kalman.state.P[(2, 2)] < prior.P[(2, 2)]);
+        approx::assert_abs_diff_eq!(kalman.state.P[(0, 0)], prior.P[(0, 0)]);
+        approx::assert_abs_diff_eq!(kalman.state.P[(1, 1)], prior.P[(1, 1)]);
+    }
+
+    #[test]
+    fn test_update_forms_agree_on_altitude_corrections() {
+        let run = |update_form| {
+            let mut kalman =
+                KalmanFilter::with_parameters(vec![].into(), KALMAN_GPS_SIGMA, KALMAN_ACC_SIGMA)
+                    .with_update_form(update_form);
+            let dt = get_cycle_duration_f64(IMU_FREQ);
+            let timestamp = SystemTime::now();
+            for step in 0..5_000 {
+                let t = step as f64 * dt;
+                kalman.predict(&Data {
+                    x: t.sin(),
+                    y: t.cos(),
+                    z: 0.1,
+                    timestamp,
+                });
+                if step % 2 == 0 {
+                    kalman
+                        .correct_altitude(&Data {
+                            z: 0.05 * t * t,
+                            ..Data::new()
+                        })
+                        .unwrap();
+                }
+                if step % 20 == 0 {
+                    kalman
+                        .correct(&Data {
+                            x: -t.sin(),
+                            y: -t.cos(),
+                            z: 0.05 * t * t,
+                            timestamp,
+                        })
+                        .unwrap();
+                }
+            }
+            kalman
+        };
+        let standard = run(KalmanUpdateForm::Standard);
+        for update_form in [KalmanUpdateForm::Joseph, KalmanUpdateForm::SquareRoot] {
+            let robust = run(update_form);
+            approx::assert_relative_eq!(
+                robust.state.x,
+                standard.state.x,
+                epsilon = 1e-6,
+                max_relative = 1e-6
+            );
+            approx::assert_relative_eq!(
+                robust.state.P,
+                standard.state.P,
+                epsilon = 1e-6,
+                max_relative = 1e-6
+            );
+        }
+    }
+
+    #[test]
+    fn test_velocity_correction_pulls_velocity_towards_measurement() {
+        let mut kalman =
+            KalmanFilter::with_parameters(vec![].into(), KALMAN_GPS_SIGMA, KALMAN_ACC_SIGMA);
+        let prior = kalman.state;
+
+        kalman
+            .correct_velocity(&Data {
+                x: 2.0,
+                y: -1.0,
+                z: 0.0,
+                timestamp: SystemTime::now(),
+            })
+            .unwrap();
+
+        assert!(kalman.state.x[3] > 0.0 && kalman.state.x[3] <= 2.0);
+        assert!(kalman.state.x[4] < 0.0 && kalman.state.x[4] >= -1.0);
+        approx::assert_abs_diff_eq!(kalman.state.x[5], 0.0);
+        for i in 3..6 {
+            assert!(kalman.state.P[(i, i)] < prior.P[(i, i)]);
+        }
+    }
+
+    #[test]
+    fn test_update_forms_agree_on_velocity_corrections() {
+        let run = |update_form| {
+            let mut kalman =
+                KalmanFilter::with_parameters(vec![].into(), KALMAN_GPS_SIGMA, KALMAN_ACC_SIGMA)
+                    .with_update_form(update_form);
+            let dt = get_cycle_duration_f64(IMU_FREQ);
+            let timestamp = SystemTime::now();
+            for step in 0..5_000 {
+                let t = step as f64 * dt;
+                kalman.predict(&Data {
+                    x: t.sin(),
+                    y: t.cos(),
+                    z: 0.0,
+                    timestamp,
+                });
+                if step % 10 == 0 {
+                    kalman
+                        .correct_velocity(&Data {
+                            x: 1.0 - t.cos(),
+                            y: t.sin(),
+                            z: 0.0,
+                            timestamp,
+                        })
+                        .unwrap();
+                }
+            }
+            kalman
+        };
+        let standard = run(KalmanUpdateForm::Standard);
+        for update_form in [KalmanUpdateForm::Joseph, KalmanUpdateForm::SquareRoot] {
+            let robust = run(update_form);
+            approx::assert_relative_eq!(
+                robust.state.x,
+                standard.state.x,
+                epsilon = 1e-6,
+                max_relative = 1e-6
+            );
+            approx::assert_relative_eq!(
+                robust.state.P,
+                standard.state.P,
+                epsilon = 1e-6,
+                max_relative = 1e-6
+            );
+        }
+    }
+
+    fn ranges_to(anchors: &[[f64; 3]], position: Vector3<f64>) -> Vec<Data> {
+        anchors
+            .iter()
+            .enumerate()
+            .map(|(index, anchor)| Data {
+                x: (position - Vector3::from(*anchor)).norm(),
+                y: index as f64,
+                z: 0.0,
+                timestamp: SystemTime::now(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_range_corrections_locate_vehicle_without_gps() {
+        let mut kalman =
+            KalmanFilter::with_parameters(vec![].into(), KALMAN_GPS_SIGMA, KALMAN_ACC_SIGMA);
+        kalman.anchors = UWB_ANCHORS
+            .iter()
+            .map(|anchor| Vector3::from(*anchor))
+            .collect();
+        kalman.initialize(Matrix6x1::new(1.0, 1.0, 1.0, 0.0, 0.0, 0.0));
+        let position = Vector3::new(5.0, -3.0, 1.5);
+
+        for _ in 0..500 {
+            kalman.predict(&Data::new());
+            for range in ranges_to(&UWB_ANCHORS, position) {
+                kalman.correct_range(&range).unwrap();
+            }
+        }
+
+        approx::assert_abs_diff_eq!(kalman.position_estimate(), position, epsilon = 1e-2);
+    }
+
+    #[test]
+    fn test_update_forms_agree_on_range_corrections() {
+        let run = |update_form| {
+            let mut kalman =
+                KalmanFilter::with_parameters(vec![].into(), KALMAN_GPS_SIGMA, KALMAN_ACC_SIGMA)
+                    .with_update_form(update_form);
+            kalman.anchors = UWB_ANCHORS
+                .iter()
+                .map(|anchor| Vector3::from(*anchor))
+                .collect();
+            kalman.initialize(Matrix6x1::new(1.0, 1.0, 1.0, 0.0, 0.0, 0.0));
+            let dt = get_cycle_duration_f64(IMU_FREQ);
+            for step in 0..2_000 {
+                let t = step as f64 * dt;
+                kalman.predict(&Data {
+                    x: -t.sin(),
+                    y: -t.cos(),
+                    z: 0.0,
+                    timestamp: SystemTime::now(),
+                });
+                if step % 2 == 0 {
+                    for range in ranges_to(&UWB_ANCHORS, Vector3::new(t.sin(), t.cos(), 1.0)) {
+                        kalman.correct_range(&range).unwrap();
+                    }
+                }
+            }
+            kalman
+        };
+        let standard = run(KalmanUpdateForm::Standard);
+        for update_form in [KalmanUpdateForm::Joseph, KalmanUpdateForm::SquareRoot] {
+            let robust = run(update_form);
+            approx::assert_relative_eq!(
+                robust.state.x,
+                standard.state.x,
+                epsilon = 1e-6,
+                max_relative = 1e-6
+            );
+            approx::assert_relative_eq!(
+                robust.state.P,
+                standard.state.P,
+                epsilon = 1e-6,
+                max_relative = 1e-6
+            );
+        }
+    }
+
+    #[test]
+    fn test_trilaterate_locates_position_from_ranges() {
+        let anchors: Vec<Vector3<f64>> = UWB_ANCHORS
+            .iter()
+            .map(|anchor| Vector3::from(*anchor))
+            .collect();
+        let position = Vector3::new(5.0, -3.0, 1.5);
+        let ranges: Vec<f64> = ranges_to(&UWB_ANCHORS, position)
+            .iter()
+            .map(|range| range.x)
+            .collect();
+
+        approx::assert_abs_diff_eq!(
+            trilaterate(&anchors, &ranges).unwrap(),
+            position,
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn test_trilaterate_needs_anchors_out_of_one_plane() {
+        let flat = [
+            [0.0, 0.0, 0.0],
+            [10.0, 0.0, 0.0],
+            [0.0, 10.0, 0.0],
+            [10.0, 10.0, 0.0],
+        ];
+        let anchors: Vec<Vector3<f64>> = flat.iter().map(|anchor| Vector3::from(*anchor)).collect();
+        let ranges: Vec<f64> = ranges_to(&flat, Vector3::new(2.0, 3.0, 1.0))
+            .iter()
+            .map(|range| range.x)
+            .collect();
+
+        assert!(trilaterate(&anchors, &ranges).is_none());
+        assert!(trilaterate(&anchors[..3], &ranges[..3]).is_none());
+    }
+
+    #[test]
+    fn test_range_correction_rejects_unknown_anchor_and_position_at_anchor() {
+        let mut kalman =
+            KalmanFilter::with_parameters(vec![].into(), KALMAN_GPS_SIGMA, KALMAN_ACC_SIGMA);
+        kalman.anchors = vec![Vector3::zeros()];
+        let prior = kalman.state;
+
+        let unknown = Data {
+            x: 5.0,
+            y: 1.0,
+            z: 0.0,
+            timestamp: SystemTime::now(),
+        };
+        assert!(matches!(
+            kalman.correct_range(&unknown),
+            Err(RangeCorrectionError::UnknownAnchor(1))
+        ));
+        let at_anchor = Data { y: 0.0, ..unknown };
+        assert!(matches!(
+            kalman.correct_range(&at_anchor),
+            Err(RangeCorrectionError::AtAnchor)
+        ));
+        assert_eq!(kalman.state.x, prior.x);
+    }
+
+    #[test]
+    fn test_gps_outage_covers_its_window_after_first_fix() {
+        let outage = GpsOutage {
+            start: Duration::from_secs(10),
+            duration: Duration::from_secs(5),
+        };
+        let first_fix = SystemTime::now();
+
+        assert!(!outage.covers(first_fix, first_fix));
+        assert!(!outage.covers(first_fix, first_fix + Duration::from_millis(9_999)));
+        assert!(outage.covers(first_fix, first_fix + Duration::from_secs(10)));
+        assert!(outage.covers(first_fix, first_fix + Duration::from_millis(14_999)));
+        assert!(!outage.covers(first_fix, first_fix + Duration::from_secs(15)));
+        assert!(!outage.covers(first_fix, first_fix - Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_initial_square_root_factor_matches_covariance() {
+        let kd: KalmanData = KalmanData::new(KALMAN_ACC_SIGMA);
+        approx::assert_relative_eq!(kd.S * kd.S.transpose(), kd.P, max_relative = 1e-12);
+    }
+
     #[test]
     fn test_max_expected_imu_interval() {
         let base = get_cycle_duration_f64(IMU_FREQ);
@@ -306,17 +1101,20 @@ mod test {
         assert!(telemetry_check(
             telemetry_from_imu,
             &mut last_imu_data_timestamp,
+            KalmanTimeReference::Arrival,
         ));
 
         std::thread::sleep(get_cycle_duration(IMU_FREQ));
         assert!(telemetry_check(
             telemetry_from_imu,
             &mut last_imu_data_timestamp,
+            KalmanTimeReference::Arrival,
         ));
         std::thread::sleep(get_cycle_duration(IMU_FREQ));
         assert!(telemetry_check(
             telemetry_from_imu,
             &mut last_imu_data_timestamp,
+            KalmanTimeReference::Arrival,
         ));
     }
 
@@ -328,18 +1126,21 @@ mod test {
         assert!(telemetry_check(
             telemetry_from_gps,
             &mut last_imu_data_timestamp,
+            KalmanTimeReference::Arrival,
         ));
 
         std::thread::sleep(get_cycle_duration(IMU_FREQ));
         assert!(telemetry_check(
             telemetry_from_gps,
             &mut last_imu_data_timestamp,
+            KalmanTimeReference::Arrival,
         ));
 
         std::thread::sleep(get_cycle_duration(IMU_FREQ));
         assert!(telemetry_check(
             telemetry_from_gps,
             &mut last_imu_data_timestamp,
+            KalmanTimeReference::Arrival,
         ));
     }
 
@@ -354,11 +1155,13 @@ mod test {
         assert!(telemetry_check(
             telemetry_from_imu,
             &mut last_imu_data_timestamp,
+            KalmanTimeReference::Arrival,
         ));
 
         assert!(!telemetry_check(
             telemetry_from_imu,
             &mut last_imu_data_timestamp,
+            KalmanTimeReference::Arrival,
         ));
 
         std::thread::sleep(get_cycle_duration(IMU_FREQ));
@@ -366,34 +1169,72 @@ mod test {
         assert!(telemetry_check(
             telemetry_from_imu,
             &mut last_imu_data_timestamp,
+            KalmanTimeReference::Arrival,
+        ));
+    }
+
+    #[test]
+    fn telemetry_check_with_measurement_time_ignores_arrival_time() {
+        let start = SystemTime::now();
+        let imu_sample = |cycles: u32| {
+            Telemetry::Acceleration(Data {
+                timestamp: start + get_cycle_duration(IMU_FREQ) * cycles,
+                ..Data::new()
+            })
+        };
+        let mut last_imu_data_timestamp = start;
+
+        // replayed samples arrive back to back, their timestamps keep the recorded intervals
+        assert!(telemetry_check(
+            imu_sample(1),
+            &mut last_imu_data_timestamp,
+            KalmanTimeReference::Measurement
+        ));
+        assert!(telemetry_check(
+            imu_sample(2),
+            &mut last_imu_data_timestamp,
+            KalmanTimeReference::Measurement
+        ));
+        assert!(!telemetry_check(
+            imu_sample(2),
+            &mut last_imu_data_timestamp,
+            KalmanTimeReference::Measurement
+        ));
+        assert!(!telemetry_check(
+            imu_sample(1),
+            &mut last_imu_data_timestamp,
+            KalmanTimeReference::Measurement
         ));
     }
 
     #[test]
     fn test_KalmanFilter_run() {    
  
-        let (tx_imu, input_rx) = mpsc::channel();
-        let tx_gps = tx_imu.clone();
+        let (tx_input, input_rx) = mpsc::channel();
+        let tx_imu = Publisher::from(vec![tx_input.clone()]);
+        let tx_gps = Publisher::from(vec![tx_input]);
         
         let (tx_kalman, rx_from_kalman) = mpsc::channel();
 
-        let transmitters : Vec<Sender<Telemetry>> = vec![tx_kalman]; 
+        let transmitters: Publisher<Telemetry> = vec![tx_kalman].into();
 
         let kalman_handle = KalmanFilter::run(
             transmitters,
             input_rx,
+            KalmanUpdateForm::Standard,
+            KalmanTimeReference::Arrival,
         );
 
     // send IMU data
-        let _ = tx_imu.send(Telemetry::Acceleration(Data { x: 1.0, y: 1.0, z: 1.0, timestamp: SystemTime::now() }));
+        let _ = tx_imu.publish(Telemetry::Acceleration(Data { x: 1.0, y: 1.0, z: 1.0, timestamp: SystemTime::now() }));
         assert!(matches!(rx_from_kalman.try_recv(), Err(std::sync::mpsc::TryRecvError::Empty)));
 
         std::thread::sleep(get_cycle_duration(IMU_FREQ));
-        let _ = tx_imu.send(Telemetry::Acceleration(Data { x: 1.0, y: 1.0, z: 1.0, timestamp: SystemTime::now() }));
+        let _ = tx_imu.publish(Telemetry::Acceleration(Data { x: 1.0, y: 1.0, z: 1.0, timestamp: SystemTime::now() }));
         assert!(matches!(rx_from_kalman.try_recv(), Err(std::sync::mpsc::TryRecvError::Empty)));
         
         std::thread::sleep(get_cycle_duration(IMU_FREQ));
-        let _ = tx_imu.send(Telemetry::Acceleration(Data { x: 1.0, y: 1.0, z: 1.0, timestamp: SystemTime::now() }));
+        let _ = tx_imu.publish(Telemetry::Acceleration(Data { x: 1.0, y: 1.0, z: 1.0, timestamp: SystemTime::now() }));
         assert!(matches!(rx_from_kalman.try_recv(), Err(std::sync::mpsc::TryRecvError::Empty)));
         
     // send GPS data
@@ -406,7 +1247,7 @@ mod test {
         };
         let mut telemetry_from_gps: Telemetry = Telemetry::Position(gps_data);
 
-        let _ = tx_gps.send(telemetry_from_gps);
+        let _ = tx_gps.publish(telemetry_from_gps);
         assert!(matches!(rx_from_kalman.try_recv(), Err(std::sync::mpsc::TryRecvError::Empty)));
 
         gps_data = Data { 
@@ -417,7 +1258,7 @@ mod test {
         };
         telemetry_from_gps = Telemetry::Position(gps_data);
 
-        let _ = tx_gps.send(telemetry_from_gps);
+        let _ = tx_gps.publish(telemetry_from_gps);
         assert!(matches!(rx_from_kalman.try_recv(), Err(std::sync::mpsc::TryRecvError::Empty)));
 
     // Kalman should start Kalmaning
@@ -425,9 +1266,14 @@ mod test {
         // send zero acceleration and expect no change in position estimate ([1,1,1] - as set with gps data)
         // why is DT_IMU added to position data??? because 1(m/s) * DT_IMU(s) = DT_IMU(m) 
         std::thread::sleep(get_cycle_duration(IMU_FREQ));
-        let _ = tx_imu.send(Telemetry::Acceleration(Data { x: 0.0, y: 0.0, z: 0.0, timestamp: SystemTime::now() }));
+        let _ = tx_imu.publish(Telemetry::Acceleration(Data {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            timestamp: SystemTime::now(),
+        }));
         match rx_from_kalman.recv() {
-            Ok(data) => {
+            Ok(Envelope { message: data, .. }) => {
                 approx::assert_abs_diff_eq!(data.data().x, 1.0 + get_cycle_duration_f64(IMU_FREQ));
                 approx::assert_abs_diff_eq!(data.data().y, 1.0 + get_cycle_duration_f64(IMU_FREQ));
                 approx::assert_abs_diff_eq!(data.data().z, 1.0 + get_cycle_duration_f64(IMU_FREQ));
@@ -435,13 +1281,13 @@ mod test {
             Err(e) => panic!("Failed to receive: {e}"),
         }
         
-        let _ = tx_gps.send(Telemetry::Position(Data { 
+        let _ = tx_gps.publish(Telemetry::Position(Data { 
             x: 1.0 + get_cycle_duration_f64(IMU_FREQ), 
             y: 1.0 + get_cycle_duration_f64(IMU_FREQ), 
             z: 1.0 + get_cycle_duration_f64(IMU_FREQ), 
             timestamp: SystemTime::now() }));
         match rx_from_kalman.recv() {
-            Ok(data) => {
+            Ok(Envelope { message: data, .. }) => {
                 approx::assert_abs_diff_eq!(data.data().x, 1.0 + get_cycle_duration_f64(IMU_FREQ));
                 approx::assert_abs_diff_eq!(data.data().y, 1.0 + get_cycle_duration_f64(IMU_FREQ));
                 approx::assert_abs_diff_eq!(data.data().z, 1.0 + get_cycle_duration_f64(IMU_FREQ));
@@ -456,5 +1302,78 @@ mod test {
         
         kalman_handle.join().unwrap();
     }
-}
 
+    #[test]
+    fn given_repeated_sample_expect_it_to_be_skipped() {
+        let (tx, input_rx) = mpsc::channel();
+        let (tx_kalman, rx_from_kalman) = mpsc::channel();
+        let kalman_handle = KalmanFilter::run(
+            vec![tx_kalman].into(),
+            input_rx,
+            KalmanUpdateForm::Standard,
+            KalmanTimeReference::Measurement,
+        );
+
+        let (imu, gps) = (SourceId::next(), SourceId::next());
+        let start = SystemTime::now();
+        let sample = |cycles: u32| Data {
+            timestamp: start + get_cycle_duration(IMU_FREQ) * cycles,
+            ..Data::new()
+        };
+        tx.send(Envelope::new(gps, 0, Telemetry::Position(sample(0))))
+            .unwrap();
+        tx.send(Envelope::new(gps, 1, Telemetry::Position(sample(1))))
+            .unwrap();
+        tx.send(Envelope::new(imu, 0, Telemetry::Acceleration(sample(2))))
+            .unwrap();
+        // a newer measurement, but the sequence says the sample was already delivered
+        tx.send(Envelope::new(imu, 0, Telemetry::Acceleration(sample(3))))
+            .unwrap();
+        drop(tx);
+        kalman_handle.join().unwrap();
+
+        assert_eq!(rx_from_kalman.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn given_ranges_and_no_gps_expect_filter_to_start_from_trilateration() {
+        let (tx, input_rx) = mpsc::channel();
+        let (tx_kalman, rx_from_kalman) = mpsc::channel();
+        let kalman_handle = KalmanFilter::run(
+            vec![tx_kalman].into(),
+            input_rx,
+            KalmanUpdateForm::Standard,
+            KalmanTimeReference::Measurement,
+        );
+
+        let (imu, uwb) = (SourceId::next(), SourceId::next());
+        let start = SystemTime::now();
+        let sample = |cycles: u32| Data {
+            timestamp: start + get_cycle_duration(IMU_FREQ) * cycles,
+            ..Data::new()
+        };
+        let position = Vector3::new(5.0, -3.0, 1.5);
+        tx.send(Envelope::new(imu, 0, Telemetry::Acceleration(sample(0))))
+            .unwrap();
+        for (sequence, range) in ranges_to(&UWB_ANCHORS, position).into_iter().enumerate() {
+            tx.send(Envelope::new(uwb, sequence as u64, Telemetry::Range(range)))
+                .unwrap();
+        }
+        tx.send(Envelope::new(imu, 1, Telemetry::Acceleration(sample(1))))
+            .unwrap();
+        drop(tx);
+        kalman_handle.join().unwrap();
+
+        let estimates: Vec<Telemetry> = rx_from_kalman
+            .try_iter()
+            .map(|envelope| envelope.message)
+            .collect();
+        assert_eq!(estimates.len(), 1);
+        let estimate = estimates[0].data();
+        approx::assert_abs_diff_eq!(
+            Vector3::new(estimate.x, estimate.y, estimate.z),
+            position,
+            epsilon = 1e-6
+        );
+    }
+}
@@ -0,0 +1,531 @@
+use super::{initialize_state_using_gps_data, kalman::KalmanFilter};
+use crate::{
+    config::{
+        TUNING_ACC_SIGMA_RANGE, TUNING_GPS_SIGMA_RANGE, TUNING_GRID_STEPS,
+        TUNING_NELDER_MEAD_ITERATIONS,
+    },
+    csv_handler::{log_path_in, read_data_log},
+    data::{Data, Telemetry},
+    log_config::{GPS_LOG, GROUNDTRUTH_LOG, IMU_LOG},
+};
+use nalgebra::{Matrix3x1, Matrix6x1};
+use std::{
+    error::Error,
+    fs::{create_dir_all, File},
+    io::Write,
+    path::Path,
+    time::SystemTime,
+};
+
+pub const TUNING_CONFIG_FILE: &str = "kalman_tuning.cfg";
+
+// Expected mean NEES of a consistent filter equals the position dimension
+const POSITION_DIMENSION: f64 = 3.0;
+
+pub struct RecordedRun {
+    imu: Vec<Data>,
+    gps: Vec<Data>,
+    groundtruth: Vec<Data>,
+}
+
+impl RecordedRun {
+    pub fn new(imu: Vec<Data>, gps: Vec<Data>, mut groundtruth: Vec<Data>) -> Self {
+        groundtruth.sort_by_key(|data| data.timestamp);
+        Self {
+            imu,
+            gps,
+            groundtruth,
+        }
+    }
+
+    pub fn load(directory: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::new(
+            read_data_log(&log_path_in(directory, IMU_LOG))?,
+            read_data_log(&log_path_in(directory, GPS_LOG))?,
+            read_data_log(&log_path_in(directory, GROUNDTRUTH_LOG))?,
+        ))
+    }
+
+    // IMU samples go first when timestamps are equal, the same way the filter predicts before correcting
+    fn measurements(&self) -> Vec<Telemetry> {
+        let mut measurements: Vec<Telemetry> = self
+            .imu
+            .iter()
+            .map(|data| Telemetry::Acceleration(*data))
+            .chain(self.gps.iter().map(|data| Telemetry::Position(*data)))
+            .collect();
+        measurements.sort_by_key(|telemetry| telemetry.data().timestamp);
+        measurements
+    }
+
+    fn groundtruth_at(&self, timestamp: SystemTime) -> Option<Matrix3x1<f64>> {
+        let next = self
+            .groundtruth
+            .partition_point(|data| data.timestamp < timestamp);
+        let after = self.groundtruth.get(next)?;
+        if after.timestamp == timestamp {
+            return Some(Matrix3x1::new(after.x, after.y, after.z));
+        }
+        let before = self.groundtruth.get(next.checked_sub(1)?)?;
+
+        let span = after
+            .timestamp
+            .duration_since(before.timestamp)
+            .ok()?
+            .as_secs_f64();
+        let ratio = timestamp
+            .duration_since(before.timestamp)
+            .ok()?
+            .as_secs_f64()
+            / span;
+        Some(Matrix3x1::new(
+            before.x + (after.x - before.x) * ratio,
+            before.y + (after.y - before.y) * ratio,
+            before.z + (after.z - before.z) * ratio,
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TuningObjective {
+    Rmse,
+    NeesDeviation,
+}
+
+impl std::fmt::Display for TuningObjective {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TuningObjective::Rmse => write!(f, "RMSE"),
+            TuningObjective::NeesDeviation => write!(f, "NEES deviation"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KalmanParameters {
+    pub gps_sigma: f64,
+    pub acc_sigma: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TuningResult {
+    pub parameters: KalmanParameters,
+    pub cost: f64,
+    pub objective: TuningObjective,
+}
+
+/// Replays the recorded run through the Kalman filter and scores the position estimate
+/// against ground truth. Returns infinity when nothing could be scored.
+pub fn evaluate(
+    run: &RecordedRun,
+    parameters: KalmanParameters,
+    objective: TuningObjective,
+) -> f64 {
+    let mut kalman =
+        KalmanFilter::with_parameters(vec![].into(), parameters.gps_sigma, parameters.acc_sigma);
+    let mut gps_samples_received: u32 = 0;
+    let mut prev_gps_data = Data::new();
+    let mut initial_state = Matrix6x1::zeros();
+
+    let mut measurements = run.measurements().into_iter();
+    for telemetry in measurements.by_ref() {
+        initialize_state_using_gps_data(
+            telemetry,
+            &mut gps_samples_received,
+            &mut initial_state,
+            &mut prev_gps_data,
+        );
+        if gps_samples_received == 2 {
+            kalman.initialize(initial_state);
+            break;
+        }
+    }
+
+    let mut squared_error_sum = 0.0;
+    let mut nees_sum = 0.0;
+    let mut scored_samples = 0;
+    for telemetry in measurements {
+        match telemetry {
+            Telemetry::Acceleration(data) => kalman.predict(&data),
+            Telemetry::Position(data) => {
+                if kalman.correct(&data).is_err() {
+                    continue;
+                }
+            }
+            Telemetry::Altitude(data) => {
+                if kalman.correct_altitude(&data).is_err() {
+                    continue;
+                }
+            }
+            Telemetry::Velocity(data) => {
+                if kalman.correct_velocity(&data).is_err() {
+                    continue;
+                }
+            }
+            Telemetry::Range(data) => {
+                if kalman.correct_range(&data).is_err() {
+                    continue;
+                }
+            }
+            Telemetry::MagneticField(_) => continue,
+        }
+        let Some(truth) = run.groundtruth_at(telemetry.data().timestamp) else {
+            continue;
+        };
+        let error = kalman.position_estimate() - truth;
+        let Some(covariance_inverse) = kalman.position_covariance().try_inverse() else {
+            continue;
+        };
+        squared_error_sum += error.norm_squared();
+        nees_sum += (error.transpose() * covariance_inverse * error)[(0, 0)];
+        scored_samples += 1;
+    }
+
+    if scored_samples == 0 {
+        return f64::INFINITY;
+    }
+    let cost = match objective {
+        TuningObjective::Rmse => (squared_error_sum / scored_samples as f64).sqrt(),
+        TuningObjective::NeesDeviation => {
+            (nees_sum / scored_samples as f64 - POSITION_DIMENSION).abs()
+        }
+    };
+    if cost.is_nan() {
+        f64::INFINITY
+    } else {
+        cost
+    }
+}
+
+fn log_spaced(range: (f64, f64), steps: usize) -> impl Iterator<Item = f64> {
+    let (min, max) = (range.0.ln(), range.1.ln());
+    let increment = if steps > 1 {
+        (max - min) / (steps - 1) as f64
+    } else {
+        0.0
+    };
+    (0..steps).map(move |step| (min + increment * step as f64).exp())
+}
+
+/// Evaluates every combination of logarithmically spaced sigmas in the given ranges.
+pub fn grid_search(
+    run: &RecordedRun,
+    objective: TuningObjective,
+    gps_sigma_range: (f64, f64),
+    acc_sigma_range: (f64, f64),
+    steps: usize,
+) -> TuningResult {
+    let mut best = TuningResult {
+        parameters: KalmanParameters {
+            gps_sigma: gps_sigma_range.0,
+            acc_sigma: acc_sigma_range.0,
+        },
+        cost: f64::INFINITY,
+        objective,
+    };
+    for gps_sigma in log_spaced(gps_sigma_range, steps) {
+        for acc_sigma in log_spaced(acc_sigma_range, steps) {
+            let parameters = KalmanParameters {
+                gps_sigma,
+                acc_sigma,
+            };
+            let cost = evaluate(run, parameters, objective);
+            if cost < best.cost {
+                best = TuningResult {
+                    parameters,
+                    cost,
+                    objective,
+                };
+            }
+        }
+    }
+    best
+}
+
+/// Nelder-Mead simplex minimization of a two dimensional cost function.
+pub fn minimize_nelder_mead(
+    mut cost: impl FnMut([f64; 2]) -> f64,
+    start: [f64; 2],
+    initial_step: f64,
+    iterations: usize,
+) -> ([f64; 2], f64) {
+    let mut simplex: Vec<([f64; 2], f64)> = [
+        start,
+        [start[0] + initial_step, start[1]],
+        [start[0], start[1] + initial_step],
+    ]
+    .into_iter()
+    .map(|point| (point, cost(point)))
+    .collect();
+
+    let along = |from: [f64; 2], to: [f64; 2], factor: f64| {
+        [
+            from[0] + factor * (to[0] - from[0]),
+            from[1] + factor * (to[1] - from[1]),
+        ]
+    };
+
+    for _ in 0..iterations {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let (best, second_worst, worst) = (simplex[0], simplex[1], simplex[2]);
+        let centroid = along(best.0, second_worst.0, 0.5);
+
+        let reflected = along(worst.0, centroid, 2.0);
+        let reflected_cost = cost(reflected);
+        if reflected_cost < best.1 {
+            let expanded = along(worst.0, centroid, 3.0);
+            let expanded_cost = cost(expanded);
+            simplex[2] = if expanded_cost < reflected_cost {
+                (expanded, expanded_cost)
+            } else {
+                (reflected, reflected_cost)
+            };
+        } else if reflected_cost < second_worst.1 {
+            simplex[2] = (reflected, reflected_cost);
+        } else {
+            let contracted = along(centroid, worst.0, 0.5);
+            let contracted_cost = cost(contracted);
+            if contracted_cost < worst.1 {
+                simplex[2] = (contracted, contracted_cost);
+            } else {
+                for vertex in simplex.iter_mut().skip(1) {
+                    let shrunk = along(best.0, vertex.0, 0.5);
+                    *vertex = (shrunk, cost(shrunk));
+                }
+            }
+        }
+    }
+
+    simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+    simplex[0]
+}
+
+/// Refines the parameters with Nelder-Mead, searching in logarithmic space to keep sigmas positive.
+pub fn nelder_mead(
+    run: &RecordedRun,
+    objective: TuningObjective,
+    start: KalmanParameters,
+    iterations: usize,
+) -> TuningResult {
+    let to_parameters = |point: [f64; 2]| KalmanParameters {
+        gps_sigma: point[0].exp(),
+        acc_sigma: point[1].exp(),
+    };
+    let (best, cost) = minimize_nelder_mead(
+        |point| evaluate(run, to_parameters(point), objective),
+        [start.gps_sigma.ln(), start.acc_sigma.ln()],
+        0.5,
+        iterations,
+    );
+    TuningResult {
+        parameters: to_parameters(best),
+        cost,
+        objective,
+    }
+}
+
+pub fn write_config(path: &Path, result: &TuningResult) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
+    }
+    let mut file = File::create(path)?;
+    writeln!(
+        file,
+        "# Kalman parameters found by auto-tuning ({}: {})",
+        result.objective, result.cost
+    )?;
+    writeln!(file, "KALMAN_GPS_SIGMA = {}", result.parameters.gps_sigma)?;
+    writeln!(file, "KALMAN_ACC_SIGMA = {}", result.parameters.acc_sigma)?;
+    Ok(())
+}
+
+/// Loads logs from `directory`, runs grid search refined by Nelder-Mead
+/// and writes the best parameters next to the logs.
+pub fn tune(directory: &Path, objective: TuningObjective) -> Result<TuningResult, Box<dyn Error>> {
+    let run = RecordedRun::load(directory)?;
+    let coarse = grid_search(
+        &run,
+        objective,
+        TUNING_GPS_SIGMA_RANGE,
+        TUNING_ACC_SIGMA_RANGE,
+        TUNING_GRID_STEPS,
+    );
+    if !coarse.cost.is_finite() {
+        return Err("Recorded logs do not contain enough data to evaluate the filter".into());
+    }
+    let refined = nelder_mead(
+        &run,
+        objective,
+        coarse.parameters,
+        TUNING_NELDER_MEAD_ITERATIONS,
+    );
+    let best = if refined.cost < coarse.cost {
+        refined
+    } else {
+        coarse
+    };
+
+    write_config(&directory.join(TUNING_CONFIG_FILE), &best)?;
+    Ok(best)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use rand_distr::{Distribution, Normal};
+    use std::{fs, time::Duration};
+
+    use crate::{config::IMU_FREQ, utils::get_cycle_duration_f64};
+
+    fn synthetic_run() -> RecordedRun {
+        let start = SystemTime::now();
+        let mut rng = StdRng::seed_from_u64(7);
+        let gps_noise = Normal::new(0.0, 2.0).unwrap();
+        let imu_noise = Normal::new(0.0, 0.2).unwrap();
+        let w = 0.5;
+        let position = |t: f64| [10.0 * (w * t).cos(), 10.0 * (w * t).sin(), t];
+        let at = |t: f64, [x, y, z]: [f64; 3]| Data {
+            x,
+            y,
+            z,
+            timestamp: start + Duration::from_secs_f64(t),
+        };
+
+        let dt = get_cycle_duration_f64(IMU_FREQ);
+        let mut imu = vec![];
+        let mut gps = vec![];
+        let mut groundtruth = vec![];
+        for step in 0..600 {
+            let t = step as f64 * dt;
+            groundtruth.push(at(t, position(t)));
+            let acceleration = [
+                -10.0 * w * w * (w * t).cos(),
+                -10.0 * w * w * (w * t).sin(),
+                0.0,
+            ];
+            imu.push(at(t, acceleration.map(|a| a + imu_noise.sample(&mut rng))));
+            if step % 4 == 0 {
+                gps.push(at(t, position(t).map(|p| p + gps_noise.sample(&mut rng))));
+            }
+        }
+        RecordedRun::new(imu, gps, groundtruth)
+    }
+
+    #[test]
+    fn test_groundtruth_is_interpolated_between_samples() {
+        let start = SystemTime::now();
+        let run = RecordedRun::new(
+            vec![],
+            vec![],
+            vec![
+                Data {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 0.0,
+                    timestamp: start,
+                },
+                Data {
+                    x: 2.0,
+                    y: 4.0,
+                    z: -2.0,
+                    timestamp: start + Duration::from_secs(2),
+                },
+            ],
+        );
+
+        let truth = run.groundtruth_at(start + Duration::from_secs(1)).unwrap();
+        approx::assert_abs_diff_eq!(truth, Matrix3x1::new(1.0, 2.0, -1.0));
+        assert!(run.groundtruth_at(start + Duration::from_secs(3)).is_none());
+    }
+
+    #[test]
+    fn test_evaluate_without_gps_is_infinite() {
+        let mut run = synthetic_run();
+        run.gps.clear();
+        let parameters = KalmanParameters {
+            gps_sigma: 1.0,
+            acc_sigma: 1.0,
+        };
+        assert!(evaluate(&run, parameters, TuningObjective::Rmse).is_infinite());
+    }
+
+    #[test]
+    fn test_evaluate_scores_reasonable_parameters_better_than_extreme_ones() {
+        let run = synthetic_run();
+        let reasonable = KalmanParameters {
+            gps_sigma: 4.0,
+            acc_sigma: 0.1,
+        };
+        let extreme = KalmanParameters {
+            gps_sigma: 0.001,
+            acc_sigma: 1000.0,
+        };
+        let reasonable_rmse = evaluate(&run, reasonable, TuningObjective::Rmse);
+        assert!(reasonable_rmse.is_finite());
+        assert!(reasonable_rmse < evaluate(&run, extreme, TuningObjective::Rmse));
+        assert!(evaluate(&run, reasonable, TuningObjective::NeesDeviation).is_finite());
+    }
+
+    #[test]
+    fn test_minimize_nelder_mead_finds_quadratic_minimum() {
+        let (point, cost) = minimize_nelder_mead(
+            |[x, y]| (x - 1.0).powi(2) + 2.0 * (y + 3.0).powi(2),
+            [0.0, 0.0],
+            1.0,
+            200,
+        );
+        approx::assert_abs_diff_eq!(point[0], 1.0, epsilon = 1e-4);
+        approx::assert_abs_diff_eq!(point[1], -3.0, epsilon = 1e-4);
+        approx::assert_abs_diff_eq!(cost, 0.0, epsilon = 1e-8);
+    }
+
+    #[test]
+    fn test_grid_search_and_nelder_mead_improve_on_start() {
+        let run = synthetic_run();
+        let objective = TuningObjective::Rmse;
+        let coarse = grid_search(&run, objective, (0.1, 100.0), (0.01, 10.0), 4);
+        let corner = KalmanParameters {
+            gps_sigma: 100.0,
+            acc_sigma: 0.01,
+        };
+        assert!(coarse.cost <= evaluate(&run, corner, objective));
+
+        let refined = nelder_mead(&run, objective, coarse.parameters, 20);
+        assert!(refined.cost <= coarse.cost);
+        approx::assert_abs_diff_eq!(
+            refined.cost,
+            evaluate(&run, refined.parameters, objective),
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn test_write_config_contains_parameters() {
+        let path = Path::new("test_output_tuning/kalman_tuning.cfg");
+        let result = TuningResult {
+            parameters: KalmanParameters {
+                gps_sigma: 12.5,
+                acc_sigma: 0.75,
+            },
+            cost: 1.0,
+            objective: TuningObjective::Rmse,
+        };
+
+        write_config(path, &result).unwrap();
+        let content = fs::read_to_string(path).unwrap();
+
+        assert!(content.contains("KALMAN_GPS_SIGMA = 12.5"));
+        assert!(content.contains("KALMAN_ACC_SIGMA = 0.75"));
+        let _ = fs::remove_dir_all("test_output_tuning");
+    }
+
+    #[test]
+    fn test_tune_without_logs_fails() {
+        assert!(tune(
+            Path::new("test_output_tuning_missing"),
+            TuningObjective::Rmse
+        )
+        .is_err());
+    }
+}
@@ -6,6 +6,11 @@ use crate::{
 
 pub mod kalman;
 pub mod inertial_navigator;
+pub mod imm;
+pub mod kalman_tuning;
+pub mod information_filter;
+pub mod complementary;
+pub mod gps_smoothing;
 
 fn initialize_state_using_gps_data(
     telemetry: Telemetry,
@@ -14,7 +19,11 @@ fn initialize_state_using_gps_data(
     prev_gps_data: &mut Data,
 ) {
     match telemetry {                          
-        Telemetry::Acceleration(_data) => {},
+        Telemetry::Acceleration(_data)
+        | Telemetry::Altitude(_data)
+        | Telemetry::MagneticField(_data)
+        | Telemetry::Velocity(_data)
+        | Telemetry::Range(_data) => {},
         Telemetry::Position(data) => {
             *gps_samples_received += 1;
             
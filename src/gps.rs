@@ -1,8 +1,10 @@
 use crate::{
-    data::{Data, Telemetry},
+    bus::Publisher,
+    config::{LOCAL_FRAME, LOCAL_ORIGIN},
+    data::{KinematicState, Telemetry},
+    nmea::{NmeaFix, NmeaSink},
     utils::get_cycle_duration,
     logger::log,
-    log_config::GPS_LOG,
 };
 
 use rand_distr::{Normal, Distribution};
@@ -11,20 +13,28 @@ use std::{
     num::NonZeroU32,
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc::Sender,
         Arc, Mutex,
     },
     thread::{self, JoinHandle},
 };
+// An additional GPS unit, registered under DataSource::GpsReceiver(index in the receiver list)
+pub struct GpsReceiverConfig {
+    pub frequency: NonZeroU32,
+    pub noise_sigma: f64,
+    pub log_name: &'static str,
+}
+
 pub struct Gps;
 
 impl Gps {
     pub fn run(
-        trajectory_generator: Arc<Mutex<Data>>,
-        mut tx: Vec<Sender<Telemetry>>,
+        trajectory_generator: Arc<Mutex<KinematicState>>,
+        tx: Publisher<Telemetry>,
         shutdown: Arc<AtomicBool>,
         frequency: NonZeroU32,
         noise_standard_deviation: f64,
+        log_name: &'static str,
+        mut nmea_sink: Option<NmeaSink>,
     ) -> JoinHandle<()> {
         
         let gaussian_noise =
@@ -33,16 +43,32 @@ impl Gps {
 
         std::thread::spawn(move || {
             while !shutdown.load(Ordering::SeqCst) {
-                let mut current_position = *trajectory_generator.lock().unwrap();
+                let (mut current_position, velocity) = {
+                    let state = trajectory_generator.lock().unwrap();
+                    (state.position_data(), state.velocity)
+                };
 
                 current_position.x += gaussian_noise.sample(&mut rng());
                 current_position.y += gaussian_noise.sample(&mut rng());
                 current_position.z += gaussian_noise.sample(&mut rng());
         
-                log(GPS_LOG, current_position);
+                let current_position = LOCAL_FRAME.simulated_to_local(current_position);
+                log(log_name, current_position);
+
+                if let Some(sink) = nmea_sink.as_mut() {
+                    let fix = NmeaFix::from_local(
+                        &LOCAL_ORIGIN,
+                        LOCAL_FRAME,
+                        &current_position,
+                        &velocity,
+                    );
+                    if let Err(e) = sink.write_sentences(&fix.sentences()) {
+                        eprintln!("GPS: NMEA output failed: {e}. Output stopped.");
+                        nmea_sink = None;
+                    }
+                }
 
-                tx.retain(|tx| tx.send(Telemetry::Position(current_position)).is_ok());
-                if tx.is_empty() {
+                if tx.publish(Telemetry::Position(current_position)) == 0 {
                     break;
                 }
                 thread::sleep(get_cycle_duration(frequency));
@@ -54,21 +80,24 @@ impl Gps {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::log_config::GPS_LOG;
     use std::sync::mpsc;
 
     #[test]
     fn given_rx_goes_out_of_scope_gps_shuts_down() {
-        let trajectory_generator = Arc::new(Mutex::new(Data::new()));
+        let trajectory_generator = Arc::new(Mutex::new(KinematicState::new()));
         let (tx, rx) = mpsc::channel();
         let shutdown_trigger = Arc::new(AtomicBool::new(false));
         let arbitrary_frequency = NonZeroU32::new(5).unwrap();
         let noise_standard_deviation = 0.0;
         let gps = Gps::run(
             trajectory_generator,
-            vec![tx],
+            vec![tx].into(),
             shutdown_trigger,
             arbitrary_frequency,
             noise_standard_deviation,
+            GPS_LOG,
+            None,
         );
         drop(rx);
         gps.join().unwrap();
@@ -76,17 +105,19 @@ mod tests {
 
     #[test]
     fn gps_produces_positions() {
-        let trajectory_generator = Arc::new(Mutex::new(Data::new()));
+        let trajectory_generator = Arc::new(Mutex::new(KinematicState::new()));
         let (tx, rx) = mpsc::channel();
         let shutdown_trigger = Arc::new(AtomicBool::new(false));
         let arbitrary_frequency = NonZeroU32::new(5).unwrap();
         let noise_standard_deviation = 0.0;
         let gps = Gps::run(
             trajectory_generator,
-            vec![tx],
+            vec![tx].into(),
             Arc::clone(&shutdown_trigger),
             arbitrary_frequency,
             noise_standard_deviation,
+            GPS_LOG,
+            None,
         );
         let two_cycles = 2 * get_cycle_duration(arbitrary_frequency);
         std::thread::sleep(two_cycles);
@@ -98,21 +129,23 @@ mod tests {
 
     #[test]
     fn gps_test_nonzero_noise() {
-        let trajectory_generator = Arc::new(Mutex::new(Data::new()));
+        let trajectory_generator = Arc::new(Mutex::new(KinematicState::new()));
         let (tx, rx) = mpsc::channel();
         let shutdown_trigger = Arc::new(AtomicBool::new(false));
         let arbitrary_frequency = NonZeroU32::new(5).unwrap();
         let noise_standard_deviation = 1.0;
         let gps = Gps::run(
             trajectory_generator,
-            vec![tx],
+            vec![tx].into(),
             shutdown_trigger,
             arbitrary_frequency,
             noise_standard_deviation,
+            GPS_LOG,
+            None,
         );
 
-        let telemetry1 = rx.recv().unwrap();
-        let telemetry2 = rx.recv().unwrap();
+        let telemetry1 = rx.recv().unwrap().message;
+        let telemetry2 = rx.recv().unwrap().message;
 
         approx::assert_abs_diff_ne!(telemetry1.data().x, telemetry2.data().x);
         approx::assert_abs_diff_ne!(telemetry1.data().y, telemetry2.data().y);
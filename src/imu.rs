@@ -1,22 +1,22 @@
 use crate::{
-    data::{Data, Telemetry},
+    bus::Publisher,
+    config::LOCAL_FRAME,
+    data::{Data, KinematicState, Telemetry},
     imu::error::NoSubscribers,
     logger::log,
     log_config::{IMU_LOG, GENERAL_LOG},
     periodic_runner,
     utils::get_cycle_duration,
 };
-use nalgebra::Vector3;
 use std::{
     error::Error,
     num::NonZeroU32,
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc::Sender,
         Arc, Mutex,
     },
     thread::JoinHandle,
-    time::Duration,
+    time::SystemTime,
 };
 
 pub mod error;
@@ -25,100 +25,64 @@ use rand::rng;
 use rand_distr::{Distribution, Normal};
 
 pub struct Imu {
-    tx: Vec<Sender<Telemetry>>,
-    position_data: Arc<Mutex<Data>>,
-    prev_position: Data,
-    prev_velocity: Vector3<f64>,
-    last_valid_acceleration: Vector3<f64>,
+    tx: Publisher<Telemetry>,
+    kinematic_state: Arc<Mutex<KinematicState>>,
+    prev_timestamp: SystemTime,
     noise_generator: Normal<f64>,
 }
 
 impl Imu {
     pub fn run(
-        position_data: Arc<Mutex<Data>>,
-        tx: Vec<Sender<Telemetry>>,
+        kinematic_state: Arc<Mutex<KinematicState>>,
+        tx: Publisher<Telemetry>,
         shutdown: Arc<AtomicBool>,
         frequency: NonZeroU32,
         noise_standard_deviation: f64,
     ) -> JoinHandle<()> {
-        let mut imu = Imu::new(position_data, tx, noise_standard_deviation);
+        let mut imu = Imu::new(kinematic_state, tx, noise_standard_deviation);
         std::thread::spawn(move || {
-            // sleep one cycle to give trajectory generator a chance to update position
-            std::thread::sleep(get_cycle_duration(frequency));
-            match imu.init_velocity() {
-                Ok(_) => {
-                    // wait one cycle before entering the main loop to give the trajectory generator
-                    // a chance to update position after calculating initial velocity
-                    std::thread::sleep(get_cycle_duration(frequency));
-
-                    if let Err(e) = periodic_runner::run_periodicaly(
-                        || imu.step(),
-                        || should_stop(&shutdown),
-                        get_cycle_duration(frequency),
-                    ) {
-                        eprintln!("Imu internal error: {e}. Aborting.")
-                    }
-
-                    log(GENERAL_LOG, "Imu removed".to_string());
-                }
-                Err(e) => {
-                    eprintln!("Imu internal error during initialization: {e}. Aborting.");
-                }
+            if let Err(e) = periodic_runner::run_periodicaly(
+                || imu.step(),
+                || should_stop(&shutdown),
+                get_cycle_duration(frequency),
+            ) {
+                eprintln!("Imu internal error: {e}. Aborting.")
             }
+
+            log(GENERAL_LOG, "Imu removed".to_string());
         })
     }
 
     fn new(
-        position_data: Arc<Mutex<Data>>,
-        tx: Vec<Sender<Telemetry>>,
+        kinematic_state: Arc<Mutex<KinematicState>>,
+        tx: Publisher<Telemetry>,
         noise_standard_deviation: f64,
     ) -> Imu {
+        let prev_timestamp = kinematic_state.lock().unwrap().timestamp;
         Imu {
             tx,
-            position_data: Arc::clone(&position_data),
-            prev_position: *position_data.lock().unwrap(),
-            prev_velocity: Vector3::new(0.0, 0.0, 0.0),
-            last_valid_acceleration: Vector3::new(0.0, 0.0, 0.0),
+            kinematic_state,
+            prev_timestamp,
             noise_generator: Normal::new(0.0, noise_standard_deviation).unwrap(),
         }
     }
 
-    fn init_velocity(&mut self) -> Result<(), Box<dyn Error>> {
-        let current_position = *self.position_data.lock().unwrap();
-
-        let delta_time = current_position
-            .timestamp
-            .duration_since(self.prev_position.timestamp)?;
-
-        if !delta_time.is_zero() {
-            self.prev_velocity =
-                calculate_velocity(&self.prev_position, &current_position, &delta_time);
-        }
-        Ok(())
-    }
-
+    // Samples the analytic acceleration of the ground truth, so the output does not depend
+    // on how the IMU and the trajectory generator threads interleave
     fn step(&mut self) -> Result<(), Box<dyn Error>> {
-        let current_position = *self.position_data.lock().unwrap();
+        let current_state = *self.kinematic_state.lock().unwrap();
 
-        let delta_time = current_position
+        current_state
             .timestamp
-            .duration_since(self.prev_position.timestamp)?;
-
-        if !delta_time.is_zero() {
-            let current_velocity =
-                calculate_velocity(&self.prev_position, &current_position, &delta_time);
-            self.last_valid_acceleration =
-                calculate_acceleration(&self.prev_velocity, &current_velocity, &delta_time);
-            self.prev_velocity = current_velocity;
-        }
-
-        self.prev_position = current_position;
+            .duration_since(self.prev_timestamp)?;
+        self.prev_timestamp = current_state.timestamp;
 
+        let acceleration = LOCAL_FRAME.simulated_to_local(current_state.acceleration_data());
         let data_to_send = Data {
-            x: self.last_valid_acceleration.x + self.noise_generator.sample(&mut rng()),
-            y: self.last_valid_acceleration.y + self.noise_generator.sample(&mut rng()),
-            z: self.last_valid_acceleration.z + self.noise_generator.sample(&mut rng()),
-            timestamp: current_position.timestamp,
+            x: acceleration.x + self.noise_generator.sample(&mut rng()),
+            y: acceleration.y + self.noise_generator.sample(&mut rng()),
+            z: acceleration.z + self.noise_generator.sample(&mut rng()),
+            ..acceleration
         };
 
         log(IMU_LOG, data_to_send);
@@ -127,11 +91,9 @@ impl Imu {
     }
 
     fn send_data(&mut self, data: Data) -> Result<(), NoSubscribers> {
-        if self.tx.is_empty() {
+        if self.tx.publish(Telemetry::Acceleration(data)) == 0 {
             return Err(NoSubscribers);
         }
-        self.tx
-            .retain(|tx| tx.send(Telemetry::Acceleration(data)).is_ok());
         Ok(())
     }
 }
@@ -140,126 +102,38 @@ fn should_stop(shutdown_flag: &Arc<AtomicBool>) -> bool {
     shutdown_flag.load(Ordering::SeqCst)
 }
 
-fn calculate_velocity(
-    prev_position: &Data,
-    current_position: &Data,
-    delta_time: &Duration,
-) -> Vector3<f64> {
-    let x = (current_position.x - prev_position.x) / delta_time.as_secs_f64();
-    let y = (current_position.y - prev_position.y) / delta_time.as_secs_f64();
-    let z = (current_position.z - prev_position.z) / delta_time.as_secs_f64();
-    Vector3::new(x, y, z)
-}
-
-fn calculate_acceleration(
-    prev_velocity: &Vector3<f64>,
-    current_velocity: &Vector3<f64>,
-    delta_time: &Duration,
-) -> Vector3<f64> {
-    (current_velocity - prev_velocity) / delta_time.as_secs_f64()
-}
-
 #[cfg(test)]
 mod test {
     use ntest_timeout::timeout;
     use std::{
+        collections::HashMap,
+        f64::consts::PI,
         sync::mpsc,
-        time::{SystemTime, SystemTimeError},
+        time::{Duration, SystemTimeError},
     };
 
-    use super::*;
-
-    #[test]
-    fn given_zero_initial_velocity_expect_correct_acceleration_calculation() {
-        let initial_velocity = Vector3::new(0.0, 0.0, 0.0);
-        let final_velocity = Vector3::new(1.0, 2.0, 3.0);
-        let delta_time = Duration::from_secs(1);
-        let expected_acceleration = Vector3::new(1.0, 2.0, 3.0);
-
-        let results = calculate_acceleration(&initial_velocity, &final_velocity, &delta_time);
-        for (result, expected) in results.iter().zip(expected_acceleration.iter()) {
-            approx::assert_abs_diff_eq!(result, expected);
-        }
-    }
+    use nalgebra::Vector3;
 
-    #[test]
-    fn given_nonzero_initial_velocity_expect_correct_acceleration_calculation() {
-        let initial_velocity = Vector3::new(0.5, 3.0, 1.0);
-        let final_velocity = Vector3::new(1.0, 2.0, 3.0);
-        let delta_time = Duration::from_secs(2);
-        let expected_acceleration = Vector3::new(0.25, -0.5, 1.0);
-
-        let results = calculate_acceleration(&initial_velocity, &final_velocity, &delta_time);
-        for (result, expected) in results.iter().zip(expected_acceleration.iter()) {
-            approx::assert_abs_diff_eq!(result, expected);
-        }
-    }
-
-    #[test]
-    fn given_two_positions_expect_valid_velocity_calculated() {
-        let initial_position = Data {
-            x: 1.0,
-            y: 2.0,
-            z: 5.0,
-            timestamp: SystemTime::now(),
-        };
-        let final_position = Data {
-            x: 2.0,
-            y: 0.0,
-            z: 5.0,
-            timestamp: SystemTime::now(),
-        };
-        let delta_time = Duration::from_secs_f64(2.0);
-        let expected_velocity = Vector3::new(0.5, -1.0, 0.0);
-
-        let result = calculate_velocity(&initial_position, &final_position, &delta_time);
-        for (result, expected) in result.iter().zip(expected_velocity.iter()) {
-            approx::assert_abs_diff_eq!(result, expected);
-        }
-    }
-
-    #[test]
-    fn given_velocity_initialization_expect_acceleration_to_be_unchanged() {
-        let position_data = Arc::new(Mutex::new(Data::new()));
-        let (tx, rx) = mpsc::channel();
-        let mut imu = Imu {
-            tx: vec![tx],
-            position_data: Arc::clone(&position_data),
-            prev_position: *position_data.lock().unwrap(),
-            prev_velocity: Vector3::new(0.0, 0.0, 0.0),
-            last_valid_acceleration: Vector3::new(0.0, 0.0, 0.0),
-            noise_generator: Normal::new(0.0, 0.0).unwrap(),
-        };
-
-        {
-            let mut position_data = position_data.lock().unwrap();
-            position_data.x = 1.0;
-            position_data.y = 2.0;
-            position_data.z = 3.0;
-            position_data.timestamp += Duration::from_secs(1);
-        }
-
-        assert!(imu.init_velocity().is_ok());
+    use super::*;
+    use crate::{config::HELIX_FREQUENCY, trajectory_generator::TrajectoryGeneratorBuilder};
 
-        approx::assert_abs_diff_eq!(imu.prev_velocity.x, 1.0);
-        approx::assert_abs_diff_eq!(imu.prev_velocity.y, 2.0);
-        approx::assert_abs_diff_eq!(imu.prev_velocity.z, 3.0);
-        approx::assert_abs_diff_eq!(imu.last_valid_acceleration.x, 0.0);
-        approx::assert_abs_diff_eq!(imu.last_valid_acceleration.y, 0.0);
-        approx::assert_abs_diff_eq!(imu.last_valid_acceleration.z, 0.0);
-        let received_elements = rx.try_iter().collect::<Vec<_>>();
-        assert!(received_elements.is_empty());
+    fn imu(
+        kinematic_state: &Arc<Mutex<KinematicState>>,
+        tx: Publisher<Telemetry>,
+        noise_standard_deviation: f64,
+    ) -> Imu {
+        Imu::new(Arc::clone(kinematic_state), tx, noise_standard_deviation)
     }
 
     #[test]
     fn given_rx_goes_out_of_scope_imu_shuts_down() {
         let shutdown_trigger = Arc::new(AtomicBool::new(false));
-        let position_data = Arc::new(Mutex::new(Data::new()));
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
         let (tx, rx) = mpsc::channel();
         let arbitrary_frequency = NonZeroU32::new(1).unwrap();
         let imu = Imu::run(
-            Arc::clone(&position_data),
-            vec![tx],
+            Arc::clone(&kinematic_state),
+            vec![tx].into(),
             Arc::clone(&shutdown_trigger),
             arbitrary_frequency,
             0.0,
@@ -282,44 +156,36 @@ mod test {
 
     #[test]
     fn test_step() {
-        let position_data = Arc::new(Mutex::new(Data::new()));
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
         let (tx, rx) = mpsc::channel();
-        let no_noise = Normal::new(0.0, 0.0).unwrap();
-        let mut imu = Imu {
-            tx: vec![tx],
-            position_data: Arc::clone(&position_data),
-            prev_position: *position_data.lock().unwrap(),
-            prev_velocity: Vector3::new(0.0, 0.0, 0.0),
-            last_valid_acceleration: Vector3::new(0.0, 0.0, 0.0),
-            noise_generator: no_noise,
+        let mut imu = imu(&kinematic_state, vec![tx].into(), 0.0);
+
+        let expected_timestamp = {
+            let mut state = kinematic_state.lock().unwrap();
+            state.position = Vector3::new(10.0, 20.0, 30.0);
+            state.velocity = Vector3::new(-1.0, 0.5, 2.0);
+            state.acceleration = Vector3::new(1.0, 2.0, 3.0);
+            state.timestamp += Duration::from_secs(1);
+            state.timestamp
         };
-
-        {
-            let mut position_data = position_data.lock().unwrap();
-            position_data.x = 1.0;
-            position_data.y = 2.0;
-            position_data.z = 3.0;
-            position_data.timestamp += Duration::from_secs(1);
-        }
         assert!(imu.step().is_ok());
 
-        let Telemetry::Acceleration(acc) = rx.recv().unwrap() else {
+        let Telemetry::Acceleration(acc) = rx.recv().unwrap().message else {
             panic!("Cannot return position!");
         };
         approx::assert_abs_diff_eq!(acc.x, 1.0);
         approx::assert_abs_diff_eq!(acc.y, 2.0);
         approx::assert_abs_diff_eq!(acc.z, 3.0);
+        assert_eq!(acc.timestamp, expected_timestamp);
 
         {
-            let mut position_data = position_data.lock().unwrap();
-            position_data.x = 1.5;
-            position_data.y = 1.5;
-            position_data.z = 2.0;
-            position_data.timestamp += Duration::from_secs(1);
+            let mut state = kinematic_state.lock().unwrap();
+            state.acceleration = Vector3::new(-0.5, -2.5, -4.0);
+            state.timestamp += Duration::from_secs(1);
         }
 
         assert!(imu.step().is_ok());
-        let Telemetry::Acceleration(acc) = rx.recv().unwrap() else {
+        let Telemetry::Acceleration(acc) = rx.recv().unwrap().message else {
             panic!("Cannot return position!");
         };
         approx::assert_abs_diff_eq!(acc.x, -0.5);
@@ -329,18 +195,10 @@ mod test {
 
     #[test]
     fn given_next_timestamp_is_behind_previous_expect_step_to_return_system_time_err() {
-        let initial_velocity = Vector3::new(0.0, 0.0, 0.0);
-        let position_data = Arc::new(Mutex::new(Data::new()));
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
         let (tx, _) = mpsc::channel();
-        let mut imu = Imu {
-            tx: vec![tx],
-            position_data: Arc::clone(&position_data),
-            prev_position: *position_data.lock().unwrap(),
-            prev_velocity: initial_velocity,
-            last_valid_acceleration: Vector3::new(0.0, 0.0, 0.0),
-            noise_generator: Normal::new(0.0, 0.0).unwrap(),
-        };
-        position_data.lock().unwrap().timestamp -= Duration::new(1, 0);
+        let mut imu = imu(&kinematic_state, vec![tx].into(), 0.0);
+        kinematic_state.lock().unwrap().timestamp -= Duration::new(1, 0);
 
         assert!(imu
             .step()
@@ -351,113 +209,123 @@ mod test {
 
     #[test]
     fn given_no_subscribers_expect_step_to_return_no_subs_error() {
-        let initial_velocity = Vector3::new(0.0, 0.0, 0.0);
-        let position_data = Arc::new(Mutex::new(Data::new()));
-        let mut imu = Imu {
-            tx: vec![],
-            position_data: Arc::clone(&position_data),
-            prev_position: *position_data.lock().unwrap(),
-            prev_velocity: initial_velocity,
-            last_valid_acceleration: Vector3::new(0.0, 0.0, 0.0),
-            noise_generator: Normal::new(0.0, 0.0).unwrap(),
-        };
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let mut imu = imu(&kinematic_state, vec![].into(), 0.0);
 
         assert!(imu.step().unwrap_err().downcast::<NoSubscribers>().is_ok());
     }
 
     #[test]
-    fn given_the_same_timestamp_expect_acceleration_from_last_valid_measurement() {
-        let position_data = Arc::new(Mutex::new(Data::new()));
+    fn given_the_same_timestamp_expect_the_same_acceleration() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
         let (tx, rx) = mpsc::channel();
-        let mut imu = Imu {
-            tx: vec![tx],
-            position_data: Arc::clone(&position_data),
-            prev_position: *position_data.lock().unwrap(),
-            prev_velocity: Vector3::new(0.0, 0.0, 0.0),
-            last_valid_acceleration: Vector3::new(0.0, 0.0, 0.0),
-            noise_generator: Normal::new(0.0, 0.0).unwrap(),
-        };
+        let mut imu = imu(&kinematic_state, vec![tx].into(), 0.0);
+        kinematic_state.lock().unwrap().acceleration = Vector3::new(1.0, 2.0, 3.0);
 
-        {
-            let mut position_data = position_data.lock().unwrap();
-            position_data.x = 1.0;
-            position_data.y = 2.0;
-            position_data.z = 3.0;
-            position_data.timestamp += Duration::from_secs(1);
-        }
+        assert!(imu.step().is_ok());
         assert!(imu.step().is_ok());
 
-        let Telemetry::Acceleration(acc) = rx.recv().unwrap() else {
-            panic!("Cannot return position!");
-        };
-        approx::assert_abs_diff_eq!(acc.x, 1.0);
-        approx::assert_abs_diff_eq!(acc.y, 2.0);
-        approx::assert_abs_diff_eq!(acc.z, 3.0);
-
-        {
-            let mut position_data = position_data.lock().unwrap();
-            position_data.x = 1.5;
-            position_data.y = 1.5;
-            position_data.z = 2.0;
+        let received: Vec<Telemetry> = rx.try_iter().map(|envelope| envelope.message).collect();
+        assert_eq!(received.len(), 2);
+        for telemetry in received {
+            let Telemetry::Acceleration(acc) = telemetry else {
+                panic!("Cannot return position!");
+            };
+            approx::assert_abs_diff_eq!(acc.x, 1.0);
+            approx::assert_abs_diff_eq!(acc.y, 2.0);
+            approx::assert_abs_diff_eq!(acc.z, 3.0);
         }
-
-        assert!(imu.step().is_ok());
-        let Telemetry::Acceleration(acc) = rx.recv().unwrap() else {
-            panic!("Cannot return position!");
-        };
-        approx::assert_abs_diff_eq!(acc.x, 1.0);
-        approx::assert_abs_diff_eq!(acc.y, 2.0);
-        approx::assert_abs_diff_eq!(acc.z, 3.0);
     }
 
     #[test]
     #[timeout(10000)]
     fn given_next_timestamp_is_behind_previous_expect_imu_to_turn_off() {
-        let position_data = Arc::new(Mutex::new(Data::new()));
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
         let shutdown = Arc::new(AtomicBool::new(false));
-        let (tx, _) = mpsc::channel();
+        let (tx, _rx) = mpsc::channel();
         let two_hertz_frequency = NonZeroU32::new(2).unwrap();
         let imu = Imu::run(
-            Arc::clone(&position_data),
-            vec![tx],
+            Arc::clone(&kinematic_state),
+            vec![tx].into(),
             Arc::clone(&shutdown),
             two_hertz_frequency,
             0.0,
         );
 
-        position_data.lock().unwrap().timestamp -= Duration::new(1, 0);
+        kinematic_state.lock().unwrap().timestamp -= Duration::new(1, 0);
 
         imu.join().unwrap();
     }
 
     #[test]
     fn given_noise_enabled_expect_output_with_noise() {
-        let position_data = Arc::new(Mutex::new(Data::new()));
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
         let (tx, rx) = mpsc::channel();
-        let noise_generator = Normal::new(0.0, 5.0).unwrap();
-        let mut imu = Imu {
-            tx: vec![tx],
-            position_data: Arc::clone(&position_data),
-            prev_position: *position_data.lock().unwrap(),
-            prev_velocity: Vector3::new(0.0, 0.0, 0.0),
-            last_valid_acceleration: Vector3::new(0.0, 0.0, 0.0),
-            noise_generator,
-        };
-
-        {
-            let mut position_data = position_data.lock().unwrap();
-            position_data.x = 1.0;
-            position_data.y = 2.0;
-            position_data.z = 3.0;
-            position_data.timestamp += Duration::from_secs(1);
-        }
+        let mut imu = imu(&kinematic_state, vec![tx].into(), 5.0);
+        kinematic_state.lock().unwrap().acceleration = Vector3::new(1.0, 2.0, 3.0);
         assert!(imu.step().is_ok());
 
-        let Telemetry::Acceleration(acc) = rx.recv().unwrap() else {
+        let Telemetry::Acceleration(acc) = rx.recv().unwrap().message else {
             panic!("Cannot return position!");
         };
         approx::assert_abs_diff_ne!(acc.x, 1.0);
         approx::assert_abs_diff_ne!(acc.y, 2.0);
         approx::assert_abs_diff_ne!(acc.z, 3.0);
     }
+
+    #[test]
+    #[timeout(10000)]
+    fn given_zero_noise_expect_analytic_acceleration_of_helical_trajectory() {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (tx_groundtruth, rx_groundtruth) = mpsc::channel();
+        let (tx_imu, rx_imu) = mpsc::channel();
+        let (kinematic_state, generator) = TrajectoryGeneratorBuilder::new()
+            .with_frequency(NonZeroU32::new(100).unwrap())
+            .with_angled_helical_mode()
+            .with_publisher(vec![tx_groundtruth].into())
+            .spawn(Arc::clone(&shutdown));
+        let imu = Imu::run(
+            kinematic_state,
+            vec![tx_imu].into(),
+            Arc::clone(&shutdown),
+            NonZeroU32::new(200).unwrap(),
+            0.0,
+        );
+
+        std::thread::sleep(Duration::from_millis(300));
+        shutdown.store(true, Ordering::SeqCst);
+        generator.join().unwrap();
+        imu.join().unwrap();
+
+        let positions: HashMap<SystemTime, Data> = rx_groundtruth
+            .try_iter()
+            .map(|envelope| (envelope.measurement_time, *envelope.message.data()))
+            .collect();
+        let angular_frequency = 2.0 * PI * HELIX_FREQUENCY;
+        let mut matched_samples = 0;
+        for envelope in rx_imu.try_iter() {
+            let acc = envelope.message.data();
+            let Some(position) = positions.get(&acc.timestamp) else {
+                continue;
+            };
+            // the helix is a harmonic motion around (50, 50, 50): a = -w^2 (p - 50)
+            approx::assert_relative_eq!(
+                acc.x,
+                -angular_frequency.powi(2) * (position.x - 50.0),
+                epsilon = 1e-9
+            );
+            approx::assert_relative_eq!(
+                acc.y,
+                -angular_frequency.powi(2) * (position.y - 50.0),
+                epsilon = 1e-9
+            );
+            approx::assert_relative_eq!(
+                acc.z,
+                -angular_frequency.powi(2) * (position.z - 50.0),
+                epsilon = 1e-9
+            );
+            matched_samples += 1;
+        }
+        assert!(matched_samples > 0);
+    }
 }
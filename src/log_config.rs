@@ -1,8 +1,19 @@
 // Log names
+pub const BAROMETER_LOG: &str = "BAROMETER_LOG";
+pub const COMPLEMENTARY_LOG: &str = "COMPLEMENTARY_LOG";
 pub const GENERAL_LOG: &str = "GENERAL_LOG";
 pub const GPS_LOG: &str = "GPS_LOG";
+pub const GPS_RECEIVER_1_LOG: &str = "GPS_RECEIVER_1_LOG";
+pub const GPS_RECEIVER_2_LOG: &str = "GPS_RECEIVER_2_LOG";
+pub const GPS_SMOOTHING_LOG: &str = "GPS_SMOOTHING_LOG";
 pub const GROUNDTRUTH_LOG: &str = "GROUNDTRUTH_LOG";
+pub const IMM_LOG: &str = "IMM_LOG";
+pub const IMM_MODEL_PROBABILITIES_LOG: &str = "IMM_MODEL_PROBABILITIES_LOG";
 pub const IMU_LOG: &str = "IMU_LOG";
+pub const INFORMATION_FILTER_LOG: &str = "INFORMATION_FILTER_LOG";
 pub const INTERTIAL_NAVIGATOR_LOG: &str = "INERTIAL_NAVIGATOR_LOG";
 pub const KALMAN_LOG: &str = "KALMAN_LOG";
+pub const MAGNETOMETER_LOG: &str = "MAGNETOMETER_LOG";
 pub const MOVING_AVERAGE_LOG: &str = "MOVING_AVERAGE_LOG";
+pub const ODOMETRY_LOG: &str = "ODOMETRY_LOG";
+pub const UWB_LOG: &str = "UWB_LOG";
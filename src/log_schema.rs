@@ -0,0 +1,277 @@
+//! Rows of the CSV logs saved to `output`. Every log starts with a header row naming the columns.
+//!
+//! Telemetry logs (sensors, ground truth and estimators):
+//! `seq,source,measurement_time,receive_time,type,x,y,z`
+//! - `seq`: index of the entry within its log, in the order it was logged
+//! - `source`: name of the log the entry belongs to, e.g. `KALMAN_LOG`
+//! - `measurement_time`: timestamp carried by the data itself
+//! - `receive_time`: time the logger received the entry
+//! - `type`: `position`, `acceleration`, `altitude`, `magnetic_field`, `velocity` or `range`
+//! - `x,y,z`: values, for `altitude` the pressure in Pa, the temperature in °C and the altitude,
+//!   for `magnetic_field` the field in µT in the body frame, for `range` the range, the anchor index
+//!   and 0, the non-line-of-sight biases are ground truth, written to the general log
+//!
+//! General log: `seq,source,receive_time,message`
+//!
+//! IMM model probabilities log:
+//! `seq,source,measurement_time,receive_time,constant_velocity,constant_acceleration,coordinated_turn`
+//!
+//! Times are local, formatted as `YYYY-MM-DD hh:mm:ss.fff`.
+use std::time::SystemTime;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    data::{string_timestamp, Data, Telemetry},
+    imm::ImmModelProbabilities,
+    logger::LogEntry,
+};
+
+pub trait LogRecord: Serialize + DeserializeOwned {
+    // has to list the fields in their declaration order
+    const HEADER: &'static [&'static str];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TelemetryKind {
+    Position,
+    Acceleration,
+    Altitude,
+    #[serde(rename = "magnetic_field")]
+    MagneticField,
+    Velocity,
+    Range,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TelemetryRecord {
+    pub seq: u64,
+    pub source: String,
+    #[serde(with = "string_timestamp")]
+    pub measurement_time: SystemTime,
+    #[serde(with = "string_timestamp")]
+    pub receive_time: SystemTime,
+    #[serde(rename = "type")]
+    pub kind: TelemetryKind,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl LogRecord for TelemetryRecord {
+    const HEADER: &'static [&'static str] = &[
+        "seq",
+        "source",
+        "measurement_time",
+        "receive_time",
+        "type",
+        "x",
+        "y",
+        "z",
+    ];
+}
+
+impl TelemetryRecord {
+    // Sensors log bare data, the kind of their measurement is known only to whoever saves the log
+    pub fn from_data(seq: u64, source: &str, kind: TelemetryKind, entry: &LogEntry<Data>) -> Self {
+        Self {
+            seq,
+            source: source.to_string(),
+            measurement_time: entry.data.timestamp,
+            receive_time: entry.timestamp,
+            kind,
+            x: entry.data.x,
+            y: entry.data.y,
+            z: entry.data.z,
+        }
+    }
+
+    pub fn from_telemetry(seq: u64, source: &str, entry: &LogEntry<Telemetry>) -> Self {
+        let (kind, data) = match entry.data {
+            Telemetry::Position(data) => (TelemetryKind::Position, data),
+            Telemetry::Acceleration(data) => (TelemetryKind::Acceleration, data),
+            Telemetry::Altitude(data) => (TelemetryKind::Altitude, data),
+            Telemetry::MagneticField(data) => (TelemetryKind::MagneticField, data),
+            Telemetry::Velocity(data) => (TelemetryKind::Velocity, data),
+            Telemetry::Range(data) => (TelemetryKind::Range, data),
+        };
+        Self::from_data(
+            seq,
+            source,
+            kind,
+            &LogEntry {
+                timestamp: entry.timestamp,
+                data,
+            },
+        )
+    }
+
+    pub fn data(&self) -> Data {
+        Data {
+            x: self.x,
+            y: self.y,
+            z: self.z,
+            timestamp: self.measurement_time,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn telemetry(&self) -> Telemetry {
+        match self.kind {
+            TelemetryKind::Position => Telemetry::Position(self.data()),
+            TelemetryKind::Acceleration => Telemetry::Acceleration(self.data()),
+            TelemetryKind::Altitude => Telemetry::Altitude(self.data()),
+            TelemetryKind::MagneticField => Telemetry::MagneticField(self.data()),
+            TelemetryKind::Velocity => Telemetry::Velocity(self.data()),
+            TelemetryKind::Range => Telemetry::Range(self.data()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeneralRecord {
+    pub seq: u64,
+    pub source: String,
+    #[serde(with = "string_timestamp")]
+    pub receive_time: SystemTime,
+    pub message: String,
+}
+
+impl LogRecord for GeneralRecord {
+    const HEADER: &'static [&'static str] = &["seq", "source", "receive_time", "message"];
+}
+
+impl GeneralRecord {
+    pub fn from_message(seq: u64, source: &str, entry: &LogEntry<String>) -> Self {
+        Self {
+            seq,
+            source: source.to_string(),
+            receive_time: entry.timestamp,
+            message: entry.data.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImmProbabilitiesRecord {
+    pub seq: u64,
+    pub source: String,
+    #[serde(with = "string_timestamp")]
+    pub measurement_time: SystemTime,
+    #[serde(with = "string_timestamp")]
+    pub receive_time: SystemTime,
+    pub constant_velocity: f64,
+    pub constant_acceleration: f64,
+    pub coordinated_turn: f64,
+}
+
+impl LogRecord for ImmProbabilitiesRecord {
+    const HEADER: &'static [&'static str] = &[
+        "seq",
+        "source",
+        "measurement_time",
+        "receive_time",
+        "constant_velocity",
+        "constant_acceleration",
+        "coordinated_turn",
+    ];
+}
+
+impl ImmProbabilitiesRecord {
+    pub fn from_probabilities(
+        seq: u64,
+        source: &str,
+        entry: &LogEntry<ImmModelProbabilities>,
+    ) -> Self {
+        Self {
+            seq,
+            source: source.to_string(),
+            measurement_time: entry.data.timestamp,
+            receive_time: entry.timestamp,
+            constant_velocity: entry.data.constant_velocity,
+            constant_acceleration: entry.data.constant_acceleration,
+            coordinated_turn: entry.data.coordinated_turn,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // the header written for empty logs has to match the one derived from the fields
+    fn derived_header<R: LogRecord>(record: &R) -> Vec<String> {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.serialize(record).unwrap();
+        let content = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        content
+            .lines()
+            .next()
+            .unwrap()
+            .split(',')
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn entry<T>(data: T) -> LogEntry<T> {
+        LogEntry {
+            timestamp: SystemTime::now(),
+            data,
+        }
+    }
+
+    #[test]
+    fn headers_match_record_fields() {
+        let position = entry(Telemetry::Position(Data::new()));
+        assert_eq!(
+            derived_header(&TelemetryRecord::from_telemetry(0, "TEST", &position)),
+            TelemetryRecord::HEADER
+        );
+
+        let message = entry("message".to_string());
+        assert_eq!(
+            derived_header(&GeneralRecord::from_message(0, "TEST", &message)),
+            GeneralRecord::HEADER
+        );
+
+        let probabilities = entry(ImmModelProbabilities {
+            constant_velocity: 0.2,
+            constant_acceleration: 0.3,
+            coordinated_turn: 0.5,
+            timestamp: SystemTime::now(),
+        });
+        assert_eq!(
+            derived_header(&ImmProbabilitiesRecord::from_probabilities(
+                0,
+                "TEST",
+                &probabilities
+            )),
+            ImmProbabilitiesRecord::HEADER
+        );
+    }
+
+    #[test]
+    fn telemetry_record_keeps_type_and_both_times() {
+        let measurement_time = SystemTime::now() - Duration::from_secs(1);
+        let acceleration = entry(Telemetry::Acceleration(Data {
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+            timestamp: measurement_time,
+        }));
+
+        let record = TelemetryRecord::from_telemetry(7, "IMU_LOG", &acceleration);
+
+        assert_eq!(record.seq, 7);
+        assert_eq!(record.source, "IMU_LOG");
+        assert_eq!(record.kind, TelemetryKind::Acceleration);
+        assert_eq!(record.measurement_time, measurement_time);
+        assert_eq!(record.receive_time, acceleration.timestamp);
+        let Telemetry::Acceleration(data) = record.telemetry() else {
+            panic!("Type tag lost");
+        };
+        approx::assert_abs_diff_eq!(data.z, 3.0);
+    }
+}
@@ -0,0 +1,441 @@
+//! Background writer streaming log rows to disk while the system runs.
+//!
+//! Every component gets its own file, opened with `open` and written through the returned
+//! `LogFile`. The writer only sees encoded bytes, `csv_header` and `csv_row` encode CSV logs.
+//! Rows travel over a bounded channel, so a writer that cannot keep up slows the producers
+//! down instead of growing memory. Files are flushed whenever the channel runs empty and
+//! rotated once they grow too large or too old: the full file is renamed to
+//! `<name>.<n>.csv` and a fresh `<name>.csv` with the same header takes its place, so the
+//! latest rows are always in the file a non-streamed run would write. `log_parts` lists the
+//! files of a log in the order they were written, parts left by an earlier run are removed
+//! when the log is opened again.
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{sync_channel, Receiver, SyncSender, TryRecvError},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use csv::WriterBuilder;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RotationPolicy {
+    pub max_file_size: u64, // bytes
+    pub max_file_age: Duration,
+}
+
+enum Command {
+    Open {
+        id: usize,
+        path: PathBuf,
+        header: Vec<u8>,
+    },
+    Write {
+        id: usize,
+        row: Vec<u8>,
+    },
+    Shutdown,
+}
+
+pub struct LogWriter {
+    tx: SyncSender<Command>,
+    next_id: usize,
+    handle: JoinHandle<()>,
+}
+
+// Handle of one streamed file, writing never blocks longer than the writer needs to make room
+pub struct LogFile {
+    id: usize,
+    tx: SyncSender<Command>,
+}
+
+impl LogFile {
+    // after shutdown there is nobody left to write, the row is dropped
+    pub fn write(&self, row: Vec<u8>) {
+        let _ = self.tx.send(Command::Write { id: self.id, row });
+    }
+}
+
+impl LogWriter {
+    // `buffer_size` is the number of rows in flight before producers block
+    pub fn start(buffer_size: usize, rotation: RotationPolicy) -> Self {
+        let (tx, rx) = sync_channel(buffer_size);
+        let handle = thread::spawn(move || write_rows(rx, rotation));
+        Self {
+            tx,
+            next_id: 0,
+            handle,
+        }
+    }
+
+    // Creates (or truncates) the file and writes the header right away,
+    // every file started by a rotation begins with the same header
+    pub fn open(&mut self, path: &Path, header: Vec<u8>) -> LogFile {
+        let id = self.next_id;
+        self.next_id += 1;
+        let _ = self.tx.send(Command::Open {
+            id,
+            path: path.to_path_buf(),
+            header,
+        });
+        LogFile {
+            id,
+            tx: self.tx.clone(),
+        }
+    }
+
+    // Writes everything sent before this call and closes the files
+    pub fn finish(self) {
+        let _ = self.tx.send(Command::Shutdown);
+        if self.handle.join().is_err() {
+            eprintln!("Log writer stopped unexpectedly");
+        }
+    }
+}
+
+pub fn csv_header(header: &[&str]) -> Vec<u8> {
+    let mut writer = WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+    match writer.write_record(header) {
+        Ok(()) => writer.into_inner().unwrap_or_default(),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub fn csv_row<R: Serialize>(record: &R) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut writer = WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+    writer.serialize(record)?;
+    Ok(writer.into_inner().map_err(|e| e.to_string())?)
+}
+
+struct ActiveFile {
+    path: PathBuf,
+    header: Vec<u8>,
+    writer: BufWriter<File>,
+    size: u64,
+    opened_at: Instant,
+    rotations: u32,
+}
+
+impl ActiveFile {
+    fn create(path: PathBuf, header: Vec<u8>) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        remove_rotated_parts(&path)?;
+        let mut writer = BufWriter::new(File::create(&path)?);
+        writer.write_all(&header)?;
+        Ok(Self {
+            path,
+            size: header.len() as u64,
+            header,
+            writer,
+            opened_at: Instant::now(),
+            rotations: 0,
+        })
+    }
+
+    fn needs_rotation(&self, row_size: usize, rotation: &RotationPolicy) -> bool {
+        let has_rows = self.size > self.header.len() as u64;
+        has_rows
+            && (self.size + row_size as u64 > rotation.max_file_size
+                || self.opened_at.elapsed() >= rotation.max_file_age)
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.writer.flush()?;
+        self.rotations += 1;
+        fs::rename(&self.path, rotated_path(&self.path, self.rotations))?;
+        self.writer = BufWriter::new(File::create(&self.path)?);
+        self.writer.write_all(&self.header)?;
+        self.size = self.header.len() as u64;
+        self.opened_at = Instant::now();
+        Ok(())
+    }
+
+    fn write(&mut self, row: &[u8], rotation: &RotationPolicy) -> std::io::Result<()> {
+        if self.needs_rotation(row.len(), rotation) {
+            self.rotate()?;
+        }
+        self.writer.write_all(row)?;
+        self.size += row.len() as u64;
+        Ok(())
+    }
+}
+
+// output/kalman_log.csv rotated for the second time becomes output/kalman_log.2.csv
+pub fn rotated_path(path: &Path, rotation: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(extension) => {
+            path.with_file_name(format!("{stem}.{rotation}.{}", extension.to_string_lossy()))
+        }
+        None => path.with_file_name(format!("{stem}.{rotation}")),
+    }
+}
+
+fn rotated_parts(path: &Path) -> impl Iterator<Item = PathBuf> + '_ {
+    (1..)
+        .map(|rotation| rotated_path(path, rotation))
+        .take_while(|part| part.exists())
+}
+
+// The rotated parts of a log, oldest first, followed by the log itself
+pub fn log_parts(path: &Path) -> Vec<PathBuf> {
+    let mut parts: Vec<PathBuf> = rotated_parts(path).collect();
+    parts.push(path.to_path_buf());
+    parts
+}
+
+// Parts left by an earlier run would otherwise be read as the beginning of the new log
+pub fn remove_rotated_parts(path: &Path) -> std::io::Result<()> {
+    let parts: Vec<PathBuf> = rotated_parts(path).collect();
+    for part in parts {
+        fs::remove_file(part)?;
+    }
+    Ok(())
+}
+
+fn write_rows(rx: Receiver<Command>, rotation: RotationPolicy) {
+    let mut files: HashMap<usize, ActiveFile> = HashMap::new();
+    loop {
+        let command = match rx.try_recv() {
+            Ok(command) => command,
+            Err(TryRecvError::Empty) => {
+                flush_all(&mut files);
+                match rx.recv() {
+                    Ok(command) => command,
+                    Err(_) => break,
+                }
+            }
+            Err(TryRecvError::Disconnected) => break,
+        };
+        match command {
+            Command::Open { id, path, header } => match ActiveFile::create(path.clone(), header) {
+                Ok(file) => {
+                    files.insert(id, file);
+                }
+                Err(e) => eprintln!("Cannot create log file {}: {e}", path.display()),
+            },
+            Command::Write { id, row } => {
+                let Some(file) = files.get_mut(&id) else {
+                    continue;
+                };
+                if let Err(e) = file.write(&row, &rotation) {
+                    eprintln!("Cannot write log file {}: {e}", file.path.display());
+                    files.remove(&id);
+                }
+            }
+            Command::Shutdown => break,
+        }
+    }
+    flush_all(&mut files);
+}
+
+fn flush_all(files: &mut HashMap<usize, ActiveFile>) {
+    for file in files.values_mut() {
+        if let Err(e) = file.writer.flush() {
+            eprintln!("Cannot flush log file {}: {e}", file.path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Row {
+        seq: u64,
+        value: f64,
+    }
+
+    const HEADER: &[&str] = &["seq", "value"];
+    const NO_ROTATION: RotationPolicy = RotationPolicy {
+        max_file_size: u64::MAX,
+        max_file_age: Duration::MAX,
+    };
+
+    fn test_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("log_stream_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    #[test]
+    fn test_rotated_path() {
+        assert_eq!(
+            rotated_path(Path::new("output/imu_log.csv"), 2),
+            PathBuf::from("output/imu_log.2.csv")
+        );
+        assert_eq!(
+            rotated_path(Path::new("imu_log"), 1),
+            PathBuf::from("imu_log.1")
+        );
+    }
+
+    #[test]
+    fn test_rows_are_on_disk_after_finish() {
+        let directory = test_directory("finish");
+        let path = directory.join("test_log.csv");
+        let mut writer = LogWriter::start(4, NO_ROTATION);
+        let file = writer.open(&path, csv_header(HEADER));
+        for seq in 0..100 {
+            file.write(csv_row(&Row { seq, value: 0.5 }).unwrap());
+        }
+        writer.finish();
+
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 101);
+        assert_eq!(lines[0], "seq,value");
+        assert_eq!(lines[100], "99,0.5");
+        let _ = fs::remove_dir_all(directory);
+    }
+
+    #[test]
+    fn test_rows_are_flushed_while_running() {
+        let directory = test_directory("running");
+        let path = directory.join("test_log.csv");
+        let mut writer = LogWriter::start(4, NO_ROTATION);
+        let file = writer.open(&path, csv_header(HEADER));
+        file.write(csv_row(&Row { seq: 0, value: 1.0 }).unwrap());
+
+        let mut content = String::new();
+        for _ in 0..100 {
+            content = fs::read_to_string(&path).unwrap_or_default();
+            if content.lines().count() == 2 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(content, "seq,value\n0,1.0\n");
+        writer.finish();
+        let _ = fs::remove_dir_all(directory);
+    }
+
+    #[test]
+    fn test_empty_file_keeps_its_header() {
+        let directory = test_directory("empty");
+        let path = directory.join("test_log.csv");
+        let mut writer = LogWriter::start(4, NO_ROTATION);
+        let _file = writer.open(&path, csv_header(HEADER));
+        writer.finish();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "seq,value\n");
+        let _ = fs::remove_dir_all(directory);
+    }
+
+    #[test]
+    fn test_rotation_by_size() {
+        let directory = test_directory("size");
+        let path = directory.join("test_log.csv");
+        // header (10 bytes) and three rows of 6 bytes fit, the fourth row does not
+        let rotation = RotationPolicy {
+            max_file_size: 28,
+            max_file_age: Duration::MAX,
+        };
+        let mut writer = LogWriter::start(4, rotation);
+        let file = writer.open(&path, csv_header(HEADER));
+        for seq in 0..7 {
+            file.write(csv_row(&Row { seq, value: 1.5 }).unwrap());
+        }
+        writer.finish();
+
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 1)).unwrap(),
+            "seq,value\n0,1.5\n1,1.5\n2,1.5\n"
+        );
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 2)).unwrap(),
+            "seq,value\n3,1.5\n4,1.5\n5,1.5\n"
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "seq,value\n6,1.5\n");
+        assert!(!rotated_path(&path, 3).exists());
+        let _ = fs::remove_dir_all(directory);
+    }
+
+    #[test]
+    fn test_rotation_by_age() {
+        let directory = test_directory("age");
+        let path = directory.join("test_log.csv");
+        let rotation = RotationPolicy {
+            max_file_size: u64::MAX,
+            max_file_age: Duration::from_millis(50),
+        };
+        let mut writer = LogWriter::start(4, rotation);
+        let file = writer.open(&path, csv_header(HEADER));
+        file.write(csv_row(&Row { seq: 0, value: 1.0 }).unwrap());
+        thread::sleep(Duration::from_millis(100));
+        file.write(csv_row(&Row { seq: 1, value: 1.0 }).unwrap());
+        writer.finish();
+
+        assert_eq!(
+            fs::read_to_string(rotated_path(&path, 1)).unwrap(),
+            "seq,value\n0,1.0\n"
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), "seq,value\n1,1.0\n");
+        let _ = fs::remove_dir_all(directory);
+    }
+
+    #[test]
+    fn test_log_parts_are_in_writing_order() {
+        let directory = test_directory("parts");
+        let path = directory.join("test_log.csv");
+        let rotation = RotationPolicy {
+            max_file_size: 16,
+            max_file_age: Duration::MAX,
+        };
+        let mut writer = LogWriter::start(4, rotation);
+        let file = writer.open(&path, csv_header(HEADER));
+        for seq in 0..3 {
+            file.write(csv_row(&Row { seq, value: 1.5 }).unwrap());
+        }
+        writer.finish();
+
+        assert_eq!(
+            log_parts(&path),
+            vec![rotated_path(&path, 1), rotated_path(&path, 2), path.clone()]
+        );
+        let _ = fs::remove_dir_all(directory);
+    }
+
+    #[test]
+    fn test_parts_of_an_earlier_run_are_removed() {
+        let directory = test_directory("earlier_run");
+        let path = directory.join("test_log.csv");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(rotated_path(&path, 1), "seq,value\n0,9.0\n").unwrap();
+        fs::write(rotated_path(&path, 2), "seq,value\n1,9.0\n").unwrap();
+
+        let mut writer = LogWriter::start(4, NO_ROTATION);
+        let file = writer.open(&path, csv_header(HEADER));
+        file.write(csv_row(&Row { seq: 0, value: 1.0 }).unwrap());
+        writer.finish();
+
+        assert_eq!(log_parts(&path), vec![path.clone()]);
+        let _ = fs::remove_dir_all(directory);
+    }
+
+    #[test]
+    fn test_rows_after_finish_are_dropped() {
+        let directory = test_directory("after_finish");
+        let path = directory.join("test_log.csv");
+        let mut writer = LogWriter::start(4, NO_ROTATION);
+        let file = writer.open(&path, csv_header(HEADER));
+        writer.finish();
+        file.write(csv_row(&Row { seq: 0, value: 1.0 }).unwrap());
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "seq,value\n");
+        let _ = fs::remove_dir_all(directory);
+    }
+}
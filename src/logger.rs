@@ -2,7 +2,7 @@ use std::{
     any::Any,
     collections::HashMap,
     fmt::Debug,
-    sync::{mpsc, Arc, Mutex},
+    sync::{mpsc, Arc, Condvar, Mutex},
     thread,
     time::SystemTime,
 };
@@ -15,27 +15,48 @@ pub struct LogEntry<T> {
     pub data: T,
 }
 
+// Receives every entry of a streamed component, in logging order
+pub type LogSink<T> = Box<dyn FnMut(&LogEntry<T>) + Send>;
+
+struct LogStorage<T> {
+    entries: Vec<LogEntry<T>>,
+    sink: Option<LogSink<T>>,
+    retain: bool,
+    pending: usize, // sent to the storing thread, not stored yet
+}
+
 pub struct Logger<T: Send + Clone + Debug + 'static> {
     sender: mpsc::Sender<LogEntry<T>>, 
-    data_storage: Arc<Mutex<Vec<LogEntry<T>>>>,
+    data_storage: Arc<Mutex<LogStorage<T>>>,
+    stored: Arc<Condvar>,
 }
 
 impl<T: Send + Clone + Debug + 'static> Logger<T> {
     fn new() -> Self {
         let (tx, rx) = mpsc::channel::<LogEntry<T>>();
-        let data_storage = Arc::new(Mutex::new(Vec::new()));
+        let data_storage = Arc::new(Mutex::new(LogStorage {
+            entries: Vec::new(),
+            sink: None,
+            retain: true,
+            pending: 0,
+        }));
+        let stored = Arc::new(Condvar::new());
         let storage_clone = Arc::clone(&data_storage);
+        let stored_clone = Arc::clone(&stored);
 
         thread::spawn(move || {
             for entry in rx {
                 let mut storage = storage_clone.lock().unwrap();
-                storage.push(entry);
+                storage.entries.push(entry);
+                storage.pending -= 1;
+                stored_clone.notify_all();
             }
         });
 
         Logger {
             sender: tx,
             data_storage,
+            stored,
         }
     }
 
@@ -44,11 +65,40 @@ impl<T: Send + Clone + Debug + 'static> Logger<T> {
             timestamp: SystemTime::now(),
             data,
         };
+        {
+            // streamed entries are handed over on the caller thread, so they are
+            // all with the sink once the caller's log call has returned
+            let mut storage = self.data_storage.lock().unwrap();
+            if let Some(sink) = &mut storage.sink {
+                sink(&entry);
+                if !storage.retain {
+                    return;
+                }
+            }
+            storage.pending += 1;
+        }
         let _ = self.sender.send(entry);
     }
 
+    // Waits for the entries still on their way to the storage, so the sink gets them first
+    fn stream(&self, mut sink: LogSink<T>, retain: bool) {
+        let mut storage = self
+            .stored
+            .wait_while(self.data_storage.lock().unwrap(), |storage| {
+                storage.pending > 0
+            })
+            .unwrap();
+        if retain {
+            storage.entries.iter().for_each(&mut sink);
+        } else {
+            storage.entries.drain(..).for_each(|entry| sink(&entry));
+        }
+        storage.sink = Some(sink);
+        storage.retain = retain;
+    }
+
     fn get_data(&self) -> Vec<LogEntry<T>> {
-        self.data_storage.lock().unwrap().clone()
+        self.data_storage.lock().unwrap().entries.clone()
     }
 }
 
@@ -74,6 +124,16 @@ pub fn log<T: Send + Clone + Debug + Sync + 'static>(component_name: &str, data:
     logger.log(data);
 }
 
+// Hands the component's entries over to the sink as they are logged, including the ones
+// logged so far. Without `retain` they are no longer kept in memory for `get_data`.
+pub fn stream<T: Send + Clone + Debug + Sync + 'static>(
+    component_name: &str,
+    sink: LogSink<T>,
+    retain: bool,
+) {
+    get_or_create_logger::<T>(component_name).stream(sink, retain);
+}
+
 pub fn get_data<T: Send + Clone + Debug + Sync + 'static>(
     component_name: &str,
 ) -> Option<Vec<LogEntry<T>>> {
@@ -122,4 +182,65 @@ mod tests {
         assert_eq!(string_data[0].data, "string data");
         assert_eq!(number_data[0].data, 42);
     }
+
+    #[test]
+    fn test_streamed_component_is_not_kept_in_memory() {
+        let streamed = Arc::new(Mutex::new(Vec::new()));
+        let streamed_clone = Arc::clone(&streamed);
+        log("streamed_component", 1);
+        thread::sleep(Duration::from_millis(10));
+
+        stream::<i32>(
+            "streamed_component",
+            Box::new(move |entry| streamed_clone.lock().unwrap().push(entry.data)),
+            false,
+        );
+        log("streamed_component", 2);
+        log("streamed_component", 3);
+
+        assert_eq!(*streamed.lock().unwrap(), vec![1, 2, 3]);
+        thread::sleep(Duration::from_millis(10));
+        assert!(get_data::<i32>("streamed_component").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_retained_streamed_component_is_kept_in_memory() {
+        let streamed = Arc::new(Mutex::new(Vec::new()));
+        let streamed_clone = Arc::clone(&streamed);
+
+        stream::<i32>(
+            "retained_component",
+            Box::new(move |entry| streamed_clone.lock().unwrap().push(entry.data)),
+            true,
+        );
+        log("retained_component", 7);
+
+        assert_eq!(*streamed.lock().unwrap(), vec![7]);
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(get_data::<i32>("retained_component").unwrap()[0].data, 7);
+    }
+
+    #[test]
+    fn test_entries_logged_just_before_streaming_reach_the_sink_in_order() {
+        for retain in [false, true] {
+            let component = format!("just_streamed_component_{retain}");
+            let streamed = Arc::new(Mutex::new(Vec::new()));
+            let streamed_clone = Arc::clone(&streamed);
+            for value in 0..100 {
+                log(&component, value);
+            }
+
+            stream::<i32>(
+                &component,
+                Box::new(move |entry| streamed_clone.lock().unwrap().push(entry.data)),
+                retain,
+            );
+            log(&component, 100);
+
+            assert_eq!(*streamed.lock().unwrap(), (0..=100).collect::<Vec<_>>());
+            thread::sleep(Duration::from_millis(10));
+            let kept = get_data::<i32>(&component).unwrap().len();
+            assert_eq!(kept, if retain { 101 } else { 0 });
+        }
+    }
 }
@@ -0,0 +1,295 @@
+use crate::{
+    bus::Publisher,
+    data::{Data, KinematicState, Telemetry},
+    log_config::{GENERAL_LOG, MAGNETOMETER_LOG},
+    logger::log,
+    periodic_runner,
+    utils::get_cycle_duration,
+};
+use std::{
+    error::Error,
+    num::NonZeroU32,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::SystemTime,
+};
+
+use nalgebra::{Matrix3, Vector3};
+use rand::rng;
+use rand_distr::{Distribution, Normal};
+
+pub mod calibration;
+
+// Earth magnetic field at the simulated site
+#[derive(Debug, Clone, Copy)]
+pub struct EarthFieldConfig {
+    pub intensity: f64,   // µT
+    pub declination: f64, // degrees, east of true north
+    pub inclination: f64, // degrees, below the horizontal
+}
+
+impl EarthFieldConfig {
+    // The field vector in east, north, up
+    pub fn enu(&self) -> Vector3<f64> {
+        let (declination, inclination) =
+            (self.declination.to_radians(), self.inclination.to_radians());
+        let horizontal = self.intensity * inclination.cos();
+        Vector3::new(
+            horizontal * declination.sin(),
+            horizontal * declination.cos(),
+            -self.intensity * inclination.sin(),
+        )
+    }
+}
+
+// Distortion of the sensor, measured = soft_iron * true + hard_iron + noise, in the body frame
+#[derive(Debug, Clone, Copy)]
+pub struct MagnetometerConfig {
+    pub hard_iron: [f64; 3],      // µT
+    pub soft_iron: [[f64; 3]; 3], // rows of the matrix
+    pub noise_sigma: f64,         // µT
+}
+
+impl MagnetometerConfig {
+    fn hard_iron(&self) -> Vector3<f64> {
+        Vector3::from(self.hard_iron)
+    }
+
+    fn soft_iron(&self) -> Matrix3<f64> {
+        Matrix3::from_fn(|row, column| self.soft_iron[row][column])
+    }
+}
+
+pub struct Magnetometer {
+    tx: Publisher<Telemetry>,
+    kinematic_state: Arc<Mutex<KinematicState>>,
+    prev_timestamp: SystemTime,
+    earth_field: Vector3<f64>,
+    hard_iron: Vector3<f64>,
+    soft_iron: Matrix3<f64>,
+    noise_generator: Normal<f64>,
+}
+
+impl Magnetometer {
+    pub fn run(
+        kinematic_state: Arc<Mutex<KinematicState>>,
+        tx: Publisher<Telemetry>,
+        shutdown: Arc<AtomicBool>,
+        frequency: NonZeroU32,
+        earth_field: EarthFieldConfig,
+        config: MagnetometerConfig,
+    ) -> JoinHandle<()> {
+        let mut magnetometer = Magnetometer::new(kinematic_state, tx, earth_field, config);
+        std::thread::spawn(move || {
+            if let Err(e) = periodic_runner::run_periodicaly(
+                || magnetometer.step(),
+                || shutdown.load(Ordering::SeqCst),
+                get_cycle_duration(frequency),
+            ) {
+                eprintln!("Magnetometer internal error: {e}. Aborting.")
+            }
+
+            log(GENERAL_LOG, "Magnetometer removed".to_string());
+        })
+    }
+
+    fn new(
+        kinematic_state: Arc<Mutex<KinematicState>>,
+        tx: Publisher<Telemetry>,
+        earth_field: EarthFieldConfig,
+        config: MagnetometerConfig,
+    ) -> Magnetometer {
+        let prev_timestamp = kinematic_state.lock().unwrap().timestamp;
+        Magnetometer {
+            tx,
+            kinematic_state,
+            prev_timestamp,
+            earth_field: earth_field.enu(),
+            hard_iron: config.hard_iron(),
+            soft_iron: config.soft_iron(),
+            noise_generator: Normal::new(0.0, config.noise_sigma).unwrap(),
+        }
+    }
+
+    // The log is what the calibration reads, so samples are logged even when nobody subscribes
+    fn step(&mut self) -> Result<(), Box<dyn Error>> {
+        let current_state = *self.kinematic_state.lock().unwrap();
+
+        current_state
+            .timestamp
+            .duration_since(self.prev_timestamp)?;
+        self.prev_timestamp = current_state.timestamp;
+
+        let body_field = current_state.body_rotation().inverse() * self.earth_field;
+        let measured = self.soft_iron * body_field + self.hard_iron;
+        let data_to_send = Data {
+            x: measured.x + self.noise_generator.sample(&mut rng()),
+            y: measured.y + self.noise_generator.sample(&mut rng()),
+            z: measured.z + self.noise_generator.sample(&mut rng()),
+            timestamp: current_state.timestamp,
+        };
+
+        log(MAGNETOMETER_LOG, data_to_send);
+        self.tx.publish(Telemetry::MagneticField(data_to_send));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntest_timeout::timeout;
+    use std::{f64::consts::FRAC_PI_2, sync::mpsc, time::Duration};
+
+    const NORTH_FIELD: EarthFieldConfig = EarthFieldConfig {
+        intensity: 50.0,
+        declination: 0.0,
+        inclination: 0.0,
+    };
+
+    const UNDISTORTED: MagnetometerConfig = MagnetometerConfig {
+        hard_iron: [0.0; 3],
+        soft_iron: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        noise_sigma: 0.0,
+    };
+
+    fn magnetometer(
+        kinematic_state: &Arc<Mutex<KinematicState>>,
+        tx: Publisher<Telemetry>,
+        config: MagnetometerConfig,
+    ) -> Magnetometer {
+        Magnetometer::new(Arc::clone(kinematic_state), tx, NORTH_FIELD, config)
+    }
+
+    fn measured_field(rx: &mpsc::Receiver<crate::envelope::Envelope<Telemetry>>) -> Data {
+        let Telemetry::MagneticField(data) = rx.recv().unwrap().message else {
+            panic!("Magnetometer should provide the magnetic field.");
+        };
+        data
+    }
+
+    #[test]
+    fn earth_field_follows_declination_and_inclination() {
+        let field = EarthFieldConfig {
+            intensity: 50.0,
+            declination: 90.0,
+            inclination: 60.0,
+        }
+        .enu();
+        approx::assert_abs_diff_eq!(field.x, 25.0, epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(field.y, 0.0, epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(field.z, -50.0 * 60f64.to_radians().sin(), epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(field.norm(), 50.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn given_vehicle_heading_north_expect_field_ahead() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, rx) = mpsc::channel();
+        let mut magnetometer = magnetometer(&kinematic_state, vec![tx].into(), UNDISTORTED);
+        kinematic_state.lock().unwrap().attitude = Vector3::new(0.0, 0.0, FRAC_PI_2);
+
+        assert!(magnetometer.step().is_ok());
+
+        let field = measured_field(&rx);
+        approx::assert_abs_diff_eq!(field.x, 50.0, epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(field.y, 0.0, epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(field.z, 0.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn given_vehicle_heading_east_expect_north_on_the_left() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, rx) = mpsc::channel();
+        let mut magnetometer = magnetometer(&kinematic_state, vec![tx].into(), UNDISTORTED);
+        kinematic_state.lock().unwrap().attitude = Vector3::zeros();
+
+        assert!(magnetometer.step().is_ok());
+
+        let field = measured_field(&rx);
+        approx::assert_abs_diff_eq!(field.x, 0.0, epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(field.y, 50.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn given_vehicle_rolled_expect_field_to_follow_the_attitude() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, rx) = mpsc::channel();
+        let mut magnetometer = magnetometer(&kinematic_state, vec![tx].into(), UNDISTORTED);
+        // heading east with the left side up, the body z axis points south
+        kinematic_state.lock().unwrap().attitude = Vector3::new(FRAC_PI_2, 0.0, 0.0);
+        kinematic_state.lock().unwrap().velocity = Vector3::new(0.0, 3.0, 0.0);
+
+        assert!(magnetometer.step().is_ok());
+
+        let field = measured_field(&rx);
+        approx::assert_abs_diff_eq!(field.x, 0.0, epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(field.y, 0.0, epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(field.z, -50.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn given_distortion_expect_soft_and_hard_iron_applied() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, rx) = mpsc::channel();
+        let distorted = MagnetometerConfig {
+            hard_iron: [1.0, -2.0, 3.0],
+            soft_iron: [[1.2, 0.1, 0.0], [0.1, 0.9, 0.0], [0.0, 0.0, 1.0]],
+            noise_sigma: 0.0,
+        };
+        let mut magnetometer = magnetometer(&kinematic_state, vec![tx].into(), distorted);
+        kinematic_state.lock().unwrap().attitude = Vector3::new(0.0, 0.0, FRAC_PI_2);
+
+        assert!(magnetometer.step().is_ok());
+
+        let field = measured_field(&rx);
+        approx::assert_abs_diff_eq!(field.x, 1.2 * 50.0 + 1.0, epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(field.y, 0.1 * 50.0 - 2.0, epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(field.z, 3.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn given_noise_enabled_expect_output_with_noise() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, rx) = mpsc::channel();
+        let noisy = MagnetometerConfig {
+            noise_sigma: 1.0,
+            ..UNDISTORTED
+        };
+        let mut magnetometer = magnetometer(&kinematic_state, vec![tx].into(), noisy);
+
+        assert!(magnetometer.step().is_ok());
+        approx::assert_abs_diff_ne!(measured_field(&rx).y, 50.0);
+    }
+
+    #[test]
+    fn given_next_timestamp_is_behind_previous_expect_step_to_fail() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, _rx) = mpsc::channel();
+        let mut magnetometer = magnetometer(&kinematic_state, vec![tx].into(), UNDISTORTED);
+        kinematic_state.lock().unwrap().timestamp -= Duration::from_secs(1);
+
+        assert!(magnetometer.step().is_err());
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn given_shutdown_signal_expect_magnetometer_to_stop_without_subscribers() {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let handle = Magnetometer::run(
+            Arc::new(Mutex::new(KinematicState::new())),
+            Vec::new().into(),
+            Arc::clone(&shutdown),
+            NonZeroU32::new(10).unwrap(),
+            NORTH_FIELD,
+            UNDISTORTED,
+        );
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(!handle.is_finished());
+        shutdown.store(true, Ordering::SeqCst);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,216 @@
+//! Hard- and soft-iron calibration of a magnetometer from a recorded log.
+//!
+//! Undistorted measurements of a constant field lie on a sphere, hard iron shifts it and soft
+//! iron deforms it into an ellipsoid. The quadric
+//! `a x² + b y² + c z² + 2f yz + 2g xz + 2h xy + 2p x + 2q y + 2r z = 1` is fitted to the
+//! measurements in the least-squares sense; its center is the hard-iron offset and its shape
+//! gives the soft-iron matrix. Only the symmetric part of the soft iron can be recovered,
+//! a rotation of the sensor axes looks like an undistorted sensor.
+use std::{error::Error, path::Path};
+
+use nalgebra::{DMatrix, DVector, Matrix3, Vector3};
+
+use crate::csv_handler::read_data_log;
+
+// Fewer measurements cannot determine the nine coefficients of the quadric
+const MIN_SAMPLES: usize = 9;
+// Relative size of the smallest singular value below which the orientations are too few
+const RANK_TOLERANCE: f64 = 1e-9;
+
+#[derive(Debug)]
+pub struct CalibrationError(pub String);
+
+impl std::fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Magnetometer calibration failed: {}", self.0)
+    }
+}
+
+impl Error for CalibrationError {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MagnetometerCalibration {
+    pub hard_iron: Vector3<f64>,
+    pub soft_iron: Matrix3<f64>,
+    correction: Matrix3<f64>, // inverse of the soft iron
+    pub rms_residual: f64,    // of the corrected field strength, µT
+}
+
+impl MagnetometerCalibration {
+    pub fn correct(&self, measurement: &Vector3<f64>) -> Vector3<f64> {
+        self.correction * (measurement - self.hard_iron)
+    }
+}
+
+impl std::fmt::Display for MagnetometerCalibration {
+    #[rustfmt::skip]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let h = &self.hard_iron;
+        let s = &self.soft_iron;
+        writeln!(f, "hard_iron: [{:.4}, {:.4}, {:.4}]", h.x, h.y, h.z)?;
+        write!(
+            f,
+            "soft_iron: [[{:.4}, {:.4}, {:.4}], [{:.4}, {:.4}, {:.4}], [{:.4}, {:.4}, {:.4}]]",
+            s[(0, 0)], s[(0, 1)], s[(0, 2)],
+            s[(1, 0)], s[(1, 1)], s[(1, 2)],
+            s[(2, 0)], s[(2, 1)], s[(2, 2)],
+        )?;
+        write!(f, "\nfield strength residual: {:.4} µT RMS", self.rms_residual)
+    }
+}
+
+// The field strength scales the soft iron, use the intensity of the Earth field at the site
+pub fn calibrate(
+    measurements: &[Vector3<f64>],
+    field_strength: f64,
+) -> Result<MagnetometerCalibration, CalibrationError> {
+    if measurements.len() < MIN_SAMPLES {
+        return Err(CalibrationError(format!(
+            "{} measurements, at least {MIN_SAMPLES} needed",
+            measurements.len()
+        )));
+    }
+    let design = DMatrix::from_fn(measurements.len(), 9, |row, column| {
+        let m = &measurements[row];
+        match column {
+            0 => m.x * m.x,
+            1 => m.y * m.y,
+            2 => m.z * m.z,
+            3 => 2.0 * m.y * m.z,
+            4 => 2.0 * m.x * m.z,
+            5 => 2.0 * m.x * m.y,
+            column => 2.0 * m[column - 6],
+        }
+    });
+    let svd = design.svd(true, true);
+    if svd.rank(svd.singular_values.max() * RANK_TOLERANCE) < 9 {
+        return Err(CalibrationError(
+            "the measurements do not cover enough orientations".to_string(),
+        ));
+    }
+    let v = svd
+        .solve(&DVector::from_element(measurements.len(), 1.0), 0.0)
+        .map_err(|e| CalibrationError(e.to_string()))?;
+
+    #[rustfmt::skip]
+    let quadratic = Matrix3::new(
+        v[0], v[5], v[4],
+        v[5], v[1], v[3],
+        v[4], v[3], v[2],
+    );
+    let linear = Vector3::new(v[6], v[7], v[8]);
+    let hard_iron = -quadratic
+        .try_inverse()
+        .ok_or_else(|| CalibrationError("the fitted quadric has no center".to_string()))?
+        * linear;
+    // (m - hard_iron)' shape (m - hard_iron) = 1
+    let shape = quadratic / (1.0 + (hard_iron.transpose() * quadratic * hard_iron)[(0, 0)]);
+    if shape.cholesky().is_none() {
+        return Err(CalibrationError(
+            "the fitted quadric is not an ellipsoid".to_string(),
+        ));
+    }
+
+    let eigen = shape.symmetric_eigen();
+    let correction = eigen.eigenvectors
+        * Matrix3::from_diagonal(&eigen.eigenvalues.map(f64::sqrt))
+        * eigen.eigenvectors.transpose()
+        * field_strength;
+    let soft_iron = correction
+        .try_inverse()
+        .ok_or_else(|| CalibrationError("the fitted soft iron is singular".to_string()))?;
+    let mut calibration = MagnetometerCalibration {
+        hard_iron,
+        soft_iron,
+        correction,
+        rms_residual: 0.0,
+    };
+    let squared_residuals: f64 = measurements
+        .iter()
+        .map(|measurement| (calibration.correct(measurement).norm() - field_strength).powi(2))
+        .sum();
+    calibration.rms_residual = (squared_residuals / measurements.len() as f64).sqrt();
+    Ok(calibration)
+}
+
+pub fn calibrate_from_log(
+    path: &Path,
+    field_strength: f64,
+) -> Result<MagnetometerCalibration, Box<dyn Error>> {
+    let measurements: Vec<Vector3<f64>> = read_data_log(path)?
+        .iter()
+        .map(|data| Vector3::new(data.x, data.y, data.z))
+        .collect();
+    Ok(calibrate(&measurements, field_strength)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Rotation3;
+
+    #[rustfmt::skip]
+    fn soft_iron() -> Matrix3<f64> {
+        Matrix3::new(
+            1.10, 0.05, -0.02,
+            0.05, 0.92, 0.03,
+            -0.02, 0.03, 1.04,
+        )
+    }
+
+    // The Earth field seen from orientations spread over the whole sphere
+    fn distorted_measurements(hard_iron: Vector3<f64>) -> Vec<Vector3<f64>> {
+        let field = Vector3::new(0.0, 20.0, -45.0);
+        let mut measurements = Vec::new();
+        for roll in 0..6 {
+            for pitch in 0..6 {
+                for yaw in 0..8 {
+                    let attitude = Rotation3::from_euler_angles(
+                        roll as f64 * 1.1,
+                        pitch as f64 * 0.6 - 1.5,
+                        yaw as f64 * 0.8,
+                    );
+                    measurements.push(soft_iron() * (attitude.inverse() * field) + hard_iron);
+                }
+            }
+        }
+        measurements
+    }
+
+    #[test]
+    fn given_distorted_measurements_expect_distortion_recovered() {
+        let hard_iron = Vector3::new(12.0, -7.5, 3.0);
+        let field_strength = Vector3::new(0.0, 20.0, -45.0).norm();
+
+        let calibration = calibrate(&distorted_measurements(hard_iron), field_strength).unwrap();
+
+        approx::assert_relative_eq!(calibration.hard_iron, hard_iron, epsilon = 1e-6);
+        approx::assert_relative_eq!(calibration.soft_iron, soft_iron(), epsilon = 1e-6);
+        for measurement in distorted_measurements(hard_iron) {
+            approx::assert_relative_eq!(
+                calibration.correct(&measurement).norm(),
+                field_strength,
+                epsilon = 1e-6
+            );
+        }
+        approx::assert_abs_diff_eq!(calibration.rms_residual, 0.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn given_too_few_measurements_expect_error() {
+        let measurements = vec![Vector3::new(1.0, 0.0, 0.0); MIN_SAMPLES - 1];
+        assert!(calibrate(&measurements, 50.0).is_err());
+    }
+
+    #[test]
+    fn given_single_plane_of_orientations_expect_error() {
+        // turning about the vertical axis only leaves the ellipsoid undetermined
+        let measurements: Vec<Vector3<f64>> = (0..36)
+            .map(|step| {
+                let yaw = step as f64 * 10f64.to_radians();
+                Vector3::new(20.0 * yaw.cos(), 20.0 * yaw.sin(), -45.0)
+            })
+            .collect();
+        assert!(calibrate(&measurements, 50.0).is_err());
+    }
+}
@@ -1,173 +1,435 @@
 use std::{
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc, Arc, Mutex,
+        mpsc::{self, Receiver},
+        Arc, Mutex,
     },
     thread::JoinHandle,
     time::SystemTime,
 };
 
 use crate::{
-    communication_registry::{CommunicationRegistry, DataSource},
+    binary_log::convert_to_csv,
+    bridge::BridgeReceiver,
+    bus::{Bus, DataSource, LinkMonitor, Publisher},
     config::*,
-    data::{Data, Telemetry},
+    data::{KinematicState, Telemetry},
+    envelope::Envelope,
     estimator_builder::EstimatorBuilder,
+    imm::ImmConfig,
+    kalman::KalmanTimeReference,
+    information_filter::GpsInput,
     logger::log,
     log_config::*,
+    log_stream::LogWriter,
+    mcap::MCAP_EXTENSION,
+    nmea::NmeaSink,
+    queue::QueueConfig,
     sensor_builder::SensorBuilder,
     trajectory_generator::TrajectoryGeneratorBuilder,
     csv_handler::*,
+    replay::{Replay, ReplaySpeed},
     visualization::{PlotterReceivers, real_time_visualization::RealTimeVisualization, static_visualization::StaticVisualization},
 };
 
 use estimators::kalman;
 use estimators::inertial_navigator;
+use estimators::imm;
+use estimators::information_filter;
+use estimators::complementary;
+use estimators::gps_smoothing;
+use estimators::kalman_tuning::{self, TuningObjective};
 
 mod average;
-mod communication_registry;
+mod barometer;
+mod binary_log;
+mod bridge;
+mod bus;
 mod config;
+mod coordinates;
 mod csv_handler;
 pub mod data;
+mod envelope;
 mod estimator_builder;
 mod gps;
 mod imu;
 mod log_config;
+mod log_schema;
+mod log_stream;
+mod mcap;
+mod nmea;
+mod odometry;
 mod logger;
+mod magnetometer;
 mod sensor_builder;
 mod trajectory_generator;
 mod utils;
+mod uwb;
 mod visualization;
 mod periodic_runner;
+mod queue;
+mod replay;
 mod estimators;
 
-#[allow(unused)]
+#[allow(unused, clippy::enum_variant_names)]
 #[derive(Debug)]
 enum Error {
     StartupError(&'static str),
+    TuningError(String),
+    CalibrationError(String),
+    ReplayError(String),
+    ConversionError(String),
+}
+
+// Estimators and sensors only start when someone listens to them
+fn publisher_with_subscribers(
+    bus: &Bus,
+    source: DataSource,
+    error: &'static str,
+) -> Result<Publisher<Telemetry>, Error> {
+    let publisher = bus.publisher(&source.topic());
+    if publisher.subscriber_count() == 0 {
+        return Err(Error::StartupError(error));
+    }
+    Ok(publisher)
+}
+
+// One receiver merging the telemetry of several sources
+fn subscribe_to(bus: &Bus, sources: &[DataSource]) -> Receiver<Envelope<Telemetry>> {
+    let (tx, rx) = mpsc::channel();
+    for source in sources {
+        bus.subscribe_with(&source.topic(), tx.clone());
+    }
+    rx
+}
+
+fn log_topics(bus: &Bus) {
+    for topic in bus.topics() {
+        log(
+            GENERAL_LOG,
+            format!(
+                "Topic {} ({}): {} publishers, {} subscribers",
+                topic.name, topic.message_type, topic.publishers, topic.subscribers
+            ),
+        );
+    }
+}
+
+// Dropped messages on a bounded subscription point at a consumer that cannot keep up
+fn report_links(link_monitor: &LinkMonitor) {
+    for report in link_monitor.reports() {
+        println!("{report}");
+        log(GENERAL_LOG, format!("Link {report}"));
+    }
 }
 
 fn start_imu(
-    trajectory_data: Arc<Mutex<Data>>,
-    communication_registry: &mut CommunicationRegistry,
+    trajectory_data: Arc<Mutex<KinematicState>>,
+    bus: &Bus,
     shutdown: Arc<AtomicBool>,
 ) -> Result<JoinHandle<()>, Error> {
-    let Some(subscribers) = communication_registry.get_registered_transmitters(DataSource::Imu)
-    else {
-        return Err(Error::StartupError(
-            "No subscribers for IMU. Start aborted.",
-        ));
-    };
+    let publisher = publisher_with_subscribers(
+        bus,
+        DataSource::Imu,
+        "No subscribers for IMU. Start aborted.",
+    )?;
     Ok(SensorBuilder::new_imu()
         .with_frequency(IMU_FREQ)
         .with_position_generator(trajectory_data)
-        .with_subscribers(subscribers)
+        .with_publisher(publisher)
         .with_output_noise(IMU_OUTPUT_NOISE_SIGMA)
         .spawn(shutdown))
 }
 
 fn start_gps(
-    trajectory_data: Arc<Mutex<Data>>,
-    communication_registry: &mut CommunicationRegistry,
+    trajectory_data: Arc<Mutex<KinematicState>>,
+    bus: &Bus,
     shutdown: Arc<AtomicBool>,
 ) -> Result<JoinHandle<()>, Error> {
-    let Some(subscribers) = communication_registry.get_registered_transmitters(DataSource::Gps)
-    else {
-        return Err(Error::StartupError(
-            "No subscribers for GPS. Start aborted.",
-        ));
-    };
-    Ok(SensorBuilder::new_gps()
+    let publisher = publisher_with_subscribers(
+        bus,
+        DataSource::Gps,
+        "No subscribers for GPS. Start aborted.",
+    )?;
+    let mut gps = SensorBuilder::new_gps()
         .with_frequency(GPS_FREQ)
         .with_position_generator(trajectory_data)
-        .with_subscribers(subscribers)
-        .with_output_noise(GPS_OUTPUT_NOISE_SIGMA)
+        .with_publisher(publisher)
+        .with_output_noise(GPS_OUTPUT_NOISE_SIGMA);
+    if let Some(nmea_output) = GPS_NMEA_OUTPUT {
+        let nmea_sink = NmeaSink::open(nmea_output)
+            .map_err(|_| Error::StartupError("GPS NMEA output could not be opened"))?;
+        if let Some(address) = nmea_sink.tcp_address() {
+            log(
+                GENERAL_LOG,
+                format!("GPS NMEA sentences served on {address}"),
+            );
+        }
+        gps = gps.with_nmea_output(nmea_sink);
+    }
+    Ok(gps.spawn(shutdown))
+}
+
+fn start_barometer(
+    trajectory_data: Arc<Mutex<KinematicState>>,
+    bus: &Bus,
+    shutdown: Arc<AtomicBool>,
+) -> Result<JoinHandle<()>, Error> {
+    let publisher = publisher_with_subscribers(
+        bus,
+        DataSource::Barometer,
+        "No subscribers for barometer. Start aborted.",
+    )?;
+    Ok(SensorBuilder::new_barometer()
+        .with_frequency(BAROMETER_FREQ)
+        .with_position_generator(trajectory_data)
+        .with_publisher(publisher)
+        .with_barometer_config(BAROMETER_CONFIG)
         .spawn(shutdown))
 }
 
-fn start_kalman(
-    communication_registry: &mut CommunicationRegistry,
+fn start_odometry(
+    trajectory_data: Arc<Mutex<KinematicState>>,
+    bus: &Bus,
+    shutdown: Arc<AtomicBool>,
 ) -> Result<JoinHandle<()>, Error> {
-    let (tx_imu, input_rx) = mpsc::channel();
-    let tx_gps = tx_imu.clone();
-    communication_registry.register_for_input(DataSource::Imu, tx_imu);
-    communication_registry.register_for_input(DataSource::Gps, tx_gps);
+    let publisher = publisher_with_subscribers(
+        bus,
+        DataSource::Odometry,
+        "No subscribers for wheel odometry. Start aborted.",
+    )?;
+    Ok(SensorBuilder::new_odometry()
+        .with_frequency(ODOMETRY_FREQ)
+        .with_position_generator(trajectory_data)
+        .with_publisher(publisher)
+        .with_odometry_config(ODOMETRY_CONFIG)
+        .spawn(shutdown))
+}
 
-    match communication_registry.get_registered_transmitters(DataSource::Kalman) {
-        Some(subscribers) => Ok(EstimatorBuilder::new_kalman()
-            .with_subscribers(subscribers)
-            .with_input_rx(input_rx)
-            .spawn()),
-        None => Err(Error::StartupError(
-            "No subscribers for Kalman. Start aborted.",
-        )),
+fn start_uwb(
+    trajectory_data: Arc<Mutex<KinematicState>>,
+    bus: &Bus,
+    shutdown: Arc<AtomicBool>,
+) -> Result<JoinHandle<()>, Error> {
+    let publisher = publisher_with_subscribers(
+        bus,
+        DataSource::Uwb,
+        "No subscribers for UWB ranging. Start aborted.",
+    )?;
+    Ok(SensorBuilder::new_uwb()
+        .with_frequency(UWB_FREQ)
+        .with_position_generator(trajectory_data)
+        .with_publisher(publisher)
+        .with_uwb_anchors(&UWB_ANCHORS)
+        .with_uwb_config(UWB_CONFIG)
+        .spawn(shutdown))
+}
+
+// Nothing consumes the magnetic field yet, the magnetometer runs for its log
+fn start_magnetometer(
+    trajectory_data: Arc<Mutex<KinematicState>>,
+    bus: &Bus,
+    shutdown: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    SensorBuilder::new_magnetometer()
+        .with_frequency(MAGNETOMETER_FREQ)
+        .with_position_generator(trajectory_data)
+        .with_publisher(bus.publisher(&DataSource::Magnetometer.topic()))
+        .with_earth_field(EARTH_MAGNETIC_FIELD)
+        .with_magnetometer_config(MAGNETOMETER_CONFIG)
+        .spawn(shutdown)
+}
+
+fn start_gps_receivers(
+    trajectory_data: Arc<Mutex<KinematicState>>,
+    bus: &Bus,
+    shutdown: Arc<AtomicBool>,
+) -> Result<Vec<JoinHandle<()>>, Error> {
+    let mut handles = Vec::new();
+    for (index, gps_receiver) in GPS_RECEIVERS.iter().enumerate() {
+        let publisher = publisher_with_subscribers(
+            bus,
+            DataSource::GpsReceiver(index as u8),
+            "No subscribers for GPS receiver. Start aborted.",
+        )?;
+        handles.push(
+            SensorBuilder::new_gps()
+                .with_frequency(gps_receiver.frequency)
+                .with_position_generator(Arc::clone(&trajectory_data))
+                .with_publisher(publisher)
+                .with_output_noise(gps_receiver.noise_sigma)
+                .with_log_name(gps_receiver.log_name)
+                .spawn(Arc::clone(&shutdown)),
+        );
     }
+    Ok(handles)
+}
+
+fn start_kalman(
+    bus: &Bus,
+    time_reference: KalmanTimeReference,
+) -> Result<JoinHandle<()>, Error> {
+    let input_rx = subscribe_to(
+        bus,
+        &[
+            DataSource::Imu,
+            DataSource::Gps,
+            DataSource::Barometer,
+            DataSource::Odometry,
+            DataSource::Uwb,
+        ],
+    );
+
+    let publisher = publisher_with_subscribers(
+        bus,
+        DataSource::Kalman,
+        "No subscribers for Kalman. Start aborted.",
+    )?;
+    Ok(EstimatorBuilder::new_kalman()
+        .with_kalman_time_reference(time_reference)
+        .with_publisher(publisher)
+        .with_input_rx(input_rx)
+        .spawn())
 }
 
 fn start_avg_filter(
-    communication_registry: &mut CommunicationRegistry,
+    bus: &Bus,
 ) -> Result<JoinHandle<()>, Error> {
-    let (tx, input_rx) = mpsc::channel();
-    communication_registry.register_for_input(DataSource::Gps, tx);
+    let input_rx = bus.subscribe(&DataSource::Gps.topic());
 
-    match communication_registry.get_registered_transmitters(DataSource::Average) {
-        Some(subscribers) => Ok(EstimatorBuilder::new_average(BUFFER_LENGTH)
-            .with_subscribers(subscribers)
-            .with_input_rx(input_rx)
-            .spawn()),
-        None => Err(Error::StartupError(
-            "No subscribers for Average filter. Start aborted.",
-        )),
-    }
+    let publisher = publisher_with_subscribers(
+        bus,
+        DataSource::Average,
+        "No subscribers for Average filter. Start aborted.",
+    )?;
+    Ok(EstimatorBuilder::new_average(BUFFER_LENGTH)
+        .with_publisher(publisher)
+        .with_input_rx(input_rx)
+        .spawn())
 }
 
 fn start_inertial_navigator(
-    communication_registry: &mut CommunicationRegistry,
+    bus: &Bus,
+) -> Result<JoinHandle<()>, Error> {
+    let input_rx = subscribe_to(bus, &[DataSource::Imu, DataSource::Gps]);
+
+    let publisher = publisher_with_subscribers(
+        bus,
+        DataSource::InertialNavigator,
+        "No subscribers for Average filter. Start aborted.",
+    )?;
+    Ok(EstimatorBuilder::new_inertial_navigator()
+        .with_publisher(publisher)
+        .with_input_rx(input_rx)
+        .spawn())
+}
+
+fn start_imm(
+    bus: &Bus,
 ) -> Result<JoinHandle<()>, Error> {
-    let (tx_imu, input_rx) = mpsc::channel();
-    let tx_gps = tx_imu.clone();
-    communication_registry.register_for_input(DataSource::Imu, tx_imu);
-    communication_registry.register_for_input(DataSource::Gps, tx_gps);
+    let input_rx = subscribe_to(bus, &[DataSource::Imu, DataSource::Gps]);
 
-    match communication_registry.get_registered_transmitters(DataSource::InertialNavigator) {
-        Some(subscribers) => Ok(EstimatorBuilder::new_inertial_navigator()
-            .with_subscribers(subscribers)
-            .with_input_rx(input_rx)
-            .spawn()),
-        None => Err(Error::StartupError(
-            "No subscribers for Average filter. Start aborted.",
-        )),
+    let publisher = publisher_with_subscribers(
+        bus,
+        DataSource::Imm,
+        "No subscribers for IMM. Start aborted.",
+    )?;
+    Ok(EstimatorBuilder::new_imm(ImmConfig::default())
+        .with_publisher(publisher)
+        .with_input_rx(input_rx)
+        .spawn())
+}
+
+fn start_gps_smoothing(
+    bus: &Bus,
+) -> Result<JoinHandle<()>, Error> {
+    let input_rx = bus.subscribe(&DataSource::Gps.topic());
+
+    let publisher = publisher_with_subscribers(
+        bus,
+        DataSource::GpsSmoothing,
+        "No subscribers for GPS smoothing filter. Start aborted.",
+    )?;
+    Ok(EstimatorBuilder::new_gps_smoothing(GPS_SMOOTHING_FILTER)
+        .map_err(|_| Error::StartupError("Invalid GPS smoothing filter. Start aborted."))?
+        .with_publisher(publisher)
+        .with_input_rx(input_rx)
+        .spawn())
+}
+
+fn start_complementary_filter(
+    bus: &Bus,
+) -> Result<JoinHandle<()>, Error> {
+    let input_rx = subscribe_to(bus, &[DataSource::Imu, DataSource::Gps]);
+
+    let publisher = publisher_with_subscribers(
+        bus,
+        DataSource::Complementary,
+        "No subscribers for Complementary filter. Start aborted.",
+    )?;
+    Ok(EstimatorBuilder::new_complementary(COMPLEMENTARY_CROSSOVER_FREQUENCY)
+        .with_publisher(publisher)
+        .with_input_rx(input_rx)
+        .spawn())
+}
+
+fn start_information_filter(
+    bus: &Bus,
+) -> Result<JoinHandle<()>, Error> {
+    let input_rx = bus.subscribe(&DataSource::Imu.topic());
+
+    let mut gps_inputs = vec![GpsInput::new(
+        bus.subscribe(&DataSource::Gps.topic()),
+        GPS_OUTPUT_NOISE_SIGMA,
+    )];
+    for (index, gps_receiver) in GPS_RECEIVERS.iter().enumerate() {
+        let rx_gps_receiver = bus.subscribe(&DataSource::GpsReceiver(index as u8).topic());
+        gps_inputs.push(GpsInput::new(rx_gps_receiver, gps_receiver.noise_sigma));
     }
+
+    let publisher = publisher_with_subscribers(
+        bus,
+        DataSource::InformationFilter,
+        "No subscribers for Information filter. Start aborted.",
+    )?;
+    Ok(EstimatorBuilder::new_information_filter()
+        .with_publisher(publisher)
+        .with_input_rx(input_rx)
+        .with_gps_inputs(gps_inputs)
+        .spawn())
 }
 
-fn start_static_visualization(
-    communication_registry: &mut CommunicationRegistry,
-    simulation_start: SystemTime,
-) -> JoinHandle<()> {
-    let (tx_avg, rx_avg) = mpsc::channel();
-    let (tx_kalman, rx_kalman) = mpsc::channel();
-    let (tx_gps, rx_gps) = mpsc::channel();
-    let (tx_inertial, rx_inertial) = mpsc::channel();
-    let (tx_groundtruth, rx_groundtruth) = mpsc::channel();
-    communication_registry.register_for_input(DataSource::Average, tx_avg);
-    communication_registry.register_for_input(DataSource::Kalman, tx_kalman);
-    communication_registry.register_for_input(DataSource::Gps, tx_gps);
-    communication_registry.register_for_input(DataSource::InertialNavigator, tx_inertial);
-    communication_registry.register_for_input(DataSource::Groundtruth, tx_groundtruth);
+fn subscribe_plotter(bus: &Bus, plotter: &'static str, config: QueueConfig) -> PlotterReceivers {
+    let subscribe = |source: DataSource| bus.subscribe_queued(&source.topic(), plotter, config);
+    PlotterReceivers::new(
+        subscribe(DataSource::Gps),
+        subscribe(DataSource::Average),
+        subscribe(DataSource::Kalman),
+        subscribe(DataSource::InertialNavigator),
+        subscribe(DataSource::Imm),
+        subscribe(DataSource::InformationFilter),
+        subscribe(DataSource::Complementary),
+        subscribe(DataSource::GpsSmoothing),
+        subscribe(DataSource::Groundtruth),
+    )
+}
 
-    StaticVisualization::run(PlotterReceivers::new(rx_gps, rx_avg, rx_kalman, rx_inertial, rx_groundtruth), simulation_start)
+fn start_static_visualization(bus: &Bus, simulation_start: SystemTime) -> JoinHandle<()> {
+    StaticVisualization::run(
+        subscribe_plotter(bus, "static plot", STATIC_PLOT_QUEUE),
+        simulation_start,
+    )
 }
 
 fn start_trajectory_generator(
-    consumer_registry: &mut CommunicationRegistry,
+    bus: &Bus,
     shutdown_trigger: Arc<AtomicBool>,
-) -> (Arc<Mutex<Data>>, JoinHandle<()>) {
-    let subscribers = consumer_registry
-        .get_registered_transmitters(DataSource::Groundtruth)
-        .unwrap_or_default();
+) -> (Arc<Mutex<KinematicState>>, JoinHandle<()>) {
     TrajectoryGeneratorBuilder::new()
         .with_frequency(GENERATOR_FREQ)
         .with_perlin_mode()
-        .with_subscribers(subscribers)
+        .with_publisher(bus.publisher(&DataSource::Groundtruth.topic()))
         .spawn(Arc::clone(&shutdown_trigger))
 }
 
@@ -175,58 +437,343 @@ fn system_shutdown(shutdown_trigger: Arc<AtomicBool>) {
     shutdown_trigger.store(true, Ordering::SeqCst);
 }
 
-fn register_dynamic_plot(
-    communication_registry: &mut CommunicationRegistry,
-) -> (PlotterReceivers, SystemTime) {
-    let simulation_start = SystemTime::now();
-    let (tx_gps, rx_gps) = mpsc::channel();
-    let (tx_avg, rx_avg) = mpsc::channel();
-    let (tx_kalman, rx_kalman) = mpsc::channel();
-    let (tx_inertial, rx_inertial) = mpsc::channel();
-    let (tx_groundtruth, rx_groundtruth) = mpsc::channel();
+fn register_dynamic_plot(bus: &Bus) -> (PlotterReceivers, SystemTime) {
+    (
+        subscribe_plotter(bus, "real-time plot", REAL_TIME_PLOT_QUEUE),
+        SystemTime::now(),
+    )
+}
 
-    communication_registry.register_for_input(DataSource::Gps, tx_gps);
-    communication_registry.register_for_input(DataSource::Average, tx_avg);
-    communication_registry.register_for_input(DataSource::Kalman, tx_kalman);
-    communication_registry.register_for_input(DataSource::InertialNavigator, tx_inertial);
-    communication_registry.register_for_input(DataSource::Groundtruth, tx_groundtruth);
+// Usage: --tune [logs directory] [rmse|nees]
+fn run_kalman_tuning(arguments: &[String]) -> Result<(), Error> {
+    let directory = arguments.first().map_or(OUTPUT_PATH, String::as_str);
+    let objective = match arguments.get(1).map(String::as_str) {
+        Some("rmse") | None => TuningObjective::Rmse,
+        Some("nees") => TuningObjective::NeesDeviation,
+        Some(objective) => {
+            return Err(Error::TuningError(format!(
+                "Unknown objective {objective}, expected rmse or nees"
+            )));
+        }
+    };
 
-    (
-        PlotterReceivers::new(
-            rx_gps,
-            rx_avg,
-            rx_kalman,
-            rx_inertial,
-            rx_groundtruth,
+    let result = kalman_tuning::tune(Path::new(directory), objective)
+        .map_err(|e| Error::TuningError(e.to_string()))?;
+    println!(
+        "Best Kalman parameters ({objective}: {}): KALMAN_GPS_SIGMA = {}, KALMAN_ACC_SIGMA = {}",
+        result.cost, result.parameters.gps_sigma, result.parameters.acc_sigma
+    );
+    Ok(())
+}
+
+// Usage: --calibrate-magnetometer [logs directory]
+fn run_magnetometer_calibration(arguments: &[String]) -> Result<(), Error> {
+    let directory = arguments.first().map_or(OUTPUT_PATH, String::as_str);
+    let path = log_path_in(Path::new(directory), MAGNETOMETER_LOG);
+    let calibration =
+        magnetometer::calibration::calibrate_from_log(&path, EARTH_MAGNETIC_FIELD.intensity)
+            .map_err(|e| Error::CalibrationError(e.to_string()))?;
+    println!(
+        "Magnetometer calibration of {}:\n{calibration}",
+        path.display()
+    );
+    Ok(())
+}
+
+// Usage: --convert <binary log> [csv file], by default next to the binary log
+fn run_conversion(arguments: &[String]) -> Result<(), Error> {
+    let input = Path::new(
+        arguments
+            .first()
+            .ok_or_else(|| Error::ConversionError("No binary log given".to_string()))?,
+    );
+    let output = arguments
+        .get(1)
+        .map_or_else(|| input.with_extension(CSV_EXTENSION), PathBuf::from);
+
+    let converted =
+        convert_to_csv(input, &output).map_err(|e| Error::ConversionError(e.to_string()))?;
+    println!(
+        "{converted} records of {} converted to {}",
+        input.display(),
+        output.display()
+    );
+    Ok(())
+}
+
+// Estimator outputs are only logged during a replay, the receivers just keep the estimators running
+fn register_replay_outputs(bus: &Bus) -> Vec<Receiver<Envelope<Telemetry>>> {
+    [
+        DataSource::Kalman,
+        DataSource::Average,
+        DataSource::InertialNavigator,
+        DataSource::Imm,
+        DataSource::InformationFilter,
+        DataSource::Complementary,
+        DataSource::GpsSmoothing,
+    ]
+    .into_iter()
+    .map(|source| bus.subscribe(&source.topic()))
+    .collect()
+}
+
+fn start_logging() -> Option<LogWriter> {
+    if !LOG_STREAMING {
+        return None;
+    }
+    let mut log_writer = LogWriter::start(LOG_STREAM_BUFFER_SIZE, LOG_ROTATION);
+    stream_logs_to_files(
+        &mut log_writer,
+        Path::new(OUTPUT_PATH),
+        LOG_TELEMETRY_FORMAT,
+    );
+    Some(log_writer)
+}
+
+fn finish_logging(log_writer: Option<LogWriter>) {
+    match log_writer {
+        Some(log_writer) => log_writer.finish(),
+        None => save_logs_to_file(),
+    }
+}
+
+fn start_mcap_recording(bus: &Bus) -> Result<Option<JoinHandle<std::io::Result<usize>>>, Error> {
+    if !MCAP_RECORDING {
+        return Ok(None);
+    }
+    let path = Path::new(OUTPUT_PATH).join(format!("recording.{MCAP_EXTENSION}"));
+    mcap::start_recording(bus, &path)
+        .map(Some)
+        .map_err(|_| Error::StartupError("MCAP recording could not be started"))
+}
+
+fn finish_mcap_recording(mcap_handle: Option<JoinHandle<std::io::Result<usize>>>) {
+    match mcap_handle.map(|handle| handle.join()) {
+        Some(Ok(Ok(recorded))) => println!("{recorded} messages recorded to MCAP"),
+        Some(Ok(Err(e))) => eprintln!("MCAP recording failed: {e}"),
+        Some(Err(_)) => eprintln!("MCAP recording stopped unexpectedly"),
+        None => {}
+    }
+}
+
+// Forwards the plotted topics to a plotting process, with --bridge [address]
+fn start_bridge(bus: &Bus, target: Option<&str>) -> Result<Option<JoinHandle<usize>>, Error> {
+    let Some(target) = target else {
+        return Ok(None);
+    };
+    bridge::start_sender(bus, &BRIDGE_SOURCES, target)
+        .map(Some)
+        .map_err(|_| Error::StartupError("UDP bridge could not be started"))
+}
+
+// Usage: --remote-plot [address], plots what a process started with --bridge forwards
+fn run_remote_plot(arguments: &[String]) -> Result<(), Error> {
+    let address = arguments.first().map_or(BRIDGE_ADDRESS, String::as_str);
+    let receiver = BridgeReceiver::bind(address)
+        .map_err(|_| Error::StartupError("UDP bridge receiver could not be bound"))?;
+    let address = receiver
+        .local_addr()
+        .map_err(|_| Error::StartupError("UDP bridge receiver could not be bound"))?;
+    let log_writer = start_logging();
+    log(GENERAL_LOG, format!("Remote plot listening on {address}"));
+
+    let bus = Bus::new();
+    let shutdown_trigger = Arc::new(AtomicBool::new(false));
+    let (receivers, simulation_start) = register_dynamic_plot(&bus);
+    let static_visu_handle = start_static_visualization(&bus, simulation_start);
+    let receiver_handle = receiver.run(&bus, &BRIDGE_SOURCES, Arc::clone(&shutdown_trigger));
+    log_topics(&bus);
+    let link_monitor = bus.link_monitor();
+    drop(bus);
+
+    RealTimeVisualization::run(receivers, simulation_start);
+    system_shutdown(shutdown_trigger);
+
+    let statistics = receiver_handle.join().unwrap();
+    println!("UDP bridge: {statistics}");
+    static_visu_handle.join().unwrap();
+    report_links(&link_monitor);
+
+    finish_logging(log_writer);
+    Ok(())
+}
+
+// Usage: --export-mcap [logs directory] [mcap file], by default recording.mcap in the logs directory
+fn run_mcap_export(arguments: &[String]) -> Result<(), Error> {
+    let directory = Path::new(arguments.first().map_or(OUTPUT_PATH, String::as_str));
+    let output = arguments.get(1).map_or_else(
+        || directory.join(format!("recording.{MCAP_EXTENSION}")),
+        PathBuf::from,
+    );
+
+    let exported =
+        mcap::export_logs(directory, &output).map_err(|e| Error::ConversionError(e.to_string()))?;
+    println!(
+        "{exported} messages of {} exported to {}",
+        directory.display(),
+        output.display()
+    );
+    Ok(())
+}
+
+fn replay_speed(argument: Option<&String>) -> ReplaySpeed {
+    match argument.map(String::as_str) {
+        Some("--fast") => ReplaySpeed::AsFastAsPossible,
+        _ => ReplaySpeed::RealTime,
+    }
+}
+
+// Usage: --replay [logs directory] [--fast]
+fn run_replay(arguments: &[String]) -> Result<(), Error> {
+    let directory = arguments.first().map_or(OUTPUT_PATH, String::as_str);
+    let replay =
+        Replay::load(Path::new(directory)).map_err(|e| Error::ReplayError(e.to_string()))?;
+    replay_through_estimators(replay, directory, replay_speed(arguments.get(1)))
+}
+
+// Usage: --replay-nmea <NMEA log> [--fast], a GPS-only replay of externally recorded fixes
+fn run_nmea_replay(arguments: &[String]) -> Result<(), Error> {
+    let path = arguments
+        .first()
+        .ok_or_else(|| Error::ReplayError("No NMEA log given".to_string()))?;
+    let replay =
+        Replay::load_nmea(Path::new(path)).map_err(|e| Error::ReplayError(e.to_string()))?;
+    replay_through_estimators(replay, path, replay_speed(arguments.get(1)))
+}
+
+fn replay_through_estimators(
+    replay: Replay,
+    origin: &str,
+    speed: ReplaySpeed,
+) -> Result<(), Error> {
+    // the recording is in memory now, so the logs may be streamed over it
+    let log_writer = start_logging();
+
+    log(
+        GENERAL_LOG,
+        format!(
+            "Replay of {} messages from {origin} start",
+            replay.message_count()
         ),
-        simulation_start,
-    )
+    );
+    let bus = Bus::new();
+    let estimator_outputs = register_replay_outputs(&bus);
+    let mcap_handle = start_mcap_recording(&bus)?;
+
+    let estimator_handles = [
+        // replayed samples arrive faster than recorded, their own timestamps keep the IMU rate
+        start_kalman(&bus, KalmanTimeReference::Measurement)?,
+        start_avg_filter(&bus)?,
+        start_inertial_navigator(&bus)?,
+        start_imm(&bus)?,
+        start_information_filter(&bus)?,
+        start_complementary_filter(&bus)?,
+        start_gps_smoothing(&bus)?,
+    ];
+    let replay_handle = replay.run(&bus, speed);
+    log_topics(&bus);
+    // release the inputs nobody replays (e.g. a missing GPS receiver log)
+    drop(bus);
+
+    replay_handle.join().unwrap();
+    for estimator_handle in estimator_handles {
+        estimator_handle.join().unwrap();
+    }
+    drop(estimator_outputs);
+    finish_mcap_recording(mcap_handle);
+
+    finish_logging(log_writer);
+    Ok(())
 }
 
 fn main() -> Result<(), Error> {
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    if arguments.first().map(String::as_str) == Some("--tune") {
+        return run_kalman_tuning(&arguments[1..]);
+    }
+    if arguments.first().map(String::as_str) == Some("--replay") {
+        return run_replay(&arguments[1..]);
+    }
+    if arguments.first().map(String::as_str) == Some("--replay-nmea") {
+        return run_nmea_replay(&arguments[1..]);
+    }
+    if arguments.first().map(String::as_str) == Some("--calibrate-magnetometer") {
+        return run_magnetometer_calibration(&arguments[1..]);
+    }
+    if arguments.first().map(String::as_str) == Some("--convert") {
+        return run_conversion(&arguments[1..]);
+    }
+    if arguments.first().map(String::as_str) == Some("--export-mcap") {
+        return run_mcap_export(&arguments[1..]);
+    }
+    if arguments.first().map(String::as_str) == Some("--remote-plot") {
+        return run_remote_plot(&arguments[1..]);
+    }
+    // Usage: --bridge [address], runs as usual and forwards the plotted topics over UDP
+    let bridge_target = match arguments.first().map(String::as_str) {
+        Some("--bridge") => Some(arguments.get(1).map_or(BRIDGE_ADDRESS, String::as_str)),
+        _ => None,
+    };
+
+    let log_writer = start_logging();
     log(GENERAL_LOG, "System start".to_string());
-    let mut communication_registry = CommunicationRegistry::new();
+    let bus = Bus::new();
     let shutdown_trigger = Arc::new(AtomicBool::new(false));
-    let (receivers, simulation_start) = register_dynamic_plot(&mut communication_registry);
-    let static_visu_handle = start_static_visualization(&mut communication_registry, simulation_start);
+    let (receivers, simulation_start) = register_dynamic_plot(&bus);
+    let mcap_handle = start_mcap_recording(&bus)?;
+    let bridge_handle = start_bridge(&bus, bridge_target)?;
+    let static_visu_handle = start_static_visualization(&bus, simulation_start);
 
     let (generated_data_handle, generator_handle) =
-        start_trajectory_generator(&mut communication_registry, Arc::clone(&shutdown_trigger));
+        start_trajectory_generator(&bus, Arc::clone(&shutdown_trigger));
 
-    let kalman_handle = start_kalman(&mut communication_registry)?;
-    let avg_handle = start_avg_filter(&mut communication_registry)?;
-    let inertial_navigator_handle = start_inertial_navigator(&mut communication_registry)?;
+    let kalman_handle = start_kalman(&bus, KALMAN_TIME_REFERENCE)?;
+    let avg_handle = start_avg_filter(&bus)?;
+    let inertial_navigator_handle = start_inertial_navigator(&bus)?;
+    let imm_handle = start_imm(&bus)?;
+    let information_filter_handle = start_information_filter(&bus)?;
+    let complementary_handle = start_complementary_filter(&bus)?;
+    let gps_smoothing_handle = start_gps_smoothing(&bus)?;
 
     let imu_handle = start_imu(
         Arc::clone(&generated_data_handle),
-        &mut communication_registry,
+        &bus,
         Arc::clone(&shutdown_trigger),
     )?;
     let gps_handle = start_gps(
         Arc::clone(&generated_data_handle),
-        &mut communication_registry,
+        &bus,
+        Arc::clone(&shutdown_trigger),
+    )?;
+    let barometer_handle = start_barometer(
+        Arc::clone(&generated_data_handle),
+        &bus,
         Arc::clone(&shutdown_trigger),
     )?;
+    let odometry_handle = start_odometry(
+        Arc::clone(&generated_data_handle),
+        &bus,
+        Arc::clone(&shutdown_trigger),
+    )?;
+    let uwb_handle = start_uwb(
+        Arc::clone(&generated_data_handle),
+        &bus,
+        Arc::clone(&shutdown_trigger),
+    )?;
+    let magnetometer_handle = start_magnetometer(
+        Arc::clone(&generated_data_handle),
+        &bus,
+        Arc::clone(&shutdown_trigger),
+    );
+    let gps_receiver_handles = start_gps_receivers(
+        Arc::clone(&generated_data_handle),
+        &bus,
+        Arc::clone(&shutdown_trigger),
+    )?;
+    log_topics(&bus);
+    let link_monitor = bus.link_monitor();
+    // every topic is wired, the streams end once their publishers stop
+    drop(bus);
 
     RealTimeVisualization::run(receivers, simulation_start);
     system_shutdown(Arc::clone(&shutdown_trigger));
@@ -234,12 +781,31 @@ fn main() -> Result<(), Error> {
     generator_handle.join().unwrap();
     imu_handle.join().unwrap();
     gps_handle.join().unwrap();
+    barometer_handle.join().unwrap();
+    magnetometer_handle.join().unwrap();
+    odometry_handle.join().unwrap();
+    uwb_handle.join().unwrap();
+    for gps_receiver_handle in gps_receiver_handles {
+        gps_receiver_handle.join().unwrap();
+    }
     kalman_handle.join().unwrap();
     avg_handle.join().unwrap();
     inertial_navigator_handle.join().unwrap();
+    imm_handle.join().unwrap();
+    information_filter_handle.join().unwrap();
+    complementary_handle.join().unwrap();
+    gps_smoothing_handle.join().unwrap();
     static_visu_handle.join().unwrap();
+    finish_mcap_recording(mcap_handle);
+    if let Some(bridge_handle) = bridge_handle {
+        println!(
+            "{} messages forwarded over UDP",
+            bridge_handle.join().unwrap()
+        );
+    }
+    report_links(&link_monitor);
 
-    save_logs_to_file();
+    finish_logging(log_writer);
 
     Ok(())
 }
@@ -254,7 +820,7 @@ mod tests {
 
     #[test]
     fn imu_startup_without_subscriber_fails() {
-        let mut communication_registry = CommunicationRegistry::new();
+        let bus = Bus::new();
         let shutdown_trigger = Arc::new(AtomicBool::new(false));
         let (generated_data_handle, _) = TrajectoryGeneratorBuilder::new()
             .with_random_mode()
@@ -262,7 +828,7 @@ mod tests {
             .spawn(Arc::clone(&shutdown_trigger));
         let result = start_imu(
             Arc::clone(&generated_data_handle),
-            &mut communication_registry,
+            &bus,
             Arc::clone(&shutdown_trigger),
         );
         assert!(result.is_err());
@@ -270,7 +836,7 @@ mod tests {
 
     #[test]
     fn gps_startup_without_subscriber_fails() {
-        let mut communication_registry = CommunicationRegistry::new();
+        let bus = Bus::new();
         let shutdown_trigger = Arc::new(AtomicBool::new(false));
         let (generated_data_handle, _) = TrajectoryGeneratorBuilder::new()
             .with_perlin_mode()
@@ -278,47 +844,97 @@ mod tests {
             .spawn(Arc::clone(&shutdown_trigger));
         let result = start_gps(
             Arc::clone(&generated_data_handle),
-            &mut communication_registry,
+            &bus,
             Arc::clone(&shutdown_trigger),
         );
         assert!(result.is_err());
     }
 
+    #[test]
+    fn tuning_with_unknown_objective_fails() {
+        let arguments = [OUTPUT_PATH.to_string(), "mae".to_string()];
+        assert!(matches!(
+            run_kalman_tuning(&arguments),
+            Err(Error::TuningError(_))
+        ));
+    }
+
     #[test]
     fn kalman_startup_without_subscriber_fails() {
-        let mut communication_registry = CommunicationRegistry::new();
-        let result = start_kalman(&mut communication_registry);
+        let bus = Bus::new();
+        let result = start_kalman(&bus, KALMAN_TIME_REFERENCE);
         assert!(result.is_err());
     }
 
     #[test]
     fn avg_startup_without_subscriber_fails() {
-        let mut communication_registry = CommunicationRegistry::new();
-        let result = start_avg_filter(&mut communication_registry);
+        let bus = Bus::new();
+        let result = start_avg_filter(&bus);
         assert!(result.is_err());
     }
 
     #[test]
     fn intertial_nav_startup_without_subscriber_fails() {
-        let mut communication_registry = CommunicationRegistry::new();
-        let result = start_inertial_navigator(&mut communication_registry);
+        let bus = Bus::new();
+        let result = start_inertial_navigator(&bus);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn imm_startup_without_subscriber_fails() {
+        let bus = Bus::new();
+        let result = start_imm(&bus);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn information_filter_startup_without_subscriber_fails() {
+        let bus = Bus::new();
+        let result = start_information_filter(&bus);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn complementary_startup_without_subscriber_fails() {
+        let bus = Bus::new();
+        let result = start_complementary_filter(&bus);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn gps_smoothing_startup_without_subscriber_fails() {
+        let bus = Bus::new();
+        let result = start_gps_smoothing(&bus);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn gps_receivers_startup_without_subscriber_fails() {
+        let bus = Bus::new();
+        let shutdown_trigger = Arc::new(AtomicBool::new(false));
+        let generated_data_handle = Arc::new(Mutex::new(KinematicState::new()));
+        let result = start_gps_receivers(
+            generated_data_handle,
+            &bus,
+            Arc::clone(&shutdown_trigger),
+        );
         assert!(result.is_err());
     }
 
     #[test]
     fn imu_startup_with_subscriber_suceeds() {
         let (tx, _) = mpsc::channel();
-        let mut communication_registry = CommunicationRegistry::new();
+        let bus = Bus::new();
         let shutdown_trigger = Arc::new(AtomicBool::new(false));
         let (generated_data_handle, _) = TrajectoryGeneratorBuilder::new()
             .with_perlin_mode()
             .with_frequency(GENERATOR_FREQ)
             .spawn(Arc::clone(&shutdown_trigger));
 
-        communication_registry.register_for_input(DataSource::Imu, tx);
+        bus.subscribe_with(&DataSource::Imu.topic(), tx);
         let result = start_imu(
             Arc::clone(&generated_data_handle),
-            &mut communication_registry,
+            &bus,
             Arc::clone(&shutdown_trigger),
         );
 
@@ -329,17 +945,17 @@ mod tests {
     #[test]
     fn gps_startup_with_subscriber_suceeds() {
         let (tx, _) = mpsc::channel();
-        let mut communication_registry = CommunicationRegistry::new();
+        let bus = Bus::new();
         let shutdown_trigger = Arc::new(AtomicBool::new(false));
         let (generated_data_handle, _) = TrajectoryGeneratorBuilder::new()
             .with_perlin_mode()
             .with_frequency(GENERATOR_FREQ)
             .spawn(Arc::clone(&shutdown_trigger));
 
-        communication_registry.register_for_input(DataSource::Gps, tx);
+        bus.subscribe_with(&DataSource::Gps.topic(), tx);
         let result = start_gps(
             Arc::clone(&generated_data_handle),
-            &mut communication_registry,
+            &bus,
             Arc::clone(&shutdown_trigger),
         );
 
@@ -350,10 +966,10 @@ mod tests {
     #[test]
     fn avg_startup_with_subscriber_suceeds() {
         let (tx, _) = mpsc::channel();
-        let mut communication_registry = CommunicationRegistry::new();
+        let bus = Bus::new();
 
-        communication_registry.register_for_input(DataSource::Average, tx);
-        let result = start_avg_filter(&mut communication_registry);
+        bus.subscribe_with(&DataSource::Average.topic(), tx);
+        let result = start_avg_filter(&bus);
 
         assert!(result.is_ok());
     }
@@ -361,10 +977,10 @@ mod tests {
     #[test]
     fn kalman_startup_with_subscriber_suceeds() {
         let (tx, _) = mpsc::channel();
-        let mut communication_registry = CommunicationRegistry::new();
+        let bus = Bus::new();
 
-        communication_registry.register_for_input(DataSource::Kalman, tx);
-        let result = start_kalman(&mut communication_registry);
+        bus.subscribe_with(&DataSource::Kalman.topic(), tx);
+        let result = start_kalman(&bus, KALMAN_TIME_REFERENCE);
 
         assert!(result.is_ok());
     }
@@ -372,12 +988,100 @@ mod tests {
     #[test]
     fn inertial_nav_startup_with_subscriber_suceeds() {
         let (tx, _) = mpsc::channel();
-        let mut communication_registry = CommunicationRegistry::new();
+        let bus = Bus::new();
+
+        bus.subscribe_with(&DataSource::InertialNavigator.topic(), tx);
+        let result = start_inertial_navigator(&bus);
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn imm_startup_with_subscriber_suceeds() {
+        let (tx, _) = mpsc::channel();
+        let bus = Bus::new();
+
+        bus.subscribe_with(&DataSource::Imm.topic(), tx);
+        let result = start_imm(&bus);
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn information_filter_startup_with_subscriber_suceeds() {
+        let (tx, _) = mpsc::channel();
+        let bus = Bus::new();
 
-        communication_registry.register_for_input(DataSource::InertialNavigator, tx);
-        let result = start_inertial_navigator(&mut communication_registry);
+        bus.subscribe_with(&DataSource::InformationFilter.topic(), tx);
+        let result = start_information_filter(&bus);
 
         assert!(result.is_ok());
+        for index in 0..GPS_RECEIVERS.len() {
+            let topic = DataSource::GpsReceiver(index as u8).topic_name();
+            assert!(bus
+                .topics()
+                .iter()
+                .any(|info| info.name == topic && info.subscribers == 1));
+        }
+    }
+
+    #[test]
+    fn complementary_startup_with_subscriber_suceeds() {
+        let (tx, _) = mpsc::channel();
+        let bus = Bus::new();
+
+        bus.subscribe_with(&DataSource::Complementary.topic(), tx);
+        let result = start_complementary_filter(&bus);
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn gps_smoothing_startup_with_subscriber_suceeds() {
+        let (tx, _) = mpsc::channel();
+        let bus = Bus::new();
+
+        bus.subscribe_with(&DataSource::GpsSmoothing.topic(), tx);
+        let result = start_gps_smoothing(&bus);
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn gps_receivers_startup_with_subscribers_suceeds() {
+        let bus = Bus::new();
+        let shutdown_trigger = Arc::new(AtomicBool::new(false));
+        let generated_data_handle = Arc::new(Mutex::new(KinematicState::new()));
+        let mut receivers = Vec::new();
+        for index in 0..GPS_RECEIVERS.len() {
+            let (tx, rx) = mpsc::channel();
+            bus.subscribe_with(&DataSource::GpsReceiver(index as u8).topic(), tx);
+            receivers.push(rx);
+        }
+
+        let handles = start_gps_receivers(
+            generated_data_handle,
+            &bus,
+            Arc::clone(&shutdown_trigger),
+        )
+        .unwrap();
+        for rx in &receivers {
+            assert!(rx.recv().is_ok());
+        }
+
+        shutdown_trigger.store(true, Ordering::SeqCst);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn kalman_tuning_without_logs_fails() {
+        let arguments = vec!["test_output_missing_logs".to_string()];
+        assert!(matches!(
+            run_kalman_tuning(&arguments),
+            Err(Error::TuningError(_))
+        ));
     }
 
     #[test]
@@ -388,13 +1092,13 @@ mod tests {
             .with_frequency(GENERATOR_FREQ)
             .spawn(Arc::clone(&shutdown_trigger));
 
-        let mut communication_registry = CommunicationRegistry::new();
+        let bus = Bus::new();
         let (tx, rx) = mpsc::channel();
-        communication_registry.register_for_input(DataSource::Imu, tx);
+        bus.subscribe_with(&DataSource::Imu.topic(), tx);
 
         let test_producer_handle = start_imu(
             Arc::clone(&generated_data_handle),
-            &mut communication_registry,
+            &bus,
             Arc::clone(&shutdown_trigger),
         );
 
@@ -0,0 +1,571 @@
+//! MCAP recording of the telemetry channels, for external inspection tools.
+//!
+//! Files are written unchunked and without a summary section, which every MCAP reader accepts.
+//! Every `DataSource` becomes a channel with JSON messages described by a JSON schema:
+//! `{"type": "position" | "acceleration" | "altitude" | "magnetic_field" | "velocity" | "range", "x", "y", "z", "measurement_time_ns"}`.
+//! The log time of a message is when it was recorded, the publish time when it entered the bus.
+//! Logs converted to MCAP only keep when the logger received a message, used for both times.
+//! The timestamp of the measurement itself is `measurement_time_ns`.
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::mpsc::{self, Sender},
+    thread::{self, JoinHandle},
+    time::SystemTime,
+};
+
+use crate::{
+    binary_log::nanoseconds,
+    bus::{Bus, DataSource},
+    config::GPS_RECEIVERS,
+    csv_handler::{log_path_in, read_log},
+    data::Telemetry,
+    envelope::Envelope,
+    log_config::*,
+    log_schema::{TelemetryKind, TelemetryRecord},
+};
+
+pub const MCAP_EXTENSION: &str = "mcap";
+const MAGIC: &[u8; 8] = b"\x89MCAP0\r\n";
+const PROFILE: &str = "";
+const LIBRARY: &str = "RustSDF";
+
+const OP_HEADER: u8 = 0x01;
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_DATA_END: u8 = 0x0F;
+
+const TELEMETRY_SCHEMA_NAME: &str = "rustsdf.Telemetry";
+const TELEMETRY_JSON_SCHEMA: &str = r#"{"type":"object","properties":{"type":{"type":"string","enum":["position","acceleration","altitude","magnetic_field","velocity","range"]},"x":{"type":"number"},"y":{"type":"number"},"z":{"type":"number"},"measurement_time_ns":{"type":"integer"}},"required":["type","x","y","z","measurement_time_ns"]}"#;
+
+// Builds MCAP records, `finish` closes the data section and the file
+pub struct McapWriter<W: Write> {
+    output: W,
+    next_schema_id: u16,
+    next_channel_id: u16,
+}
+
+impl<W: Write> McapWriter<W> {
+    pub fn new(mut output: W) -> io::Result<Self> {
+        output.write_all(MAGIC)?;
+        let mut header = Vec::new();
+        put_string(&mut header, PROFILE);
+        put_string(&mut header, LIBRARY);
+        write_record(&mut output, OP_HEADER, &header)?;
+        Ok(Self {
+            output,
+            next_schema_id: 1, // 0 stands for "no schema"
+            next_channel_id: 0,
+        })
+    }
+
+    pub fn add_schema(&mut self, name: &str, encoding: &str, data: &[u8]) -> io::Result<u16> {
+        let id = self.next_schema_id;
+        self.next_schema_id += 1;
+        let mut schema = id.to_le_bytes().to_vec();
+        put_string(&mut schema, name);
+        put_string(&mut schema, encoding);
+        put_bytes(&mut schema, data);
+        write_record(&mut self.output, OP_SCHEMA, &schema)?;
+        Ok(id)
+    }
+
+    pub fn add_channel(
+        &mut self,
+        schema_id: u16,
+        topic: &str,
+        message_encoding: &str,
+        metadata: &[(&str, &str)],
+    ) -> io::Result<u16> {
+        let id = self.next_channel_id;
+        self.next_channel_id += 1;
+        let mut channel = id.to_le_bytes().to_vec();
+        channel.extend_from_slice(&schema_id.to_le_bytes());
+        put_string(&mut channel, topic);
+        put_string(&mut channel, message_encoding);
+        let mut entries = Vec::new();
+        for (key, value) in metadata {
+            put_string(&mut entries, key);
+            put_string(&mut entries, value);
+        }
+        put_bytes(&mut channel, &entries);
+        write_record(&mut self.output, OP_CHANNEL, &channel)?;
+        Ok(id)
+    }
+
+    pub fn write_message(
+        &mut self,
+        channel_id: u16,
+        sequence: u32,
+        log_time: SystemTime,
+        publish_time: SystemTime,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let mut message = channel_id.to_le_bytes().to_vec();
+        message.extend_from_slice(&sequence.to_le_bytes());
+        message.extend_from_slice(&nanoseconds(log_time).to_le_bytes());
+        message.extend_from_slice(&nanoseconds(publish_time).to_le_bytes());
+        message.extend_from_slice(data);
+        write_record(&mut self.output, OP_MESSAGE, &message)
+    }
+
+    // Data section CRC and summary are left out, zero marks them as absent
+    pub fn finish(mut self) -> io::Result<W> {
+        write_record(&mut self.output, OP_DATA_END, &0u32.to_le_bytes())?;
+        let mut footer = 0u64.to_le_bytes().to_vec();
+        footer.extend_from_slice(&0u64.to_le_bytes());
+        footer.extend_from_slice(&0u32.to_le_bytes());
+        write_record(&mut self.output, OP_FOOTER, &footer)?;
+        self.output.write_all(MAGIC)?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+fn write_record(output: &mut impl Write, opcode: u8, content: &[u8]) -> io::Result<()> {
+    output.write_all(&[opcode])?;
+    output.write_all(&(content.len() as u64).to_le_bytes())?;
+    output.write_all(content)
+}
+
+fn put_string(buffer: &mut Vec<u8>, value: &str) {
+    put_bytes(buffer, value.as_bytes());
+}
+
+fn put_bytes(buffer: &mut Vec<u8>, value: &[u8]) {
+    buffer.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    buffer.extend_from_slice(value);
+}
+
+// JSON has no NaN or infinity
+fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+pub fn telemetry_json(
+    kind: TelemetryKind,
+    x: f64,
+    y: f64,
+    z: f64,
+    measurement_time: SystemTime,
+) -> Vec<u8> {
+    let kind = match kind {
+        TelemetryKind::Position => "position",
+        TelemetryKind::Acceleration => "acceleration",
+        TelemetryKind::Altitude => "altitude",
+        TelemetryKind::MagneticField => "magnetic_field",
+        TelemetryKind::Velocity => "velocity",
+        TelemetryKind::Range => "range",
+    };
+    format!(
+        r#"{{"type":"{kind}","x":{},"y":{},"z":{},"measurement_time_ns":{}}}"#,
+        json_number(x),
+        json_number(y),
+        json_number(z),
+        nanoseconds(measurement_time)
+    )
+    .into_bytes()
+}
+
+fn telemetry_message(telemetry: &Telemetry) -> Vec<u8> {
+    let (kind, data) = match telemetry {
+        Telemetry::Position(data) => (TelemetryKind::Position, data),
+        Telemetry::Acceleration(data) => (TelemetryKind::Acceleration, data),
+        Telemetry::Altitude(data) => (TelemetryKind::Altitude, data),
+        Telemetry::MagneticField(data) => (TelemetryKind::MagneticField, data),
+        Telemetry::Velocity(data) => (TelemetryKind::Velocity, data),
+        Telemetry::Range(data) => (TelemetryKind::Range, data),
+    };
+    telemetry_json(kind, data.x, data.y, data.z, data.timestamp)
+}
+
+// Every telemetry channel together with the log it is saved to
+pub fn recorded_sources() -> Vec<(DataSource, &'static str)> {
+    let mut sources = vec![
+        (DataSource::Imu, IMU_LOG),
+        (DataSource::Gps, GPS_LOG),
+        (DataSource::Barometer, BAROMETER_LOG),
+        (DataSource::Magnetometer, MAGNETOMETER_LOG),
+        (DataSource::Odometry, ODOMETRY_LOG),
+        (DataSource::Uwb, UWB_LOG),
+        (DataSource::Groundtruth, GROUNDTRUTH_LOG),
+        (DataSource::Kalman, KALMAN_LOG),
+        (DataSource::Average, MOVING_AVERAGE_LOG),
+        (DataSource::InertialNavigator, INTERTIAL_NAVIGATOR_LOG),
+        (DataSource::Imm, IMM_LOG),
+        (DataSource::InformationFilter, INFORMATION_FILTER_LOG),
+        (DataSource::Complementary, COMPLEMENTARY_LOG),
+        (DataSource::GpsSmoothing, GPS_SMOOTHING_LOG),
+    ];
+    for (index, gps_receiver) in GPS_RECEIVERS.iter().enumerate() {
+        sources.push((DataSource::GpsReceiver(index as u8), gps_receiver.log_name));
+    }
+    sources
+}
+
+struct TelemetryChannels {
+    ids: Vec<(DataSource, u16)>,
+}
+
+impl TelemetryChannels {
+    fn add_all<W: Write>(
+        writer: &mut McapWriter<W>,
+        sources: &[(DataSource, &str)],
+    ) -> io::Result<Self> {
+        let schema_id = writer.add_schema(
+            TELEMETRY_SCHEMA_NAME,
+            "jsonschema",
+            TELEMETRY_JSON_SCHEMA.as_bytes(),
+        )?;
+        let mut ids = Vec::new();
+        for (source, log_name) in sources {
+            let id = writer.add_channel(
+                schema_id,
+                &source.topic_name(),
+                "json",
+                &[("log", log_name)],
+            )?;
+            ids.push((*source, id));
+        }
+        Ok(Self { ids })
+    }
+
+    fn id(&self, source: DataSource) -> Option<u16> {
+        self.ids
+            .iter()
+            .find(|(channel, _)| *channel == source)
+            .map(|(_, id)| *id)
+    }
+}
+
+fn create_file(path: &Path) -> io::Result<BufWriter<File>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(BufWriter::new(File::create(path)?))
+}
+
+// Subscribes to every channel and records until all of them are closed.
+// The handle returns the number of recorded messages.
+pub fn start_recording(bus: &Bus, path: &Path) -> io::Result<JoinHandle<io::Result<usize>>> {
+    let sources = recorded_sources();
+    let mut writer = McapWriter::new(create_file(path)?)?;
+    let channels = TelemetryChannels::add_all(&mut writer, &sources)?;
+
+    // one forwarder per channel tags the messages, so a single thread can write them in arrival order
+    let (tx_recorder, rx_recorder) =
+        mpsc::channel::<(DataSource, Envelope<Telemetry>, SystemTime)>();
+    for (source, _) in &sources {
+        let rx = bus.subscribe(&source.topic());
+        let (source, tx_recorder): (DataSource, Sender<_>) = (*source, tx_recorder.clone());
+        thread::spawn(move || {
+            for envelope in rx {
+                if tx_recorder
+                    .send((source, envelope, SystemTime::now()))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+    }
+    drop(tx_recorder);
+
+    Ok(thread::spawn(move || {
+        let mut recorded = 0;
+        for (source, envelope, log_time) in rx_recorder {
+            let Some(channel_id) = channels.id(source) else {
+                continue;
+            };
+            // the bus sequence, so messages lost before the recorder show up as gaps
+            let sequence = envelope.sequence as u32;
+            let telemetry = envelope.message;
+            writer.write_message(
+                channel_id,
+                sequence,
+                log_time,
+                envelope.publish_time,
+                &telemetry_message(&telemetry),
+            )?;
+            recorded += 1;
+        }
+        writer.finish()?;
+        Ok(recorded)
+    }))
+}
+
+// Converts the telemetry logs found in the directory, returns the number of exported messages
+pub fn export_logs(directory: &Path, path: &Path) -> Result<usize, Box<dyn Error>> {
+    let sources: Vec<(DataSource, &str)> = recorded_sources()
+        .into_iter()
+        .filter(|(_, log_name)| log_path_in(directory, log_name).exists())
+        .collect();
+    if sources.is_empty() {
+        return Err(format!("No telemetry logs in {}", directory.display()).into());
+    }
+
+    let mut messages: Vec<(u16, TelemetryRecord)> = Vec::new();
+    let mut writer = McapWriter::new(create_file(path)?)?;
+    let channels = TelemetryChannels::add_all(&mut writer, &sources)?;
+    for (source, log_name) in &sources {
+        let channel_id = channels.id(*source).ok_or("Missing channel")?;
+        let records = read_log::<TelemetryRecord>(&log_path_in(directory, log_name))?;
+        messages.extend(records.into_iter().map(|record| (channel_id, record)));
+    }
+    // readers replay unindexed files in file order, so it has to follow the log time
+    messages.sort_by_key(|(_, record)| record.receive_time);
+
+    for (channel_id, record) in &messages {
+        writer.write_message(
+            *channel_id,
+            record.seq as u32,
+            record.receive_time,
+            record.receive_time,
+            &telemetry_json(
+                record.kind,
+                record.x,
+                record.y,
+                record.z,
+                record.measurement_time,
+            ),
+        )?;
+    }
+    writer.finish()?;
+    Ok(messages.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Data;
+    use crate::log_schema::LogRecord;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[derive(Debug, PartialEq)]
+    struct Record {
+        opcode: u8,
+        content: Vec<u8>,
+    }
+
+    // Minimal reader following the MCAP specification, enough to check the written structure
+    fn parse(bytes: &[u8]) -> Vec<Record> {
+        assert_eq!(&bytes[..8], MAGIC);
+        assert_eq!(&bytes[bytes.len() - 8..], MAGIC);
+        let mut records = Vec::new();
+        let mut rest = &bytes[8..bytes.len() - 8];
+        while !rest.is_empty() {
+            let length = u64::from_le_bytes(rest[1..9].try_into().unwrap()) as usize;
+            records.push(Record {
+                opcode: rest[0],
+                content: rest[9..9 + length].to_vec(),
+            });
+            rest = &rest[9 + length..];
+        }
+        records
+    }
+
+    fn string_at(content: &[u8], offset: usize) -> (String, usize) {
+        let length = u32::from_le_bytes(content[offset..offset + 4].try_into().unwrap()) as usize;
+        (
+            String::from_utf8(content[offset + 4..offset + 4 + length].to_vec()).unwrap(),
+            offset + 4 + length,
+        )
+    }
+
+    fn u64_at(content: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(content[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn data(x: f64, timestamp: SystemTime) -> Data {
+        Data {
+            x,
+            y: 2.0,
+            z: 3.0,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn written_file_follows_record_layout() {
+        let mut writer = McapWriter::new(Vec::new()).unwrap();
+        let schema_id = writer.add_schema("schema", "jsonschema", b"{}").unwrap();
+        let channel_id = writer
+            .add_channel(schema_id, "/topic", "json", &[("key", "value")])
+            .unwrap();
+        let log_time = UNIX_EPOCH + Duration::from_nanos(1_000_000_123);
+        writer
+            .write_message(channel_id, 7, log_time, UNIX_EPOCH, b"{\"x\":1}")
+            .unwrap();
+        let records = parse(&writer.finish().unwrap());
+
+        let opcodes: Vec<u8> = records.iter().map(|record| record.opcode).collect();
+        assert_eq!(
+            opcodes,
+            vec![
+                OP_HEADER,
+                OP_SCHEMA,
+                OP_CHANNEL,
+                OP_MESSAGE,
+                OP_DATA_END,
+                OP_FOOTER
+            ]
+        );
+        assert_eq!(string_at(&records[0].content, 4).0, LIBRARY);
+
+        let schema = &records[1].content;
+        assert_eq!(u16::from_le_bytes([schema[0], schema[1]]), 1);
+        let (name, offset) = string_at(schema, 2);
+        assert_eq!(name, "schema");
+        assert_eq!(string_at(schema, offset).0, "jsonschema");
+
+        let channel = &records[2].content;
+        assert_eq!(u16::from_le_bytes([channel[2], channel[3]]), schema_id);
+        let (topic, offset) = string_at(channel, 4);
+        assert_eq!(topic, "/topic");
+        assert_eq!(string_at(channel, offset).0, "json");
+
+        let message = &records[3].content;
+        assert_eq!(u16::from_le_bytes([message[0], message[1]]), channel_id);
+        assert_eq!(u32::from_le_bytes(message[2..6].try_into().unwrap()), 7);
+        assert_eq!(u64_at(message, 6), 1_000_000_123);
+        assert_eq!(u64_at(message, 14), 0);
+        assert_eq!(&message[22..], b"{\"x\":1}");
+        assert_eq!(records[5].content, vec![0; 20]);
+    }
+
+    #[test]
+    fn telemetry_json_is_valid_for_any_value() {
+        let json = telemetry_json(
+            TelemetryKind::Acceleration,
+            1.5,
+            f64::NAN,
+            -2.0,
+            UNIX_EPOCH + Duration::from_nanos(5),
+        );
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            r#"{"type":"acceleration","x":1.5,"y":null,"z":-2,"measurement_time_ns":5}"#
+        );
+    }
+
+    #[test]
+    fn recording_writes_every_channel_until_closed() {
+        let path = std::env::temp_dir().join(format!("mcap_recording_{}.mcap", std::process::id()));
+        let bus = Bus::new();
+        let handle = start_recording(&bus, &path).unwrap();
+
+        let measurement_time = SystemTime::now();
+        let imu = bus.publisher(&DataSource::Imu.topic());
+        let kalman = bus.publisher(&DataSource::Kalman.topic());
+        imu.publish(Telemetry::Acceleration(data(1.0, measurement_time)));
+        kalman.publish(Telemetry::Position(data(2.0, measurement_time)));
+        kalman.publish(Telemetry::Position(data(3.0, measurement_time)));
+        drop((imu, kalman, bus));
+
+        assert_eq!(handle.join().unwrap().unwrap(), 3);
+        let records = parse(&fs::read(&path).unwrap());
+        let _ = fs::remove_file(&path);
+
+        let channels = records
+            .iter()
+            .filter(|record| record.opcode == OP_CHANNEL)
+            .count();
+        assert_eq!(channels, recorded_sources().len());
+        let messages: Vec<&Record> = records
+            .iter()
+            .filter(|record| record.opcode == OP_MESSAGE)
+            .collect();
+        assert_eq!(messages.len(), 3);
+        // published after the measurement, recorded after being published
+        assert!(messages.iter().all(|message| {
+            let (log_time, publish_time) =
+                (u64_at(&message.content, 6), u64_at(&message.content, 14));
+            nanoseconds(measurement_time) <= publish_time && publish_time <= log_time
+        }));
+        // channels are numbered in the order of the recorded sources
+        let kalman_channel = recorded_sources()
+            .iter()
+            .position(|(source, _)| *source == DataSource::Kalman)
+            .unwrap() as u16;
+        let kalman_sequences: Vec<u32> = messages
+            .iter()
+            .filter(|message| message.content[..2] == kalman_channel.to_le_bytes())
+            .map(|message| u32::from_le_bytes(message.content[2..6].try_into().unwrap()))
+            .collect();
+        assert_eq!(kalman_sequences, vec![0, 1]);
+    }
+
+    #[test]
+    fn export_converts_logs_ordered_by_log_time() {
+        let directory = std::env::temp_dir().join(format!("mcap_export_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let start = SystemTime::now();
+        let write_log = |log_name: &str, kind: &str, receive_offsets: &[u64]| {
+            let mut content =
+                String::from_utf8(crate::log_stream::csv_header(TelemetryRecord::HEADER)).unwrap();
+            for (seq, offset) in receive_offsets.iter().enumerate() {
+                let record = TelemetryRecord {
+                    seq: seq as u64,
+                    source: log_name.to_string(),
+                    measurement_time: start,
+                    receive_time: start + Duration::from_secs(*offset),
+                    kind: if kind == "position" {
+                        TelemetryKind::Position
+                    } else {
+                        TelemetryKind::Acceleration
+                    },
+                    x: *offset as f64,
+                    y: 0.0,
+                    z: 0.0,
+                };
+                content.push_str(
+                    &String::from_utf8(crate::log_stream::csv_row(&record).unwrap()).unwrap(),
+                );
+            }
+            fs::write(log_path_in(&directory, log_name), content).unwrap();
+        };
+        write_log(IMU_LOG, "acceleration", &[0, 2]);
+        write_log(GPS_LOG, "position", &[1]);
+        let path = directory.join("export.mcap");
+
+        let exported = export_logs(&directory, &path).unwrap();
+        let records = parse(&fs::read(&path).unwrap());
+        let _ = fs::remove_dir_all(&directory);
+
+        assert_eq!(exported, 3);
+        assert_eq!(
+            records
+                .iter()
+                .filter(|record| record.opcode == OP_CHANNEL)
+                .count(),
+            2
+        );
+        let messages: Vec<&Record> = records
+            .iter()
+            .filter(|record| record.opcode == OP_MESSAGE)
+            .collect();
+        let log_times: Vec<u64> = messages
+            .iter()
+            .map(|message| u64_at(&message.content, 6))
+            .collect();
+        assert!(log_times.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(messages
+            .iter()
+            .all(|message| u64_at(&message.content, 14) == u64_at(&message.content, 6)));
+    }
+
+    #[test]
+    fn given_directory_without_logs_expect_error() {
+        let directory =
+            std::env::temp_dir().join(format!("mcap_export_empty_{}", std::process::id()));
+        assert!(export_logs(&directory, &directory.join("export.mcap")).is_err());
+    }
+}
@@ -0,0 +1,589 @@
+//! NMEA 0183 sentences for the simulated GPS, and a reader for recorded NMEA logs.
+//!
+//! Positions in the local frame around `LOCAL_ORIGIN` are reported as WGS84 geodetic
+//! coordinates. Every fix is written as GGA, GSA, RMC and VTG sentences from a fictional constellation.
+//! Recorded logs are read from their GGA sentences (the only ones with an altitude); RMC
+//! sentences provide the date, which GGA lacks. Other sentences are ignored.
+use std::{
+    error::Error,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, ErrorKind, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use nalgebra::Vector3;
+
+use crate::{
+    coordinates::{GeodeticPosition, LocalFrame},
+    data::Data,
+};
+
+const KNOTS_PER_METER_PER_SECOND: f64 = 3600.0 / 1852.0;
+const KILOMETERS_PER_HOUR_PER_METER_PER_SECOND: f64 = 3.6;
+
+// The fictional constellation every simulated fix is computed from
+const SIMULATED_SATELLITES: [u8; 8] = [2, 5, 7, 12, 15, 20, 24, 29];
+const SIMULATED_PDOP: f64 = 1.6;
+const SIMULATED_HDOP: f64 = 0.9;
+const SIMULATED_VDOP: f64 = 1.3;
+const TCP_WRITE_TIMEOUT: Duration = Duration::from_millis(100); // a stalled client is dropped
+
+// A GPS fix with everything the sentences report, velocity in m/s east and north
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NmeaFix {
+    pub time: SystemTime,
+    pub position: GeodeticPosition,
+    pub velocity_east: f64,
+    pub velocity_north: f64,
+}
+
+impl NmeaFix {
+    // Position in the local frame, velocity as simulated (east, north, up)
+    pub fn from_local(
+        origin: &GeodeticPosition,
+        frame: LocalFrame,
+        position: &Data,
+        velocity: &Vector3<f64>,
+    ) -> Self {
+        Self {
+            time: position.timestamp,
+            position: frame
+                .local_to_geodetic(origin, Vector3::new(position.x, position.y, position.z)),
+            velocity_east: velocity.x,
+            velocity_north: velocity.y,
+        }
+    }
+
+    fn speed(&self) -> f64 {
+        self.velocity_east.hypot(self.velocity_north)
+    }
+
+    // Degrees clockwise from true north
+    fn course(&self) -> f64 {
+        self.velocity_east
+            .atan2(self.velocity_north)
+            .to_degrees()
+            .rem_euclid(360.0)
+    }
+
+    // GGA, GSA, RMC and VTG, each with its checksum, without line endings
+    pub fn sentences(&self) -> [String; 4] {
+        let utc: DateTime<Utc> = self.time.into();
+        let time = format!(
+            "{}.{:02}",
+            utc.format("%H%M%S"),
+            utc.timestamp_subsec_millis() / 10
+        );
+        let (latitude, north_south) = format_coordinate(self.position.latitude, 2, ('N', 'S'));
+        let (longitude, east_west) = format_coordinate(self.position.longitude, 3, ('E', 'W'));
+        let (speed, course) = (self.speed(), self.course());
+        let satellites: Vec<String> = (0..12)
+            .map(|slot| {
+                SIMULATED_SATELLITES
+                    .get(slot)
+                    .map_or(String::new(), |prn| format!("{prn:02}"))
+            })
+            .collect();
+
+        [
+            format!(
+                "GPGGA,{time},{latitude},{north_south},{longitude},{east_west},1,{:02},{SIMULATED_HDOP:.1},{:.1},M,0.0,M,,",
+                SIMULATED_SATELLITES.len(),
+                self.position.altitude,
+            ),
+            format!(
+                "GPGSA,A,3,{},{SIMULATED_PDOP:.1},{SIMULATED_HDOP:.1},{SIMULATED_VDOP:.1}",
+                satellites.join(",")
+            ),
+            format!(
+                "GPRMC,{time},A,{latitude},{north_south},{longitude},{east_west},{:.2},{course:.1},{},,,A",
+                speed * KNOTS_PER_METER_PER_SECOND,
+                utc.format("%d%m%y"),
+            ),
+            format!(
+                "GPVTG,{course:.1},T,,M,{:.2},N,{:.2},K,A",
+                speed * KNOTS_PER_METER_PER_SECOND,
+                speed * KILOMETERS_PER_HOUR_PER_METER_PER_SECOND,
+            ),
+        ]
+        .map(|body| format!("${body}*{:02X}", checksum(&body)))
+    }
+}
+
+fn checksum(body: &str) -> u8 {
+    body.bytes().fold(0, |checksum, byte| checksum ^ byte)
+}
+
+// ddmm.mmmmm for latitudes, dddmm.mmmmm for longitudes
+fn format_coordinate(
+    value: f64,
+    degree_digits: usize,
+    hemispheres: (char, char),
+) -> (String, char) {
+    let hemisphere = if value < 0.0 {
+        hemispheres.1
+    } else {
+        hemispheres.0
+    };
+    let mut degrees = value.abs().trunc();
+    let mut minutes = (value.abs() - degrees) * 60.0;
+    // minutes rounding up to 60 carry into the degrees
+    if (minutes * 1e5).round() >= 60.0 * 1e5 {
+        degrees += 1.0;
+        minutes = 0.0;
+    }
+    (
+        format!("{:0degree_digits$}{minutes:08.5}", degrees as u32),
+        hemisphere,
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NmeaOutput {
+    #[allow(dead_code)]
+    File(&'static str), // created, or truncated when it exists
+    #[allow(dead_code)]
+    Device(&'static str), // an existing device, e.g. one end of a pseudo-terminal pair
+    #[allow(dead_code)]
+    Tcp(&'static str), // served to every client connecting to the address
+}
+
+pub enum NmeaSink {
+    Writer(Box<dyn Write + Send>),
+    Tcp {
+        listener: TcpListener,
+        clients: Vec<TcpStream>,
+    },
+}
+
+impl NmeaSink {
+    pub fn open(output: NmeaOutput) -> io::Result<Self> {
+        match output {
+            NmeaOutput::File(path) => Ok(NmeaSink::Writer(Box::new(BufWriter::new(File::create(
+                path,
+            )?)))),
+            NmeaOutput::Device(path) => Ok(NmeaSink::Writer(Box::new(
+                OpenOptions::new().write(true).open(path)?,
+            ))),
+            NmeaOutput::Tcp(address) => {
+                let listener = TcpListener::bind(address)?;
+                listener.set_nonblocking(true)?;
+                Ok(NmeaSink::Tcp {
+                    listener,
+                    clients: Vec::new(),
+                })
+            }
+        }
+    }
+
+    pub fn tcp_address(&self) -> Option<SocketAddr> {
+        match self {
+            NmeaSink::Tcp { listener, .. } => listener.local_addr().ok(),
+            NmeaSink::Writer(_) => None,
+        }
+    }
+
+    // Disconnected TCP clients are dropped, only failing files and devices are errors
+    pub fn write_sentences(&mut self, sentences: &[String]) -> io::Result<()> {
+        let mut lines = String::new();
+        for sentence in sentences {
+            lines.push_str(sentence);
+            lines.push_str("\r\n");
+        }
+        match self {
+            NmeaSink::Writer(writer) => {
+                writer.write_all(lines.as_bytes())?;
+                writer.flush()
+            }
+            NmeaSink::Tcp { listener, clients } => {
+                loop {
+                    match listener.accept() {
+                        Ok((client, _)) => {
+                            client.set_nonblocking(false)?;
+                            client.set_write_timeout(Some(TCP_WRITE_TIMEOUT))?;
+                            clients.push(client);
+                        }
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                        Err(e) => return Err(e),
+                    }
+                }
+                clients.retain_mut(|client| client.write_all(lines.as_bytes()).is_ok());
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct InvalidNmea(String);
+
+impl fmt::Display for InvalidNmea {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid NMEA sentence: {}", self.0)
+    }
+}
+
+impl Error for InvalidNmea {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NmeaSentence {
+    Gga {
+        time_of_day: NaiveTime,
+        position: Option<GeodeticPosition>,
+    }, // no position without a fix
+    Rmc {
+        time_of_day: NaiveTime,
+        date: NaiveDate,
+    },
+    Other,
+}
+
+fn invalid(reason: &str, sentence: &str) -> InvalidNmea {
+    InvalidNmea(format!("{reason} in {sentence}"))
+}
+
+fn parse_time_of_day(field: &str) -> Option<NaiveTime> {
+    let (whole, fraction) = field.split_once('.').unwrap_or((field, ""));
+    if whole.len() != 6 {
+        return None;
+    }
+    let nanoseconds = match fraction {
+        "" => 0,
+        fraction => (format!("0.{fraction}").parse::<f64>().ok()? * 1e9).round() as u32,
+    };
+    NaiveTime::from_hms_nano_opt(
+        whole[0..2].parse().ok()?,
+        whole[2..4].parse().ok()?,
+        whole[4..6].parse().ok()?,
+        nanoseconds,
+    )
+}
+
+fn parse_date(field: &str) -> Option<NaiveDate> {
+    if field.len() != 6 {
+        return None;
+    }
+    let year: i32 = field[4..6].parse().ok()?;
+    NaiveDate::from_ymd_opt(
+        if year < 80 { 2000 + year } else { 1900 + year },
+        field[2..4].parse().ok()?,
+        field[0..2].parse().ok()?,
+    )
+}
+
+fn parse_coordinate(value: &str, hemisphere: &str, negative: &str) -> Option<f64> {
+    let value: f64 = value.parse().ok()?;
+    let degrees = (value / 100.0).trunc();
+    let coordinate = degrees + (value - degrees * 100.0) / 60.0;
+    Some(if hemisphere == negative {
+        -coordinate
+    } else {
+        coordinate
+    })
+}
+
+// A sentence with a checksum must match it, one without is accepted as it is
+pub fn parse_sentence(line: &str) -> Result<NmeaSentence, InvalidNmea> {
+    let line = line.trim();
+    let body = line
+        .strip_prefix('$')
+        .ok_or_else(|| invalid("missing $", line))?;
+    let body = match body.split_once('*') {
+        Some((body, expected)) => {
+            let expected = u8::from_str_radix(expected, 16)
+                .map_err(|_| invalid("malformed checksum", line))?;
+            if checksum(body) != expected {
+                return Err(invalid("checksum mismatch", line));
+            }
+            body
+        }
+        None => body,
+    };
+    let fields: Vec<&str> = body.split(',').collect();
+    // talker (GP, GN, GL, ...) followed by the sentence type
+    let sentence_type = fields[0].get(2..).unwrap_or_default();
+    let field = |index: usize| fields.get(index).copied().unwrap_or_default();
+    match sentence_type {
+        "GGA" => {
+            let time_of_day =
+                parse_time_of_day(field(1)).ok_or_else(|| invalid("malformed time", line))?;
+            if field(6).is_empty() || field(6) == "0" {
+                return Ok(NmeaSentence::Gga {
+                    time_of_day,
+                    position: None,
+                });
+            }
+            let position = GeodeticPosition {
+                latitude: parse_coordinate(field(2), field(3), "S")
+                    .ok_or_else(|| invalid("malformed latitude", line))?,
+                longitude: parse_coordinate(field(4), field(5), "W")
+                    .ok_or_else(|| invalid("malformed longitude", line))?,
+                altitude: field(9)
+                    .parse()
+                    .map_err(|_| invalid("malformed altitude", line))?,
+            };
+            Ok(NmeaSentence::Gga {
+                time_of_day,
+                position: Some(position),
+            })
+        }
+        "RMC" => Ok(NmeaSentence::Rmc {
+            time_of_day: parse_time_of_day(field(1))
+                .ok_or_else(|| invalid("malformed time", line))?,
+            date: parse_date(field(9)).ok_or_else(|| invalid("malformed date", line))?,
+        }),
+        _ => Ok(NmeaSentence::Other),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NmeaLog {
+    pub fixes: Vec<(SystemTime, GeodeticPosition)>,
+    pub skipped_lines: usize, // invalid sentences, e.g. cut short or corrupted
+}
+
+// GGA sentences before the first RMC take its date, without any RMC the log starts on 1970-01-01
+pub fn read_nmea_log(path: &Path) -> io::Result<NmeaLog> {
+    let content = fs::read_to_string(path)?;
+    let mut skipped_lines = 0;
+    let sentences: Vec<NmeaSentence> = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            parse_sentence(line)
+                .inspect_err(|_| skipped_lines += 1)
+                .ok()
+        })
+        .collect();
+
+    let mut date = sentences
+        .iter()
+        .find_map(|sentence| match sentence {
+            NmeaSentence::Rmc { date, .. } => Some(*date),
+            _ => None,
+        })
+        .unwrap_or(DateTime::UNIX_EPOCH.date_naive());
+    let mut fixes: Vec<(SystemTime, GeodeticPosition)> = Vec::new();
+    for sentence in sentences {
+        match sentence {
+            NmeaSentence::Rmc { date: rmc_date, .. } => date = rmc_date,
+            NmeaSentence::Gga {
+                time_of_day,
+                position: Some(position),
+            } => {
+                let mut time: SystemTime = date.and_time(time_of_day).and_utc().into();
+                // GGA time going back by more than half a day passed midnight
+                if let Some((last_time, _)) = fixes.last() {
+                    if time + Duration::from_secs(12 * 3600) < *last_time {
+                        date = date.succ_opt().unwrap_or(date);
+                        time = date.and_time(time_of_day).and_utc().into();
+                    }
+                }
+                fixes.push((time, position));
+            }
+            _ => {}
+        }
+    }
+    Ok(NmeaLog {
+        fixes,
+        skipped_lines,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader},
+        time::UNIX_EPOCH,
+    };
+
+    const ORIGIN: GeodeticPosition = GeodeticPosition {
+        latitude: 52.2297,
+        longitude: 21.0122,
+        altitude: 100.0,
+    };
+
+    // 2024-03-15 12:35:19.50 UTC
+    fn fix_time() -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(1_710_506_119_500)
+    }
+
+    fn fix() -> NmeaFix {
+        NmeaFix {
+            time: fix_time(),
+            position: GeodeticPosition {
+                latitude: 48.1173,
+                longitude: -11.5166667,
+                altitude: 545.4,
+            },
+            velocity_east: 0.0,
+            velocity_north: -2.0,
+        }
+    }
+
+    #[test]
+    fn test_sentences_of_a_fix() {
+        let [gga, gsa, rmc, vtg] = fix().sentences();
+        assert!(gga
+            .starts_with("$GPGGA,123519.50,4807.03800,N,01131.00000,W,1,08,0.9,545.4,M,0.0,M,,*"));
+        assert!(gsa.starts_with("$GPGSA,A,3,02,05,07,12,15,20,24,29,,,,,1.6,0.9,1.3*"));
+        assert!(
+            rmc.starts_with("$GPRMC,123519.50,A,4807.03800,N,01131.00000,W,3.89,180.0,150324,,,A*")
+        );
+        assert!(vtg.starts_with("$GPVTG,180.0,T,,M,3.89,N,7.20,K,A*"));
+        for sentence in [gga, gsa, rmc, vtg] {
+            assert!(parse_sentence(&sentence).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_known_sentence_checksum() {
+        let sentence = "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47";
+        let NmeaSentence::Gga {
+            time_of_day,
+            position: Some(position),
+        } = parse_sentence(sentence).unwrap()
+        else {
+            panic!("GGA fix expected");
+        };
+        assert_eq!(time_of_day, NaiveTime::from_hms_opt(12, 35, 19).unwrap());
+        approx::assert_abs_diff_eq!(position.latitude, 48.0 + 7.038 / 60.0, epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(position.longitude, 11.0 + 31.0 / 60.0, epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(position.altitude, 545.4);
+        assert!(parse_sentence(&sentence.replace("*47", "*48")).is_err());
+    }
+
+    #[test]
+    fn test_gga_without_fix_has_no_position() {
+        assert_eq!(
+            parse_sentence("$GNGGA,000001.00,,,,,0,00,99.9,,,,,,"),
+            Ok(NmeaSentence::Gga {
+                time_of_day: NaiveTime::from_hms_opt(0, 0, 1).unwrap(),
+                position: None
+            })
+        );
+        assert_eq!(
+            parse_sentence("$GPGSV,3,1,11,03,03,111,00"),
+            Ok(NmeaSentence::Other)
+        );
+    }
+
+    #[test]
+    fn test_fix_of_a_simulated_position() {
+        let position = Data {
+            x: 0.0,
+            y: 1852.0,
+            z: 12.5,
+            timestamp: fix_time(),
+        };
+        let fix = NmeaFix::from_local(
+            &ORIGIN,
+            LocalFrame::Enu,
+            &position,
+            &Vector3::new(3.0, 4.0, 1.0),
+        );
+        let ned = Data {
+            x: 1852.0,
+            y: 0.0,
+            z: -12.5,
+            ..position
+        };
+        assert_eq!(
+            NmeaFix::from_local(&ORIGIN, LocalFrame::Ned, &ned, &Vector3::zeros()).position,
+            fix.position
+        );
+        // a minute of latitude is about a nautical mile
+        approx::assert_abs_diff_eq!(
+            (fix.position.latitude - ORIGIN.latitude) * 60.0,
+            1.0,
+            epsilon = 0.01
+        );
+        approx::assert_abs_diff_eq!(fix.position.longitude, ORIGIN.longitude, epsilon = 1e-9);
+        approx::assert_abs_diff_eq!(fix.speed(), 5.0);
+        approx::assert_abs_diff_eq!(fix.course(), 36.8699, epsilon = 1e-4);
+    }
+
+    #[test]
+    fn test_written_log_is_read_back() {
+        let path = Path::new("test_output_nmea_round_trip.nmea");
+        let mut sink =
+            NmeaSink::open(NmeaOutput::File("test_output_nmea_round_trip.nmea")).unwrap();
+        let velocity = Vector3::new(1.0, 1.0, 0.0);
+        let written: Vec<Data> = (0..3)
+            .map(|second| Data {
+                x: 10.0 * second as f64,
+                y: -5.0,
+                z: 2.0,
+                timestamp: fix_time() + Duration::from_secs(second),
+            })
+            .collect();
+        for data in &written {
+            sink.write_sentences(
+                &NmeaFix::from_local(&ORIGIN, LocalFrame::Enu, data, &velocity).sentences(),
+            )
+            .unwrap();
+        }
+        drop(sink);
+        fs::write(
+            path,
+            fs::read_to_string(path).unwrap() + "$GPGGA,cut short\r\n",
+        )
+        .unwrap();
+        let log = read_nmea_log(path).unwrap();
+        let _ = fs::remove_file(path);
+
+        assert_eq!(log.skipped_lines, 1);
+        assert_eq!(log.fixes.len(), 3);
+        for ((time, position), data) in log.fixes.iter().zip(&written) {
+            assert_eq!(*time, data.timestamp);
+            // minutes with 5 decimals resolve about 2 cm
+            let enu = position.to_ecef().to_enu(&ORIGIN);
+            approx::assert_abs_diff_eq!(
+                Vector3::new(enu.east, enu.north, enu.up),
+                Vector3::new(data.x, data.y, data.z),
+                epsilon = 0.05
+            );
+        }
+    }
+
+    #[test]
+    fn test_log_passing_midnight_continues_on_the_next_day() {
+        let path = Path::new("test_output_nmea_midnight.nmea");
+        fs::write(
+            path,
+            "$GPRMC,235959.00,A,4807.038,N,01131.000,E,0.0,0.0,311224,,,A\n\
+             $GPGGA,235959.00,4807.038,N,01131.000,E,1,08,0.9,545.4,M,0.0,M,,\n\
+             $GPGGA,000000.00,4807.038,N,01131.000,E,1,08,0.9,545.4,M,0.0,M,,\n",
+        )
+        .unwrap();
+        let log = read_nmea_log(path).unwrap();
+        let _ = fs::remove_file(path);
+        assert_eq!(
+            log.fixes[1].0.duration_since(log.fixes[0].0).unwrap(),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn test_tcp_clients_receive_sentences() {
+        let mut sink = NmeaSink::open(NmeaOutput::Tcp("127.0.0.1:0")).unwrap();
+        let client = TcpStream::connect(sink.tcp_address().unwrap()).unwrap();
+        let sentences = fix().sentences();
+        // the client is accepted on the next write
+        while let NmeaSink::Tcp { clients, .. } = &sink {
+            if !clients.is_empty() {
+                break;
+            }
+            sink.write_sentences(&[]).unwrap();
+        }
+        sink.write_sentences(&sentences).unwrap();
+
+        let mut lines = BufReader::new(client).lines();
+        assert_eq!(lines.next().unwrap().unwrap(), sentences[0]);
+    }
+}
@@ -0,0 +1,264 @@
+use crate::{
+    bus::Publisher,
+    config::LOCAL_FRAME,
+    data::{Data, KinematicState, Telemetry},
+    log_config::{GENERAL_LOG, ODOMETRY_LOG},
+    logger::log,
+    periodic_runner,
+    utils::get_cycle_duration,
+};
+use std::{
+    error::Error,
+    num::NonZeroU32,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
+};
+
+use rand::{rng, Rng};
+use rand_distr::{Distribution, Normal};
+
+// Errors of velocities derived from wheel encoders
+#[derive(Debug, Clone, Copy)]
+pub struct OdometryConfig {
+    pub scale_error: f64, // relative, e.g. of a wrong wheel radius
+    pub noise_sigma: f64, // m/s
+    pub slip_rate: f64,   // slip events per second
+    pub slip_duration: Duration,
+    pub slip_ratio: f64, // relative overestimate while the wheels slip
+}
+
+pub struct WheelOdometry {
+    tx: Publisher<Telemetry>,
+    kinematic_state: Arc<Mutex<KinematicState>>,
+    prev_timestamp: SystemTime,
+    config: OdometryConfig,
+    slip_end: Option<SystemTime>,
+    noise_generator: Normal<f64>,
+}
+
+impl WheelOdometry {
+    pub fn run(
+        kinematic_state: Arc<Mutex<KinematicState>>,
+        tx: Publisher<Telemetry>,
+        shutdown: Arc<AtomicBool>,
+        frequency: NonZeroU32,
+        config: OdometryConfig,
+    ) -> JoinHandle<()> {
+        let mut odometry = WheelOdometry::new(kinematic_state, tx, config);
+        std::thread::spawn(move || {
+            if let Err(e) = periodic_runner::run_periodicaly(
+                || odometry.step(),
+                || shutdown.load(Ordering::SeqCst),
+                get_cycle_duration(frequency),
+            ) {
+                eprintln!("Wheel odometry internal error: {e}. Aborting.")
+            }
+
+            log(GENERAL_LOG, "Wheel odometry removed".to_string());
+        })
+    }
+
+    fn new(
+        kinematic_state: Arc<Mutex<KinematicState>>,
+        tx: Publisher<Telemetry>,
+        config: OdometryConfig,
+    ) -> WheelOdometry {
+        let prev_timestamp = kinematic_state.lock().unwrap().timestamp;
+        WheelOdometry {
+            tx,
+            kinematic_state,
+            prev_timestamp,
+            config,
+            slip_end: None,
+            noise_generator: Normal::new(0.0, config.noise_sigma).unwrap(),
+        }
+    }
+
+    // Slip events start at random, as a Poisson process, and last for the configured duration
+    fn update_slip(&mut self, timestamp: SystemTime, elapsed: Duration) {
+        if self.slip_end.is_some_and(|slip_end| timestamp >= slip_end) {
+            self.slip_end = None;
+        }
+        let slip_probability = 1.0 - (-self.config.slip_rate * elapsed.as_secs_f64()).exp();
+        if self.slip_end.is_none() && rng().random_bool(slip_probability) {
+            self.slip_end = Some(timestamp + self.config.slip_duration);
+            log(GENERAL_LOG, "Wheel odometry: wheels slipping".to_string());
+        }
+    }
+
+    fn step(&mut self) -> Result<(), Box<dyn Error>> {
+        let current_state = *self.kinematic_state.lock().unwrap();
+
+        let elapsed = current_state
+            .timestamp
+            .duration_since(self.prev_timestamp)?;
+        self.prev_timestamp = current_state.timestamp;
+        self.update_slip(current_state.timestamp, elapsed);
+
+        let slip = if self.slip_end.is_some() {
+            1.0 + self.config.slip_ratio
+        } else {
+            1.0
+        };
+        let scale = (1.0 + self.config.scale_error) * slip;
+        let velocity = LOCAL_FRAME.simulated_to_local(current_state.velocity_data());
+        let data_to_send = Data {
+            x: velocity.x * scale + self.noise_generator.sample(&mut rng()),
+            y: velocity.y * scale + self.noise_generator.sample(&mut rng()),
+            z: velocity.z * scale + self.noise_generator.sample(&mut rng()),
+            ..velocity
+        };
+
+        log(ODOMETRY_LOG, data_to_send);
+        if self.tx.publish(Telemetry::Velocity(data_to_send)) == 0 {
+            return Err("No subscribers for wheel odometry output.".into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector3;
+    use ntest_timeout::timeout;
+    use std::sync::mpsc;
+
+    const IDEAL: OdometryConfig = OdometryConfig {
+        scale_error: 0.0,
+        noise_sigma: 0.0,
+        slip_rate: 0.0,
+        slip_duration: Duration::from_secs(1),
+        slip_ratio: 0.0,
+    };
+
+    fn odometry(
+        kinematic_state: &Arc<Mutex<KinematicState>>,
+        tx: Publisher<Telemetry>,
+        config: OdometryConfig,
+    ) -> WheelOdometry {
+        WheelOdometry::new(Arc::clone(kinematic_state), tx, config)
+    }
+
+    fn measured_velocity(rx: &mpsc::Receiver<crate::envelope::Envelope<Telemetry>>) -> Data {
+        let Telemetry::Velocity(data) = rx.recv().unwrap().message else {
+            panic!("Wheel odometry should provide velocity.");
+        };
+        data
+    }
+
+    fn advance(kinematic_state: &Arc<Mutex<KinematicState>>, elapsed: Duration) {
+        kinematic_state.lock().unwrap().timestamp += elapsed;
+    }
+
+    #[test]
+    fn given_ideal_sensor_expect_groundtruth_velocity() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, rx) = mpsc::channel();
+        let mut odometry = odometry(&kinematic_state, vec![tx].into(), IDEAL);
+        kinematic_state.lock().unwrap().velocity = Vector3::new(3.0, -1.0, 0.5);
+
+        assert!(odometry.step().is_ok());
+
+        let velocity = measured_velocity(&rx);
+        approx::assert_abs_diff_eq!(velocity.x, 3.0);
+        approx::assert_abs_diff_eq!(velocity.y, -1.0);
+        approx::assert_abs_diff_eq!(velocity.z, 0.5);
+        assert_eq!(
+            velocity.timestamp,
+            kinematic_state.lock().unwrap().timestamp
+        );
+    }
+
+    #[test]
+    fn given_scale_error_expect_scaled_velocity() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, rx) = mpsc::channel();
+        let mut odometry = odometry(
+            &kinematic_state,
+            vec![tx].into(),
+            OdometryConfig {
+                scale_error: 0.05,
+                ..IDEAL
+            },
+        );
+        kinematic_state.lock().unwrap().velocity = Vector3::new(10.0, 0.0, 0.0);
+
+        assert!(odometry.step().is_ok());
+        approx::assert_abs_diff_eq!(measured_velocity(&rx).x, 10.5, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn given_slip_event_expect_overestimate_until_it_ends() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, rx) = mpsc::channel();
+        let always_slipping = OdometryConfig {
+            slip_rate: f64::INFINITY,
+            slip_ratio: 0.5,
+            ..IDEAL
+        };
+        let mut odometry = odometry(&kinematic_state, vec![tx].into(), always_slipping);
+        kinematic_state.lock().unwrap().velocity = Vector3::new(2.0, 0.0, 0.0);
+
+        advance(&kinematic_state, Duration::from_millis(100));
+        assert!(odometry.step().is_ok());
+        approx::assert_abs_diff_eq!(measured_velocity(&rx).x, 3.0, epsilon = 1e-12);
+        assert!(odometry.slip_end.is_some());
+
+        odometry.config.slip_rate = 0.0;
+        advance(&kinematic_state, Duration::from_millis(500));
+        assert!(odometry.step().is_ok());
+        approx::assert_abs_diff_eq!(measured_velocity(&rx).x, 3.0, epsilon = 1e-12);
+
+        advance(&kinematic_state, Duration::from_millis(500));
+        assert!(odometry.step().is_ok());
+        approx::assert_abs_diff_eq!(measured_velocity(&rx).x, 2.0, epsilon = 1e-12);
+        assert!(odometry.slip_end.is_none());
+    }
+
+    #[test]
+    fn given_noise_enabled_expect_output_with_noise() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, rx) = mpsc::channel();
+        let mut odometry = odometry(
+            &kinematic_state,
+            vec![tx].into(),
+            OdometryConfig {
+                noise_sigma: 1.0,
+                ..IDEAL
+            },
+        );
+
+        assert!(odometry.step().is_ok());
+        approx::assert_abs_diff_ne!(measured_velocity(&rx).x, 0.0);
+    }
+
+    #[test]
+    fn given_next_timestamp_is_behind_previous_expect_step_to_fail() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, _rx) = mpsc::channel();
+        let mut odometry = odometry(&kinematic_state, vec![tx].into(), IDEAL);
+        kinematic_state.lock().unwrap().timestamp -= Duration::from_secs(1);
+
+        assert!(odometry.step().is_err());
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn given_rx_goes_out_of_scope_odometry_shuts_down() {
+        let (tx, rx) = mpsc::channel();
+        let handle = WheelOdometry::run(
+            Arc::new(Mutex::new(KinematicState::new())),
+            vec![tx].into(),
+            Arc::new(AtomicBool::new(false)),
+            NonZeroU32::new(10).unwrap(),
+            IDEAL,
+        );
+        drop(rx);
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,338 @@
+//! Bounded single-consumer queue, the receiving end of a bus subscription.
+//!
+//! What happens when a slow consumer lets the queue fill up is chosen per queue: the
+//! publisher waits (`Block`), the oldest queued message makes room (`DropOldest`), the new
+//! message is discarded (`DropNewest`) or only the most recent message is kept (`KeepLatest`).
+//! Every queue counts the messages it accepted and dropped. The receiving side mirrors
+//! `mpsc::Receiver`, including its error types, so consumers can use either.
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{
+        mpsc::{RecvError, SendError, TryRecvError},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    Block,
+    DropOldest,
+    #[allow(dead_code)]
+    DropNewest,
+    #[allow(dead_code)]
+    KeepLatest,
+}
+
+impl fmt::Display for OverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            OverflowPolicy::Block => "block",
+            OverflowPolicy::DropOldest => "drop oldest",
+            OverflowPolicy::DropNewest => "drop newest",
+            OverflowPolicy::KeepLatest => "keep latest",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueConfig {
+    pub capacity: usize, // ignored by KeepLatest, which holds a single message
+    pub policy: OverflowPolicy,
+}
+
+impl QueueConfig {
+    // Never drops nor blocks, for consumers that need every message
+    pub const UNBOUNDED: QueueConfig = QueueConfig {
+        capacity: usize::MAX,
+        policy: OverflowPolicy::DropOldest,
+    };
+
+    fn effective_capacity(&self) -> usize {
+        match self.policy {
+            OverflowPolicy::KeepLatest => 1,
+            _ => self.capacity.max(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueStatistics {
+    pub queued: u64,
+    pub dropped: u64,
+}
+
+struct State<T> {
+    messages: VecDeque<T>,
+    senders: usize,
+    receiver_alive: bool,
+    statistics: QueueStatistics,
+}
+
+struct Shared<T> {
+    config: QueueConfig,
+    state: Mutex<State<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap()
+    }
+}
+
+pub fn bounded<T>(config: QueueConfig) -> (QueueSender<T>, QueueReceiver<T>) {
+    let shared = Arc::new(Shared {
+        config,
+        state: Mutex::new(State {
+            messages: VecDeque::new(),
+            senders: 1,
+            receiver_alive: true,
+            statistics: QueueStatistics::default(),
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+    });
+    (
+        QueueSender {
+            shared: Arc::clone(&shared),
+        },
+        QueueReceiver { shared },
+    )
+}
+
+pub struct QueueSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueSender<T> {
+    // Fails only once the receiver is gone, a dropped message still counts as sent
+    pub fn send(&self, message: T) -> Result<(), SendError<T>> {
+        let capacity = self.shared.config.effective_capacity();
+        let mut state = self.shared.lock();
+        if !state.receiver_alive {
+            return Err(SendError(message));
+        }
+        if state.messages.len() >= capacity {
+            match self.shared.config.policy {
+                OverflowPolicy::Block => {
+                    state = self
+                        .shared
+                        .not_full
+                        .wait_while(state, |state| {
+                            state.receiver_alive && state.messages.len() >= capacity
+                        })
+                        .unwrap();
+                    if !state.receiver_alive {
+                        return Err(SendError(message));
+                    }
+                }
+                OverflowPolicy::DropOldest | OverflowPolicy::KeepLatest => {
+                    state.messages.pop_front();
+                    state.statistics.dropped += 1;
+                }
+                OverflowPolicy::DropNewest => {
+                    state.statistics.dropped += 1;
+                    return Ok(());
+                }
+            }
+        }
+        state.messages.push_back(message);
+        state.statistics.queued += 1;
+        self.shared.not_empty.notify_one();
+        Ok(())
+    }
+
+    pub fn monitor(&self) -> QueueMonitor<T> {
+        QueueMonitor {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Clone for QueueSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for QueueSender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            self.shared.not_empty.notify_all();
+        }
+    }
+}
+
+// Reads the statistics without keeping the queue open like a sender would
+pub struct QueueMonitor<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueMonitor<T> {
+    pub fn statistics(&self) -> QueueStatistics {
+        self.shared.lock().statistics
+    }
+}
+
+pub struct QueueReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueReceiver<T> {
+    fn take(&self, state: &mut State<T>) -> Option<T> {
+        let message = state.messages.pop_front()?;
+        self.shared.not_full.notify_one();
+        Some(message)
+    }
+
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self
+            .shared
+            .not_empty
+            .wait_while(self.shared.lock(), |state| {
+                state.messages.is_empty() && state.senders > 0
+            })
+            .unwrap();
+        self.take(&mut state).ok_or(RecvError)
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock();
+        match self.take(&mut state) {
+            Some(message) => Ok(message),
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(move || self.recv().ok())
+    }
+}
+
+impl<T> Drop for QueueReceiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.receiver_alive = false;
+        state.messages.clear();
+        self.shared.not_full.notify_all();
+    }
+}
+
+impl<T> fmt::Debug for QueueReceiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("QueueReceiver")
+            .field("config", &self.shared.config)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntest_timeout::timeout;
+    use std::{thread, time::Duration};
+
+    fn config(capacity: usize, policy: OverflowPolicy) -> QueueConfig {
+        QueueConfig { capacity, policy }
+    }
+
+    fn queued<T>(rx: &QueueReceiver<T>) -> Vec<T> {
+        std::iter::from_fn(|| rx.try_recv().ok()).collect()
+    }
+
+    #[test]
+    fn test_drop_oldest_keeps_newest_messages() {
+        let (tx, rx) = bounded(config(3, OverflowPolicy::DropOldest));
+        for message in 0..5 {
+            tx.send(message).unwrap();
+        }
+        assert_eq!(queued(&rx), vec![2, 3, 4]);
+        assert_eq!(
+            tx.monitor().statistics(),
+            QueueStatistics {
+                queued: 5,
+                dropped: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_drop_newest_keeps_oldest_messages() {
+        let (tx, rx) = bounded(config(3, OverflowPolicy::DropNewest));
+        for message in 0..5 {
+            tx.send(message).unwrap();
+        }
+        assert_eq!(queued(&rx), vec![0, 1, 2]);
+        assert_eq!(
+            tx.monitor().statistics(),
+            QueueStatistics {
+                queued: 3,
+                dropped: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_keep_latest_holds_a_single_message() {
+        let (tx, rx) = bounded(config(10, OverflowPolicy::KeepLatest));
+        for message in 0..5 {
+            tx.send(message).unwrap();
+        }
+        assert_eq!(queued(&rx), vec![4]);
+        assert_eq!(tx.monitor().statistics().dropped, 4);
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn test_block_waits_for_the_receiver() {
+        let (tx, rx) = bounded(config(1, OverflowPolicy::Block));
+        let sender = thread::spawn(move || {
+            for message in 0..3 {
+                tx.send(message).unwrap();
+            }
+            tx.monitor().statistics()
+        });
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(rx.recv(), Ok(0));
+        assert_eq!(rx.iter().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(
+            sender.join().unwrap(),
+            QueueStatistics {
+                queued: 3,
+                dropped: 0
+            }
+        );
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn test_blocked_sender_fails_when_receiver_is_dropped() {
+        let (tx, rx) = bounded(config(1, OverflowPolicy::Block));
+        tx.send(0).unwrap();
+        let sender = thread::spawn(move || tx.send(1));
+        thread::sleep(Duration::from_millis(50));
+        drop(rx);
+        assert!(sender.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_receiver_sees_disconnect_after_remaining_messages() {
+        let (tx, rx) = bounded(QueueConfig::UNBOUNDED);
+        let second_tx = tx.clone();
+        tx.send(1).unwrap();
+        drop(tx);
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        drop(second_tx);
+        assert_eq!(rx.recv(), Err(RecvError));
+    }
+}
@@ -0,0 +1,399 @@
+use std::{
+    error::Error,
+    path::Path,
+    thread::JoinHandle,
+    time::{Instant, SystemTime},
+};
+
+use crate::{
+    bus::{Bus, DataSource, Publisher},
+    config::{GPS_RECEIVERS, LOCAL_FRAME},
+    csv_handler::{log_path_in, read_data_log},
+    data::{Data, Telemetry},
+    log_config::{
+        BAROMETER_LOG, GENERAL_LOG, GPS_LOG, GROUNDTRUTH_LOG, IMU_LOG, MAGNETOMETER_LOG,
+        ODOMETRY_LOG, UWB_LOG,
+    },
+    logger::log,
+    nmea::read_nmea_log,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaySpeed {
+    RealTime,
+    AsFastAsPossible,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ReplayedMessage {
+    source: DataSource,
+    log_name: &'static str,
+    telemetry: Telemetry,
+}
+
+// Sensor logs of a previous run, merged into a single stream ordered by the original timestamps
+pub struct Replay {
+    messages: Vec<ReplayedMessage>,
+}
+
+impl Replay {
+    // IMU and GPS logs are required, ground truth, barometer, magnetometer, wheel odometry, UWB ranges
+    // and additional GPS receivers are replayed when present
+    pub fn load(directory: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut replay = Replay {
+            messages: Vec::new(),
+        };
+        replay.add_log(
+            directory,
+            DataSource::Imu,
+            IMU_LOG,
+            Telemetry::Acceleration,
+            true,
+        )?;
+        replay.add_log(
+            directory,
+            DataSource::Gps,
+            GPS_LOG,
+            Telemetry::Position,
+            true,
+        )?;
+        replay.add_log(
+            directory,
+            DataSource::Groundtruth,
+            GROUNDTRUTH_LOG,
+            Telemetry::Position,
+            false,
+        )?;
+        replay.add_log(
+            directory,
+            DataSource::Barometer,
+            BAROMETER_LOG,
+            Telemetry::Altitude,
+            false,
+        )?;
+        replay.add_log(
+            directory,
+            DataSource::Magnetometer,
+            MAGNETOMETER_LOG,
+            Telemetry::MagneticField,
+            false,
+        )?;
+        replay.add_log(
+            directory,
+            DataSource::Odometry,
+            ODOMETRY_LOG,
+            Telemetry::Velocity,
+            false,
+        )?;
+        replay.add_log(directory, DataSource::Uwb, UWB_LOG, Telemetry::Range, false)?;
+        for (index, gps_receiver) in GPS_RECEIVERS.iter().enumerate() {
+            replay.add_log(
+                directory,
+                DataSource::GpsReceiver(index as u8),
+                gps_receiver.log_name,
+                Telemetry::Position,
+                false,
+            )?;
+        }
+        // stable sort, messages with equal timestamps keep the order of the logs above
+        replay
+            .messages
+            .sort_by_key(|message| message.telemetry.data().timestamp);
+        Ok(replay)
+    }
+
+    // GPS fixes of a recorded NMEA log, in the local frame around the first fix
+    pub fn load_nmea(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let nmea_log = read_nmea_log(path)?;
+        if nmea_log.skipped_lines > 0 {
+            log(
+                GENERAL_LOG,
+                format!(
+                    "{} invalid NMEA sentences of {} skipped",
+                    nmea_log.skipped_lines,
+                    path.display()
+                ),
+            );
+        }
+        let Some(&(_, origin)) = nmea_log.fixes.first() else {
+            return Err(format!("No GPS fix in {}", path.display()).into());
+        };
+        let messages = nmea_log
+            .fixes
+            .into_iter()
+            .map(|(timestamp, position)| {
+                let local = LOCAL_FRAME.geodetic_to_local(&origin, position);
+                ReplayedMessage {
+                    source: DataSource::Gps,
+                    log_name: GPS_LOG,
+                    telemetry: Telemetry::Position(Data {
+                        x: local.x,
+                        y: local.y,
+                        z: local.z,
+                        timestamp,
+                    }),
+                }
+            })
+            .collect();
+        Ok(Replay { messages })
+    }
+
+    fn add_log(
+        &mut self,
+        directory: &Path,
+        source: DataSource,
+        log_name: &'static str,
+        to_telemetry: fn(Data) -> Telemetry,
+        required: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = log_path_in(directory, log_name);
+        if !required && !path.exists() {
+            return Ok(());
+        }
+        self.messages.extend(
+            read_data_log(&path)?
+                .into_iter()
+                .map(|data| ReplayedMessage {
+                    source,
+                    log_name,
+                    telemetry: to_telemetry(data),
+                }),
+        );
+        Ok(())
+    }
+
+    pub fn message_count(&self) -> usize {
+        self.messages.len()
+    }
+
+    // Publishes every replayed source on the bus, consumers subscribe before the replay starts.
+    // Replayed messages are logged again, so the logs of the run stay complete.
+    pub fn run(self, bus: &Bus, speed: ReplaySpeed) -> JoinHandle<()> {
+        let mut publishers: Vec<(DataSource, Publisher<Telemetry>)> = Vec::new();
+        for message in &self.messages {
+            if !publishers
+                .iter()
+                .any(|(source, _)| *source == message.source)
+            {
+                publishers.push((message.source, bus.publisher(&message.source.topic())));
+            }
+        }
+
+        std::thread::spawn(move || {
+            let replay_start = Instant::now();
+            let first_timestamp = self
+                .messages
+                .first()
+                .map(|message| message.telemetry.data().timestamp);
+            for message in self.messages {
+                if let (ReplaySpeed::RealTime, Some(first_timestamp)) = (speed, first_timestamp) {
+                    wait_until(
+                        replay_start,
+                        first_timestamp,
+                        message.telemetry.data().timestamp,
+                    );
+                }
+                log(message.log_name, *message.telemetry.data());
+                if let Some((_, publisher)) = publishers
+                    .iter()
+                    .find(|(source, _)| *source == message.source)
+                {
+                    publisher.publish(message.telemetry);
+                }
+            }
+            log(GENERAL_LOG, "Replay finished".to_string());
+        })
+    }
+}
+
+fn wait_until(replay_start: Instant, first_timestamp: SystemTime, timestamp: SystemTime) {
+    let offset = timestamp
+        .duration_since(first_timestamp)
+        .unwrap_or_default();
+    if let Some(remaining) = offset.checked_sub(replay_start.elapsed()) {
+        std::thread::sleep(remaining);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        log_schema::{TelemetryKind, TelemetryRecord},
+        logger::LogEntry,
+    };
+    use ntest_timeout::timeout;
+    use std::{fs, path::PathBuf, sync::mpsc, time::Duration};
+
+    fn data(x: f64, timestamp: SystemTime) -> Data {
+        Data {
+            x,
+            y: 0.0,
+            z: 0.0,
+            timestamp,
+        }
+    }
+
+    fn write_log(directory: &Path, log_name: &str, entries: &[Data]) {
+        fs::create_dir_all(directory).unwrap();
+        let kind = if log_name == IMU_LOG {
+            TelemetryKind::Acceleration
+        } else {
+            TelemetryKind::Position
+        };
+        let mut writer = csv::Writer::from_path(log_path_in(directory, log_name)).unwrap();
+        for (seq, data) in entries.iter().enumerate() {
+            let entry = LogEntry {
+                timestamp: data.timestamp,
+                data: *data,
+            };
+            writer
+                .serialize(TelemetryRecord::from_data(
+                    seq as u64, log_name, kind, &entry,
+                ))
+                .unwrap();
+        }
+        writer.flush().unwrap();
+    }
+
+    fn recorded_run(directory: &str) -> (PathBuf, SystemTime) {
+        let directory = PathBuf::from(directory);
+        let start = SystemTime::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        write_log(
+            &directory,
+            IMU_LOG,
+            &[data(1.0, at(0)), data(2.0, at(100)), data(3.0, at(200))],
+        );
+        write_log(
+            &directory,
+            GPS_LOG,
+            &[data(10.0, at(50)), data(20.0, at(150))],
+        );
+        (directory, start)
+    }
+
+    #[test]
+    fn given_missing_imu_log_expect_error() {
+        assert!(Replay::load(Path::new("test_output_replay_missing")).is_err());
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn replay_publishes_messages_in_timestamp_order() {
+        let (directory, _) = recorded_run("test_output_replay_order");
+        let replay = Replay::load(&directory).unwrap();
+        let _ = fs::remove_dir_all(&directory);
+        assert_eq!(replay.message_count(), 5);
+
+        let bus = Bus::new();
+        let (tx, rx) = mpsc::channel();
+        bus.subscribe_with(&DataSource::Imu.topic(), tx.clone());
+        bus.subscribe_with(&DataSource::Gps.topic(), tx);
+        replay
+            .run(&bus, ReplaySpeed::AsFastAsPossible)
+            .join()
+            .unwrap();
+
+        let received: Vec<Telemetry> = rx.try_iter().map(|envelope| envelope.message).collect();
+        let values: Vec<f64> = received
+            .iter()
+            .map(|telemetry| telemetry.data().x)
+            .collect();
+        assert_eq!(values, vec![1.0, 10.0, 2.0, 20.0, 3.0]);
+        assert!(matches!(received[1], Telemetry::Position(_)));
+        assert!(matches!(received[2], Telemetry::Acceleration(_)));
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn replay_keeps_original_timestamps_and_optional_logs() {
+        let (directory, start) = recorded_run("test_output_replay_groundtruth");
+        write_log(
+            &directory,
+            GROUNDTRUTH_LOG,
+            &[data(5.0, start + Duration::from_millis(120))],
+        );
+        let replay = Replay::load(&directory).unwrap();
+        let _ = fs::remove_dir_all(&directory);
+
+        let bus = Bus::new();
+        let (tx_groundtruth, rx_groundtruth) = mpsc::channel();
+        bus.subscribe_with(&DataSource::Groundtruth.topic(), tx_groundtruth);
+        replay
+            .run(&bus, ReplaySpeed::AsFastAsPossible)
+            .join()
+            .unwrap();
+
+        let groundtruth = rx_groundtruth.recv().unwrap().message;
+        let difference = match groundtruth.data().timestamp.duration_since(start) {
+            Ok(difference) => difference,
+            Err(e) => e.duration(),
+        };
+        // timestamps are logged with millisecond resolution
+        assert!(difference.as_millis().abs_diff(120) <= 1);
+        assert!(rx_groundtruth.try_recv().is_err());
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn real_time_replay_follows_recorded_timing() {
+        let (directory, _) = recorded_run("test_output_replay_real_time");
+        let replay = Replay::load(&directory).unwrap();
+        let _ = fs::remove_dir_all(&directory);
+
+        let bus = Bus::new();
+        let (tx, rx) = mpsc::channel();
+        bus.subscribe_with(&DataSource::Imu.topic(), tx);
+        let replay_start = Instant::now();
+        replay.run(&bus, ReplaySpeed::RealTime).join().unwrap();
+
+        assert!(replay_start.elapsed() >= Duration::from_millis(195));
+        assert_eq!(rx.try_iter().count(), 3);
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn nmea_replay_publishes_gps_fixes_relative_to_the_first() {
+        let path = Path::new("test_output_replay.nmea");
+        fs::write(
+            path,
+            "$GPRMC,120000.00,A,5213.78200,N,02100.73200,E,0.0,0.0,150324,,,A\n\
+             $GPGGA,120000.00,5213.78200,N,02100.73200,E,1,08,0.9,100.0,M,0.0,M,,\n\
+             $GPGGA,120001.00,5213.78200,N,02100.73200,E,1,08,0.9,103.0,M,0.0,M,,\n",
+        )
+        .unwrap();
+        let replay = Replay::load_nmea(path).unwrap();
+        let _ = fs::remove_file(path);
+        assert_eq!(replay.message_count(), 2);
+
+        let bus = Bus::new();
+        let rx = bus.subscribe(&DataSource::Gps.topic());
+        replay
+            .run(&bus, ReplaySpeed::AsFastAsPossible)
+            .join()
+            .unwrap();
+        let received: Vec<Data> = rx
+            .try_iter()
+            .map(|envelope| *envelope.message.data())
+            .collect();
+        approx::assert_abs_diff_eq!(received[0].z, 0.0, epsilon = 1e-6);
+        approx::assert_abs_diff_eq!(received[1].z, 3.0, epsilon = 1e-6);
+        assert_eq!(
+            received[1]
+                .timestamp
+                .duration_since(received[0].timestamp)
+                .unwrap(),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn given_nmea_log_without_fix_expect_error() {
+        let path = Path::new("test_output_replay_no_fix.nmea");
+        fs::write(path, "$GPGGA,120000.00,,,,,0,00,99.9,,,,,,\n").unwrap();
+        let result = Replay::load_nmea(path);
+        let _ = fs::remove_file(path);
+        assert!(result.is_err());
+    }
+}
@@ -1,27 +1,50 @@
 use std::{
     num::NonZeroU32,
-    sync::{atomic::AtomicBool, mpsc::Sender, Arc, Mutex},
+    sync::{atomic::AtomicBool, Arc, Mutex},
     thread::JoinHandle,
 };
 
 use crate::{
-    data::{Data, Telemetry},
+    barometer::{Barometer, BarometerConfig},
+    bus::Publisher,
+    config::{
+        BAROMETER_CONFIG, EARTH_MAGNETIC_FIELD, MAGNETOMETER_CONFIG, ODOMETRY_CONFIG, UWB_ANCHORS,
+        UWB_CONFIG,
+    },
+    data::{KinematicState, Telemetry},
     gps::Gps,
     imu::Imu,
+    log_config::GPS_LOG,
+    magnetometer::{EarthFieldConfig, Magnetometer, MagnetometerConfig},
+    nmea::NmeaSink,
+    odometry::{OdometryConfig, WheelOdometry},
+    uwb::{UwbConfig, UwbRanging},
 };
 
 #[derive(PartialEq, Eq, Debug)]
 enum ProviderType {
     Gps,
     Imu,
+    Barometer,
+    Magnetometer,
+    Odometry,
+    Uwb,
 }
 
 pub struct SensorBuilder {
     provider_type: ProviderType,
     frequency: NonZeroU32,
-    transmitters: Vec<Sender<Telemetry>>,
-    position_generator: Arc<Mutex<Data>>,
+    publisher: Publisher<Telemetry>,
+    position_generator: Arc<Mutex<KinematicState>>,
     noise_standard_deviation: f64,
+    log_name: &'static str,
+    nmea_sink: Option<NmeaSink>,
+    barometer_config: BarometerConfig,
+    earth_field: EarthFieldConfig,
+    magnetometer_config: MagnetometerConfig,
+    odometry_config: OdometryConfig,
+    uwb_anchors: &'static [[f64; 3]],
+    uwb_config: UwbConfig,
 }
 
 impl SensorBuilder {
@@ -29,9 +52,17 @@ impl SensorBuilder {
         Self {
             provider_type: ProviderType::Imu,
             frequency: NonZeroU32::new(1).unwrap(),
-            transmitters: Vec::new(),
-            position_generator: Arc::new(Mutex::new(Data::new())),
+            publisher: Vec::new().into(),
+            position_generator: Arc::new(Mutex::new(KinematicState::new())),
             noise_standard_deviation: 0.0,
+            log_name: GPS_LOG,
+            nmea_sink: None,
+            barometer_config: BAROMETER_CONFIG,
+            earth_field: EARTH_MAGNETIC_FIELD,
+            magnetometer_config: MAGNETOMETER_CONFIG,
+            odometry_config: ODOMETRY_CONFIG,
+            uwb_anchors: &UWB_ANCHORS,
+            uwb_config: UWB_CONFIG,
         }
     }
 
@@ -49,46 +80,159 @@ impl SensorBuilder {
         }
     }
 
+    pub fn new_barometer() -> Self {
+        Self {
+            provider_type: ProviderType::Barometer,
+            ..Self::default()
+        }
+    }
+
+    pub fn new_magnetometer() -> Self {
+        Self {
+            provider_type: ProviderType::Magnetometer,
+            ..Self::default()
+        }
+    }
+
+    pub fn new_odometry() -> Self {
+        Self {
+            provider_type: ProviderType::Odometry,
+            ..Self::default()
+        }
+    }
+
+    pub fn new_uwb() -> Self {
+        Self {
+            provider_type: ProviderType::Uwb,
+            ..Self::default()
+        }
+    }
+
     pub fn with_frequency(self, frequency: NonZeroU32) -> Self {
         Self { frequency, ..self }
     }
 
-    pub fn with_position_generator(self, position_generator: Arc<Mutex<Data>>) -> Self {
+    pub fn with_position_generator(self, position_generator: Arc<Mutex<KinematicState>>) -> Self {
         Self {
             position_generator,
             ..self
         }
     }
 
-    pub fn with_subscribers(self, transmitters: Vec<Sender<Telemetry>>) -> Self {
+    pub fn with_publisher(self, publisher: Publisher<Telemetry>) -> Self {
+        Self { publisher, ..self }
+    }
+
+    pub fn with_output_noise(self, noise_standard_deviation: f64) -> Self {
         Self {
-            transmitters,
+            noise_standard_deviation,
             ..self
         }
     }
 
-    pub fn with_output_noise(self, noise_standard_deviation: f64) -> Self {
+    // Only GPS units log under a configurable name, so several of them can run side by side
+    pub fn with_log_name(self, log_name: &'static str) -> Self {
+        Self { log_name, ..self }
+    }
+
+    // Only GPS units write NMEA sentences
+    pub fn with_nmea_output(self, nmea_sink: NmeaSink) -> Self {
         Self {
-            noise_standard_deviation,
+            nmea_sink: Some(nmea_sink),
+            ..self
+        }
+    }
+
+    // Only barometers use it, instead of the output noise
+    pub fn with_barometer_config(self, barometer_config: BarometerConfig) -> Self {
+        Self {
+            barometer_config,
+            ..self
+        }
+    }
+
+    // Only magnetometers sample the Earth field, distorted by their own config
+    pub fn with_earth_field(self, earth_field: EarthFieldConfig) -> Self {
+        Self {
+            earth_field,
+            ..self
+        }
+    }
+
+    pub fn with_magnetometer_config(self, magnetometer_config: MagnetometerConfig) -> Self {
+        Self {
+            magnetometer_config,
+            ..self
+        }
+    }
+
+    // Only wheel odometry uses it, instead of the output noise
+    pub fn with_odometry_config(self, odometry_config: OdometryConfig) -> Self {
+        Self {
+            odometry_config,
+            ..self
+        }
+    }
+
+    // Only UWB ranging uses them, the anchors in the local frame and the range errors
+    pub fn with_uwb_anchors(self, uwb_anchors: &'static [[f64; 3]]) -> Self {
+        Self {
+            uwb_anchors,
             ..self
         }
     }
 
+    pub fn with_uwb_config(self, uwb_config: UwbConfig) -> Self {
+        Self { uwb_config, ..self }
+    }
+
     pub fn spawn(self, shutdown: Arc<AtomicBool>) -> JoinHandle<()> {
         match self.provider_type {
             ProviderType::Imu => Imu::run(
                 self.position_generator,
-                self.transmitters,
+                self.publisher,
                 shutdown,
                 self.frequency,
                 self.noise_standard_deviation,
             ),
             ProviderType::Gps => Gps::run(
                 self.position_generator,
-                self.transmitters,
+                self.publisher,
                 shutdown,
                 self.frequency,
                 self.noise_standard_deviation,
+                self.log_name,
+                self.nmea_sink,
+            ),
+            ProviderType::Barometer => Barometer::run(
+                self.position_generator,
+                self.publisher,
+                shutdown,
+                self.frequency,
+                self.barometer_config,
+            ),
+            ProviderType::Magnetometer => Magnetometer::run(
+                self.position_generator,
+                self.publisher,
+                shutdown,
+                self.frequency,
+                self.earth_field,
+                self.magnetometer_config,
+            ),
+            ProviderType::Odometry => WheelOdometry::run(
+                self.position_generator,
+                self.publisher,
+                shutdown,
+                self.frequency,
+                self.odometry_config,
+            ),
+            ProviderType::Uwb => UwbRanging::run(
+                self.position_generator,
+                self.publisher,
+                shutdown,
+                self.frequency,
+                self.uwb_anchors,
+                self.uwb_config,
             ),
         }
     }
@@ -104,7 +248,7 @@ mod tests {
         let expected_freq = NonZeroU32::new(1).unwrap();
         let imu_config = SensorBuilder::default();
         assert_eq!(imu_config.frequency, expected_freq);
-        assert!(imu_config.transmitters.is_empty());
+        assert_eq!(imu_config.publisher.subscriber_count(), 0);
     }
 
     #[test]
@@ -119,6 +263,88 @@ mod tests {
         assert_eq!(builder_cfg.provider_type, ProviderType::Gps);
     }
 
+    #[test]
+    fn given_new_barometer_expect_builder_with_barometer_as_signal_provider() {
+        let builder_cfg = SensorBuilder::new_barometer();
+        assert_eq!(builder_cfg.provider_type, ProviderType::Barometer);
+    }
+
+    #[test]
+    fn given_barometer_config_expect_builder_with_set_config() {
+        let barometer_config = BarometerConfig {
+            noise_sigma: 2.5,
+            ..BAROMETER_CONFIG
+        };
+        let builder_cfg = SensorBuilder::new_barometer().with_barometer_config(barometer_config);
+        assert_eq!(builder_cfg.barometer_config.noise_sigma, 2.5);
+    }
+
+    #[test]
+    fn given_new_magnetometer_expect_builder_with_magnetometer_as_signal_provider() {
+        let builder_cfg = SensorBuilder::new_magnetometer();
+        assert_eq!(builder_cfg.provider_type, ProviderType::Magnetometer);
+    }
+
+    #[test]
+    fn given_magnetometer_config_expect_builder_with_set_config() {
+        let earth_field = EarthFieldConfig {
+            declination: -3.0,
+            ..EARTH_MAGNETIC_FIELD
+        };
+        let magnetometer_config = MagnetometerConfig {
+            hard_iron: [1.0, 2.0, 3.0],
+            ..MAGNETOMETER_CONFIG
+        };
+        let builder_cfg = SensorBuilder::new_magnetometer()
+            .with_earth_field(earth_field)
+            .with_magnetometer_config(magnetometer_config);
+        assert_eq!(builder_cfg.earth_field.declination, -3.0);
+        assert_eq!(builder_cfg.magnetometer_config.hard_iron, [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn given_new_odometry_expect_builder_with_odometry_as_signal_provider() {
+        let builder_cfg = SensorBuilder::new_odometry();
+        assert_eq!(builder_cfg.provider_type, ProviderType::Odometry);
+    }
+
+    #[test]
+    fn given_odometry_config_expect_builder_with_set_config() {
+        let odometry_config = OdometryConfig {
+            scale_error: -0.1,
+            ..ODOMETRY_CONFIG
+        };
+        let builder_cfg = SensorBuilder::new_odometry().with_odometry_config(odometry_config);
+        assert_eq!(builder_cfg.odometry_config.scale_error, -0.1);
+    }
+
+    #[test]
+    fn given_new_uwb_expect_builder_with_uwb_as_signal_provider() {
+        let builder_cfg = SensorBuilder::new_uwb();
+        assert_eq!(builder_cfg.provider_type, ProviderType::Uwb);
+    }
+
+    #[test]
+    fn given_uwb_anchors_and_config_expect_builder_with_set_values() {
+        const ANCHORS: [[f64; 3]; 1] = [[1.0, 2.0, 3.0]];
+        let uwb_config = UwbConfig {
+            noise_sigma: 0.5,
+            ..UWB_CONFIG
+        };
+        let builder_cfg = SensorBuilder::new_uwb()
+            .with_uwb_anchors(&ANCHORS)
+            .with_uwb_config(uwb_config);
+        assert_eq!(builder_cfg.uwb_anchors, &ANCHORS);
+        assert_eq!(builder_cfg.uwb_config.noise_sigma, 0.5);
+    }
+
+    #[test]
+    fn given_log_name_expect_builder_with_set_log_name() {
+        let builder_cfg =
+            SensorBuilder::new_gps().with_log_name(crate::log_config::GPS_RECEIVER_1_LOG);
+        assert_eq!(builder_cfg.log_name, crate::log_config::GPS_RECEIVER_1_LOG);
+    }
+
     #[test]
     fn given_new_frequency_expect_builder_with_set_frequency() {
         let frequency = NonZeroU32::new(5).unwrap();
@@ -127,11 +353,11 @@ mod tests {
     }
 
     #[test]
-    fn given_subscribers_expect_builder_with_provided_senders() {
+    fn given_publisher_expect_builder_with_its_subscribers() {
         let (tx_1, _) = std::sync::mpsc::channel();
         let (tx_2, _) = std::sync::mpsc::channel();
-        let builder_cfg = SensorBuilder::default().with_subscribers(vec![tx_1, tx_2]);
-        assert_eq!(builder_cfg.transmitters.len(), 2);
+        let builder_cfg = SensorBuilder::default().with_publisher(vec![tx_1, tx_2].into());
+        assert_eq!(builder_cfg.publisher.subscriber_count(), 2);
     }
 
     #[test]
@@ -143,19 +369,23 @@ mod tests {
 
     #[test]
     fn given_position_generator_expect_builder_with_provided_generator() {
-        let position_generator = Arc::new(Mutex::new(Data::new()));
+        let position_generator = Arc::new(Mutex::new(KinematicState::new()));
         let builder_cfg =
             SensorBuilder::default().with_position_generator(Arc::clone(&position_generator));
         let expected_x = 1.0;
         let expected_y = 2.0;
         let expected_z = 3.0;
         {
-            let mut pos = position_generator.lock().unwrap();
-            pos.x = expected_x;
-            pos.y = expected_y;
-            pos.z = expected_z;
+            let mut state = position_generator.lock().unwrap();
+            state.position.x = expected_x;
+            state.position.y = expected_y;
+            state.position.z = expected_z;
         }
-        let position_data = builder_cfg.position_generator.lock().unwrap();
+        let position_data = builder_cfg
+            .position_generator
+            .lock()
+            .unwrap()
+            .position_data();
         approx::assert_abs_diff_eq!(position_data.x, expected_x);
         approx::assert_abs_diff_eq!(position_data.y, expected_y);
         approx::assert_abs_diff_eq!(position_data.z, expected_z);
@@ -167,10 +397,10 @@ mod tests {
         let shutdown = Arc::new(AtomicBool::new(false));
         let (tx, rx) = std::sync::mpsc::channel();
         let handle = SensorBuilder::new_imu()
-            .with_subscribers(vec![tx])
+            .with_publisher(vec![tx].into())
             .spawn(Arc::clone(&shutdown));
 
-        let Telemetry::Acceleration(_) = rx.recv().unwrap() else {
+        let Telemetry::Acceleration(_) = rx.recv().unwrap().message else {
             panic!("IMU should provide acceleration. Got position.")
         };
         shutdown.store(true, std::sync::atomic::Ordering::SeqCst);
@@ -183,13 +413,96 @@ mod tests {
         let shutdown = Arc::new(AtomicBool::new(false));
         let (tx, rx) = std::sync::mpsc::channel();
         let handle = SensorBuilder::new_gps()
-            .with_subscribers(vec![tx])
+            .with_publisher(vec![tx].into())
             .spawn(Arc::clone(&shutdown));
 
-        let Telemetry::Position(_) = rx.recv().unwrap() else {
+        let Telemetry::Position(_) = rx.recv().unwrap().message else {
             panic!("GPS should provide position. Got acceleration.")
         };
         shutdown.store(true, std::sync::atomic::Ordering::SeqCst);
         handle.join().unwrap();
     }
+
+    #[test]
+    #[timeout(10000)]
+    fn given_barometer_builder_expect_spawn_to_start_barometer() {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = std::sync::mpsc::channel();
+        let handle = SensorBuilder::new_barometer()
+            .with_publisher(vec![tx].into())
+            .spawn(Arc::clone(&shutdown));
+
+        let Telemetry::Altitude(_) = rx.recv().unwrap().message else {
+            panic!("Barometer should provide altitude.")
+        };
+        shutdown.store(true, std::sync::atomic::Ordering::SeqCst);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn given_magnetometer_builder_expect_spawn_to_start_magnetometer() {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = std::sync::mpsc::channel();
+        let handle = SensorBuilder::new_magnetometer()
+            .with_publisher(vec![tx].into())
+            .spawn(Arc::clone(&shutdown));
+
+        let Telemetry::MagneticField(_) = rx.recv().unwrap().message else {
+            panic!("Magnetometer should provide the magnetic field.")
+        };
+        shutdown.store(true, std::sync::atomic::Ordering::SeqCst);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn given_odometry_builder_expect_spawn_to_start_odometry() {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = std::sync::mpsc::channel();
+        let handle = SensorBuilder::new_odometry()
+            .with_publisher(vec![tx].into())
+            .spawn(Arc::clone(&shutdown));
+
+        let Telemetry::Velocity(_) = rx.recv().unwrap().message else {
+            panic!("Wheel odometry should provide velocity.")
+        };
+        shutdown.store(true, std::sync::atomic::Ordering::SeqCst);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn given_uwb_builder_expect_spawn_to_start_ranging() {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = std::sync::mpsc::channel();
+        let handle = SensorBuilder::new_uwb()
+            .with_publisher(vec![tx].into())
+            .spawn(Arc::clone(&shutdown));
+
+        let Telemetry::Range(_) = rx.recv().unwrap().message else {
+            panic!("UWB ranging should provide ranges.")
+        };
+        shutdown.store(true, std::sync::atomic::Ordering::SeqCst);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn given_nmea_output_expect_gps_to_write_sentences() {
+        let path = "test_output_sensor_builder.nmea";
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = std::sync::mpsc::channel();
+        let handle = SensorBuilder::new_gps()
+            .with_publisher(vec![tx].into())
+            .with_nmea_output(NmeaSink::open(crate::nmea::NmeaOutput::File(path)).unwrap())
+            .spawn(Arc::clone(&shutdown));
+
+        rx.recv().unwrap();
+        shutdown.store(true, std::sync::atomic::Ordering::SeqCst);
+        handle.join().unwrap();
+        let sentences = std::fs::read_to_string(path).unwrap();
+        let _ = std::fs::remove_file(path);
+        assert!(sentences.starts_with("$GPGGA,"));
+    }
 }
@@ -1,48 +1,120 @@
-use crate::data::Data;
+use crate::csv_handler::read_recorded_trajectory;
+use crate::data::KinematicState;
 use crate::log_config::{GROUNDTRUTH_LOG, GENERAL_LOG};
 use crate::logger::log;
-use noise::{NoiseFn, Perlin};
+use nalgebra::Vector3;
+use noise::permutationtable::{NoiseHasher, PermutationTable};
 use rand::Rng;
 use std::{
+    error::Error,
     f64::consts::PI,
     num::NonZeroU32,
+    path::Path,
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc::Sender,
         Arc, Mutex,
     },
     thread::JoinHandle,
     time::{SystemTime, UNIX_EPOCH},
 };
 
-use crate::config::HELIX_FREQUENCY;
+use crate::config::{CAR_LIMITS, HELIX_FREQUENCY, LOCAL_FRAME, PEDESTRIAN_LIMITS, QUADROTOR_LIMITS};
 use crate::utils::get_cycle_duration;
 use crate::Telemetry;
-
+use crate::bus::Publisher;
+
+pub mod recorded;
+pub mod spline;
+pub mod vehicle;
+
+use recorded::{attitude_from_recording, spline_from_recording, AttitudeTrack};
+use spline::{InvalidWaypoints, QuinticSpline, Waypoint};
+use vehicle::{Vehicle, VehicleLimits};
+
+// Range of the generated coordinates is [0, 2 * HALF_RANGE]
+const HALF_RANGE: f64 = 50.0;
+const DETERMINISTIC_PERLIN_STEP: f64 = 0.1;
+// Time to move from one random point to the next in the random mode, in s
+const RANDOM_SEGMENT_DURATION: f64 = 5.0;
+// Below this speed, in m/s, modes without an attitude of their own keep the last heading
+const HEADING_MIN_SPEED: f64 = 0.1;
+// 2 / sqrt(3), scales 3D Perlin noise to [-1, 1] as noise::Perlin does
+const PERLIN_SCALE: f64 = 1.154_700_538_379_251_5;
+// Gradients of noise::Perlin in 3D, indexed by the lower 4 bits of the lattice point hash
+#[rustfmt::skip]
+const PERLIN_GRADIENTS: [[f64; 3]; 16] = [
+    [1.0, 1.0, 0.0], [-1.0, 1.0, 0.0], [1.0, -1.0, 0.0], [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0], [-1.0, 0.0, 1.0], [1.0, 0.0, -1.0], [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0], [0.0, -1.0, 1.0], [0.0, 1.0, -1.0], [0.0, -1.0, -1.0],
+    [1.0, 1.0, 0.0], [-1.0, 1.0, 0.0], [0.0, -1.0, 1.0], [0.0, -1.0, -1.0],
+]; 16] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [0.0, -1.0, 1.0],
+    [0.0, -1.0, -1.0],
+];
+
+// Straight segment between two random points of the cube, the current one of the random mode
 #[derive(Clone, Copy)]
+pub struct RandomSegment {
+    start: Vector3<f64>,
+    end: Vector3<f64>,
+    index: u64,
+}
+
+impl RandomSegment {
+    fn new() -> Self {
+        RandomSegment {
+            start: random_point(),
+            end: random_point(),
+            index: 0,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub enum GenerationMode {
-    Random,
+    Random(RandomSegment),
     Perlin,
     DeterminicticPerlin,
-    AngledHelical(f64),
+    AngledHelical(f64), // angular frequency in rad/s
+    Spline(QuinticSpline),
+    Vehicle(Vehicle),
+    FromFile(QuinticSpline, Option<AttitudeTrack>),
 }
 
 pub struct TrajectoryGenerator {
-    data_handle: Arc<Mutex<Data>>,
+    data_handle: Arc<Mutex<KinematicState>>,
     shutdown_trigger: Arc<AtomicBool>,
     mode: GenerationMode,
     seed: u32,
     step: f64,
-    subscribers: Vec<Sender<Telemetry>>,
+    frequency: NonZeroU32,
+    start_time: SystemTime,
+    attitude: Vector3<f64>, // of the last generated state
+    publisher: Publisher<Telemetry>,
 }
 
 impl TrajectoryGenerator {
     fn new(
-        data_handle: Arc<Mutex<Data>>,
+        data_handle: Arc<Mutex<KinematicState>>,
         shutdown_trigger: Arc<AtomicBool>,
         mode: GenerationMode,
         seed: u32,
-        subscribers: Vec<Sender<Telemetry>>,
+        frequency: NonZeroU32,
+        publisher: Publisher<Telemetry>,
     ) -> TrajectoryGenerator {
         TrajectoryGenerator {
             data_handle,
@@ -50,101 +122,242 @@ impl TrajectoryGenerator {
             mode,
             seed,
             step: 0.0,
-            subscribers,
+            frequency,
+            start_time: SystemTime::now(),
+            attitude: Vector3::zeros(),
+            publisher,
         }
     }
 
-    fn generate_data(&mut self) -> Data {
-        match self.mode {
-            GenerationMode::Random => self.generate_rnd_data(),
+    // The attitude comes from the vehicle models and recordings that have one,
+    // the other modes follow their velocity
+    fn generate_data(&mut self) -> KinematicState {
+        let mut state = self.generate_motion();
+        let attitude = match &self.mode {
+            GenerationMode::Vehicle(vehicle) => Some(vehicle.attitude()),
+            GenerationMode::FromFile(_, Some(track)) => {
+                Some(track.at(self.elapsed_secs(state.timestamp)))
+            }
+            _ => None,
+        };
+        state.attitude =
+            attitude.unwrap_or_else(|| attitude_along(&state.velocity, &self.attitude));
+        self.attitude = state.attitude;
+        state
+    }
+
+    fn generate_motion(&mut self) -> KinematicState {
+        match &self.mode {
+            GenerationMode::Random(_) => self.generate_rnd_data(),
             GenerationMode::Perlin => self.generate_perlin_data(),
             GenerationMode::DeterminicticPerlin => self.generate_deterministic_perlin_data(),
-            GenerationMode::AngledHelical(step) => self.generate_angled_helical_data(step),
+            GenerationMode::AngledHelical(angular_frequency) => {
+                self.generate_angled_helical_data(*angular_frequency)
+            }
+            GenerationMode::Spline(spline) | GenerationMode::FromFile(spline, _) => {
+                self.generate_spline_data(spline)
+            }
+            GenerationMode::Vehicle(_) => self.generate_vehicle_data(),
         }
     }
 
-    fn generate_perlin_data(&self) -> Data {
-        let perlin = Perlin::new(self.seed);
+    fn elapsed_secs(&self, timestamp: SystemTime) -> f64 {
+        timestamp
+            .duration_since(self.start_time)
+            .unwrap_or_default()
+            .as_secs_f64()
+    }
+
+    fn generate_perlin_data(&self) -> KinematicState {
+        let perlin = PermutationTable::new(self.seed);
+        let timestamp = SystemTime::now();
 
-        let secs = SystemTime::now()
+        let secs = timestamp
             .duration_since(UNIX_EPOCH)
             .expect("Time went backwards")
             .as_secs_f64();
 
-        let x = upscale(perlin.get([secs, 0.0, 0.0]));
-        let y = upscale(perlin.get([0.0, secs, 0.0]));
-        let z = upscale(perlin.get([0.0, 0.0, secs]));
-        
-        Data {
-            x,
-            y,
-            z,
-            timestamp: SystemTime::now(),
-        }
+        sample_perlin(&perlin, secs, 1.0, timestamp)
     }
 
-    fn generate_deterministic_perlin_data(&mut self) -> Data {
-        let perlin = Perlin::new(self.seed);
-        self.step += 0.1;
+    fn generate_deterministic_perlin_data(&mut self) -> KinematicState {
+        let perlin = PermutationTable::new(self.seed);
+        self.step += DETERMINISTIC_PERLIN_STEP;
 
-        let x = upscale(perlin.get([self.step, 0.0, 0.0]));
-        let y = upscale(perlin.get([0.0, self.step, 0.0]));
-        let z = upscale(perlin.get([0.0, 0.0, self.step]));
+        // the noise input advances by a fixed step per cycle, so its rate follows the nominal frequency
+        let input_rate = DETERMINISTIC_PERLIN_STEP * self.frequency.get() as f64;
+        sample_perlin(&perlin, self.step, input_rate, SystemTime::now())
+    }
 
-        Data {
-            x,
-            y,
-            z,
-            timestamp: SystemTime::now(),
+    // Moves from one random point to the next, starting and stopping at rest on a quintic profile,
+    // so the position stays continuous and the acceleration is what moves the vehicle
+    fn generate_rnd_data(&mut self) -> KinematicState {
+        let timestamp = SystemTime::now();
+        let elapsed = self.elapsed_secs(timestamp);
+        let GenerationMode::Random(segment) = &mut self.mode else {
+            unreachable!("Random data requested outside of the random mode");
+        };
+        let index = (elapsed / RANDOM_SEGMENT_DURATION) as u64;
+        while segment.index < index {
+            segment.start = segment.end;
+            segment.end = random_point();
+            segment.index += 1;
+        }
+        let progress = elapsed / RANDOM_SEGMENT_DURATION - index as f64;
+        let (fade, fade_rate, fade_acceleration) = quintic_fade(progress);
+        let displacement = segment.end - segment.start;
+        KinematicState {
+            position: segment.start + displacement * fade,
+            velocity: displacement * (fade_rate / RANDOM_SEGMENT_DURATION),
+            acceleration: displacement * (fade_acceleration / RANDOM_SEGMENT_DURATION.powi(2)),
+            attitude: Vector3::zeros(),
+            timestamp,
+        }
+    }
+
+    fn generate_angled_helical_data(&self, angular_frequency: f64) -> KinematicState {
+        let timestamp = SystemTime::now();
+        let phase = angular_frequency * self.elapsed_secs(timestamp);
+        let (sin, cos) = phase.sin_cos();
+        KinematicState {
+            position: Vector3::new(upscale(sin), upscale(cos), upscale(sin)),
+            velocity: Vector3::new(cos, -sin, cos) * (HALF_RANGE * angular_frequency),
+            acceleration: Vector3::new(sin, cos, sin) * (-HALF_RANGE * angular_frequency.powi(2)),
+            attitude: Vector3::zeros(),
+            timestamp,
         }
     }
 
-    fn generate_rnd_data(&self) -> Data {
-        let mut rng = rand::rng();
-        Data {
-            x: rng.random_range(0.0..=100.0),
-            y: rng.random_range(0.0..=100.0),
-            z: rng.random_range(0.0..=100.0),
-            timestamp: SystemTime::now(),
+    // The spline is evaluated at the elapsed wall-clock time, so position, velocity and
+    // acceleration stay analytically consistent regardless of the generator frequency
+    fn generate_spline_data(&self, spline: &QuinticSpline) -> KinematicState {
+        let timestamp = SystemTime::now();
+        let sample = spline.sample(self.elapsed_secs(timestamp));
+        KinematicState {
+            position: sample.position,
+            velocity: sample.velocity,
+            acceleration: sample.acceleration,
+            attitude: Vector3::zeros(),
+            timestamp,
         }
     }
 
-    fn generate_angled_helical_data(&mut self, step_size: f64) -> Data {
-        self.step += step_size;
-        Data {
-            x: upscale(self.step.sin()),
-            y: upscale(self.step.cos()),
-            z: upscale(self.step.sin()),
-            timestamp: SystemTime::now(),
+    // Vehicle models are integrated up to the elapsed wall-clock time, like the spline is sampled
+    fn generate_vehicle_data(&mut self) -> KinematicState {
+        let timestamp = SystemTime::now();
+        let elapsed = self.elapsed_secs(timestamp);
+        let GenerationMode::Vehicle(vehicle) = &mut self.mode else {
+            unreachable!("Vehicle data requested outside of the vehicle mode");
+        };
+        let sample = vehicle.advance_to(elapsed);
+        KinematicState {
+            position: sample.position,
+            velocity: sample.velocity,
+            acceleration: sample.acceleration,
+            attitude: Vector3::zeros(),
+            timestamp,
         }
     }
 }
 
-#[inline]
-fn upscale(value: f64) -> f64 {
-    (value + 1.0) * 50.0
+// Every coordinate is the 3D Perlin noise of noise::Perlin sampled along its own axis, differentiated
+// in closed form. The result depends only on the input, not on the generator timing.
+fn sample_perlin(
+    perlin: &PermutationTable,
+    input: f64,
+    input_rate: f64,
+    timestamp: SystemTime,
+) -> KinematicState {
+    let mut state = KinematicState {
+        timestamp,
+        ..KinematicState::new()
+    };
+    for axis in 0..3 {
+        let (noise, noise_rate, noise_acceleration) = perlin_on_axis(perlin, axis, input);
+        state.position[axis] = upscale(noise);
+        state.velocity[axis] = noise_rate * input_rate * HALF_RANGE;
+        state.acceleration[axis] = noise_acceleration * input_rate.powi(2) * HALF_RANGE;
+    }
+    state
+}
+
+// On an axis only the lattice points of the axis contribute, so the noise reduces to the 1D gradient noise
+// n = a d + s(d) (b (d - 1) - a d), with d the distance from the lattice point below, a and b the gradient
+// components along the axis at the lattice points around and s the quintic fade curve.
+// Returns the noise with its first and second derivative.
+fn perlin_on_axis(perlin: &PermutationTable, axis: usize, input: f64) -> (f64, f64, f64) {
+    let lattice = input.floor();
+    let d = input - lattice;
+    let gradient = |offset: isize| {
+        let mut point = [0; 3];
+        point[axis] = lattice as isize + offset;
+        PERLIN_GRADIENTS[perlin.hash(&point) & 0b1111][axis]
+    };
+    let (a, b) = (gradient(0), gradient(1));
+    let (fade, fade_rate, fade_acceleration) = quintic_fade(d);
+    let blend = b * (d - 1.0) - a * d;
+    (
+        PERLIN_SCALE * (a * d + fade * blend),
+        PERLIN_SCALE * (a + fade_rate * blend + fade * (b - a)),
+        PERLIN_SCALE * (fade_acceleration * blend + 2.0 * fade_rate * (b - a)),
+    )
+}
+
+// s = 6t^5 - 15t^4 + 10t^3 with its first and second derivative, from 0 to 1 with both at zero on the ends
+fn quintic_fade(t: f64) -> (f64, f64, f64) {
+    (
+        t.powi(3) * (t * (t * 6.0 - 15.0) + 10.0),
+        30.0 * t.powi(2) * (t - 1.0).powi(2),
+        60.0 * t * (2.0 * t - 1.0) * (t - 1.0),
+    )
+}
+
+// Heads and climbs along the velocity without rolling, the previous attitude is kept when nearly at rest
+fn attitude_along(velocity: &Vector3<f64>, previous: &Vector3<f64>) -> Vector3<f64> {
+    if velocity.norm() < HEADING_MIN_SPEED {
+        return *previous;
+    }
+    let horizontal_speed = velocity.xy().norm();
+    let yaw = if horizontal_speed < HEADING_MIN_SPEED {
+        previous.z
+    } else {
+        velocity.y.atan2(velocity.x)
+    };
+    // a positive rotation about the left axis pitches the nose down
+    Vector3::new(0.0, -velocity.z.atan2(horizontal_speed), yaw)
+}
+
+fn random_point() -> Vector3<f64> {
+    let mut rng = rand::rng();
+    Vector3::new(
+        rng.random_range(0.0..=2.0 * HALF_RANGE),
+        rng.random_range(0.0..=2.0 * HALF_RANGE),
+        rng.random_range(0.0..=2.0 * HALF_RANGE),
+    )
 }
 
 #[inline]
-fn get_helix_step(gen_freq: u32) -> f64 {
-    2.0 * PI * (HELIX_FREQUENCY / (gen_freq as f64))
+fn upscale(value: f64) -> f64 {
+    (value + 1.0) * HALF_RANGE
 }
 
 pub struct TrajectoryGeneratorBuilder {
     mode: GenerationMode,
     frequency: NonZeroU32,
     seed: Option<u32>,
-    subscribers: Vec<Sender<Telemetry>>,
+    vehicle_limits: Option<VehicleLimits>,
+    publisher: Publisher<Telemetry>,
 }
 
 #[allow(dead_code)]
 impl TrajectoryGeneratorBuilder {
     pub fn new() -> Self {
         TrajectoryGeneratorBuilder {
-            mode: GenerationMode::Random,
+            mode: GenerationMode::Random(RandomSegment::new()),
             frequency: NonZeroU32::new(1).unwrap(),
             seed: None,
-            subscribers: vec![],
+            vehicle_limits: None,
+            publisher: Vec::new().into(),
         }
     }
 
@@ -159,13 +372,49 @@ impl TrajectoryGeneratorBuilder {
     }
 
     pub fn with_random_mode(mut self) -> Self {
-        self.mode = GenerationMode::Random;
+        self.mode = GenerationMode::Random(RandomSegment::new());
         self
     }
 
-    // make sure to delcare frequency before this mode
     pub fn with_angled_helical_mode(mut self) -> Self {
-        self.mode = GenerationMode::AngledHelical(get_helix_step(self.frequency.get()));
+        self.mode = GenerationMode::AngledHelical(2.0 * PI * HELIX_FREQUENCY);
+        self
+    }
+
+    pub fn with_waypoint_mode(mut self, waypoints: &[Waypoint]) -> Result<Self, InvalidWaypoints> {
+        self.mode = GenerationMode::Spline(QuinticSpline::new(waypoints)?);
+        Ok(self)
+    }
+
+    // Replays a recorded trajectory (CSV with time,x,y,z and optionally vx,vy,vz columns),
+    // interpolated between the recorded samples like the waypoint mode
+    pub fn with_file_mode(mut self, path: &Path) -> Result<Self, Box<dyn Error>> {
+        let samples = read_recorded_trajectory(path)?;
+        self.mode = GenerationMode::FromFile(
+            spline_from_recording(&samples)?,
+            attitude_from_recording(&samples),
+        );
+        Ok(self)
+    }
+
+    pub fn with_car_mode(mut self) -> Self {
+        self.mode = GenerationMode::Vehicle(Vehicle::car(CAR_LIMITS));
+        self
+    }
+
+    pub fn with_quadrotor_mode(mut self) -> Self {
+        self.mode = GenerationMode::Vehicle(Vehicle::quadrotor(QUADROTOR_LIMITS));
+        self
+    }
+
+    pub fn with_pedestrian_mode(mut self) -> Self {
+        self.mode = GenerationMode::Vehicle(Vehicle::pedestrian(PEDESTRIAN_LIMITS));
+        self
+    }
+
+    // Overrides the default limits of the vehicle modes, ignored by the other modes
+    pub fn with_vehicle_limits(mut self, limits: VehicleLimits) -> Self {
+        self.vehicle_limits = Some(limits);
         self
     }
 
@@ -184,21 +433,30 @@ impl TrajectoryGeneratorBuilder {
         self
     }
 
-    pub fn with_subscribers(self, subscribers: Vec<Sender<Telemetry>>) -> Self {
+    pub fn with_publisher(self, publisher: Publisher<Telemetry>) -> Self {
         Self {
-            subscribers,
+            publisher,
             ..self
         }
     }
 
-    pub fn spawn(self, shutdown_trigger: Arc<AtomicBool>) -> (Arc<Mutex<Data>>, JoinHandle<()>) {
-        let data_handle = Arc::new(Mutex::new(Data::new()));
+    pub fn spawn(
+        mut self,
+        shutdown_trigger: Arc<AtomicBool>,
+    ) -> (Arc<Mutex<KinematicState>>, JoinHandle<()>) {
+        if let (GenerationMode::Vehicle(vehicle), Some(limits)) =
+            (&mut self.mode, self.vehicle_limits)
+        {
+            vehicle.set_limits(limits);
+        }
+        let data_handle = Arc::new(Mutex::new(KinematicState::new()));
         let mut generator = TrajectoryGenerator::new(
             Arc::clone(&data_handle),
             Arc::clone(&shutdown_trigger),
             self.mode,
             self.seed.unwrap_or_default(),
-            self.subscribers,
+            self.frequency,
+            self.publisher,
         );
 
         *generator.data_handle.lock().unwrap() = generator.generate_data();
@@ -206,16 +464,13 @@ impl TrajectoryGeneratorBuilder {
         let frequency = self.frequency;
         let generator_handle = std::thread::spawn(move || {
             while !generator.shutdown_trigger.load(Ordering::SeqCst) {
-                let data = generator.generate_data();
+                let state = generator.generate_data();
+                let data = LOCAL_FRAME.simulated_to_local(state.position_data());
                 log(GROUNDTRUTH_LOG, data);
                 {
-                    *generator.data_handle.lock().unwrap() = data;
-                }
-                if !generator.subscribers.is_empty() {
-                    generator
-                        .subscribers
-                        .retain(|tx| tx.send(Telemetry::Position(data)).is_ok());
+                    *generator.data_handle.lock().unwrap() = state;
                 }
+                generator.publisher.publish(Telemetry::Position(data));
 
                 std::thread::sleep(get_cycle_duration(frequency));
             }
@@ -229,6 +484,7 @@ impl TrajectoryGeneratorBuilder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use noise::{NoiseFn, Perlin};
     use std::sync::atomic::AtomicBool;
     use std::sync::mpsc;
     use std::sync::Arc;
@@ -241,7 +497,7 @@ mod tests {
         let (data_handle, handle) = TrajectoryGeneratorBuilder::new()
             .with_frequency(NonZeroU32::new(10).unwrap())
             .with_random_mode()
-            .with_subscribers(vec![tx])
+            .with_publisher(vec![tx].into())
             .spawn(Arc::clone(&shutdown));
 
         std::thread::sleep(Duration::from_millis(300));
@@ -249,7 +505,7 @@ mod tests {
         shutdown.store(true, Ordering::SeqCst);
         handle.join().unwrap();
 
-        let data = data_handle.lock().unwrap();
+        let data = data_handle.lock().unwrap().position_data();
         assert!(data.x >= 0.0 && data.x <= 100.0);
         assert!(data.y >= 0.0 && data.y <= 100.0);
         assert!(data.z >= 0.0 && data.z <= 100.0);
@@ -263,7 +519,7 @@ mod tests {
         let (data_handle, handle) = TrajectoryGeneratorBuilder::new()
             .with_frequency(NonZeroU32::new(5).unwrap())
             .with_angled_helical_mode()
-            .with_subscribers(vec![tx])
+            .with_publisher(vec![tx].into())
             .spawn(Arc::clone(&shutdown));
 
         std::thread::sleep(Duration::from_millis(300));
@@ -271,7 +527,7 @@ mod tests {
         shutdown.store(true, Ordering::SeqCst);
         handle.join().unwrap();
 
-        let data = data_handle.lock().unwrap();
+        let data = data_handle.lock().unwrap().position_data();
         assert!(data.x >= 0.0 && data.x <= 100.0);
         assert!(data.y >= 0.0 && data.y <= 100.0);
         assert!(data.z >= 0.0 && data.z <= 100.0);
@@ -285,7 +541,7 @@ mod tests {
         let (data_handle, handle) = TrajectoryGeneratorBuilder::new()
             .with_frequency(NonZeroU32::new(5).unwrap())
             .with_perlin_mode()
-            .with_subscribers(vec![tx])
+            .with_publisher(vec![tx].into())
             .spawn(Arc::clone(&shutdown));
 
         std::thread::sleep(Duration::from_millis(300));
@@ -293,7 +549,7 @@ mod tests {
         shutdown.store(true, Ordering::SeqCst);
         handle.join().unwrap();
 
-        let data = data_handle.lock().unwrap();
+        let data = data_handle.lock().unwrap().position_data();
         assert!(data.x >= 0.0 && data.x <= 100.0);
         assert!(data.y >= 0.0 && data.y <= 100.0);
         assert!(data.z >= 0.0 && data.z <= 100.0);
@@ -307,7 +563,7 @@ mod tests {
         let (data_handle, handle) = TrajectoryGeneratorBuilder::new()
             .with_frequency(NonZeroU32::new(5).unwrap())
             .with_determinisitic_perlin_mode()
-            .with_subscribers(vec![tx])
+            .with_publisher(vec![tx].into())
             .spawn(Arc::clone(&shutdown));
 
         std::thread::sleep(Duration::from_millis(300));
@@ -315,13 +571,297 @@ mod tests {
         shutdown.store(true, Ordering::SeqCst);
         handle.join().unwrap();
 
-        let data = data_handle.lock().unwrap();
+        let data = data_handle.lock().unwrap().position_data();
         assert!(data.x >= 0.0 && data.x <= 100.0);
         assert!(data.y >= 0.0 && data.y <= 100.0);
         assert!(data.z >= 0.0 && data.z <= 100.0);
         assert!(rx.try_recv().is_ok());
     }
 
+    #[test]
+    fn test_waypoint_trajectory_generator_follows_spline() {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+        let waypoints = [
+            Waypoint::new(0.0, 0.0, 0.0, 0.0),
+            Waypoint::new(0.2, 10.0, 20.0, 30.0),
+        ];
+        let (data_handle, handle) = TrajectoryGeneratorBuilder::new()
+            .with_frequency(NonZeroU32::new(50).unwrap())
+            .with_waypoint_mode(&waypoints)
+            .unwrap()
+            .with_publisher(vec![tx].into())
+            .spawn(Arc::clone(&shutdown));
+
+        std::thread::sleep(Duration::from_millis(400));
+
+        shutdown.store(true, Ordering::SeqCst);
+        handle.join().unwrap();
+
+        let data = data_handle.lock().unwrap().position_data();
+        approx::assert_relative_eq!(data.x, 10.0, epsilon = 1e-9);
+        approx::assert_relative_eq!(data.y, 20.0, epsilon = 1e-9);
+        approx::assert_relative_eq!(data.z, 30.0, epsilon = 1e-9);
+        assert!(rx.try_recv().is_ok());
+    }
+
+    fn generator(mode: GenerationMode) -> TrajectoryGenerator {
+        TrajectoryGenerator::new(
+            Arc::new(Mutex::new(KinematicState::new())),
+            Arc::new(AtomicBool::new(false)),
+            mode,
+            0,
+            NonZeroU32::new(10).unwrap(),
+            vec![].into(),
+        )
+    }
+
+    #[test]
+    fn test_helical_state_is_analytically_consistent() {
+        let angular_frequency = 2.0 * PI * HELIX_FREQUENCY;
+        let mut generator = generator(GenerationMode::AngledHelical(angular_frequency));
+        generator.start_time -= Duration::from_millis(1234);
+
+        let state = generator.generate_data();
+        let offset = state.position - Vector3::repeat(HALF_RANGE);
+        // harmonic motion around the center: a = -w^2 (p - c), v_x = w (p_y - c), v_y = -w (p_x - c)
+        approx::assert_relative_eq!(
+            state.acceleration,
+            -offset * angular_frequency.powi(2),
+            epsilon = 1e-9
+        );
+        approx::assert_relative_eq!(
+            state.velocity.x,
+            angular_frequency * offset.y,
+            epsilon = 1e-9
+        );
+        approx::assert_relative_eq!(
+            state.velocity.y,
+            -angular_frequency * offset.x,
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn test_perlin_position_matches_the_noise_crate() {
+        let (perlin, table) = (Perlin::new(0x859), PermutationTable::new(0x859));
+        for input in [0.0, 0.35, 1.0, 1.7, 4.2, 123.456, 1.7e9 + 0.25] {
+            let state = sample_perlin(&table, input, 1.0, SystemTime::now());
+            approx::assert_relative_eq!(
+                state.position.x,
+                upscale(perlin.get([input, 0.0, 0.0])),
+                epsilon = 1e-9
+            );
+            approx::assert_relative_eq!(
+                state.position.y,
+                upscale(perlin.get([0.0, input, 0.0])),
+                epsilon = 1e-9
+            );
+            approx::assert_relative_eq!(
+                state.position.z,
+                upscale(perlin.get([0.0, 0.0, input])),
+                epsilon = 1e-9
+            );
+        }
+    }
+
+    #[test]
+    fn test_perlin_derivatives_match_the_noise_field() {
+        let perlin = PermutationTable::new(0x859);
+        let timestamp = SystemTime::now();
+        let (input_rate, delta) = (2.0, 1e-2);
+        for input in [0.35, 1.7, 4.2] {
+            let state = sample_perlin(&perlin, input, input_rate, timestamp);
+            let before = sample_perlin(&perlin, input - delta, input_rate, timestamp);
+            let after = sample_perlin(&perlin, input + delta, input_rate, timestamp);
+            let dt = delta / input_rate;
+            approx::assert_relative_eq!(
+                state.velocity,
+                (after.position - before.position) / (2.0 * dt),
+                max_relative = 1e-2
+            );
+            approx::assert_relative_eq!(
+                state.acceleration,
+                (after.velocity - before.velocity) / (2.0 * dt),
+                max_relative = 5e-2
+            );
+        }
+    }
+
+    #[test]
+    fn test_random_state_moves_between_random_points() {
+        let segment = RandomSegment::new();
+        let mut generator = generator(GenerationMode::Random(segment));
+        generator.start_time -= Duration::from_secs_f64(RANDOM_SEGMENT_DURATION / 2.0);
+
+        let state = generator.generate_data();
+        // about halfway, near the peak speed of the quintic profile
+        let progress = generator.elapsed_secs(state.timestamp) / RANDOM_SEGMENT_DURATION;
+        let (fade, fade_rate, fade_acceleration) = quintic_fade(progress);
+        let displacement = segment.end - segment.start;
+        approx::assert_relative_eq!(
+            state.position,
+            segment.start + displacement * fade,
+            epsilon = 1e-9
+        );
+        approx::assert_relative_eq!(
+            state.velocity,
+            displacement * fade_rate / RANDOM_SEGMENT_DURATION,
+            epsilon = 1e-9
+        );
+        approx::assert_relative_eq!(
+            state.acceleration,
+            displacement * fade_acceleration / RANDOM_SEGMENT_DURATION.powi(2),
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn test_random_state_is_continuous_across_segments() {
+        let mut generator = generator(GenerationMode::Random(RandomSegment::new()));
+        generator.start_time -= Duration::from_secs_f64(RANDOM_SEGMENT_DURATION * 2.5);
+
+        generator.generate_data();
+        let GenerationMode::Random(segment) = generator.mode else {
+            panic!("The generator should stay in the random mode.");
+        };
+        assert_eq!(segment.index, 2);
+        for point in [segment.start, segment.end] {
+            assert!(point
+                .iter()
+                .all(|coordinate| (0.0..=2.0 * HALF_RANGE).contains(coordinate)));
+        }
+        // both ends of a segment are at rest, so the position, velocity and acceleration join up
+        assert_eq!(quintic_fade(0.0), (0.0, 0.0, 0.0));
+        assert_eq!(quintic_fade(1.0), (1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_attitude_follows_velocity_and_holds_heading_at_rest() {
+        let previous = Vector3::new(0.0, 0.0, 1.0);
+        approx::assert_relative_eq!(
+            attitude_along(&Vector3::new(0.0, 2.0, 2.0), &previous),
+            Vector3::new(0.0, -PI / 4.0, PI / 2.0)
+        );
+        assert_eq!(
+            attitude_along(&Vector3::new(0.01, 0.0, 0.0), &previous),
+            previous
+        );
+        approx::assert_relative_eq!(
+            attitude_along(&Vector3::new(0.0, 0.0, -1.0), &previous),
+            Vector3::new(0.0, PI / 2.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_random_mode_keeps_heading_when_stopping() {
+        let mut generator = generator(GenerationMode::Random(RandomSegment::new()));
+        generator.attitude = Vector3::new(0.0, 0.2, 1.0);
+        // the segment starts at rest
+        generator.start_time = SystemTime::now();
+
+        let state = generator.generate_data();
+        assert_eq!(state.attitude, Vector3::new(0.0, 0.2, 1.0));
+    }
+
+    #[test]
+    fn test_vehicle_mode_publishes_the_vehicle_attitude() {
+        let mut generator = generator(GenerationMode::Vehicle(Vehicle::quadrotor(
+            QUADROTOR_LIMITS,
+        )));
+        generator.start_time -= Duration::from_secs(5);
+
+        let state = generator.generate_data();
+        let GenerationMode::Vehicle(vehicle) = &generator.mode else {
+            panic!("The generator should stay in the vehicle mode.");
+        };
+        assert_eq!(state.attitude, vehicle.attitude());
+        assert!(state.attitude.x != 0.0 || state.attitude.y != 0.0);
+    }
+
+    #[test]
+    fn test_vehicle_trajectory_generators_respect_limits() {
+        let limits = VehicleLimits {
+            max_speed: 3.0,
+            max_acceleration: 1.0,
+        };
+        let builders = [
+            TrajectoryGeneratorBuilder::new().with_car_mode(),
+            TrajectoryGeneratorBuilder::new().with_quadrotor_mode(),
+            TrajectoryGeneratorBuilder::new().with_pedestrian_mode(),
+        ];
+        for builder in builders {
+            let shutdown = Arc::new(AtomicBool::new(false));
+            let (tx, rx) = mpsc::channel();
+            let (data_handle, handle) = builder
+                .with_frequency(NonZeroU32::new(50).unwrap())
+                .with_vehicle_limits(limits)
+                .with_publisher(vec![tx].into())
+                .spawn(Arc::clone(&shutdown));
+
+            std::thread::sleep(Duration::from_millis(300));
+
+            shutdown.store(true, Ordering::SeqCst);
+            handle.join().unwrap();
+
+            let state = *data_handle.lock().unwrap();
+            assert!(state.velocity.norm() > 0.0);
+            assert!(state.velocity.norm() <= limits.max_speed + 1e-9);
+            assert!(state.acceleration.norm() <= limits.max_acceleration + 1e-9);
+            assert!(rx.try_recv().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_file_trajectory_generator_follows_recording() {
+        const RECORDING_DIRECTORY: &str = "test_output_generator_recording";
+        let path = Path::new(RECORDING_DIRECTORY).join("recording.csv");
+        std::fs::create_dir_all(RECORDING_DIRECTORY).unwrap();
+        std::fs::write(
+            &path,
+            "time,x,y,z\n10.0,0.0,0.0,0.0\n10.1,5.0,5.0,5.0\n10.2,10.0,20.0,30.0\n",
+        )
+        .unwrap();
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+        let (data_handle, handle) = TrajectoryGeneratorBuilder::new()
+            .with_frequency(NonZeroU32::new(50).unwrap())
+            .with_file_mode(&path)
+            .unwrap()
+            .with_publisher(vec![tx].into())
+            .spawn(Arc::clone(&shutdown));
+
+        std::thread::sleep(Duration::from_millis(400));
+
+        shutdown.store(true, Ordering::SeqCst);
+        handle.join().unwrap();
+        let _ = std::fs::remove_dir_all(RECORDING_DIRECTORY);
+
+        let state = *data_handle.lock().unwrap();
+        approx::assert_relative_eq!(
+            state.position,
+            Vector3::new(10.0, 20.0, 30.0),
+            epsilon = 1e-9
+        );
+        approx::assert_abs_diff_eq!(state.velocity, Vector3::zeros(), epsilon = 1e-9);
+        assert!(rx.try_recv().is_ok());
+    }
+
+    #[test]
+    fn test_missing_recording_is_rejected() {
+        let result = TrajectoryGeneratorBuilder::new()
+            .with_file_mode(Path::new("test_output_missing/recording.csv"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_invalid_waypoints_are_rejected() {
+        let result = TrajectoryGeneratorBuilder::new()
+            .with_waypoint_mode(&[Waypoint::new(0.0, 0.0, 0.0, 0.0)]);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_shutdown_trigger_stops_generation() {
         let shutdown = Arc::new(AtomicBool::new(false));
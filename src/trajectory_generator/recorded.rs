@@ -0,0 +1,177 @@
+use std::f64::consts::PI;
+
+use nalgebra::Vector3;
+
+use super::spline::{InvalidWaypoints, QuinticSpline, Waypoint};
+
+// One row of a recorded reference trajectory, `time` is in seconds. The velocity and the attitude
+// columns (roll, pitch and yaw in rad) are optional, other columns are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+pub struct RecordedSample {
+    pub time: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub vx: Option<f64>,
+    pub vy: Option<f64>,
+    pub vz: Option<f64>,
+    pub roll: Option<f64>,
+    pub pitch: Option<f64>,
+    pub yaw: Option<f64>,
+}
+
+impl RecordedSample {
+    fn velocity(&self) -> Option<Vector3<f64>> {
+        Some(Vector3::new(self.vx?, self.vy?, self.vz?))
+    }
+
+    fn attitude(&self) -> Option<Vector3<f64>> {
+        Some(Vector3::new(self.roll?, self.pitch?, self.yaw?))
+    }
+}
+
+// Recorded roll, pitch and yaw, interpolated linearly between the samples the shorter way round
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttitudeTrack {
+    times: Vec<f64>,
+    attitudes: Vec<Vector3<f64>>,
+}
+
+impl AttitudeTrack {
+    // Held constant before the first and after the last recorded attitude
+    pub fn at(&self, time: f64) -> Vector3<f64> {
+        let index = self
+            .times
+            .partition_point(|sample_time| *sample_time <= time);
+        if index == 0 {
+            return self.attitudes[0];
+        }
+        let (previous_time, previous) = (self.times[index - 1], self.attitudes[index - 1]);
+        let Some((next_time, next)) = self.times.get(index).zip(self.attitudes.get(index)) else {
+            return previous;
+        };
+        let fraction = (time - previous_time) / (next_time - previous_time);
+        previous + (next - previous).map(wrap_angle) * fraction
+    }
+}
+
+fn wrap_angle(angle: f64) -> f64 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+// Rows without a complete attitude are skipped, None when no row has one
+pub fn attitude_from_recording(samples: &[RecordedSample]) -> Option<AttitudeTrack> {
+    let start_time = samples.first()?.time;
+    let (times, attitudes): (Vec<f64>, Vec<Vector3<f64>>) = samples
+        .iter()
+        .filter_map(|sample| Some((sample.time - start_time, sample.attitude()?)))
+        .unzip();
+    (!times.is_empty()).then_some(AttitudeTrack { times, attitudes })
+}
+
+// The recording is shifted so that its first sample is reached at the start of the generation
+pub fn spline_from_recording(
+    samples: &[RecordedSample],
+) -> Result<QuinticSpline, InvalidWaypoints> {
+    let start_time = samples
+        .first()
+        .map(|sample| sample.time)
+        .unwrap_or_default();
+    let waypoints: Vec<Waypoint> = samples
+        .iter()
+        .map(|sample| Waypoint {
+            time: sample.time - start_time,
+            position: Vector3::new(sample.x, sample.y, sample.z),
+        })
+        .collect();
+    let velocities: Vec<Option<Vector3<f64>>> =
+        samples.iter().map(RecordedSample::velocity).collect();
+    QuinticSpline::with_velocities(&waypoints, &velocities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(time: f64, x: f64, velocity: Option<f64>) -> RecordedSample {
+        RecordedSample {
+            time,
+            x,
+            y: 2.0 * x,
+            z: 0.0,
+            vx: velocity,
+            vy: velocity.map(|v| 2.0 * v),
+            vz: velocity.map(|_| 0.0),
+            roll: None,
+            pitch: None,
+            yaw: None,
+        }
+    }
+
+    #[test]
+    fn recording_is_shifted_to_start_at_zero() {
+        let spline =
+            spline_from_recording(&[sample(100.0, 1.0, None), sample(101.0, 3.0, None)]).unwrap();
+        approx::assert_relative_eq!(spline.sample(0.0).position, Vector3::new(1.0, 2.0, 0.0));
+        approx::assert_relative_eq!(spline.sample(1.0).position, Vector3::new(3.0, 6.0, 0.0));
+    }
+
+    #[test]
+    fn recorded_velocities_are_kept_at_the_samples() {
+        let recording = [
+            sample(0.0, 0.0, Some(1.0)),
+            sample(1.0, 1.0, Some(1.0)),
+            sample(2.0, 2.0, Some(1.0)),
+        ];
+        let spline = spline_from_recording(&recording).unwrap();
+        for time in [0.0, 1.0, 2.0] {
+            approx::assert_relative_eq!(
+                spline.sample(time).velocity,
+                Vector3::new(1.0, 2.0, 0.0),
+                epsilon = 1e-12
+            );
+        }
+    }
+
+    #[test]
+    fn partial_velocity_is_treated_as_missing() {
+        let mut with_partial_velocity = sample(0.0, 0.0, Some(1.0));
+        with_partial_velocity.vz = None;
+        assert_eq!(with_partial_velocity.velocity(), None);
+    }
+
+    #[test]
+    fn recorded_attitude_is_interpolated_the_shorter_way_round() {
+        let with_yaw = |time, yaw| RecordedSample {
+            roll: Some(0.0),
+            pitch: Some(0.1),
+            yaw: Some(yaw),
+            ..sample(time, 0.0, None)
+        };
+        let track = attitude_from_recording(&[
+            with_yaw(10.0, 3.0),
+            with_yaw(11.0, -3.0),
+            sample(12.0, 0.0, None),
+        ])
+        .unwrap();
+
+        approx::assert_relative_eq!(track.at(-1.0), Vector3::new(0.0, 0.1, 3.0));
+        let halfway = track.at(0.5);
+        approx::assert_relative_eq!(halfway.y, 0.1);
+        approx::assert_relative_eq!(halfway.z, 3.0 + (2.0 * PI - 6.0) / 2.0, epsilon = 1e-12);
+        approx::assert_relative_eq!(track.at(5.0).z, -3.0);
+    }
+
+    #[test]
+    fn recording_without_attitude_has_no_track() {
+        assert_eq!(
+            attitude_from_recording(&[sample(0.0, 0.0, None), sample(1.0, 1.0, None)]),
+            None
+        );
+    }
+
+    #[test]
+    fn given_unordered_recording_expect_error() {
+        assert!(spline_from_recording(&[sample(1.0, 0.0, None), sample(0.5, 1.0, None)]).is_err());
+    }
+}
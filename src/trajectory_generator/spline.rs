@@ -0,0 +1,276 @@
+use nalgebra::Vector3;
+
+// A position the trajectory has to pass through, `time` is in seconds since generation start
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Waypoint {
+    pub time: f64,
+    pub position: Vector3<f64>,
+}
+
+impl Waypoint {
+    #[allow(dead_code)]
+    pub fn new(time: f64, x: f64, y: f64, z: f64) -> Self {
+        Self {
+            time,
+            position: Vector3::new(x, y, z),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KinematicSample {
+    pub position: Vector3<f64>,
+    pub velocity: Vector3<f64>,
+    pub acceleration: Vector3<f64>,
+}
+
+#[derive(Debug)]
+pub struct InvalidWaypoints(&'static str);
+
+impl std::fmt::Display for InvalidWaypoints {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid waypoints: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidWaypoints {}
+
+// Quintic polynomial in normalized time s = (t - start_time) / duration
+#[derive(Debug, Clone)]
+struct Segment {
+    start_time: f64,
+    duration: f64,
+    coefficients: [Vector3<f64>; 6],
+}
+
+impl Segment {
+    // Quintic Hermite interpolation of position, velocity and acceleration at both ends.
+    // With zero velocity and acceleration at both ends this is the minimum-jerk segment.
+    fn new(
+        start: &Waypoint,
+        end: &Waypoint,
+        start_state: (Vector3<f64>, Vector3<f64>),
+        end_state: (Vector3<f64>, Vector3<f64>),
+    ) -> Self {
+        let duration = end.time - start.time;
+        let delta = end.position - start.position;
+        let (v0, a0) = (start_state.0 * duration, start_state.1 * duration.powi(2));
+        let (v1, a1) = (end_state.0 * duration, end_state.1 * duration.powi(2));
+        Self {
+            start_time: start.time,
+            duration,
+            coefficients: [
+                start.position,
+                v0,
+                a0 * 0.5,
+                delta * 10.0 - v0 * 6.0 - v1 * 4.0 - a0 * 1.5 + a1 * 0.5,
+                delta * -15.0 + v0 * 8.0 + v1 * 7.0 + a0 * 1.5 - a1,
+                delta * 6.0 - v0 * 3.0 - v1 * 3.0 - a0 * 0.5 + a1 * 0.5,
+            ],
+        }
+    }
+
+    fn sample(&self, time: f64) -> KinematicSample {
+        let s = ((time - self.start_time) / self.duration).clamp(0.0, 1.0);
+        let c = &self.coefficients;
+        let position = c[0] + (c[1] + (c[2] + (c[3] + (c[4] + c[5] * s) * s) * s) * s) * s;
+        let velocity =
+            c[1] + (c[2] * 2.0 + (c[3] * 3.0 + (c[4] * 4.0 + c[5] * (5.0 * s)) * s) * s) * s;
+        let acceleration = c[2] * 2.0 + (c[3] * 6.0 + (c[4] * 12.0 + c[5] * (20.0 * s)) * s) * s;
+        KinematicSample {
+            position,
+            velocity: velocity / self.duration,
+            acceleration: acceleration / self.duration.powi(2),
+        }
+    }
+}
+
+// C2 continuous path through timed waypoints. The trajectory starts and ends at rest,
+// interior velocities and accelerations come from finite differences of the waypoints.
+#[derive(Debug, Clone)]
+pub struct QuinticSpline {
+    segments: Vec<Segment>,
+}
+
+impl QuinticSpline {
+    pub fn new(waypoints: &[Waypoint]) -> Result<Self, InvalidWaypoints> {
+        Self::with_velocities(waypoints, &vec![None; waypoints.len()])
+    }
+
+    // Known waypoint velocities replace the estimated ones, accelerations are always estimated
+    pub fn with_velocities(
+        waypoints: &[Waypoint],
+        velocities: &[Option<Vector3<f64>>],
+    ) -> Result<Self, InvalidWaypoints> {
+        if waypoints.len() < 2 {
+            return Err(InvalidWaypoints("at least two waypoints are required"));
+        }
+        if waypoints
+            .windows(2)
+            .any(|pair| pair[1].time <= pair[0].time)
+        {
+            return Err(InvalidWaypoints(
+                "waypoint times must be strictly increasing",
+            ));
+        }
+        if waypoints.iter().any(|waypoint| {
+            !waypoint.time.is_finite() || !waypoint.position.iter().all(|p| p.is_finite())
+        }) {
+            return Err(InvalidWaypoints("waypoints must be finite"));
+        }
+        if velocities.len() != waypoints.len() {
+            return Err(InvalidWaypoints(
+                "every waypoint needs its own velocity entry",
+            ));
+        }
+
+        let slope = |i: usize| {
+            (waypoints[i + 1].position - waypoints[i].position)
+                / (waypoints[i + 1].time - waypoints[i].time)
+        };
+        let knot_states: Vec<(Vector3<f64>, Vector3<f64>)> = (0..waypoints.len())
+            .map(|i| {
+                let (velocity, acceleration) = if i == 0 || i == waypoints.len() - 1 {
+                    (Vector3::zeros(), Vector3::zeros())
+                } else {
+                    let (before, after) = (slope(i - 1), slope(i));
+                    let span = waypoints[i + 1].time - waypoints[i - 1].time;
+                    ((before + after) * 0.5, (after - before) * (2.0 / span))
+                };
+                (velocities[i].unwrap_or(velocity), acceleration)
+            })
+            .collect();
+
+        let segments = (0..waypoints.len() - 1)
+            .map(|i| {
+                Segment::new(
+                    &waypoints[i],
+                    &waypoints[i + 1],
+                    knot_states[i],
+                    knot_states[i + 1],
+                )
+            })
+            .collect();
+        Ok(Self { segments })
+    }
+
+    // Before the first waypoint and after the last one the trajectory rests at them
+    pub fn sample(&self, time: f64) -> KinematicSample {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.start_time + segment.duration < time)
+            .min(self.segments.len() - 1);
+        self.segments[index].sample(time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square_waypoints() -> Vec<Waypoint> {
+        vec![
+            Waypoint::new(0.0, 0.0, 0.0, 0.0),
+            Waypoint::new(2.0, 10.0, 0.0, 5.0),
+            Waypoint::new(3.0, 10.0, 10.0, 5.0),
+            Waypoint::new(5.0, 0.0, 10.0, 0.0),
+            Waypoint::new(8.0, 0.0, 0.0, 0.0),
+        ]
+    }
+
+    #[test]
+    fn given_too_few_waypoints_expect_error() {
+        assert!(QuinticSpline::new(&[]).is_err());
+        assert!(QuinticSpline::new(&[Waypoint::new(0.0, 1.0, 1.0, 1.0)]).is_err());
+    }
+
+    #[test]
+    fn given_unordered_waypoints_expect_error() {
+        let waypoints = [
+            Waypoint::new(1.0, 0.0, 0.0, 0.0),
+            Waypoint::new(1.0, 1.0, 1.0, 1.0),
+        ];
+        assert!(QuinticSpline::new(&waypoints).is_err());
+    }
+
+    #[test]
+    fn spline_passes_through_waypoints() {
+        let waypoints = square_waypoints();
+        let spline = QuinticSpline::new(&waypoints).unwrap();
+        for waypoint in &waypoints {
+            approx::assert_relative_eq!(
+                spline.sample(waypoint.time).position,
+                waypoint.position,
+                epsilon = 1e-9
+            );
+        }
+    }
+
+    #[test]
+    fn spline_starts_and_ends_at_rest() {
+        let spline = QuinticSpline::new(&square_waypoints()).unwrap();
+        for time in [-1.0, 0.0, 8.0, 10.0] {
+            let sample = spline.sample(time);
+            approx::assert_abs_diff_eq!(sample.velocity, Vector3::zeros(), epsilon = 1e-12);
+            approx::assert_abs_diff_eq!(sample.acceleration, Vector3::zeros(), epsilon = 1e-12);
+        }
+        approx::assert_relative_eq!(
+            spline.sample(10.0).position,
+            Vector3::zeros(),
+            epsilon = 1e-12
+        );
+    }
+
+    #[test]
+    fn velocity_and_acceleration_are_derivatives_of_position() {
+        let spline = QuinticSpline::new(&square_waypoints()).unwrap();
+        let h = 1e-5;
+        let mut time = 0.05;
+        while time < 8.0 {
+            let (before, sample, after) = (
+                spline.sample(time - h),
+                spline.sample(time),
+                spline.sample(time + h),
+            );
+            approx::assert_relative_eq!(
+                sample.velocity,
+                (after.position - before.position) / (2.0 * h),
+                epsilon = 1e-5
+            );
+            approx::assert_relative_eq!(
+                sample.acceleration,
+                (after.velocity - before.velocity) / (2.0 * h),
+                epsilon = 1e-5
+            );
+            time += 0.1;
+        }
+    }
+
+    #[test]
+    fn spline_is_twice_continuously_differentiable_at_waypoints() {
+        let spline = QuinticSpline::new(&square_waypoints()).unwrap();
+        let eps = 1e-9;
+        for waypoint in &square_waypoints()[1..4] {
+            let (before, after) = (
+                spline.sample(waypoint.time - eps),
+                spline.sample(waypoint.time + eps),
+            );
+            approx::assert_relative_eq!(before.velocity, after.velocity, epsilon = 1e-6);
+            approx::assert_relative_eq!(before.acceleration, after.acceleration, epsilon = 1e-6);
+        }
+    }
+
+    #[test]
+    fn two_waypoints_give_minimum_jerk_profile() {
+        let spline = QuinticSpline::new(&[
+            Waypoint::new(0.0, 0.0, 0.0, 0.0),
+            Waypoint::new(2.0, 4.0, 0.0, 0.0),
+        ])
+        .unwrap();
+        let middle = spline.sample(1.0);
+        // minimum jerk: p = d(10s^3 - 15s^4 + 6s^5), peak velocity 1.875 d / T in the middle
+        approx::assert_relative_eq!(middle.position.x, 2.0, epsilon = 1e-12);
+        approx::assert_relative_eq!(middle.velocity.x, 1.875 * 4.0 / 2.0, epsilon = 1e-12);
+        approx::assert_abs_diff_eq!(middle.acceleration.x, 0.0, epsilon = 1e-12);
+    }
+}
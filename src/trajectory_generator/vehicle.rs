@@ -0,0 +1,445 @@
+use std::f64::consts::PI;
+
+use nalgebra::{Vector2, Vector3};
+
+use super::spline::KinematicSample;
+
+const GRAVITY: f64 = 9.81;
+// Models are integrated with a fixed step, independent of the generator frequency
+const INTEGRATION_STEP: f64 = 1e-3;
+// All vehicles start at rest in the middle of the plotted area
+const START_POSITION: Vector3<f64> = Vector3::new(50.0, 50.0, 0.0);
+// Fraction of the speed limit the car and the pedestrian try to keep
+const CRUISE_SPEED_RATIO: f64 = 0.8;
+
+// Car, kinematic bicycle model driven by a slowly oscillating steering command
+const CAR_WHEELBASE: f64 = 2.7;
+const CAR_MAX_STEERING_ANGLE: f64 = 0.5;
+const CAR_STEERING_PERIOD: f64 = 20.0;
+const CAR_SPEED_GAIN: f64 = 0.5;
+
+// Quadrotor, cascaded position/velocity controller following a figure eight
+const QUADROTOR_MAX_TILT: f64 = PI / 6.0;
+const QUADROTOR_ALTITUDE: f64 = 10.0;
+const QUADROTOR_FIGURE_EIGHT_SIZE: f64 = 30.0;
+const QUADROTOR_FIGURE_EIGHT_PERIOD: f64 = 40.0;
+const QUADROTOR_POSITION_GAIN: f64 = 1.0;
+const QUADROTOR_VELOCITY_GAIN: f64 = 2.0;
+
+// Pedestrian, every step accelerates in its first half and decelerates in the second one
+const PEDESTRIAN_STEP_FREQUENCY: f64 = 2.0;
+const PEDESTRIAN_GAIT_ACCELERATION: f64 = 0.8;
+const PEDESTRIAN_SPEED_GAIN: f64 = 1.0;
+const PEDESTRIAN_TURN_RATE: f64 = 0.2;
+const PEDESTRIAN_TURN_PERIOD: f64 = 30.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VehicleLimits {
+    pub max_speed: f64,
+    pub max_acceleration: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Vehicle {
+    model: Model,
+    limits: VehicleLimits,
+    time: f64,
+}
+
+#[derive(Debug, Clone)]
+enum Model {
+    Car(GroundState),
+    Quadrotor(QuadrotorState),
+    Pedestrian(GroundState),
+}
+
+// Planar motion along the heading, shared by the car and the pedestrian
+#[derive(Debug, Clone, Default)]
+struct GroundState {
+    position: Vector3<f64>,
+    heading: f64,
+    speed: f64,
+    acceleration: Vector3<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct QuadrotorState {
+    position: Vector3<f64>,
+    velocity: Vector3<f64>,
+    attitude: Vector3<f64>, // roll, pitch, yaw
+    thrust: f64,            // specific thrust along the body z axis
+}
+
+impl Vehicle {
+    pub fn car(limits: VehicleLimits) -> Self {
+        Self::new(Model::Car(GroundState::at_start()), limits)
+    }
+
+    pub fn quadrotor(limits: VehicleLimits) -> Self {
+        let hover = QuadrotorState {
+            position: START_POSITION + Vector3::z() * QUADROTOR_ALTITUDE,
+            thrust: GRAVITY,
+            ..Default::default()
+        };
+        Self::new(Model::Quadrotor(hover), limits)
+    }
+
+    pub fn pedestrian(limits: VehicleLimits) -> Self {
+        Self::new(Model::Pedestrian(GroundState::at_start()), limits)
+    }
+
+    fn new(model: Model, limits: VehicleLimits) -> Self {
+        Self {
+            model,
+            limits,
+            time: 0.0,
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: VehicleLimits) {
+        self.limits = limits;
+    }
+
+    // Integrates the model up to `time` (seconds since start), earlier times leave it unchanged
+    pub fn advance_to(&mut self, time: f64) -> KinematicSample {
+        while self.time < time {
+            let dt = INTEGRATION_STEP.min(time - self.time);
+            match &mut self.model {
+                Model::Car(state) => state.step_car(self.time, dt, &self.limits),
+                Model::Quadrotor(state) => state.step(self.time, dt, &self.limits),
+                Model::Pedestrian(state) => state.step_pedestrian(self.time, dt, &self.limits),
+            }
+            self.time += dt;
+        }
+        self.sample()
+    }
+
+    // Roll, pitch and yaw, the ground vehicles stay level and keep their heading when stopped
+    pub fn attitude(&self) -> Vector3<f64> {
+        match &self.model {
+            Model::Car(state) | Model::Pedestrian(state) => Vector3::new(0.0, 0.0, state.heading),
+            Model::Quadrotor(state) => state.attitude,
+        }
+    }
+
+    fn sample(&self) -> KinematicSample {
+        match &self.model {
+            Model::Car(state) | Model::Pedestrian(state) => KinematicSample {
+                position: state.position,
+                velocity: state.velocity(),
+                acceleration: state.acceleration,
+            },
+            Model::Quadrotor(state) => KinematicSample {
+                position: state.position,
+                velocity: state.velocity,
+                acceleration: state.acceleration(),
+            },
+        }
+    }
+}
+
+impl GroundState {
+    fn at_start() -> Self {
+        Self {
+            position: START_POSITION,
+            ..Default::default()
+        }
+    }
+
+    fn direction(&self) -> Vector3<f64> {
+        Vector3::new(self.heading.cos(), self.heading.sin(), 0.0)
+    }
+
+    fn velocity(&self) -> Vector3<f64> {
+        self.direction() * self.speed
+    }
+
+    fn step_car(&mut self, time: f64, dt: f64, limits: &VehicleLimits) {
+        let steering = CAR_MAX_STEERING_ANGLE * (2.0 * PI * time / CAR_STEERING_PERIOD).sin();
+        let yaw_rate = self.speed * steering.tan() / CAR_WHEELBASE;
+        let longitudinal = CAR_SPEED_GAIN * (CRUISE_SPEED_RATIO * limits.max_speed - self.speed);
+        self.step(longitudinal, yaw_rate, dt, limits);
+    }
+
+    fn step_pedestrian(&mut self, time: f64, dt: f64, limits: &VehicleLimits) {
+        let gait = if (time * PEDESTRIAN_STEP_FREQUENCY).fract() < 0.5 {
+            PEDESTRIAN_GAIT_ACCELERATION
+        } else {
+            -PEDESTRIAN_GAIT_ACCELERATION
+        };
+        let yaw_rate = PEDESTRIAN_TURN_RATE * (2.0 * PI * time / PEDESTRIAN_TURN_PERIOD).sin();
+        let longitudinal =
+            PEDESTRIAN_SPEED_GAIN * (CRUISE_SPEED_RATIO * limits.max_speed - self.speed) + gait;
+        self.step(longitudinal, yaw_rate, dt, limits);
+    }
+
+    // The longitudinal command gets the acceleration budget first, the turn gets what remains
+    fn step(&mut self, longitudinal: f64, yaw_rate: f64, dt: f64, limits: &VehicleLimits) {
+        let mut longitudinal =
+            longitudinal.clamp(-limits.max_acceleration, limits.max_acceleration);
+        if (self.speed >= limits.max_speed && longitudinal > 0.0)
+            || (self.speed <= 0.0 && longitudinal < 0.0)
+        {
+            longitudinal = 0.0;
+        }
+        let lateral_budget = (limits.max_acceleration.powi(2) - longitudinal.powi(2)).sqrt();
+        let lateral = (self.speed * yaw_rate).clamp(-lateral_budget, lateral_budget);
+        let yaw_rate = if self.speed > 0.0 {
+            lateral / self.speed
+        } else {
+            0.0
+        };
+
+        let normal = Vector3::new(-self.heading.sin(), self.heading.cos(), 0.0);
+        self.acceleration = self.direction() * longitudinal + normal * lateral;
+        self.position += self.velocity() * dt + self.acceleration * (0.5 * dt * dt);
+        self.speed = (self.speed + longitudinal * dt).clamp(0.0, limits.max_speed);
+        self.heading += yaw_rate * dt;
+    }
+}
+
+impl QuadrotorState {
+    fn acceleration(&self) -> Vector3<f64> {
+        body_z_axis(&self.attitude) * self.thrust - Vector3::z() * GRAVITY
+    }
+
+    fn step(&mut self, time: f64, dt: f64, limits: &VehicleLimits) {
+        let phase = 2.0 * PI * time / QUADROTOR_FIGURE_EIGHT_PERIOD;
+        let reference = START_POSITION
+            + Vector3::new(phase.sin(), phase.sin() * phase.cos(), 0.0)
+                * QUADROTOR_FIGURE_EIGHT_SIZE
+            + Vector3::z() * QUADROTOR_ALTITUDE;
+
+        let velocity_command =
+            (QUADROTOR_POSITION_GAIN * (reference - self.position)).cap_magnitude(limits.max_speed);
+        let mut thrust_vector = (QUADROTOR_VELOCITY_GAIN * (velocity_command - self.velocity))
+            .cap_magnitude(limits.max_acceleration)
+            + Vector3::z() * GRAVITY;
+        let horizontal = Vector2::new(thrust_vector.x, thrust_vector.y)
+            .cap_magnitude(thrust_vector.z * QUADROTOR_MAX_TILT.tan());
+        thrust_vector.x = horizontal.x;
+        thrust_vector.y = horizontal.y;
+        // less thrust rather than a velocity clipped behind the back of the reported acceleration
+        let net_acceleration = thrust_vector - Vector3::z() * GRAVITY;
+        let fraction =
+            speed_limited_fraction(&self.velocity, &(net_acceleration * dt), limits.max_speed);
+        thrust_vector = net_acceleration * fraction + Vector3::z() * GRAVITY;
+
+        // attitude that points the body z axis along the thrust, yaw follows the direction of flight
+        let yaw = if self.velocity.xy().norm() > 0.1 {
+            self.velocity.y.atan2(self.velocity.x)
+        } else {
+            self.attitude.z
+        };
+        self.thrust = thrust_vector.norm();
+        let thrust_direction = thrust_vector / self.thrust;
+        let roll = (yaw.sin() * thrust_direction.x - yaw.cos() * thrust_direction.y).asin();
+        let pitch = (yaw.cos() * thrust_direction.x + yaw.sin() * thrust_direction.y)
+            .atan2(thrust_direction.z);
+        self.attitude = Vector3::new(roll, pitch, yaw);
+
+        let acceleration = self.acceleration();
+        self.position += self.velocity * dt + acceleration * (0.5 * dt * dt);
+        self.velocity += acceleration * dt;
+    }
+}
+
+// Largest fraction of the velocity change `delta` that keeps the speed within `max_speed`.
+// Scaling the net acceleration down never tilts the thrust further.
+fn speed_limited_fraction(velocity: &Vector3<f64>, delta: &Vector3<f64>, max_speed: f64) -> f64 {
+    if (velocity + delta).norm() <= max_speed || velocity.norm() > max_speed {
+        return 1.0;
+    }
+    let a = delta.norm_squared();
+    let b = velocity.dot(delta);
+    let c = velocity.norm_squared() - max_speed * max_speed;
+    ((-b + (b * b - a * c).sqrt()) / a).clamp(0.0, 1.0)
+}
+
+// Z axis of the body frame expressed in the world frame for ZYX Euler angles
+fn body_z_axis(attitude: &Vector3<f64>) -> Vector3<f64> {
+    let (sin_roll, cos_roll) = attitude.x.sin_cos();
+    let (sin_pitch, cos_pitch) = attitude.y.sin_cos();
+    let (sin_yaw, cos_yaw) = attitude.z.sin_cos();
+    Vector3::new(
+        cos_yaw * sin_pitch * cos_roll + sin_yaw * sin_roll,
+        sin_yaw * sin_pitch * cos_roll - cos_yaw * sin_roll,
+        cos_pitch * cos_roll,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: VehicleLimits = VehicleLimits {
+        max_speed: 5.0,
+        max_acceleration: 2.0,
+    };
+
+    fn vehicles() -> [Vehicle; 3] {
+        [
+            Vehicle::car(LIMITS),
+            Vehicle::quadrotor(LIMITS),
+            Vehicle::pedestrian(LIMITS),
+        ]
+    }
+
+    #[test]
+    fn vehicles_start_at_rest() {
+        for mut vehicle in vehicles() {
+            let sample = vehicle.advance_to(0.0);
+            assert_eq!(sample.velocity, Vector3::zeros());
+            approx::assert_abs_diff_eq!(sample.acceleration, Vector3::zeros(), epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn vehicles_respect_velocity_and_acceleration_limits() {
+        for mut vehicle in vehicles() {
+            let mut time = 0.0;
+            while time < 120.0 {
+                time += 0.05;
+                let sample = vehicle.advance_to(time);
+                assert!(sample.velocity.norm() <= LIMITS.max_speed + 1e-9);
+                assert!(sample.acceleration.norm() <= LIMITS.max_acceleration + 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn vehicles_move_consistently_with_their_derivatives() {
+        // the interval lies within the first half of a pedestrian step, away from the gait switches
+        for mut vehicle in vehicles() {
+            vehicle.advance_to(10.1);
+            let dt = 0.01;
+            let (before, after) = (vehicle.sample(), vehicle.advance_to(10.1 + dt));
+            let mean_velocity = (before.velocity + after.velocity) * 0.5;
+            approx::assert_relative_eq!(
+                (after.position - before.position) / dt,
+                mean_velocity,
+                epsilon = 1e-2
+            );
+            let mean_acceleration = (before.acceleration + after.acceleration) * 0.5;
+            approx::assert_relative_eq!(
+                (after.velocity - before.velocity) / dt,
+                mean_acceleration,
+                epsilon = 5e-2
+            );
+        }
+    }
+
+    #[test]
+    fn ground_vehicles_stay_on_the_ground_and_reach_cruise_speed() {
+        for mut vehicle in [Vehicle::car(LIMITS), Vehicle::pedestrian(LIMITS)] {
+            let sample = vehicle.advance_to(30.0);
+            approx::assert_abs_diff_eq!(sample.position.z, 0.0);
+            assert!(sample.velocity.norm() > 0.5 * CRUISE_SPEED_RATIO * LIMITS.max_speed);
+        }
+    }
+
+    #[test]
+    fn pedestrian_acceleration_changes_with_every_step() {
+        let mut pedestrian = Vehicle::pedestrian(LIMITS);
+        pedestrian.advance_to(20.0);
+        let half_step = 0.5 / PEDESTRIAN_STEP_FREQUENCY;
+        let accelerating = pedestrian.advance_to(20.0 + 0.5 * half_step);
+        let decelerating = pedestrian.advance_to(20.0 + 1.5 * half_step);
+        let forward = accelerating.velocity.normalize();
+        assert!(accelerating.acceleration.dot(&forward) > 0.0);
+        assert!(decelerating.acceleration.dot(&forward) < 0.0);
+    }
+
+    #[test]
+    fn quadrotor_thrust_and_attitude_produce_its_acceleration() {
+        let mut quadrotor = Vehicle::quadrotor(LIMITS);
+        let mut time = 0.0;
+        while time < 40.0 {
+            time += 0.5;
+            let sample = quadrotor.advance_to(time);
+            let Model::Quadrotor(state) = &quadrotor.model else {
+                panic!("Not a quadrotor");
+            };
+            assert!(state.attitude.x.abs() <= QUADROTOR_MAX_TILT + 1e-9);
+            assert!(state.attitude.y.abs() <= QUADROTOR_MAX_TILT + 1e-9);
+            let thrust = sample.acceleration + Vector3::z() * GRAVITY;
+            approx::assert_relative_eq!(thrust.norm(), state.thrust, epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn quadrotor_reaches_its_speed_limit_through_its_acceleration() {
+        // the tilt limit shortens the horizontal acceleration only, which would overshoot the speed limit
+        let limits = VehicleLimits {
+            max_speed: 2.0,
+            max_acceleration: 20.0,
+        };
+        let mut quadrotor = Vehicle::quadrotor(limits);
+        let mut before = quadrotor.advance_to(0.0);
+        let dt = INTEGRATION_STEP;
+        let mut time = 0.0;
+        while time < 20.0 {
+            time += dt;
+            let after = quadrotor.advance_to(time);
+            assert!(after.velocity.norm() <= limits.max_speed + 1e-9);
+            approx::assert_relative_eq!(
+                (after.velocity - before.velocity) / dt,
+                after.acceleration,
+                epsilon = 1e-6
+            );
+            before = after;
+        }
+    }
+
+    #[test]
+    fn given_velocity_change_beyond_the_speed_limit_expect_it_shortened() {
+        let velocity = Vector3::new(1.0, 0.0, 0.0);
+        assert_eq!(
+            speed_limited_fraction(&velocity, &Vector3::new(0.5, 0.0, 0.0), 2.0),
+            1.0
+        );
+        approx::assert_relative_eq!(
+            speed_limited_fraction(&velocity, &Vector3::new(2.0, 0.0, 0.0), 2.0),
+            0.5
+        );
+        approx::assert_relative_eq!(
+            speed_limited_fraction(&velocity, &Vector3::new(0.0, 2.0, 0.0), 2.0),
+            3.0_f64.sqrt() / 2.0
+        );
+    }
+
+    #[test]
+    fn vehicle_attitude_follows_the_model() {
+        let mut car = Vehicle::car(LIMITS);
+        let sample = car.advance_to(30.0);
+        let attitude = car.attitude();
+        approx::assert_abs_diff_eq!(attitude.xy(), nalgebra::Vector2::zeros());
+        approx::assert_relative_eq!(
+            sample.velocity.normalize(),
+            Vector3::new(attitude.z.cos(), attitude.z.sin(), 0.0),
+            epsilon = 1e-9
+        );
+
+        let mut quadrotor = Vehicle::quadrotor(LIMITS);
+        let sample = quadrotor.advance_to(5.0);
+        let thrust = sample.acceleration + Vector3::z() * GRAVITY;
+        approx::assert_relative_eq!(
+            body_z_axis(&quadrotor.attitude()),
+            thrust.normalize(),
+            epsilon = 1e-9
+        );
+    }
+
+    #[test]
+    fn given_new_limits_expect_them_to_be_respected() {
+        let slow = VehicleLimits {
+            max_speed: 1.0,
+            max_acceleration: 0.5,
+        };
+        let mut car = Vehicle::car(LIMITS);
+        car.set_limits(slow);
+        let sample = car.advance_to(30.0);
+        assert!(sample.velocity.norm() <= slow.max_speed + 1e-9);
+        assert!(sample.acceleration.norm() <= slow.max_acceleration + 1e-9);
+    }
+}
@@ -0,0 +1,281 @@
+use crate::{
+    bus::Publisher,
+    config::LOCAL_FRAME,
+    data::{Data, KinematicState, Telemetry},
+    log_config::{GENERAL_LOG, UWB_LOG},
+    logger::log,
+    periodic_runner,
+    utils::get_cycle_duration,
+};
+use std::{
+    error::Error,
+    num::NonZeroU32,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, SystemTime},
+};
+
+use nalgebra::Vector3;
+use rand::{rng, Rng};
+use rand_distr::{Distribution, Exp, Normal};
+
+// Errors of the ranges between the vehicle and every anchor
+#[derive(Debug, Clone, Copy)]
+pub struct UwbConfig {
+    pub noise_sigma: f64, // m
+    pub nlos_rate: f64,   // non-line-of-sight events per second and anchor
+    pub nlos_duration: Duration,
+    pub nlos_bias: f64, // m, mean of the exponentially distributed excess path
+}
+
+// The signal of an anchor reaches the vehicle by a longer, reflected path until the event ends
+#[derive(Debug, Clone, Copy)]
+struct NlosEvent {
+    end: SystemTime,
+    bias: f64,
+}
+
+pub struct UwbRanging {
+    tx: Publisher<Telemetry>,
+    kinematic_state: Arc<Mutex<KinematicState>>,
+    prev_timestamp: SystemTime,
+    anchors: Vec<Vector3<f64>>,
+    config: UwbConfig,
+    nlos: Vec<Option<NlosEvent>>,
+    noise_generator: Normal<f64>,
+    bias_generator: Exp<f64>,
+}
+
+impl UwbRanging {
+    pub fn run(
+        kinematic_state: Arc<Mutex<KinematicState>>,
+        tx: Publisher<Telemetry>,
+        shutdown: Arc<AtomicBool>,
+        frequency: NonZeroU32,
+        anchors: &[[f64; 3]],
+        config: UwbConfig,
+    ) -> JoinHandle<()> {
+        let mut uwb = UwbRanging::new(kinematic_state, tx, anchors, config);
+        std::thread::spawn(move || {
+            if let Err(e) = periodic_runner::run_periodicaly(
+                || uwb.step(),
+                || shutdown.load(Ordering::SeqCst),
+                get_cycle_duration(frequency),
+            ) {
+                eprintln!("UWB ranging internal error: {e}. Aborting.")
+            }
+
+            log(GENERAL_LOG, "UWB ranging removed".to_string());
+        })
+    }
+
+    fn new(
+        kinematic_state: Arc<Mutex<KinematicState>>,
+        tx: Publisher<Telemetry>,
+        anchors: &[[f64; 3]],
+        config: UwbConfig,
+    ) -> UwbRanging {
+        let prev_timestamp = kinematic_state.lock().unwrap().timestamp;
+        UwbRanging {
+            tx,
+            kinematic_state,
+            prev_timestamp,
+            anchors: anchors
+                .iter()
+                .map(|anchor| Vector3::from(*anchor))
+                .collect(),
+            config,
+            nlos: vec![None; anchors.len()],
+            noise_generator: Normal::new(0.0, config.noise_sigma).unwrap(),
+            bias_generator: Exp::new(1.0 / config.nlos_bias).unwrap(),
+        }
+    }
+
+    // Non-line-of-sight events of every anchor start at random, as a Poisson process
+    fn update_nlos(&mut self, timestamp: SystemTime, elapsed: Duration) {
+        let nlos_probability = 1.0 - (-self.config.nlos_rate * elapsed.as_secs_f64()).exp();
+        for (anchor, nlos) in self.nlos.iter_mut().enumerate() {
+            if nlos.is_some_and(|event| timestamp >= event.end) {
+                *nlos = None;
+            }
+            if nlos.is_none() && rng().random_bool(nlos_probability) {
+                let bias = self.bias_generator.sample(&mut rng());
+                *nlos = Some(NlosEvent {
+                    end: timestamp + self.config.nlos_duration,
+                    bias,
+                });
+                // ground truth, the published ranges do not carry it
+                log(
+                    GENERAL_LOG,
+                    format!(
+                        "UWB ranging: anchor {anchor} out of line of sight, range bias {bias:.3} m"
+                    ),
+                );
+            }
+        }
+    }
+
+    // Publishes one range per anchor, see Telemetry::Range
+    fn step(&mut self) -> Result<(), Box<dyn Error>> {
+        let current_state = *self.kinematic_state.lock().unwrap();
+
+        let elapsed = current_state
+            .timestamp
+            .duration_since(self.prev_timestamp)?;
+        self.prev_timestamp = current_state.timestamp;
+        self.update_nlos(current_state.timestamp, elapsed);
+
+        let position = LOCAL_FRAME.simulated_to_local(current_state.position_data());
+        let position = Vector3::new(position.x, position.y, position.z);
+        let mut subscribers = 0;
+        for (index, anchor) in self.anchors.iter().enumerate() {
+            let bias = self.nlos[index].map_or(0.0, |event| event.bias);
+            let range = (position - anchor).norm() + bias + self.noise_generator.sample(&mut rng());
+            let data_to_send = Data {
+                x: range.max(0.0),
+                y: index as f64,
+                z: 0.0,
+                timestamp: current_state.timestamp,
+            };
+
+            log(UWB_LOG, data_to_send);
+            subscribers = self.tx.publish(Telemetry::Range(data_to_send));
+        }
+        if subscribers == 0 {
+            return Err("No subscribers for UWB ranging output.".into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ntest_timeout::timeout;
+    use std::sync::mpsc;
+
+    const ANCHORS: [[f64; 3]; 2] = [[0.0, 0.0, 0.0], [10.0, 0.0, 2.0]];
+
+    const IDEAL: UwbConfig = UwbConfig {
+        noise_sigma: 0.0,
+        nlos_rate: 0.0,
+        nlos_duration: Duration::from_secs(1),
+        nlos_bias: 1.0,
+    };
+
+    fn uwb(
+        kinematic_state: &Arc<Mutex<KinematicState>>,
+        tx: Publisher<Telemetry>,
+        config: UwbConfig,
+    ) -> UwbRanging {
+        UwbRanging::new(Arc::clone(kinematic_state), tx, &ANCHORS, config)
+    }
+
+    fn measured_ranges(rx: &mpsc::Receiver<crate::envelope::Envelope<Telemetry>>) -> Vec<Data> {
+        (0..ANCHORS.len())
+            .map(|_| {
+                let Telemetry::Range(data) = rx.recv().unwrap().message else {
+                    panic!("UWB ranging should provide ranges.");
+                };
+                data
+            })
+            .collect()
+    }
+
+    fn advance(kinematic_state: &Arc<Mutex<KinematicState>>, elapsed: Duration) {
+        kinematic_state.lock().unwrap().timestamp += elapsed;
+    }
+
+    #[test]
+    fn given_ideal_sensor_expect_distance_to_every_anchor() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, rx) = mpsc::channel();
+        let mut uwb = uwb(&kinematic_state, vec![tx].into(), IDEAL);
+        kinematic_state.lock().unwrap().position = Vector3::new(3.0, 4.0, 0.0);
+
+        assert!(uwb.step().is_ok());
+
+        let ranges = measured_ranges(&rx);
+        approx::assert_abs_diff_eq!(ranges[0].x, 5.0, epsilon = 1e-12);
+        approx::assert_abs_diff_eq!(ranges[0].y, 0.0);
+        approx::assert_abs_diff_eq!(ranges[1].x, (49.0f64 + 16.0 + 4.0).sqrt(), epsilon = 1e-12);
+        approx::assert_abs_diff_eq!(ranges[1].y, 1.0);
+        assert!(ranges
+            .iter()
+            .all(|range| range.timestamp == kinematic_state.lock().unwrap().timestamp));
+    }
+
+    #[test]
+    fn given_nlos_event_expect_positive_bias_until_it_ends() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, rx) = mpsc::channel();
+        let always_nlos = UwbConfig {
+            nlos_rate: f64::INFINITY,
+            ..IDEAL
+        };
+        let mut uwb = uwb(&kinematic_state, vec![tx].into(), always_nlos);
+        kinematic_state.lock().unwrap().position = Vector3::new(0.0, 5.0, 0.0);
+
+        advance(&kinematic_state, Duration::from_millis(100));
+        assert!(uwb.step().is_ok());
+        let bias = uwb.nlos[0].unwrap().bias;
+        let biased = measured_ranges(&rx);
+        assert!(bias > 0.0);
+        approx::assert_abs_diff_eq!(biased[0].x, 5.0 + bias, epsilon = 1e-12);
+        assert!(biased.iter().all(|range| range.z == 0.0));
+
+        uwb.config.nlos_rate = 0.0;
+        advance(&kinematic_state, Duration::from_secs(1));
+        assert!(uwb.step().is_ok());
+        let ranges = measured_ranges(&rx);
+        approx::assert_abs_diff_eq!(ranges[0].x, 5.0, epsilon = 1e-12);
+        assert!(uwb.nlos.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn given_noise_enabled_expect_output_with_noise() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, rx) = mpsc::channel();
+        let mut uwb = uwb(
+            &kinematic_state,
+            vec![tx].into(),
+            UwbConfig {
+                noise_sigma: 0.5,
+                ..IDEAL
+            },
+        );
+        kinematic_state.lock().unwrap().position = Vector3::new(0.0, 5.0, 0.0);
+
+        assert!(uwb.step().is_ok());
+        approx::assert_abs_diff_ne!(measured_ranges(&rx)[0].x, 5.0);
+    }
+
+    #[test]
+    fn given_next_timestamp_is_behind_previous_expect_step_to_fail() {
+        let kinematic_state = Arc::new(Mutex::new(KinematicState::new()));
+        let (tx, _rx) = mpsc::channel();
+        let mut uwb = uwb(&kinematic_state, vec![tx].into(), IDEAL);
+        kinematic_state.lock().unwrap().timestamp -= Duration::from_secs(1);
+
+        assert!(uwb.step().is_err());
+    }
+
+    #[test]
+    #[timeout(10000)]
+    fn given_rx_goes_out_of_scope_uwb_shuts_down() {
+        let (tx, rx) = mpsc::channel();
+        let handle = UwbRanging::run(
+            Arc::new(Mutex::new(KinematicState::new())),
+            vec![tx].into(),
+            Arc::new(AtomicBool::new(false)),
+            NonZeroU32::new(10).unwrap(),
+            &ANCHORS,
+            IDEAL,
+        );
+        drop(rx);
+        handle.join().unwrap();
+    }
+}
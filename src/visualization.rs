@@ -1,7 +1,7 @@
 pub mod real_time_visualization;
 pub mod static_visualization;
 
-use std::{collections::VecDeque, sync::mpsc::Receiver, time::SystemTime};
+use std::{collections::VecDeque, time::SystemTime};
 
 use plotters::{
     coord::{
@@ -14,6 +14,10 @@ use plotters::{
 use crate::{
     config,
     data::{Data, Telemetry},
+    envelope::{Envelope, SequenceStatus, SequenceTracker},
+    log_config::GENERAL_LOG,
+    logger::log,
+    queue::QueueReceiver,
 };
 
 enum VisualizationType {
@@ -26,6 +30,10 @@ enum PlotDataType {
     Avg,
     Kalman,
     Inertial,
+    Imm,
+    Information,
+    Complementary,
+    GpsSmoothing,
     Groundtruth,
 }
 
@@ -43,26 +51,39 @@ impl std::fmt::Display for PlotAxis {
 }
 
 pub struct PlotterReceivers {
-    rx_gps: Receiver<Telemetry>,
-    rx_avg: Receiver<Telemetry>,
-    rx_kalman: Receiver<Telemetry>,
-    rx_inertial: Receiver<Telemetry>,
-    rx_groundtruth: Receiver<Telemetry>,
+    rx_gps: QueueReceiver<Envelope<Telemetry>>,
+    rx_avg: QueueReceiver<Envelope<Telemetry>>,
+    rx_kalman: QueueReceiver<Envelope<Telemetry>>,
+    rx_inertial: QueueReceiver<Envelope<Telemetry>>,
+    rx_imm: QueueReceiver<Envelope<Telemetry>>,
+    rx_information: QueueReceiver<Envelope<Telemetry>>,
+    rx_complementary: QueueReceiver<Envelope<Telemetry>>,
+    rx_smoothing: QueueReceiver<Envelope<Telemetry>>,
+    rx_groundtruth: QueueReceiver<Envelope<Telemetry>>,
 }
 
 impl PlotterReceivers {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        rx_gps: Receiver<Telemetry>,
-        rx_avg: Receiver<Telemetry>,
-        rx_kalman: Receiver<Telemetry>,
-        rx_inertial: Receiver<Telemetry>,
-        rx_groundtruth: Receiver<Telemetry>,
+        rx_gps: QueueReceiver<Envelope<Telemetry>>,
+        rx_avg: QueueReceiver<Envelope<Telemetry>>,
+        rx_kalman: QueueReceiver<Envelope<Telemetry>>,
+        rx_inertial: QueueReceiver<Envelope<Telemetry>>,
+        rx_imm: QueueReceiver<Envelope<Telemetry>>,
+        rx_information: QueueReceiver<Envelope<Telemetry>>,
+        rx_complementary: QueueReceiver<Envelope<Telemetry>>,
+        rx_smoothing: QueueReceiver<Envelope<Telemetry>>,
+        rx_groundtruth: QueueReceiver<Envelope<Telemetry>>,
     ) -> PlotterReceivers {
         PlotterReceivers {
             rx_gps,
             rx_avg,
             rx_kalman,
             rx_inertial,
+            rx_imm,
+            rx_information,
+            rx_complementary,
+            rx_smoothing,
             rx_groundtruth,
         }
     }
@@ -74,15 +95,24 @@ pub struct Visualization {
     avg_data: VecDeque<Data>,
     kalman_data: VecDeque<Data>,
     inertial_data: VecDeque<Data>,
+    imm_data: VecDeque<Data>,
+    information_data: VecDeque<Data>,
+    complementary_data: VecDeque<Data>,
+    smoothing_data: VecDeque<Data>,
     groundtruth_data: VecDeque<Data>,
-    rx_gps: Receiver<Telemetry>,
-    rx_avg: Receiver<Telemetry>,
-    rx_kalman: Receiver<Telemetry>,
-    rx_inertial: Receiver<Telemetry>,
-    rx_groundtruth: Receiver<Telemetry>,
+    rx_gps: QueueReceiver<Envelope<Telemetry>>,
+    rx_avg: QueueReceiver<Envelope<Telemetry>>,
+    rx_kalman: QueueReceiver<Envelope<Telemetry>>,
+    rx_inertial: QueueReceiver<Envelope<Telemetry>>,
+    rx_imm: QueueReceiver<Envelope<Telemetry>>,
+    rx_information: QueueReceiver<Envelope<Telemetry>>,
+    rx_complementary: QueueReceiver<Envelope<Telemetry>>,
+    rx_smoothing: QueueReceiver<Envelope<Telemetry>>,
+    rx_groundtruth: QueueReceiver<Envelope<Telemetry>>,
     plot_start: u128,
     plot_stop: u128,
     simulation_start: SystemTime,
+    sequence_tracker: SequenceTracker,
 }
 
 impl Visualization {
@@ -96,31 +126,49 @@ impl Visualization {
             PlotDataType::Avg => (&self.rx_avg, &mut self.avg_data),
             PlotDataType::Kalman => (&self.rx_kalman, &mut self.kalman_data),
             PlotDataType::Inertial => (&self.rx_inertial, &mut self.inertial_data),
+            PlotDataType::Imm => (&self.rx_imm, &mut self.imm_data),
+            PlotDataType::Information => (&self.rx_information, &mut self.information_data),
+            PlotDataType::Complementary => (&self.rx_complementary, &mut self.complementary_data),
+            PlotDataType::GpsSmoothing => (&self.rx_smoothing, &mut self.smoothing_data),
             PlotDataType::Groundtruth => (&self.rx_groundtruth, &mut self.groundtruth_data),
         };
 
         match visualization_type {
             VisualizationType::Static => {
-                for data in rx {
-                    match data {
+                for data in rx.iter() {
+                    if self.sequence_tracker.check(&data) == SequenceStatus::OutOfOrder {
+                        continue;
+                    }
+                    match data.message {
                         Telemetry::Position(d) => {
                             rx_data.push_back(d);
                         }
-                        Telemetry::Acceleration(_) => {
-                            panic!("Acceleration should not be passed as an input!");
+                        Telemetry::Acceleration(_)
+                        | Telemetry::Altitude(_)
+                        | Telemetry::MagneticField(_)
+                        | Telemetry::Velocity(_)
+                        | Telemetry::Range(_) => {
+                            panic!("Only positions should be passed as an input!");
                         }
                     }
                 }
             }
             VisualizationType::Dynamic => {
                 while let Ok(data) = rx.try_recv() {
-                    match data {
+                    if self.sequence_tracker.check(&data) == SequenceStatus::OutOfOrder {
+                        continue;
+                    }
+                    match data.message {
                         Telemetry::Position(d) => {
                             rx_data.pop_front();
                             rx_data.push_back(d);
                         }
-                        Telemetry::Acceleration(_) => {
-                            panic!("Acceleration should not be passed as an input!");
+                        Telemetry::Acceleration(_)
+                        | Telemetry::Altitude(_)
+                        | Telemetry::MagneticField(_)
+                        | Telemetry::Velocity(_)
+                        | Telemetry::Range(_) => {
+                            panic!("Only positions should be passed as an input!");
                         }
                     }
                 }
@@ -128,6 +176,20 @@ impl Visualization {
         }
     }
 
+    // Samples lost on the way (e.g. dropped by a full queue) leave gaps in the plotted series
+    fn log_sequence_issues(&self, plot_name: &str) {
+        let (missing, out_of_order) = (
+            self.sequence_tracker.missing(),
+            self.sequence_tracker.out_of_order(),
+        );
+        if missing > 0 || out_of_order > 0 {
+            log(
+                GENERAL_LOG,
+                format!("{plot_name}: {missing} samples missing, {out_of_order} out of order samples skipped"),
+            );
+        }
+    }
+
     fn draw_coordinate<DB>(&mut self, root: DrawingArea<DB, Shift>, coord: PlotAxis)
     where
         DB: DrawingBackend,
@@ -185,6 +247,34 @@ impl Visualization {
             &mut chart,
             coord,
         );
+        self.chart_data(
+            &self.imm_data,
+            "IMM estimator",
+            config::IMM_PLOT_COLOR,
+            &mut chart,
+            coord,
+        );
+        self.chart_data(
+            &self.information_data,
+            "Information filter",
+            config::INFORMATION_PLOT_COLOR,
+            &mut chart,
+            coord,
+        );
+        self.chart_data(
+            &self.complementary_data,
+            "Complementary filter",
+            config::COMPLEMENTARY_PLOT_COLOR,
+            &mut chart,
+            coord,
+        );
+        self.chart_data(
+            &self.smoothing_data,
+            "GPS smoothing",
+            config::GPS_SMOOTHING_PLOT_COLOR,
+            &mut chart,
+            coord,
+        );
 
         chart
             .configure_series_labels()
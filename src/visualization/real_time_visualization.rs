@@ -1,4 +1,4 @@
-use std::{collections::VecDeque, sync::mpsc::Receiver, time::SystemTime};
+use std::{collections::VecDeque, time::SystemTime};
 
 use piston_window::{EventLoop, PistonWindow, WindowSettings};
 use plotters::prelude::*;
@@ -7,6 +7,8 @@ use plotters_piston::{draw_piston_window, PistonBackend};
 use crate::{
     config,
     data::{Data, Telemetry},
+    envelope::{Envelope, SequenceTracker},
+    queue::QueueReceiver,
     visualization::{self, Visualization},
 };
 
@@ -16,13 +18,18 @@ pub struct RealTimeVisualization {
 }
 
 impl RealTimeVisualization {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        rx_gps: Receiver<Telemetry>,
-        rx_avg: Receiver<Telemetry>,
-        rx_kalman: Receiver<Telemetry>,
-
-        rx_inertial: Receiver<Telemetry>,
-        rx_groundtruth: Receiver<Telemetry>,
+        rx_gps: QueueReceiver<Envelope<Telemetry>>,
+        rx_avg: QueueReceiver<Envelope<Telemetry>>,
+        rx_kalman: QueueReceiver<Envelope<Telemetry>>,
+
+        rx_inertial: QueueReceiver<Envelope<Telemetry>>,
+        rx_imm: QueueReceiver<Envelope<Telemetry>>,
+        rx_information: QueueReceiver<Envelope<Telemetry>>,
+        rx_complementary: QueueReceiver<Envelope<Telemetry>>,
+        rx_smoothing: QueueReceiver<Envelope<Telemetry>>,
+        rx_groundtruth: QueueReceiver<Envelope<Telemetry>>,
         simulation_start: SystemTime,
     ) -> RealTimeVisualization {
         RealTimeVisualization {
@@ -43,6 +50,26 @@ impl RealTimeVisualization {
                     [Data::new();
                         (config::PLOT_RANGE_WINDOW * config::IMU_FREQ.get() as u128) as usize],
                 ),
+                imm_data: VecDeque::from(
+                    [Data::new();
+                        (config::PLOT_RANGE_WINDOW
+                            * (config::IMU_FREQ.get() + config::GPS_FREQ.get()) as u128)
+                            as usize],
+                ),
+                information_data: VecDeque::from(
+                    [Data::new();
+                        (config::PLOT_RANGE_WINDOW * config::IMU_FREQ.get() as u128) as usize],
+                ),
+                complementary_data: VecDeque::from(
+                    [Data::new();
+                        (config::PLOT_RANGE_WINDOW
+                            * (config::IMU_FREQ.get() + config::GPS_FREQ.get()) as u128)
+                            as usize],
+                ),
+                smoothing_data: VecDeque::from(
+                    [Data::new();
+                        (config::PLOT_RANGE_WINDOW * config::GPS_FREQ.get() as u128) as usize],
+                ),
                 groundtruth_data: VecDeque::from(
                     [Data::new();
                         (config::PLOT_RANGE_WINDOW * config::GENERATOR_FREQ.get() as u128) as usize],
@@ -51,6 +78,10 @@ impl RealTimeVisualization {
                 rx_avg,
                 rx_kalman,
                 rx_inertial,
+                rx_imm,
+                rx_information,
+                rx_complementary,
+                rx_smoothing,
                 rx_groundtruth,
                 plot_start: SystemTime::now()
                     .duration_since(simulation_start)
@@ -61,6 +92,7 @@ impl RealTimeVisualization {
                     .unwrap()
                     .as_millis(),
                 simulation_start,
+                sequence_tracker: SequenceTracker::default(),
             },
         }
     }
@@ -79,6 +111,10 @@ impl RealTimeVisualization {
             receivers.rx_avg,
             receivers.rx_kalman,
             receivers.rx_inertial,
+            receivers.rx_imm,
+            receivers.rx_information,
+            receivers.rx_complementary,
+            receivers.rx_smoothing,
             receivers.rx_groundtruth,
             simulation_start,
         );
@@ -100,6 +136,22 @@ impl RealTimeVisualization {
                 visualization::PlotDataType::Inertial,
                 visualization::VisualizationType::Dynamic,
             );
+            real_time_visualization.visualization.get_plot_data(
+                visualization::PlotDataType::Imm,
+                visualization::VisualizationType::Dynamic,
+            );
+            real_time_visualization.visualization.get_plot_data(
+                visualization::PlotDataType::Information,
+                visualization::VisualizationType::Dynamic,
+            );
+            real_time_visualization.visualization.get_plot_data(
+                visualization::PlotDataType::Complementary,
+                visualization::VisualizationType::Dynamic,
+            );
+            real_time_visualization.visualization.get_plot_data(
+                visualization::PlotDataType::GpsSmoothing,
+                visualization::VisualizationType::Dynamic,
+            );
             real_time_visualization.visualization.get_plot_data(
                 visualization::PlotDataType::Groundtruth,
                 visualization::VisualizationType::Dynamic,
@@ -110,6 +162,9 @@ impl RealTimeVisualization {
         })
         .is_some()
         {}
+        real_time_visualization
+            .visualization
+            .log_sequence_issues("Real-time visualization");
     }
 
     fn draw(&mut self, b: PistonBackend<'_, '_>) {
@@ -160,35 +215,48 @@ impl RealTimeVisualization {
 mod tests {
     use super::*;
 
-    use std::{
-        sync::mpsc::{self, Sender},
-        thread::sleep,
-        time::Duration,
-    };
+    use std::{thread::sleep, time::Duration};
 
-    use crate::{visualization, Data};
+    use crate::{
+        data::Data,
+        envelope::SourceId,
+        queue::{self, QueueConfig, QueueSender},
+        visualization,
+    };
 
     #[allow(clippy::type_complexity)]
     fn prepare_test_env() -> (
         RealTimeVisualization,
-        Sender<Telemetry>,
-        Sender<Telemetry>,
-        Sender<Telemetry>,
-        Sender<Telemetry>,
-        Sender<Telemetry>,
+        QueueSender<Envelope<Telemetry>>,
+        QueueSender<Envelope<Telemetry>>,
+        QueueSender<Envelope<Telemetry>>,
+        QueueSender<Envelope<Telemetry>>,
+        QueueSender<Envelope<Telemetry>>,
+        QueueSender<Envelope<Telemetry>>,
+        QueueSender<Envelope<Telemetry>>,
+        QueueSender<Envelope<Telemetry>>,
+        QueueSender<Envelope<Telemetry>>,
     ) {
         let simulation_start = SystemTime::now();
-        let (tx_gps, rx_gps) = mpsc::channel();
-        let (tx_avg, rx_avg) = mpsc::channel();
-        let (tx_kalman, rx_kalman) = mpsc::channel();
-        let (tx_inertial, rx_inertial) = mpsc::channel();
-        let (tx_groundtruth, rx_groundtruth) = mpsc::channel();
+        let (tx_gps, rx_gps) = queue::bounded(QueueConfig::UNBOUNDED);
+        let (tx_avg, rx_avg) = queue::bounded(QueueConfig::UNBOUNDED);
+        let (tx_kalman, rx_kalman) = queue::bounded(QueueConfig::UNBOUNDED);
+        let (tx_inertial, rx_inertial) = queue::bounded(QueueConfig::UNBOUNDED);
+        let (tx_imm, rx_imm) = queue::bounded(QueueConfig::UNBOUNDED);
+        let (tx_information, rx_information) = queue::bounded(QueueConfig::UNBOUNDED);
+        let (tx_complementary, rx_complementary) = queue::bounded(QueueConfig::UNBOUNDED);
+        let (tx_smoothing, rx_smoothing) = queue::bounded(QueueConfig::UNBOUNDED);
+        let (tx_groundtruth, rx_groundtruth) = queue::bounded(QueueConfig::UNBOUNDED);
 
         let real_time_visualization = RealTimeVisualization::new(
             rx_gps,
             rx_avg,
             rx_kalman,
             rx_inertial,
+            rx_imm,
+            rx_information,
+            rx_complementary,
+            rx_smoothing,
             rx_groundtruth,
             simulation_start,
         );
@@ -199,6 +267,10 @@ mod tests {
             tx_avg,
             tx_kalman,
             tx_inertial,
+            tx_imm,
+            tx_information,
+            tx_complementary,
+            tx_smoothing,
             tx_groundtruth,
         )
     }
@@ -206,7 +278,7 @@ mod tests {
     #[test]
     #[should_panic]
     fn test_get_plot_data_wrong_input() {
-        let (mut real_time_visualization, _, tx_avg, _, _, _) = prepare_test_env();
+        let (mut real_time_visualization, _, tx_avg, _, _, _, _, _, _, _) = prepare_test_env();
 
         assert!(
             real_time_visualization
@@ -219,12 +291,16 @@ mod tests {
                 == 0.0
         );
 
-        let _ = tx_avg.send(Telemetry::Acceleration(Data {
-            x: 1.0,
-            y: 1.0,
-            z: 1.0,
-            timestamp: SystemTime::now(),
-        }));
+        let _ = tx_avg.send(Envelope::new(
+            SourceId::next(),
+            0,
+            Telemetry::Acceleration(Data {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+                timestamp: SystemTime::now(),
+            }),
+        ));
         real_time_visualization.visualization.get_plot_data(
             visualization::PlotDataType::Avg,
             visualization::VisualizationType::Dynamic,
@@ -243,8 +319,18 @@ mod tests {
 
     #[test]
     fn test_get_plot_data_correct_input() {
-        let (mut real_time_visualization, tx_gps, tx_avg, tx_kalman, tx_inertial, tx_groundtruth) =
-            prepare_test_env();
+        let (
+            mut real_time_visualization,
+            tx_gps,
+            tx_avg,
+            tx_kalman,
+            tx_inertial,
+            tx_imm,
+            tx_information,
+            tx_complementary,
+            tx_smoothing,
+            tx_groundtruth,
+        ) = prepare_test_env();
 
         assert_eq!(
             real_time_visualization
@@ -297,12 +383,16 @@ mod tests {
             0.0
         );
 
-        let _ = tx_gps.send(Telemetry::Position(Data {
-            x: 1.0,
-            y: 1.0,
-            z: 1.0,
-            timestamp: SystemTime::now(),
-        }));
+        let _ = tx_gps.send(Envelope::new(
+            SourceId::next(),
+            0,
+            Telemetry::Position(Data {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+                timestamp: SystemTime::now(),
+            }),
+        ));
         real_time_visualization.visualization.get_plot_data(
             visualization::PlotDataType::Gps,
             visualization::VisualizationType::Dynamic,
@@ -318,12 +408,16 @@ mod tests {
             1.0
         );
 
-        let _ = tx_avg.send(Telemetry::Position(Data {
-            x: 1.0,
-            y: 1.0,
-            z: 1.0,
-            timestamp: SystemTime::now(),
-        }));
+        let _ = tx_avg.send(Envelope::new(
+            SourceId::next(),
+            0,
+            Telemetry::Position(Data {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+                timestamp: SystemTime::now(),
+            }),
+        ));
         real_time_visualization.visualization.get_plot_data(
             visualization::PlotDataType::Avg,
             visualization::VisualizationType::Dynamic,
@@ -339,12 +433,16 @@ mod tests {
             1.0
         );
 
-        let _ = tx_kalman.send(Telemetry::Position(Data {
-            x: 1.0,
-            y: 1.0,
-            z: 1.0,
-            timestamp: SystemTime::now(),
-        }));
+        let _ = tx_kalman.send(Envelope::new(
+            SourceId::next(),
+            0,
+            Telemetry::Position(Data {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+                timestamp: SystemTime::now(),
+            }),
+        ));
         real_time_visualization.visualization.get_plot_data(
             visualization::PlotDataType::Kalman,
             visualization::VisualizationType::Dynamic,
@@ -360,12 +458,16 @@ mod tests {
             1.0
         );
 
-        let _ = tx_inertial.send(Telemetry::Position(Data {
-            x: 1.0,
-            y: 1.0,
-            z: 1.0,
-            timestamp: SystemTime::now(),
-        }));
+        let _ = tx_inertial.send(Envelope::new(
+            SourceId::next(),
+            0,
+            Telemetry::Position(Data {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+                timestamp: SystemTime::now(),
+            }),
+        ));
         real_time_visualization.visualization.get_plot_data(
             visualization::PlotDataType::Inertial,
             visualization::VisualizationType::Dynamic,
@@ -381,12 +483,116 @@ mod tests {
             1.0
         );
 
-        let _ = tx_groundtruth.send(Telemetry::Position(Data {
-            x: 1.0,
-            y: 1.0,
-            z: 1.0,
-            timestamp: SystemTime::now(),
-        }));
+        let _ = tx_imm.send(Envelope::new(
+            SourceId::next(),
+            0,
+            Telemetry::Position(Data {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+                timestamp: SystemTime::now(),
+            }),
+        ));
+        real_time_visualization.visualization.get_plot_data(
+            visualization::PlotDataType::Imm,
+            visualization::VisualizationType::Dynamic,
+        );
+        assert_eq!(
+            real_time_visualization
+                .visualization
+                .imm_data
+                .iter()
+                .last()
+                .unwrap()
+                .x,
+            1.0
+        );
+
+        let _ = tx_information.send(Envelope::new(
+            SourceId::next(),
+            0,
+            Telemetry::Position(Data {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+                timestamp: SystemTime::now(),
+            }),
+        ));
+        real_time_visualization.visualization.get_plot_data(
+            visualization::PlotDataType::Information,
+            visualization::VisualizationType::Dynamic,
+        );
+        assert_eq!(
+            real_time_visualization
+                .visualization
+                .information_data
+                .iter()
+                .last()
+                .unwrap()
+                .x,
+            1.0
+        );
+
+        let _ = tx_complementary.send(Envelope::new(
+            SourceId::next(),
+            0,
+            Telemetry::Position(Data {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+                timestamp: SystemTime::now(),
+            }),
+        ));
+        real_time_visualization.visualization.get_plot_data(
+            visualization::PlotDataType::Complementary,
+            visualization::VisualizationType::Dynamic,
+        );
+        assert_eq!(
+            real_time_visualization
+                .visualization
+                .complementary_data
+                .iter()
+                .last()
+                .unwrap()
+                .x,
+            1.0
+        );
+
+        let _ = tx_smoothing.send(Envelope::new(
+            SourceId::next(),
+            0,
+            Telemetry::Position(Data {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+                timestamp: SystemTime::now(),
+            }),
+        ));
+        real_time_visualization.visualization.get_plot_data(
+            visualization::PlotDataType::GpsSmoothing,
+            visualization::VisualizationType::Dynamic,
+        );
+        assert_eq!(
+            real_time_visualization
+                .visualization
+                .smoothing_data
+                .iter()
+                .last()
+                .unwrap()
+                .x,
+            1.0
+        );
+
+        let _ = tx_groundtruth.send(Envelope::new(
+            SourceId::next(),
+            0,
+            Telemetry::Position(Data {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+                timestamp: SystemTime::now(),
+            }),
+        ));
         real_time_visualization.visualization.get_plot_data(
             visualization::PlotDataType::Groundtruth,
             visualization::VisualizationType::Dynamic,
@@ -405,7 +611,7 @@ mod tests {
 
     #[test]
     fn test_update_plot_range_stop_value() {
-        let (mut real_time_visualization, _, _, _, _, _) = prepare_test_env();
+        let (mut real_time_visualization, _, _, _, _, _, _, _, _, _) = prepare_test_env();
 
         sleep(Duration::from_millis(10));
         let kalman_time = SystemTime::now();
@@ -442,7 +648,7 @@ mod tests {
 
     #[test]
     fn test_update_plot_range_start_value() {
-        let (mut real_time_visualization, _, _, _, _, _) = prepare_test_env();
+        let (mut real_time_visualization, _, _, _, _, _, _, _, _, _) = prepare_test_env();
 
         sleep(Duration::from_millis(100));
         let kalman_time = SystemTime::now();
@@ -493,7 +699,7 @@ mod tests {
     #[test]
     #[should_panic]
     fn test_update_plot_range_access_empty_buffer() {
-        let (mut real_time_visualization, _, _, _, _, _) = prepare_test_env();
+        let (mut real_time_visualization, _, _, _, _, _, _, _, _, _) = prepare_test_env();
 
         real_time_visualization.visualization.kalman_data.clear();
         assert!(real_time_visualization.visualization.kalman_data.is_empty());
@@ -1,28 +1,38 @@
 use std::{
-    collections::VecDeque, sync::mpsc::Receiver, thread, thread::JoinHandle, time::SystemTime,
+    collections::VecDeque, thread, thread::JoinHandle, time::SystemTime,
 };
 
 use plotters::prelude::*;
 
 use crate::{
     data::Telemetry,
-    logger::log,
-    log_config::GENERAL_LOG,
+    envelope::{Envelope, SequenceTracker},
+    imm::ImmModelProbabilities,
+    logger::{get_data, log},
+    log_config::{GENERAL_LOG, IMM_MODEL_PROBABILITIES_LOG},
+    queue::QueueReceiver,
     visualization::{self, Visualization},
 };
 
+type ProbabilitySeries = (&'static str, RGBColor, fn(&ImmModelProbabilities) -> f64);
+
 #[derive(Debug)]
 pub struct StaticVisualization {
     visualization: Visualization,
 }
 
 impl StaticVisualization {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        rx_gps: Receiver<Telemetry>,
-        rx_avg: Receiver<Telemetry>,
-        rx_kalman: Receiver<Telemetry>,
-        rx_inertial: Receiver<Telemetry>,
-        rx_groundtruth: Receiver<Telemetry>,
+        rx_gps: QueueReceiver<Envelope<Telemetry>>,
+        rx_avg: QueueReceiver<Envelope<Telemetry>>,
+        rx_kalman: QueueReceiver<Envelope<Telemetry>>,
+        rx_inertial: QueueReceiver<Envelope<Telemetry>>,
+        rx_imm: QueueReceiver<Envelope<Telemetry>>,
+        rx_information: QueueReceiver<Envelope<Telemetry>>,
+        rx_complementary: QueueReceiver<Envelope<Telemetry>>,
+        rx_smoothing: QueueReceiver<Envelope<Telemetry>>,
+        rx_groundtruth: QueueReceiver<Envelope<Telemetry>>,
         simulation_start: SystemTime,
     ) -> StaticVisualization {
         StaticVisualization {
@@ -31,11 +41,19 @@ impl StaticVisualization {
                 avg_data: VecDeque::new(),
                 kalman_data: VecDeque::new(),
                 inertial_data: VecDeque::new(),
+                imm_data: VecDeque::new(),
+                information_data: VecDeque::new(),
+                complementary_data: VecDeque::new(),
+                smoothing_data: VecDeque::new(),
                 groundtruth_data: VecDeque::new(),
                 rx_gps,
                 rx_avg,
                 rx_kalman,
                 rx_inertial,
+                rx_imm,
+                rx_information,
+                rx_complementary,
+                rx_smoothing,
                 rx_groundtruth,
                 plot_start: SystemTime::now()
                     .duration_since(simulation_start)
@@ -46,6 +64,7 @@ impl StaticVisualization {
                     .unwrap()
                     .as_millis(),
                 simulation_start,
+                sequence_tracker: SequenceTracker::default(),
             },
         }
     }
@@ -59,6 +78,10 @@ impl StaticVisualization {
             receivers.rx_avg,
             receivers.rx_kalman,
             receivers.rx_inertial,
+            receivers.rx_imm,
+            receivers.rx_information,
+            receivers.rx_complementary,
+            receivers.rx_smoothing,
             receivers.rx_groundtruth,
             simulation_start,
         );
@@ -80,6 +103,22 @@ impl StaticVisualization {
                 visualization::PlotDataType::Inertial,
                 visualization::VisualizationType::Static,
             );
+            static_visualization.visualization.get_plot_data(
+                visualization::PlotDataType::Imm,
+                visualization::VisualizationType::Static,
+            );
+            static_visualization.visualization.get_plot_data(
+                visualization::PlotDataType::Information,
+                visualization::VisualizationType::Static,
+            );
+            static_visualization.visualization.get_plot_data(
+                visualization::PlotDataType::Complementary,
+                visualization::VisualizationType::Static,
+            );
+            static_visualization.visualization.get_plot_data(
+                visualization::PlotDataType::GpsSmoothing,
+                visualization::VisualizationType::Static,
+            );
             static_visualization.visualization.get_plot_data(
                 visualization::PlotDataType::Groundtruth,
                 visualization::VisualizationType::Static,
@@ -87,6 +126,10 @@ impl StaticVisualization {
 
             static_visualization.update_plot_range();
             static_visualization.draw();
+            static_visualization.draw_imm_model_probabilities();
+            static_visualization
+                .visualization
+                .log_sequence_issues("Static visualization");
             log(GENERAL_LOG, "Static visualization removed".to_string());
         })
     }
@@ -112,6 +155,69 @@ impl StaticVisualization {
             .draw_coordinate(lower_2, visualization::PlotAxis::Z);
     }
 
+    fn draw_imm_model_probabilities(&self) {
+        let Some(probabilities) = get_data::<ImmModelProbabilities>(IMM_MODEL_PROBABILITIES_LOG)
+        else {
+            return;
+        };
+
+        let root = BitMapBackend::new("output/plot_imm_model_probabilities.png", (2000, 500))
+            .into_drawing_area();
+        root.fill(&WHITE).unwrap();
+
+        let mut chart = ChartBuilder::on(&root)
+            .caption("IMM model probabilities", ("comic-sans", 30))
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .margin(20)
+            .build_cartesian_2d(
+                self.visualization.plot_start..self.visualization.plot_stop,
+                0.0..1.0,
+            )
+            .unwrap();
+
+        chart
+            .configure_mesh()
+            .x_desc("time")
+            .y_desc("probability")
+            .draw()
+            .unwrap();
+
+        let series: [ProbabilitySeries; 3] = [
+            ("Constant velocity", BLUE, |p| p.constant_velocity),
+            ("Constant acceleration", RED, |p| p.constant_acceleration),
+            ("Coordinated turn", GREEN, |p| p.coordinated_turn),
+        ];
+        for (label, color, probability) in series {
+            chart
+                .draw_series(LineSeries::new(
+                    probabilities.iter().filter_map(|entry| {
+                        Some((
+                            entry
+                                .data
+                                .timestamp
+                                .duration_since(self.visualization.simulation_start)
+                                .ok()?
+                                .as_millis(),
+                            probability(&entry.data),
+                        ))
+                    }),
+                    color,
+                ))
+                .unwrap()
+                .label(label)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+        }
+
+        chart
+            .configure_series_labels()
+            .border_style(BLACK)
+            .background_style(WHITE.mix(100.0))
+            .position(SeriesLabelPosition::UpperRight)
+            .draw()
+            .unwrap();
+    }
+
     fn update_plot_range(&mut self) {
         self.visualization.plot_start = self
             .visualization
@@ -141,34 +247,50 @@ mod tests {
 
     use std::{
         path::Path,
-        sync::mpsc::{self, Sender},
         thread::sleep,
         time::Duration,
     };
 
-    use crate::{visualization, Data};
+    use crate::{
+        data::Data,
+        envelope::SourceId,
+        queue::{self, QueueConfig, QueueSender},
+        visualization,
+    };
 
     #[allow(clippy::type_complexity)]
     fn prepare_test_env() -> (
         StaticVisualization,
-        Sender<Telemetry>,
-        Sender<Telemetry>,
-        Sender<Telemetry>,
-        Sender<Telemetry>,
-        Sender<Telemetry>,
+        QueueSender<Envelope<Telemetry>>,
+        QueueSender<Envelope<Telemetry>>,
+        QueueSender<Envelope<Telemetry>>,
+        QueueSender<Envelope<Telemetry>>,
+        QueueSender<Envelope<Telemetry>>,
+        QueueSender<Envelope<Telemetry>>,
+        QueueSender<Envelope<Telemetry>>,
+        QueueSender<Envelope<Telemetry>>,
+        QueueSender<Envelope<Telemetry>>,
     ) {
         let simulation_start = SystemTime::now();
-        let (tx_gps, rx_gps) = mpsc::channel();
-        let (tx_avg, rx_avg) = mpsc::channel();
-        let (tx_kalman, rx_kalman) = mpsc::channel();
-        let (tx_inertial, rx_inertial) = mpsc::channel();
-        let (tx_groundtruth, rx_groundtruth) = mpsc::channel();
+        let (tx_gps, rx_gps) = queue::bounded(QueueConfig::UNBOUNDED);
+        let (tx_avg, rx_avg) = queue::bounded(QueueConfig::UNBOUNDED);
+        let (tx_kalman, rx_kalman) = queue::bounded(QueueConfig::UNBOUNDED);
+        let (tx_inertial, rx_inertial) = queue::bounded(QueueConfig::UNBOUNDED);
+        let (tx_imm, rx_imm) = queue::bounded(QueueConfig::UNBOUNDED);
+        let (tx_information, rx_information) = queue::bounded(QueueConfig::UNBOUNDED);
+        let (tx_complementary, rx_complementary) = queue::bounded(QueueConfig::UNBOUNDED);
+        let (tx_smoothing, rx_smoothing) = queue::bounded(QueueConfig::UNBOUNDED);
+        let (tx_groundtruth, rx_groundtruth) = queue::bounded(QueueConfig::UNBOUNDED);
 
         let static_visualization = StaticVisualization::new(
             rx_gps,
             rx_avg,
             rx_kalman,
             rx_inertial,
+            rx_imm,
+            rx_information,
+            rx_complementary,
+            rx_smoothing,
             rx_groundtruth,
             simulation_start,
         );
@@ -179,13 +301,17 @@ mod tests {
             tx_avg,
             tx_kalman,
             tx_inertial,
+            tx_imm,
+            tx_information,
+            tx_complementary,
+            tx_smoothing,
             tx_groundtruth,
         )
     }
 
     #[test]
     fn test_plot_file_generated() {
-        let (mut static_visualization, _, _, _, _, _) = prepare_test_env();
+        let (mut static_visualization, _, _, _, _, _, _, _, _, _) = prepare_test_env();
         static_visualization.draw();
 
         let path = Path::new("output/plot_gps_avg_kalman.png");
@@ -195,7 +321,7 @@ mod tests {
     #[test]
     #[should_panic]
     fn test_get_plot_data_wrong_input() {
-        let (mut static_visualization, _, tx_avg, _, _, _) = prepare_test_env();
+        let (mut static_visualization, _, tx_avg, _, _, _, _, _, _, _) = prepare_test_env();
 
         assert!(
             static_visualization
@@ -208,12 +334,12 @@ mod tests {
                 == 0.0
         );
 
-        let _ = tx_avg.send(Telemetry::Acceleration(Data {
+        let _ = tx_avg.send(Envelope::new(SourceId::next(), 0, Telemetry::Acceleration(Data {
             x: 1.0,
             y: 1.0,
             z: 1.0,
             timestamp: SystemTime::now(),
-        }));
+        })));
         static_visualization.visualization.get_plot_data(
             visualization::PlotDataType::Avg,
             visualization::VisualizationType::Static,
@@ -232,21 +358,38 @@ mod tests {
 
     #[test]
     fn test_get_plot_data_correct_input() {
-        let (mut static_visualization, tx_gps, tx_avg, tx_kalman, tx_inertial, tx_groundtruth) =
-            prepare_test_env();
+        let (
+            mut static_visualization,
+            tx_gps,
+            tx_avg,
+            tx_kalman,
+            tx_inertial,
+            tx_imm,
+            tx_information,
+            tx_complementary,
+            tx_smoothing,
+            tx_groundtruth,
+        ) = prepare_test_env();
 
         assert_eq!(static_visualization.visualization.gps_data.len(), 0);
         assert_eq!(static_visualization.visualization.avg_data.len(), 0);
         assert_eq!(static_visualization.visualization.kalman_data.len(), 0);
         assert_eq!(static_visualization.visualization.inertial_data.len(), 0);
+        assert_eq!(static_visualization.visualization.imm_data.len(), 0);
+        assert_eq!(static_visualization.visualization.information_data.len(), 0);
+        assert_eq!(
+            static_visualization.visualization.complementary_data.len(),
+            0
+        );
+        assert_eq!(static_visualization.visualization.smoothing_data.len(), 0);
         assert_eq!(static_visualization.visualization.groundtruth_data.len(), 0);
 
-        let _ = tx_gps.send(Telemetry::Position(Data {
+        let _ = tx_gps.send(Envelope::new(SourceId::next(), 0, Telemetry::Position(Data {
             x: 1.0,
             y: 1.0,
             z: 1.0,
             timestamp: SystemTime::now(),
-        }));
+        })));
         drop(tx_gps);
         static_visualization.visualization.get_plot_data(
             visualization::PlotDataType::Gps,
@@ -263,12 +406,12 @@ mod tests {
             1.0
         );
 
-        let _ = tx_avg.send(Telemetry::Position(Data {
+        let _ = tx_avg.send(Envelope::new(SourceId::next(), 0, Telemetry::Position(Data {
             x: 1.0,
             y: 1.0,
             z: 1.0,
             timestamp: SystemTime::now(),
-        }));
+        })));
         drop(tx_avg);
         static_visualization.visualization.get_plot_data(
             visualization::PlotDataType::Avg,
@@ -285,12 +428,12 @@ mod tests {
             1.0
         );
 
-        let _ = tx_kalman.send(Telemetry::Position(Data {
+        let _ = tx_kalman.send(Envelope::new(SourceId::next(), 0, Telemetry::Position(Data {
             x: 1.0,
             y: 1.0,
             z: 1.0,
             timestamp: SystemTime::now(),
-        }));
+        })));
         drop(tx_kalman);
         static_visualization.visualization.get_plot_data(
             visualization::PlotDataType::Kalman,
@@ -307,12 +450,12 @@ mod tests {
             1.0
         );
 
-        let _ = tx_inertial.send(Telemetry::Position(Data {
+        let _ = tx_inertial.send(Envelope::new(SourceId::next(), 0, Telemetry::Position(Data {
             x: 1.0,
             y: 1.0,
             z: 1.0,
             timestamp: SystemTime::now(),
-        }));
+        })));
         drop(tx_inertial);
         static_visualization.visualization.get_plot_data(
             visualization::PlotDataType::Inertial,
@@ -329,12 +472,100 @@ mod tests {
             1.0
         );
 
-        let _ = tx_groundtruth.send(Telemetry::Position(Data {
+        let _ = tx_imm.send(Envelope::new(SourceId::next(), 0, Telemetry::Position(Data {
+            x: 1.0,
+            y: 1.0,
+            z: 1.0,
+            timestamp: SystemTime::now(),
+        })));
+        drop(tx_imm);
+        static_visualization.visualization.get_plot_data(
+            visualization::PlotDataType::Imm,
+            visualization::VisualizationType::Static,
+        );
+        assert_eq!(
+            static_visualization
+                .visualization
+                .imm_data
+                .iter()
+                .last()
+                .unwrap()
+                .x,
+            1.0
+        );
+
+        let _ = tx_information.send(Envelope::new(SourceId::next(), 0, Telemetry::Position(Data {
+            x: 1.0,
+            y: 1.0,
+            z: 1.0,
+            timestamp: SystemTime::now(),
+        })));
+        drop(tx_information);
+        static_visualization.visualization.get_plot_data(
+            visualization::PlotDataType::Information,
+            visualization::VisualizationType::Static,
+        );
+        assert_eq!(
+            static_visualization
+                .visualization
+                .information_data
+                .iter()
+                .last()
+                .unwrap()
+                .x,
+            1.0
+        );
+
+        let _ = tx_complementary.send(Envelope::new(SourceId::next(), 0, Telemetry::Position(Data {
+            x: 1.0,
+            y: 1.0,
+            z: 1.0,
+            timestamp: SystemTime::now(),
+        })));
+        drop(tx_complementary);
+        static_visualization.visualization.get_plot_data(
+            visualization::PlotDataType::Complementary,
+            visualization::VisualizationType::Static,
+        );
+        assert_eq!(
+            static_visualization
+                .visualization
+                .complementary_data
+                .iter()
+                .last()
+                .unwrap()
+                .x,
+            1.0
+        );
+
+        let _ = tx_smoothing.send(Envelope::new(SourceId::next(), 0, Telemetry::Position(Data {
+            x: 1.0,
+            y: 1.0,
+            z: 1.0,
+            timestamp: SystemTime::now(),
+        })));
+        drop(tx_smoothing);
+        static_visualization.visualization.get_plot_data(
+            visualization::PlotDataType::GpsSmoothing,
+            visualization::VisualizationType::Static,
+        );
+        assert_eq!(
+            static_visualization
+                .visualization
+                .smoothing_data
+                .iter()
+                .last()
+                .unwrap()
+                .x,
+            1.0
+        );
+
+        let _ = tx_groundtruth.send(Envelope::new(SourceId::next(), 0, Telemetry::Position(Data {
             x: 1.0,
             y: 1.0,
             z: 1.0,
             timestamp: SystemTime::now(),
-        }));
+        })));
         drop(tx_groundtruth);
         static_visualization.visualization.get_plot_data(
             visualization::PlotDataType::Groundtruth,
@@ -354,7 +585,7 @@ mod tests {
 
     #[test]
     fn test_update_plot_range_start_value() {
-        let (mut real_time_visualization, _, _, _, _, _) = prepare_test_env();
+        let (mut real_time_visualization, _, _, _, _, _, _, _, _, _) = prepare_test_env();
 
         sleep(Duration::from_millis(100));
         let gps_time = SystemTime::now();
@@ -417,7 +648,7 @@ mod tests {
 
     #[test]
     fn test_update_plot_range_stop_value() {
-        let (mut real_time_visualization, _, _, _, _, _) = prepare_test_env();
+        let (mut real_time_visualization, _, _, _, _, _, _, _, _, _) = prepare_test_env();
 
         sleep(Duration::from_millis(100));
         let gps_time = SystemTime::now();